@@ -0,0 +1,39 @@
+// headless benchmark of main.rs's 100x100x5 icosphere grid, with the camera in front of one corner of it so most of the grid is off screen.
+// draws through a RecordingDevice, so it times culling and instance uploads rather than the gpu
+// run with cargo bench --bench culling
+
+use IG2::ecs::{World, RenderComponent};
+use IG2::graphics::{GraphicsEngine, RecordingDevice, Mesh};
+use IG2::transform::{Transform, dvec3};
+
+const N_FRAMES: u32 = 60;
+
+fn main() {
+    let resolution = (1280, 720);
+    let mut engine = GraphicsEngine::new(Box::new(RecordingDevice::null()), resolution);
+    let mesh = Mesh::from_obj("models/icosphere.obj", 0, engine.world_shader_id).expect("the benchmark needs models/icosphere.obj");
+    let mut world = World::new();
+    for x in -1..100 {
+        for y in -1..100 {
+            for z in 5..10 {
+                world.build_entity().with(Transform::meters(dvec3(x as f64 * 3.0, y as f64 * 3.0, z as f64 * 3.0))).with(RenderComponent::new(mesh)).build();
+            }
+        }
+    }
+    engine.camera.transform.setpos_meters(dvec3(-20.0, -20.0, -60.0)); // puts the camera at (20, 20, 60) looking down -z, since camera_offset() negates it
+    engine.update_entities(&mut world);
+
+    for culling in [false, true] {
+        engine.frustum_culling = culling;
+        let start = std::time::Instant::now();
+        for _ in 0..N_FRAMES {
+            engine.update(resolution);
+            engine.update_entities(&mut world);
+            engine.draw();
+        }
+        let elapsed = start.elapsed();
+        let (n_drawn, n_instances) = engine.culling_stats();
+        println!("Culling {}: drew {} of {} instances, {:?} per frame", if culling {"on"} else {"off"}, n_drawn, n_instances, elapsed / N_FRAMES);
+    }
+    engine.cleanup();
+}
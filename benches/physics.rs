@@ -0,0 +1,40 @@
+// headless benchmark of the 100x100x5 icosphere grid from main.rs, as spheres falling onto a floor big enough to catch all of them
+// run with cargo bench --bench physics
+
+extern crate nalgebra_glm as glm;
+
+use glm::vec3;
+
+use IG2::gameobjects::ColliderType;
+use IG2::phys::{BodySet, PhysBody, do_physics_parallel};
+use IG2::transform::dvec3;
+
+const N_STEPS: u32 = 120;
+
+fn main() {
+    let mut set = BodySet::new();
+
+    let mut floor = PhysBody::new(ColliderType::Box);
+    floor.anchored = true;
+    floor.transform.setpos_meters(dvec3(148.5, -10.0, 148.5));
+    floor.transform.setscl(vec3(310.0, 1.0, 310.0));
+    set.insert(floor);
+
+    for x in -1..100 {
+        for y in -1..100 {
+            for z in 5..10 {
+                let mut sphere = PhysBody::new(ColliderType::Sphere);
+                sphere.transform.setpos_meters(dvec3(x as f64 * 3.0, y as f64 * 3.0, z as f64 * 3.0));
+                set.insert(sphere);
+            }
+        }
+    }
+
+    println!("Benchmarking {} bodies on {} threads.", set.len(), rayon::current_num_threads());
+    let start = std::time::Instant::now();
+    for _ in 0..N_STEPS {
+        do_physics_parallel(&set);
+    }
+    let elapsed = start.elapsed();
+    println!("Stepping {} times took {:?} ({:?} per step)", N_STEPS, elapsed, elapsed/N_STEPS);
+}
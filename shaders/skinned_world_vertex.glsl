@@ -0,0 +1,44 @@
+#version 460 // TODO: DON'T USE THIS SHADER VERSION
+
+// same as world_vertex.glsl, but each vertex is moved by up to 4 joints of a skeleton first
+
+layout(location=0) in vec3 vertexPos;
+layout(location=1) in vec4 vertexColor;
+layout(location=2) in vec3 vertexNormal;
+layout(location=3) in vec2 texCoords;
+layout(location=4) in mat4 model;
+// locations 5-7 are part of model
+layout(location=8) in float textureZ;
+layout(location=9) in vec4 jointIndices; // floats because that's what the meshpool's vbo holds
+layout(location=10) in vec4 jointWeights;
+
+uniform mat4 proj;
+uniform mat4 camera;
+uniform mat4 modelToLightSpace;
+
+const int MAX_JOINTS = 128; // has to match animation::MAX_JOINTS
+
+// MAX_JOINTS matrices for every instance slot in the meshpool
+layout (std430, binding=4) buffer joint_matrices
+{
+    mat4 joints[];
+};
+
+out vec3 fragmentColor;
+out vec3 fragmentNormal;
+out vec3 fragmentTexCoords;
+out vec4 lightSpaceCoords;
+
+void main()
+{
+    int base = (gl_BaseInstance + gl_InstanceID) * MAX_JOINTS;
+    mat4 skin = jointWeights.x * joints[base + int(jointIndices.x)]
+              + jointWeights.y * joints[base + int(jointIndices.y)]
+              + jointWeights.z * joints[base + int(jointIndices.z)]
+              + jointWeights.w * joints[base + int(jointIndices.w)];
+
+    gl_Position = proj * camera * model * skin * vec4(vertexPos, 1.0);
+    fragmentColor = vertexColor.xyz;
+    fragmentNormal = normalize(mat3(skin) * vertexNormal);
+    fragmentTexCoords = vec3(texCoords.xy, textureZ);
+}
@@ -0,0 +1,121 @@
+// Plays a clip plus any number of tweens on one object. As an ECS component, animation_system() drives it every frame.
+// Tweens are applied after the clip, so a tween on a property the clip also animates wins until it finishes.
+
+use std::rc::Rc;
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::gameobjects::Renderable;
+
+use super::*;
+
+pub struct Animator {
+    pub clip: Option<Rc<AnimationClip>>, // Rc so lots of objects can share a clip
+    pub time: f32, // seconds into the clip, before looping is applied
+    pub speed: f32, // 1 is normal speed, negative plays backwards
+    pub playing: bool,
+    tweens: Vec<Tween>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        return Self { clip: None, time: 0.0, speed: 1.0, playing: true, tweens: Vec::new() };
+    }
+
+    pub fn with_clip(clip: Rc<AnimationClip>) -> Self {
+        let mut animator = Self::new();
+        animator.play(clip);
+        return animator;
+    }
+
+    // starts the clip from the beginning
+    pub fn play(&mut self, clip: Rc<AnimationClip>) {
+        self.clip = Some(clip);
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.clip = None;
+        self.time = 0.0;
+    }
+
+    // tweens run in the order they were added, so one added after another on the same property takes over from it
+    pub fn add_tween(&mut self, tween: Tween) {
+        self.tweens.push(tween);
+    }
+
+    pub fn clear_tweens(&mut self) {
+        self.tweens.clear();
+    }
+
+    pub fn n_tweens(&self) -> usize {
+        return self.tweens.len();
+    }
+
+    // true once a non looping clip has reached its end and every tween is done
+    pub fn is_finished(&self) -> bool {
+        return self.tweens.is_empty() && self.clip.as_ref().map_or(true, |clip| clip.is_finished(self.time));
+    }
+
+    // moves time forwards and returns what the object should look like now. finished tweens are removed (after being applied one last time, so they land exactly on their target).
+    // current gives what the object looks like before animating, for tweens that are just starting
+    pub fn advance(&mut self, dt: f32, current: impl Fn() -> AnimatedValues) -> AnimatedValues {
+        let mut values = AnimatedValues::none();
+        if let Some(clip) = &self.clip {
+            if self.playing {
+                self.time += dt * self.speed;
+            }
+            values = clip.sample(self.time);
+        }
+        for tween in self.tweens.iter_mut() {
+            let tween_values = tween.update(dt, || current());
+            values.overwrite_with(tween_values);
+        }
+        self.tweens.retain(|tween| !tween.is_finished());
+        return values;
+    }
+
+    // for the old gameobject structs that aren't in the ECS, call once a frame
+    pub fn update_renderable(&mut self, dt: f32, obj: &mut dyn Renderable) {
+        let values = self.advance(dt, || AnimatedValues::current(obj.transform(), obj.get_rgba(), obj.get_texture_z()));
+        values.apply_to_renderable(obj);
+    }
+}
+
+// plays every entity's Animator forward by dt seconds, moving its transform (or LocalTransform if it has a Parent) and RenderComponent.
+// call once a frame before physics_system() and GraphicsEngine::update_entities()
+pub fn animation_system(world: &mut World, dt: f32) {
+    let entities: Vec<Entity> = world.query::<Animator>().map(|(entity, _)| entity).collect();
+    for entity in entities {
+        let parented = world.has::<Parent>(entity) && world.has::<LocalTransform>(entity);
+        let transform = if parented {world.get::<LocalTransform>(entity).map(|l| l.get().clone())} else {world.get::<Transform>(entity).map(|t| t.clone())};
+        let transform = match transform {
+            Some(transform) => transform,
+            None => Transform::empty()
+        };
+        let (color, texture_z) = match world.get::<RenderComponent>(entity) {
+            Some(render) => (render.rgba(), render.texture_z()),
+            None => (glm::vec4(1.0, 1.0, 1.0, 1.0), 0.0)
+        };
+
+        let values = world.get_mut::<Animator>(entity).unwrap().advance(dt, || AnimatedValues::current(&transform, color, texture_z));
+
+        if values.affects_transform() {
+            if parented {
+                values.apply_to_transform(world.get_mut::<LocalTransform>(entity).unwrap().get_mut());
+            }
+            else if let Some(transform) = world.get_mut::<Transform>(entity) {
+                values.apply_to_transform(transform);
+            }
+        }
+        if let Some(render) = world.get_mut::<RenderComponent>(entity) {
+            if let Some(color) = values.color {
+                render.set_rgba(color);
+            }
+            if let Some(texture_z) = values.texture_z {
+                render.set_texture_z(texture_z);
+            }
+        }
+    }
+}
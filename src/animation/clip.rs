@@ -0,0 +1,171 @@
+// An animation clip is a set of tracks played together, one per property. Any track can be left out and that property won't be touched.
+// Position/rotation/scale are in whatever space the object's transform is: world space, or relative to the parent for entities with a Parent.
+
+use glm::{Vec3, Vec4, Quat, I64Vec3};
+
+use crate::transform::*;
+use crate::gameobjects::Renderable;
+
+use super::*;
+
+// what an animation or tween wants an object to look like this frame, None for properties it doesn't animate
+#[derive(Clone)]
+pub struct AnimatedValues {
+    pub position: Option<I64Vec3>,
+    pub rotation: Option<Quat>,
+    pub scale: Option<Vec3>,
+    pub color: Option<Vec4>,
+    pub texture_z: Option<f32>,
+}
+
+impl AnimatedValues {
+    pub fn none() -> Self {
+        return Self { position: None, rotation: None, scale: None, color: None, texture_z: None };
+    }
+
+    // everything about the object, for tweens to start from
+    pub fn current(transform: &Transform, color: Vec4, texture_z: f32) -> Self {
+        return Self { position: Some(transform.pos()), rotation: Some(transform.rot_quat()), scale: Some(transform.scl()), color: Some(color), texture_z: Some(texture_z) };
+    }
+
+    // later values win
+    pub fn overwrite_with(&mut self, other: AnimatedValues) {
+        self.position = other.position.or(self.position);
+        self.rotation = other.rotation.or(self.rotation);
+        self.scale = other.scale.or(self.scale);
+        self.color = other.color.or(self.color);
+        self.texture_z = other.texture_z.or(self.texture_z);
+    }
+
+    // only touches the transform if something about it is animated, so it doesn't get marked as changed for nothing
+    pub fn apply_to_transform(&self, transform: &mut Transform) {
+        if let Some(position) = self.position {
+            transform.setpos(position);
+        }
+        if let Some(rotation) = self.rotation {
+            transform.set_rot_quat(rotation);
+        }
+        if let Some(scale) = self.scale {
+            transform.setscl(scale);
+        }
+    }
+
+    pub fn affects_transform(&self) -> bool {
+        return self.position.is_some() || self.rotation.is_some() || self.scale.is_some();
+    }
+
+    // for the old gameobject structs
+    pub fn apply_to_renderable(&self, obj: &mut dyn Renderable) {
+        self.apply_to_transform(obj.transform_mut());
+        if let Some(color) = self.color {
+            obj.set_rgba(color);
+        }
+        if let Some(texture_z) = self.texture_z {
+            obj.set_texture_z(texture_z);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoopMode {
+    Once, // stops on the last frame
+    Loop,
+    PingPong, // plays forwards then backwards
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub position: Option<Track<I64Vec3>>,
+    pub rotation: Option<Track<Quat>>,
+    pub scale: Option<Track<Vec3>>,
+    pub color: Option<Track<Vec4>>,
+    pub texture_z: Option<Track<f32>>,
+    pub loop_mode: LoopMode,
+}
+
+impl AnimationClip {
+    pub fn new(loop_mode: LoopMode) -> Self {
+        return Self { position: None, rotation: None, scale: None, color: None, texture_z: None, loop_mode };
+    }
+
+    pub fn with_position(mut self, track: Track<I64Vec3>) -> Self {
+        self.position = Some(track);
+        return self;
+    }
+
+    pub fn with_rotation(mut self, track: Track<Quat>) -> Self {
+        self.rotation = Some(track);
+        return self;
+    }
+
+    pub fn with_scale(mut self, track: Track<Vec3>) -> Self {
+        self.scale = Some(track);
+        return self;
+    }
+
+    pub fn with_color(mut self, track: Track<Vec4>) -> Self {
+        self.color = Some(track);
+        return self;
+    }
+
+    pub fn with_texture_z(mut self, track: Track<f32>) -> Self {
+        self.texture_z = Some(track);
+        return self;
+    }
+
+    // length of the longest track
+    pub fn duration(&self) -> f32 {
+        let durations = [
+            self.position.as_ref().map(|t| t.duration()),
+            self.rotation.as_ref().map(|t| t.duration()),
+            self.scale.as_ref().map(|t| t.duration()),
+            self.color.as_ref().map(|t| t.duration()),
+            self.texture_z.as_ref().map(|t| t.duration()),
+        ];
+        return durations.iter().flatten().fold(0.0, |a: f32, b| a.max(*b));
+    }
+
+    // time into the clip after playing for elapsed seconds, taking looping into account
+    pub fn wrap_time(&self, elapsed: f32) -> f32 {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        return match self.loop_mode {
+            LoopMode::Once => elapsed.clamp(0.0, duration),
+            LoopMode::Loop => elapsed.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let t = elapsed.rem_euclid(duration * 2.0);
+                if t > duration {duration * 2.0 - t} else {t}
+            }
+        };
+    }
+
+    pub fn is_finished(&self, elapsed: f32) -> bool {
+        return self.loop_mode == LoopMode::Once && elapsed >= self.duration();
+    }
+
+    // time is in seconds since the start of the clip, and is wrapped for looping clips
+    pub fn sample(&self, elapsed: f32) -> AnimatedValues {
+        let time = self.wrap_time(elapsed);
+        return AnimatedValues {
+            position: self.position.as_ref().and_then(|t| t.sample(time)),
+            rotation: self.rotation.as_ref().and_then(|t| t.sample(time)),
+            scale: self.scale.as_ref().and_then(|t| t.sample(time)),
+            color: self.color.as_ref().and_then(|t| t.sample(time)),
+            texture_z: self.texture_z.as_ref().and_then(|t| t.sample(time)),
+        };
+    }
+
+    // a clip that fades the colour from one value to another, handy for flashes/highlights
+    pub fn color_fade(from: Vec4, to: Vec4, duration: f32, loop_mode: LoopMode) -> Self {
+        return Self::new(loop_mode).with_color(Track::new(Interpolation::Linear).key(0.0, from).key(duration, to));
+    }
+
+    // a clip that rotates around axis by angle (radians), like a door swinging open from rotation start
+    pub fn swing(start: Quat, axis: Vec3, angle: f32, duration: f32, loop_mode: LoopMode) -> Self {
+        let end = glm::quat_angle_axis(angle, &axis) * start;
+        return Self::new(loop_mode).with_rotation(Track::new(Interpolation::Cubic).key(0.0, start).key(duration, end));
+    }
+}
+
@@ -0,0 +1,94 @@
+// Easing curves for tweens, from https://easings.net. They all map 0 -> 0 and 1 -> 1, some (Back, Elastic) overshoot in between.
+
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    BackOut, // goes a bit past the end then settles back
+    ElasticOut, // wobbles around the end like a spring
+    BounceOut, // bounces on the end like a dropped ball
+}
+
+impl Easing {
+    // t is clamped to 0..1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => if t < 0.5 {2.0 * t * t} else {1.0 - (-2.0 * t + 2.0).powi(2) / 2.0},
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => if t < 0.5 {4.0 * t * t * t} else {1.0 - (-2.0 * t + 2.0).powi(3) / 2.0},
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Easing::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                }
+                else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Easing::BounceOut => {
+                let n1 = 7.5625;
+                let d1 = 2.75;
+                if t < 1.0 / d1 {
+                    n1 * t * t
+                }
+                else if t < 2.0 / d1 {
+                    let t = t - 1.5 / d1;
+                    n1 * t * t + 0.75
+                }
+                else if t < 2.5 / d1 {
+                    let t = t - 2.25 / d1;
+                    n1 * t * t + 0.9375
+                }
+                else {
+                    let t = t - 2.625 / d1;
+                    n1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Easing; 13] = [
+        Easing::Linear, Easing::QuadIn, Easing::QuadOut, Easing::QuadInOut, Easing::CubicIn, Easing::CubicOut, Easing::CubicInOut,
+        Easing::SineIn, Easing::SineOut, Easing::SineInOut, Easing::BackOut, Easing::ElasticOut, Easing::BounceOut,
+    ];
+
+    #[test]
+    fn every_easing_starts_at_0_and_ends_at_1() {
+        for easing in ALL {
+            assert!(easing.apply(0.0).abs() < 1e-6, "{:?}(0) = {}", easing, easing.apply(0.0));
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{:?}(1) = {}", easing, easing.apply(1.0));
+            assert_eq!(easing.apply(-0.5), easing.apply(0.0), "{:?} should clamp t", easing);
+            assert_eq!(easing.apply(1.5), easing.apply(1.0), "{:?} should clamp t", easing);
+        }
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert_eq!(Easing::QuadInOut.apply(0.5), 0.5);
+        assert!(Easing::BackOut.apply(0.8) > 1.0, "BackOut should overshoot");
+    }
+}
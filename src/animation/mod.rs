@@ -0,0 +1,12 @@
+mod easing;
+pub use easing::*;
+mod track;
+pub use track::*;
+mod tween;
+pub use tween::*;
+mod clip;
+pub use clip::*;
+mod animator;
+pub use animator::*;
+mod skeleton;
+pub use skeleton::*;
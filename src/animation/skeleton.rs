@@ -0,0 +1,396 @@
+// Skeletal animation: a skeleton is a tree of joints, a pose is where every joint is (relative to its parent), and clips are keyframe tracks per joint.
+// Poses are evaluated and blended on the CPU, then GraphicsEngine::update_entities() hands the resulting joint matrices to the skinned mesh's MeshPool,
+// and the skinned vertex shader moves each vertex by a weighted mix of up to 4 joints' matrices.
+// Joint transforms are f32 and in the mesh's own units (before it's scaled into 1m^3), since a character is small enough that precision isn't a worry.
+
+use std::rc::Rc;
+use std::collections::HashMap;
+
+use glm::{Vec3, Quat, Mat4, vec3};
+
+use crate::ecs::*;
+
+use super::*;
+
+pub const MAX_JOINTS: usize = 128; // per skeleton, this many joint matrices are reserved per skinned instance on the gpu
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JointTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl JointTransform {
+    pub fn identity() -> Self {
+        return Self { translation: vec3(0.0, 0.0, 0.0), rotation: glm::quat_identity(), scale: vec3(1.0, 1.0, 1.0) };
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        return glm::translation(&self.translation) * glm::quat_to_mat4(&self.rotation) * glm::scaling(&self.scale);
+    }
+
+    pub fn lerp(a: &JointTransform, b: &JointTransform, t: f32) -> JointTransform {
+        return JointTransform {
+            translation: a.translation + (b.translation - a.translation) * t,
+            rotation: slerp(&a.rotation, &b.rotation, t),
+            scale: a.scale + (b.scale - a.scale) * t,
+        };
+    }
+}
+
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>, // index into Skeleton::joints, always less than this joint's index
+    pub rest: JointTransform, // relative to parent, used for any joint a clip doesn't animate
+    pub inverse_bind: Mat4, // takes a vertex from mesh space to this joint's space when the mesh was bound to the skeleton
+}
+
+pub struct Skeleton {
+    joints: Vec<Joint>, // parents always come before their children, so one pass front to back resolves the whole tree
+    names: HashMap<String, usize>,
+}
+
+impl Skeleton {
+    // panics if a joint's parent comes after it, or there are more than MAX_JOINTS joints
+    pub fn new(joints: Vec<Joint>) -> Self {
+        assert!(joints.len() <= MAX_JOINTS, "Skeleton has {} joints, but at most {} are supported", joints.len(), MAX_JOINTS);
+        for (i, joint) in joints.iter().enumerate() {
+            if let Some(parent) = joint.parent {
+                assert!(parent < i, "Joint {} ({}) has parent {}, but parents have to come before their children", i, joint.name, parent);
+            }
+        }
+        let names = joints.iter().enumerate().map(|(i, joint)| (joint.name.clone(), i)).collect();
+        return Self { joints, names };
+    }
+
+    // builds the inverse bind matrices from the rest pose, for when the mesh was modeled in the skeleton's rest pose
+    pub fn from_rest_pose(joints: Vec<(String, Option<usize>, JointTransform)>) -> Self {
+        let mut skeleton = Self::new(joints.into_iter().map(|(name, parent, rest)| Joint { name, parent, rest, inverse_bind: glm::identity() }).collect());
+        let globals = skeleton.global_matrices(&skeleton.rest_pose());
+        for (joint, global) in skeleton.joints.iter_mut().zip(globals) {
+            joint.inverse_bind = global.try_inverse().unwrap_or(glm::identity());
+        }
+        return skeleton;
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        return &self.joints;
+    }
+
+    pub fn n_joints(&self) -> usize {
+        return self.joints.len();
+    }
+
+    pub fn joint_index(&self, name: &str) -> Option<usize> {
+        return self.names.get(name).copied();
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        return Pose { joints: self.joints.iter().map(|j| j.rest).collect() };
+    }
+
+    // each joint's transform relative to the mesh (instead of its parent)
+    pub fn global_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for (i, joint) in self.joints.iter().enumerate() {
+            let local = pose.joints[i].to_mat4();
+            globals.push(match joint.parent {
+                Some(parent) => globals[parent] * local,
+                None => local
+            });
+        }
+        return globals;
+    }
+
+    // what the skinned vertex shader wants: for each joint, the matrix moving a vertex from where it was bound to where the pose puts it
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        return self.global_matrices(pose).iter().zip(self.joints.iter()).map(|(global, joint)| global * joint.inverse_bind).collect();
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pose {
+    pub joints: Vec<JointTransform>, // relative to each joint's parent, same order as Skeleton::joints
+}
+
+impl Pose {
+    // weight 0 is all a, 1 is all b. both have to be for the same skeleton
+    pub fn blend(a: &Pose, b: &Pose, weight: f32) -> Pose {
+        return Pose { joints: a.joints.iter().zip(b.joints.iter()).map(|(a, b)| JointTransform::lerp(a, b, weight)).collect() };
+    }
+
+    pub fn blend_into(&mut self, other: &Pose, weight: f32) {
+        for (a, b) in self.joints.iter_mut().zip(other.joints.iter()) {
+            *a = JointTransform::lerp(a, b, weight);
+        }
+    }
+}
+
+// keyframes for one joint. untracked properties keep whatever the pose already had
+#[derive(Clone)]
+pub struct JointChannel {
+    pub joint: usize,
+    pub translation: Option<Track<Vec3>>,
+    pub rotation: Option<Track<Quat>>,
+    pub scale: Option<Track<Vec3>>,
+}
+
+impl JointChannel {
+    pub fn new(joint: usize) -> Self {
+        return Self { joint, translation: None, rotation: None, scale: None };
+    }
+}
+
+#[derive(Clone)]
+pub struct SkeletalClip {
+    pub name: String,
+    pub channels: Vec<JointChannel>,
+    pub loop_mode: LoopMode,
+}
+
+impl SkeletalClip {
+    pub fn new(name: &str, loop_mode: LoopMode) -> Self {
+        return Self { name: name.to_string(), channels: Vec::new(), loop_mode };
+    }
+
+    pub fn with_channel(mut self, channel: JointChannel) -> Self {
+        self.channels.push(channel);
+        return self;
+    }
+
+    pub fn duration(&self) -> f32 {
+        let mut duration: f32 = 0.0;
+        for channel in self.channels.iter() {
+            duration = duration.max(channel.translation.as_ref().map_or(0.0, |t| t.duration()));
+            duration = duration.max(channel.rotation.as_ref().map_or(0.0, |t| t.duration()));
+            duration = duration.max(channel.scale.as_ref().map_or(0.0, |t| t.duration()));
+        }
+        return duration;
+    }
+
+    pub fn wrap_time(&self, elapsed: f32) -> f32 {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        return match self.loop_mode {
+            LoopMode::Once => elapsed.clamp(0.0, duration),
+            LoopMode::Loop => elapsed.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let t = elapsed.rem_euclid(duration * 2.0);
+                if t > duration {duration * 2.0 - t} else {t}
+            }
+        };
+    }
+
+    // overwrites the animated joints of pose with the clip at elapsed seconds (wrapped for looping clips)
+    pub fn sample_into(&self, elapsed: f32, pose: &mut Pose) {
+        let time = self.wrap_time(elapsed);
+        for channel in self.channels.iter() {
+            let joint = match pose.joints.get_mut(channel.joint) {
+                Some(joint) => joint,
+                None => continue
+            };
+            if let Some(translation) = channel.translation.as_ref().and_then(|t| t.sample(time)) {
+                joint.translation = translation;
+            }
+            if let Some(rotation) = channel.rotation.as_ref().and_then(|t| t.sample(time)) {
+                joint.rotation = rotation;
+            }
+            if let Some(scale) = channel.scale.as_ref().and_then(|t| t.sample(time)) {
+                joint.scale = scale;
+            }
+        }
+    }
+}
+
+struct ClipLayer {
+    clip: Rc<SkeletalClip>,
+    time: f32,
+    weight: f32,
+    fade_speed: f32, // weight per second, negative while fading out
+}
+
+// plays and crossfades skeletal clips for one entity, along with a RenderComponent whose mesh has a Skin
+pub struct SkeletalAnimator {
+    pub skeleton: Rc<Skeleton>,
+    pub speed: f32,
+    layers: Vec<ClipLayer>, // later layers are blended over earlier ones
+    pose: Pose,
+    joint_matrices: Vec<Mat4>,
+}
+
+impl SkeletalAnimator {
+    pub fn new(skeleton: Rc<Skeleton>) -> Self {
+        let pose = skeleton.rest_pose();
+        let joint_matrices = skeleton.joint_matrices(&pose);
+        return Self { skeleton, speed: 1.0, layers: Vec::new(), pose, joint_matrices };
+    }
+
+    // switches to clip straight away
+    pub fn play(&mut self, clip: Rc<SkeletalClip>) {
+        self.layers.clear();
+        self.layers.push(ClipLayer { clip, time: 0.0, weight: 1.0, fade_speed: 0.0 });
+    }
+
+    // blends from whatever's playing to clip over duration seconds
+    pub fn crossfade(&mut self, clip: Rc<SkeletalClip>, duration: f32) {
+        if duration <= 0.0 || self.layers.is_empty() {
+            return self.play(clip);
+        }
+        for layer in self.layers.iter_mut() {
+            layer.fade_speed = -1.0 / duration;
+        }
+        self.layers.push(ClipLayer { clip, time: 0.0, weight: 0.0, fade_speed: 1.0 / duration });
+    }
+
+    pub fn playing(&self) -> Option<&Rc<SkeletalClip>> {
+        return self.layers.last().map(|layer| &layer.clip);
+    }
+
+    pub fn pose(&self) -> &Pose {
+        return &self.pose;
+    }
+
+    pub fn joint_matrices(&self) -> &[Mat4] {
+        return &self.joint_matrices;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for layer in self.layers.iter_mut() {
+            layer.time += dt * self.speed;
+            layer.weight = (layer.weight + layer.fade_speed * dt).clamp(0.0, 1.0);
+        }
+        // layers that have faded out completely can go, as long as something is left to play
+        while self.layers.len() > 1 && self.layers[0].weight <= 0.0 && self.layers[0].fade_speed < 0.0 {
+            self.layers.remove(0);
+        }
+
+        // each layer starts from the rest pose so joints its clip doesn't animate blend towards rest instead of towards the layer below
+        let rest = self.skeleton.rest_pose();
+        self.pose = rest.clone();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let mut layer_pose = rest.clone();
+            layer.clip.sample_into(layer.time, &mut layer_pose);
+            if i == 0 {
+                self.pose = layer_pose;
+            }
+            else {
+                self.pose.blend_into(&layer_pose, layer.weight);
+            }
+        }
+        self.joint_matrices = self.skeleton.joint_matrices(&self.pose);
+    }
+}
+
+// updates the pose of every SkeletalAnimator, GraphicsEngine::update_entities() then sends them to the gpu
+pub fn skeletal_animation_system(world: &mut World, dt: f32) {
+    for (_, animator) in world.query_mut::<SkeletalAnimator>() {
+        animator.update(dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::{MeshPool, RecordingDevice, N_FLOATS_PER_SKINNED_VERTEX};
+
+    use super::*;
+
+    fn assert_mat_near(actual: &Mat4, expected: &Mat4, what: &str) {
+        assert!((actual - expected).abs().max() < 1e-5, "Expected {} to be {:?}, but it was {:?}", what, expected, actual);
+    }
+
+    // a root at the origin with an arm sticking 1 up out of it
+    fn arm() -> Rc<Skeleton> {
+        let elbow = JointTransform { translation: vec3(0.0, 1.0, 0.0), ..JointTransform::identity() };
+        return Rc::new(Skeleton::from_rest_pose(vec![
+            (String::from("root"), None, JointTransform::identity()),
+            (String::from("elbow"), Some(0), elbow),
+        ]));
+    }
+
+    // holds the elbow at translation and rotation for the whole clip
+    fn hold(name: &str, translation: Vec3, rotation: Quat) -> Rc<SkeletalClip> {
+        let mut channel = JointChannel::new(1);
+        channel.translation = Some(Track::new(Interpolation::Linear).key(0.0, translation).key(1.0, translation));
+        channel.rotation = Some(Track::new(Interpolation::Linear).key(0.0, rotation).key(1.0, rotation));
+        return Rc::new(SkeletalClip::new(name, LoopMode::Loop).with_channel(channel));
+    }
+
+    #[test]
+    fn bind_pose_gives_identity_joint_matrices() {
+        let skeleton = arm();
+        let identity: Mat4 = glm::identity();
+        for (i, matrix) in skeleton.joint_matrices(&skeleton.rest_pose()).iter().enumerate() {
+            assert_mat_near(matrix, &identity, &format!("joint {}'s bind pose matrix", i));
+        }
+        let animator = SkeletalAnimator::new(skeleton.clone());
+        assert!(animator.joint_matrices().iter().all(|m| (m - identity).abs().max() < 1e-5), "A new animator should start in the bind pose.");
+    }
+
+    #[test]
+    fn blending_two_clips_halfway() {
+        let skeleton = arm();
+        let quarter_turn = glm::quat_angle_axis(std::f32::consts::FRAC_PI_2, &vec3(0.0, 0.0, 1.0));
+        let left = hold("left", vec3(-1.0, 1.0, 0.0), glm::quat_identity());
+        let right = hold("right", vec3(1.0, 1.0, 0.0), quarter_turn);
+
+        let mut animator = SkeletalAnimator::new(skeleton.clone());
+        animator.play(left.clone());
+        animator.crossfade(right.clone(), 1.0);
+        animator.update(0.5);
+
+        // halfway between the clips is back over the root, turned an eighth of the way around
+        let elbow = animator.pose().joints[1];
+        assert!((elbow.translation - vec3(0.0, 1.0, 0.0)).norm() < 1e-5, "Expected the elbow halfway at (0, 1, 0), but it's at {:?}", elbow.translation);
+        let eighth_turn = glm::quat_angle_axis(std::f32::consts::FRAC_PI_4, &vec3(0.0, 0.0, 1.0));
+        assert!(glm::quat_dot(&elbow.rotation, &eighth_turn).abs() > 1.0 - 1e-5, "Expected the elbow turned 45 degrees, but its rotation is {:?}", elbow.rotation);
+
+        // same as blending the sampled poses directly
+        let (mut a, mut b) = (skeleton.rest_pose(), skeleton.rest_pose());
+        left.sample_into(0.5, &mut a);
+        right.sample_into(0.5, &mut b);
+        let blended = Pose::blend(&a, &b, 0.5);
+        for (i, (expected, actual)) in skeleton.joint_matrices(&blended).iter().zip(animator.joint_matrices()).enumerate() {
+            assert_mat_near(actual, expected, &format!("joint {}'s blended matrix", i));
+        }
+
+        // a vertex bound 1 above the elbow swings around it
+        let vertex = animator.joint_matrices()[1] * glm::vec4(0.0, 2.0, 0.0, 1.0);
+        let expected = vec3(-std::f32::consts::FRAC_1_SQRT_2, 1.0 + std::f32::consts::FRAC_1_SQRT_2, 0.0);
+        assert!((vertex.xyz() - expected).norm() < 1e-5, "Expected the skinned vertex at {:?}, but it's at {:?}", expected, vertex.xyz());
+
+        animator.update(0.5); // the crossfade is over, so only right is left
+        assert!(Rc::ptr_eq(animator.playing().unwrap(), &right));
+        assert!((animator.pose().joints[1].translation - vec3(1.0, 1.0, 0.0)).norm() < 1e-5);
+    }
+
+    // the matrices end up in the meshpool's joint buffer, MAX_JOINTS per instance
+    #[test]
+    fn joint_matrices_reach_the_meshpool() {
+        let device = RecordingDevice::new();
+        let mut pool = MeshPool::new_skinned(&device, 3, 3, 2);
+        let vertices = vec![0.0; 3 * N_FLOATS_PER_SKINNED_VERTEX];
+        let (slot, _) = pool.add_mesh(&device, 1, &vertices, &vec![0, 1, 2], 2, false);
+
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        pose.joints[1].translation = vec3(3.0, 1.0, 0.0);
+        let matrices = skeleton.joint_matrices(&pose);
+        pool.set_joint_matrices(slot, 1, &matrices);
+
+        let joints = device.buffer_contents(pool.joint_buffer()).expect("the joint buffer doesn't exist");
+        let matrix_at = |instance: usize, joint: usize| {
+            let offset = (instance * MAX_JOINTS + joint) * 64;
+            let floats: Vec<f32> = joints[offset..offset + 64].chunks_exact(4).map(|f| f32::from_ne_bytes(f.try_into().unwrap())).collect();
+            return Mat4::from_column_slice(&floats);
+        };
+        let identity: Mat4 = glm::identity();
+        assert_mat_near(&matrix_at(0, 1), &identity, "the untouched instance's elbow");
+        assert_mat_near(&matrix_at(1, 0), &matrices[0], "the root");
+        assert_mat_near(&matrix_at(1, 1), &matrices[1], "the elbow");
+        assert_mat_near(&matrix_at(1, 2), &identity, "a joint past the skeleton");
+        pool.cleanup(&device);
+    }
+}
@@ -0,0 +1,229 @@
+// Keyframe tracks: a value at a few points in time, with the values in between interpolated.
+// Positions interpolate as offsets from the previous keyframe in f64, so a track far from the origin is as smooth as one near it.
+// Rotations use slerp (always the short way around), and cubic rotation is catmull-rom on the quaternion then renormalized.
+
+use glm::{Vec3, Vec4, Quat, I64Vec3, DVec3};
+
+use crate::transform::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    Step, // holds each keyframe's value until the next one
+    Linear,
+    Cubic, // catmull-rom, passes through every keyframe with no sudden changes in speed
+}
+
+#[derive(Clone)]
+pub struct Keyframe<T> {
+    pub time: f32, // seconds
+    pub value: T,
+}
+
+pub trait Animatable: Clone {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self;
+
+    // the value between p1 and p2 (t from 0 to 1), with p0 and p3 being the keyframes either side of them.
+    // times are when each of them happen, so unevenly spaced keyframes don't speed up or slow down
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self;
+}
+
+// one component of a catmull-rom spline, in hermite form with the tangents scaled for non uniform keyframe times
+fn catmull_rom_component(p: [f64; 4], times: [f32; 4], t: f64) -> f64 {
+    let [t0, t1, t2, t3] = times.map(|t| t as f64);
+    let span = (t2 - t1).max(f64::EPSILON);
+    let m1 = if t2 > t0 {(p[2] - p[0]) / (t2 - t0) * span} else {p[2] - p[1]};
+    let m2 = if t3 > t1 {(p[3] - p[1]) / (t3 - t1) * span} else {p[2] - p[1]};
+    let t2_ = t * t;
+    let t3_ = t2_ * t;
+    return (2.0 * t3_ - 3.0 * t2_ + 1.0) * p[1] + (t3_ - 2.0 * t2_ + t) * m1 + (-2.0 * t3_ + 3.0 * t2_) * p[2] + (t3_ - t2_) * m2;
+}
+
+impl Animatable for f32 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        return a + (b - a) * t;
+    }
+
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self {
+        return catmull_rom_component(p.map(|v| *v as f64), times, t as f64) as f32;
+    }
+}
+
+impl Animatable for Vec3 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        return a + (b - a) * t;
+    }
+
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self {
+        return Vec3::from_fn(|i, _| catmull_rom_component(p.map(|v| v[i] as f64), times, t as f64) as f32);
+    }
+}
+
+impl Animatable for Vec4 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        return a + (b - a) * t;
+    }
+
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self {
+        return Vec4::from_fn(|i, _| catmull_rom_component(p.map(|v| v[i] as f64), times, t as f64) as f32);
+    }
+}
+
+// micrometers
+impl Animatable for I64Vec3 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        return a + i64vec3_from_dvec3(&(dvec3_from_i64vec3(&(b - a)) * t as f64));
+    }
+
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self {
+        let offsets = p.map(|v| dvec3_from_i64vec3(&(v - p[1])));
+        let offset = DVec3::from_fn(|i, _| catmull_rom_component(offsets.map(|o| o[i]), times, t as f64));
+        return p[1] + i64vec3_from_dvec3(&offset);
+    }
+}
+
+impl Animatable for Quat {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        return slerp(a, b, t);
+    }
+
+    fn catmull_rom(p: [&Self; 4], times: [f32; 4], t: f32) -> Self {
+        // q and -q are the same rotation, flip them all to p1's side so the spline doesn't go the long way around
+        let aligned = p.map(|q| if q.dot(p[1]) < 0.0 {-q} else {*q});
+        let coords = Vec4::from_fn(|i, _| catmull_rom_component(aligned.map(|q| q.coords[i] as f64), times, t as f64) as f32);
+        return Quat::from_vector(coords).normalize();
+    }
+}
+
+// spherical interpolation, always the short way around. falls back to a normalized lerp when a and b are nearly the same, where slerp divides by ~0
+pub fn slerp(a: &Quat, b: &Quat, t: f32) -> Quat {
+    let mut dot = a.dot(b);
+    let b = if dot < 0.0 {dot = -dot; -b} else {*b};
+    if dot > 0.9995 {
+        return (a + (b - a) * t).normalize();
+    }
+    let theta = dot.clamp(-1.0, 1.0).acos();
+    let sin_theta = theta.sin();
+    return a * (((1.0 - t) * theta).sin() / sin_theta) + b * ((t * theta).sin() / sin_theta);
+}
+
+#[derive(Clone)]
+pub struct Track<T: Animatable> {
+    keyframes: Vec<Keyframe<T>>, // sorted by time
+    pub interpolation: Interpolation,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        return Self { keyframes: Vec::new(), interpolation };
+    }
+
+    // keyframes can be added in any order. adding one at the same time as an existing keyframe replaces it
+    pub fn key(mut self, time: f32, value: T) -> Self {
+        self.insert(time, value);
+        return self;
+    }
+
+    pub fn insert(&mut self, time: f32, value: T) {
+        let i = self.keyframes.partition_point(|k| k.time < time);
+        if i < self.keyframes.len() && self.keyframes[i].time == time {
+            self.keyframes[i].value = value;
+        }
+        else {
+            self.keyframes.insert(i, Keyframe { time, value });
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        return &self.keyframes;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.keyframes.is_empty();
+    }
+
+    // time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        return self.keyframes.last().map_or(0.0, |k| k.time);
+    }
+
+    // before the first keyframe it's the first keyframe's value, after the last it's the last's. None if there are no keyframes
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if time <= first.time {
+            return Some(first.value.clone());
+        }
+        if time >= last.time {
+            return Some(last.value.clone());
+        }
+
+        let i = keys.partition_point(|k| k.time <= time) - 1; // keys[i] is the one before time, keys[i + 1] the one after
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let t = (time - a.time) / (b.time - a.time);
+        return Some(match self.interpolation {
+            Interpolation::Step => a.value.clone(),
+            Interpolation::Linear => T::lerp(&a.value, &b.value, t),
+            Interpolation::Cubic => {
+                // at the ends there's no keyframe beyond, so repeat the end one
+                let before = &keys[i.saturating_sub(1)];
+                let after = &keys[(i + 2).min(keys.len() - 1)];
+                T::catmull_rom([&before.value, &a.value, &b.value, &after.value], [before.time, a.time, b.time, after.time], t)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling() {
+        // added out of order, and the second key at 1.0 replaces the first
+        let track = Track::new(Interpolation::Linear).key(3.0, 30.0f32).key(0.0, 0.0).key(1.0, 99.0).key(1.0, 10.0);
+        assert_eq!(track.keyframes().iter().map(|k| k.time).collect::<Vec<_>>(), [0.0, 1.0, 3.0]);
+        assert_eq!(track.duration(), 3.0);
+        assert_eq!(track.sample(-1.0), Some(0.0)); // holds the ends
+        assert_eq!(track.sample(5.0), Some(30.0));
+        assert_eq!(track.sample(0.5), Some(5.0));
+        assert_eq!(track.sample(2.0), Some(20.0));
+
+        let mut step = track.clone();
+        step.interpolation = Interpolation::Step;
+        assert_eq!(step.sample(0.99), Some(0.0));
+        assert_eq!(step.sample(1.0), Some(10.0));
+
+        // catmull-rom goes through every key, and with the tangents scaled by key spacing a straight line stays straight even though the keys are uneven
+        let mut cubic = track.clone();
+        cubic.interpolation = Interpolation::Cubic;
+        for (time, expected) in [(0.0, 0.0), (1.0, 10.0), (3.0, 30.0), (2.0, 20.0), (0.5, 5.0)] {
+            let value = cubic.sample(time).unwrap();
+            assert!((value - expected).abs() < 1e-4, "cubic sample at {} was {}, expected {}", time, value, expected);
+        }
+
+        assert!(Track::<f32>::new(Interpolation::Linear).sample(0.0).is_none());
+    }
+
+    #[test]
+    fn positions_far_from_the_origin_interpolate_to_the_micrometer() {
+        let far = i64vec3(1_000_000_000_000_000i64, -3, 0);
+        let track = Track::new(Interpolation::Linear).key(0.0, far).key(1.0, far + i64vec3(1_000_000i64, 0, 2));
+        assert_eq!(track.sample(0.5), Some(far + i64vec3(500_000i64, 0, 1)));
+
+        let mut cubic = track.clone();
+        cubic.interpolation = Interpolation::Cubic;
+        assert_eq!(cubic.sample(0.5), Some(far + i64vec3(500_000i64, 0, 1)));
+    }
+
+    #[test]
+    fn rotations_slerp_the_short_way() {
+        let a = glm::quat_identity();
+        let b = glm::quat_angle_axis(std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 1.0, 0.0));
+        let halfway = glm::quat_angle_axis(std::f32::consts::FRAC_PI_4, &glm::vec3(0.0, 1.0, 0.0));
+        for track in [Track::new(Interpolation::Linear).key(0.0, a).key(1.0, b), Track::new(Interpolation::Linear).key(0.0, a).key(1.0, -b)] {
+            let q = track.sample(0.5).unwrap();
+            assert!(q.dot(&halfway).abs() > 0.99999, "halfway between 0 and 90 degrees was {:?}", q);
+        }
+    }
+}
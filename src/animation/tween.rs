@@ -0,0 +1,129 @@
+// Tweens: move one property from wherever it is now to a target over some time, like tweening a door open or a button's colour.
+// Where it starts from is read from the object the first time the tween is updated, so tweens can be queued up without knowing the current value.
+
+use glm::{Vec3, Vec4, Quat, I64Vec3};
+
+use super::*;
+
+#[derive(Clone)]
+pub enum TweenTarget {
+    Position(I64Vec3), // micrometers
+    Rotation(Quat),
+    Scale(Vec3),
+    Color(Vec4),
+    TextureZ(f32),
+}
+
+#[derive(Clone)]
+pub struct Tween {
+    pub target: TweenTarget,
+    pub duration: f32, // seconds
+    pub delay: f32, // seconds to wait before starting
+    pub easing: Easing,
+    elapsed: f32,
+    from: Option<AnimatedValues>, // None until it starts
+}
+
+impl Tween {
+    pub fn new(target: TweenTarget, duration: f32, easing: Easing) -> Self {
+        return Self { target, duration, delay: 0.0, easing, elapsed: 0.0, from: None };
+    }
+
+    pub fn position(to: I64Vec3, duration: f32, easing: Easing) -> Self {
+        return Self::new(TweenTarget::Position(to), duration, easing);
+    }
+
+    pub fn rotation(to: Quat, duration: f32, easing: Easing) -> Self {
+        return Self::new(TweenTarget::Rotation(to), duration, easing);
+    }
+
+    pub fn scale(to: Vec3, duration: f32, easing: Easing) -> Self {
+        return Self::new(TweenTarget::Scale(to), duration, easing);
+    }
+
+    pub fn color(to: Vec4, duration: f32, easing: Easing) -> Self {
+        return Self::new(TweenTarget::Color(to), duration, easing);
+    }
+
+    pub fn texture_z(to: f32, duration: f32, easing: Easing) -> Self {
+        return Self::new(TweenTarget::TextureZ(to), duration, easing);
+    }
+
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        return self;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.elapsed >= self.delay + self.duration;
+    }
+
+    // advances by dt seconds and returns the values to apply (only the property this tween targets is set).
+    // current is what the object looks like now, only read on the tween's first frame
+    pub fn update(&mut self, dt: f32, current: impl FnOnce() -> AnimatedValues) -> AnimatedValues {
+        self.elapsed += dt;
+        if self.elapsed < self.delay {
+            return AnimatedValues::none();
+        }
+        let from = self.from.get_or_insert_with(current);
+        let t = if self.duration > 0.0 {self.easing.apply((self.elapsed - self.delay) / self.duration)} else {1.0};
+
+        let mut values = AnimatedValues::none();
+        // Back/Elastic easing goes past 1, which the lerps extrapolate to so it overshoots the target
+        match &self.target {
+            TweenTarget::Position(to) => values.position = Some(from.position.map_or(*to, |from| Animatable::lerp(&from, to, t))),
+            TweenTarget::Rotation(to) => values.rotation = Some(from.rotation.map_or(*to, |from| Animatable::lerp(&from, to, t))),
+            TweenTarget::Scale(to) => values.scale = Some(from.scale.map_or(*to, |from| Animatable::lerp(&from, to, t))),
+            TweenTarget::Color(to) => values.color = Some(from.color.map_or(*to, |from| Animatable::lerp(&from, to, t))),
+            TweenTarget::TextureZ(to) => values.texture_z = Some(from.texture_z.map_or(*to, |from| Animatable::lerp(&from, to, t))),
+        }
+        return values;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::{vec3, vec4};
+
+    use crate::transform::*;
+    use crate::ecs::*;
+
+    use super::*;
+
+    #[test]
+    fn tweens_land_on_their_targets_and_finish() {
+        let mut world = World::new();
+        let target_rot = glm::quat_angle_axis(1.0, &vec3(0.0, 1.0, 0.0));
+        let mut animator = Animator::new();
+        animator.add_tween(Tween::position(i64vec3(1_000_000i64, 2_000_000, -3), 1.0, Easing::QuadInOut));
+        animator.add_tween(Tween::rotation(target_rot, 0.5, Easing::SineOut));
+        animator.add_tween(Tween::scale(vec3(2.0, 3.0, 4.0), 0.5, Easing::BackOut).with_delay(0.5));
+        animator.add_tween(Tween::color(vec4(1.0, 0.0, 0.0, 1.0), 0.25, Easing::Linear));
+        animator.add_tween(Tween::texture_z(3.0, 0.0, Easing::BounceOut)); // zero length, so it just jumps there
+        let entity = world.build_entity().with(Transform::new(i64vec3(0i64, 0, 0))).with(RenderComponent::new(0)).with(animator).build();
+
+        for frame in 1..=90 {
+            animation_system(&mut world, 1.0 / 60.0);
+            let transform = world.get::<Transform>(entity).unwrap();
+            match frame {
+                1 => assert_eq!(world.get::<RenderComponent>(entity).unwrap().texture_z(), 3.0),
+                25 => assert_eq!(transform.scl(), vec3(1.0, 1.0, 1.0), "the scale tween shouldn't have started yet"),
+                30 => {
+                    let pos = transform.pos();
+                    assert!((pos.x - 500_000).abs() < 1000, "QuadInOut should be halfway at half time, but x is {}", pos.x);
+                }
+                _ => {}
+            }
+        }
+
+        let animator = world.get::<Animator>(entity).unwrap();
+        assert!(animator.is_finished() && animator.n_tweens() == 0);
+        let transform = world.get::<Transform>(entity).unwrap();
+        assert_eq!(transform.pos(), i64vec3(1_000_000i64, 2_000_000, -3));
+        assert!(transform.rot_quat().dot(&target_rot).abs() > 0.99999);
+        assert!(glm::distance(&transform.scl(), &vec3(2.0, 3.0, 4.0)) < 1e-5);
+        let render = world.get::<RenderComponent>(entity).unwrap();
+        assert!(glm::distance(&render.rgba(), &vec4(1.0, 0.0, 0.0, 1.0)) < 1e-6);
+        assert_eq!(render.texture_z(), 3.0);
+    }
+}
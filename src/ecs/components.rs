@@ -0,0 +1,107 @@
+// The engine's built in components. Transform (from transform.rs) is a component too.
+// These hold the same data the old gameobject structs did, just split up so an entity only pays for what it uses.
+
+use glm::{Vec3, Vec4, vec3, vec4, I64Vec3};
+
+use crate::transform::*;
+use crate::gameobjects::ColliderType;
+
+// for finding entities again and for showing in scene files/editors, doesn't have to be unique
+pub struct Name(pub String);
+
+// draws a mesh at the entity's Transform. GraphicsEngine::update_entities() picks these up automatically
+pub struct RenderComponent {
+    mesh_id: usize, // uuid of Mesh, so we know when two cube meshes/etc. are the same and can be instanced
+    color: Vec4,
+    texture_z: f32,
+    color_changed: bool,
+    texture_z_changed: bool,
+    pub(crate) draw_id: Option<usize>, // None until the GraphicsEngine gives it somewhere to be drawn
+    pub(crate) lod: usize, // which of the mesh's levels of detail it's drawn with, 0 is the full mesh
+}
+
+impl RenderComponent {
+    pub fn new(mesh_id: usize) -> Self {
+        return Self {
+            mesh_id,
+            color: vec4(0.6, 0.6, 0.6, 0.5),
+            texture_z: -1.0,
+            color_changed: true,
+            texture_z_changed: true,
+            draw_id: None,
+            lod: 0,
+        };
+    }
+
+    pub fn mesh_id(&self) -> usize {
+        return self.mesh_id;
+    }
+
+    pub fn draw_id(&self) -> Option<usize> {
+        return self.draw_id;
+    }
+
+    // 0 for the full mesh, i for mesh.lods[i - 1]. GraphicsEngine::update_entities() picks it by how big the entity is on screen
+    pub fn lod(&self) -> usize {
+        return self.lod;
+    }
+
+    pub fn rgba(&self) -> Vec4 {
+        return self.color;
+    }
+
+    pub fn set_rgba(&mut self, color: Vec4) {
+        self.color = color;
+        self.color_changed = true;
+    }
+
+    pub fn texture_z(&self) -> f32 {
+        return self.texture_z;
+    }
+
+    pub fn set_texture_z(&mut self, texture_z: f32) {
+        self.texture_z = texture_z;
+        self.texture_z_changed = true;
+    }
+
+    // makes the next take_changes() return everything, for when it gets a new instance that doesn't have its color/texture z yet
+    pub(crate) fn mark_changed(&mut self) {
+        self.color_changed = true;
+        self.texture_z_changed = true;
+    }
+
+    // returns what changed since the last call (color, texture z) and clears it
+    pub(crate) fn take_changes(&mut self) -> (Option<Vec4>, Option<f32>) {
+        let color = if self.color_changed {Some(self.color)} else {None};
+        let texture_z = if self.texture_z_changed {Some(self.texture_z)} else {None};
+        self.color_changed = false;
+        self.texture_z_changed = false;
+        return (color, texture_z);
+    }
+}
+
+// makes the entity solid. Without a RigidBodyComponent it's anchored, like the floor
+pub struct ColliderComponent {
+    pub collider_type: ColliderType,
+    pub friction: f32,
+    pub elasticity: f32,
+}
+
+impl ColliderComponent {
+    pub fn new(collider_type: ColliderType) -> Self {
+        return Self { collider_type, friction: 0.4, elasticity: 0.3 };
+    }
+}
+
+// lets physics move the entity. Needs a ColliderComponent too, since mass and inertia come from the collider's shape
+pub struct RigidBodyComponent {
+    pub density: f32,
+    pub velocity: I64Vec3, // micrometers/sec
+    pub angular_velocity: Vec3,
+}
+
+impl RigidBodyComponent {
+    pub fn new() -> Self {
+        return Self { density: 1.0, velocity: i64vec3(0, 0, 0), angular_velocity: vec3(0.0, 0.0, 0.0) };
+    }
+}
@@ -0,0 +1,58 @@
+// An entity is just an id. The generation gets bumped every time an index is reused, so an old Entity for something that was despawned
+// won't accidentally find whatever got spawned into its slot afterwards.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Entity {
+    pub(super) index: u32,
+    pub(super) generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> usize {
+        return self.index as usize;
+    }
+
+    pub fn generation(&self) -> u32 {
+        return self.generation;
+    }
+}
+
+pub struct EntityAllocator {
+    generations: Vec<u32>, // current generation of each index
+    alive: Vec<bool>,
+    free_indices: Vec<u32>, // indices of despawned entities, reused before making new ones
+}
+
+impl EntityAllocator {
+    pub fn new() -> Self {
+        return Self { generations: Vec::new(), alive: Vec::new(), free_indices: Vec::new() };
+    }
+
+    pub fn allocate(&mut self) -> Entity {
+        if let Some(index) = self.free_indices.pop() {
+            self.alive[index as usize] = true;
+            return Entity { index, generation: self.generations[index as usize] };
+        }
+        self.generations.push(0);
+        self.alive.push(true);
+        return Entity { index: (self.generations.len() - 1) as u32, generation: 0 };
+    }
+
+    // returns false if the entity was already dead
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.alive[entity.index()] = false;
+        self.generations[entity.index()] += 1;
+        self.free_indices.push(entity.index);
+        return true;
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        return entity.index() < self.generations.len() && self.alive[entity.index()] && self.generations[entity.index()] == entity.generation;
+    }
+
+    pub fn n_alive(&self) -> usize {
+        return self.generations.len() - self.free_indices.len();
+    }
+}
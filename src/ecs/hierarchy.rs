@@ -0,0 +1,270 @@
+// Parent/child transform hierarchy, for attaching a gun to a hand, a wheel to a car, a camera to a player, etc.
+// A child's LocalTransform is relative to its parent. Its Transform component stays world space (that's what physics and rendering read),
+// and gets recalculated by propagate_transforms() only when the local transform was changed or the parent's world transform moved.
+// Whole trees are skipped when their root didn't move and no LocalTransform anywhere was touched, so move children through their LocalTransform, not their Transform.
+// Positions are composed as parent pos (i64) + rotated/scaled offset, so a child far from the origin keeps the same micrometer precision as its parent.
+// Scale composes per axis, so a non-uniformly scaled parent with a rotated child won't shear it like a full matrix would.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use glm::{DVec3, Quat, Vec3, I64Vec3};
+
+use crate::transform::*;
+
+use super::*;
+
+pub struct Parent(pub Entity);
+
+pub struct Children(pub Vec<Entity>);
+
+// bumped every time any LocalTransform is created or changed, so propagate_transforms() can tell when nothing below the roots can have moved.
+// global instead of per world because LocalTransform doesn't know which world it's in; other worlds' changes just cost an extra walk
+static LOCAL_TRANSFORM_CHANGES: AtomicU64 = AtomicU64::new(0);
+
+type TransformKey = (I64Vec3, Quat, Vec3);
+
+fn transform_key(transform: &Transform) -> TransformKey {
+    return (transform.pos(), transform.rot_quat(), transform.scl());
+}
+
+// what propagate_transforms() saw last time it ran on a world, kept as a resource on that world
+struct PropagatedHierarchy {
+    local_changes: u64,
+    roots: BTreeMap<Entity, TransformKey>,
+}
+
+pub struct LocalTransform {
+    transform: Transform,
+    dirty: bool,
+    parent_world: Option<TransformKey>, // the parent's world pos/rot/scl last time we were resolved, so we notice it moving
+}
+
+impl LocalTransform {
+    pub fn new(transform: Transform) -> Self {
+        LOCAL_TRANSFORM_CHANGES.fetch_add(1, Ordering::Relaxed);
+        return Self { transform, dirty: true, parent_world: None };
+    }
+
+    pub fn get(&self) -> &Transform {
+        return &self.transform;
+    }
+
+    // marks the world transform as needing recalculation
+    pub fn get_mut(&mut self) -> &mut Transform {
+        self.mark_dirty();
+        return &mut self.transform;
+    }
+
+    pub fn set(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.mark_dirty();
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        LOCAL_TRANSFORM_CHANGES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// the world transform of something at local relative to parent
+pub fn compose_transforms(parent: &Transform, local: &Transform) -> Transform {
+    let offset = dvec3_from_i64vec3(&local.pos()).component_mul(&DVec3::new(parent.scl().x as f64, parent.scl().y as f64, parent.scl().z as f64));
+    let rotated = glm::quat_rotate_vec3(&dquat(&parent.rot_quat()), &offset);
+    let mut world = Transform::new(parent.pos() + i64vec3_from_dvec3(&rotated));
+    world.set_rot_quat(parent.rot_quat() * local.rot_quat());
+    world.setscl(parent.scl().component_mul(&local.scl()));
+    return world;
+}
+
+// inverse of compose_transforms(), what local transform puts something at world when attached to parent
+pub fn relative_transform(parent: &Transform, world: &Transform) -> Transform {
+    let inverse_rot = glm::quat_inverse(&parent.rot_quat());
+    let offset = glm::quat_rotate_vec3(&dquat(&inverse_rot), &dvec3_from_i64vec3(&(world.pos() - parent.pos())));
+    let offset = offset.component_div(&DVec3::new(parent.scl().x as f64, parent.scl().y as f64, parent.scl().z as f64));
+    let mut local = Transform::new(i64vec3_from_dvec3(&offset));
+    local.set_rot_quat(inverse_rot * world.rot_quat());
+    local.setscl(world.scl().component_div(&parent.scl()));
+    return local;
+}
+
+impl World {
+    // attaches child to parent without moving it in the world. Both need a Transform.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        let mut ancestor = Some(parent);
+        while ancestor.is_some() {
+            assert!(ancestor.unwrap() != child, "Tried to make {:?} a child of its own descendant {:?}", child, parent);
+            ancestor = self.parent(ancestor.unwrap());
+        }
+        self.remove_parent(child);
+
+        let local = relative_transform(self.get::<Transform>(parent).unwrap(), self.get::<Transform>(child).unwrap());
+        self.insert(child, LocalTransform::new(local));
+        self.insert(child, Parent(parent));
+        match self.get_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => {self.insert(parent, Children(vec![child]));}
+        }
+    }
+
+    // attaches child to parent at local (relative to the parent), moving it in the world to match.
+    // for building things out of parts, where the offsets are known but the world transforms aren't. child doesn't need a Transform yet
+    pub fn set_parent_local(&mut self, child: Entity, parent: Entity, local: Transform) {
+        let world_transform = compose_transforms(self.get::<Transform>(parent).unwrap(), &local);
+        self.insert(child, world_transform);
+        self.set_parent(child, parent);
+        self.get_mut::<LocalTransform>(child).unwrap().set(local);
+    }
+
+    // detaches child from its parent, leaving it where it is in the world
+    pub fn remove_parent(&mut self, child: Entity) {
+        let parent = match self.remove::<Parent>(child) {
+            Some(parent) => parent.0,
+            None => return
+        };
+        self.remove::<LocalTransform>(child);
+        if let Some(children) = self.get_mut::<Children>(parent) {
+            children.0.retain(|c| *c != child);
+        }
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        return self.get::<Parent>(entity).map(|p| p.0);
+    }
+
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        return self.get::<Children>(entity).map(|c| c.0.as_slice()).unwrap_or(&[]);
+    }
+
+    // despawns the entity and everything attached to it
+    pub fn despawn_recursive(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+        let children = self.children(entity).to_vec();
+        for child in children {
+            self.despawn_recursive(child);
+        }
+        self.despawn(entity);
+    }
+}
+
+// brings the world space Transform of every child up to date. Roots (entities with Children but no Parent) are left alone.
+// If no LocalTransform changed since the last call, only the trees whose root moved get walked.
+// GraphicsEngine::update_entities() and physics_system() call this themselves, call it manually if you need world transforms somewhere else.
+pub fn propagate_transforms(world: &mut World) {
+    let local_changes = LOCAL_TRANSFORM_CHANGES.load(Ordering::Relaxed);
+    let last = world.remove_resource::<PropagatedHierarchy>();
+    let any_local_changes = last.as_ref().map_or(true, |last| last.local_changes != local_changes);
+
+    let roots: Vec<Entity> = world.query::<Children>().map(|(e, _)| e).filter(|e| !world.has::<Parent>(*e)).collect();
+    let mut root_keys = BTreeMap::new();
+    for root in roots {
+        let key = match world.get::<Transform>(root) {
+            Some(t) => transform_key(t),
+            None => continue
+        };
+        let moved = last.as_ref().map_or(true, |last| last.roots.get(&root) != Some(&key));
+        if any_local_changes || moved {
+            propagate_children(world, root);
+        }
+        root_keys.insert(root, key);
+    }
+
+    world.insert_resource(PropagatedHierarchy { local_changes, roots: root_keys });
+}
+
+fn propagate_children(world: &mut World, parent: Entity) {
+    let parent_transform = match world.get::<Transform>(parent) {
+        Some(t) => t.clone(),
+        None => return
+    };
+    let parent_key = transform_key(&parent_transform);
+
+    for child in world.children(parent).to_vec() {
+        if let Some(local) = world.get_mut::<LocalTransform>(child) {
+            if local.dirty || local.parent_world != Some(parent_key) {
+                local.dirty = false;
+                local.parent_world = Some(parent_key);
+                let resolved = compose_transforms(&parent_transform, &local.transform);
+                world.insert(child, resolved);
+            }
+        }
+        propagate_children(world, child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent_transform() -> Transform {
+        let mut parent = Transform::new(I64Vec3::new(1_000_000_000_000, -20_000_000, 3_500_000));
+        parent.set_rot_quat(glm::quat_angle_axis(1.1, &glm::normalize(&glm::vec3(1.0, 2.0, -0.5))));
+        parent.setscl(glm::vec3(2.0, 0.5, 3.0));
+        return parent;
+    }
+
+    fn child_transform() -> Transform {
+        let mut child = Transform::new(I64Vec3::new(1_000_004_000_000, -17_000_000, 1_250_000));
+        child.set_rot_quat(glm::quat_angle_axis(-0.4, &glm::vec3(0.0, 1.0, 0.0)));
+        child.setscl(glm::vec3(1.5, 1.5, 1.5));
+        return child;
+    }
+
+    fn assert_transforms_near(actual: &Transform, expected: &Transform) {
+        let offset = actual.pos() - expected.pos();
+        assert!(offset.iter().all(|d| d.abs() <= 2), "Position is off by {:?}um", offset);
+        assert!(glm::quat_dot(&actual.rot_quat(), &expected.rot_quat()).abs() > 0.99999, "Rotation {:?} should be {:?}", actual.rot_quat(), expected.rot_quat());
+        assert!(glm::distance(&actual.scl(), &expected.scl()) < 1e-5, "Scale {:?} should be {:?}", actual.scl(), expected.scl());
+    }
+
+    #[test]
+    fn relative_transform_inverts_compose_transforms() {
+        let parent = parent_transform();
+        let child = child_transform();
+        assert_transforms_near(&compose_transforms(&parent, &relative_transform(&parent, &child)), &child);
+
+        let mut local = Transform::new(I64Vec3::new(250_000, 0, -1_000_000));
+        local.set_rot_quat(glm::quat_angle_axis(0.3, &glm::vec3(0.0, 0.0, 1.0)));
+        assert_transforms_near(&relative_transform(&parent, &compose_transforms(&parent, &local)), &local);
+    }
+
+    #[test]
+    fn set_parent_keeps_world_transform() {
+        let mut world = World::new();
+        let parent = world.build_entity().with(parent_transform()).build();
+        let child = world.build_entity().with(child_transform()).build();
+
+        world.set_parent(child, parent);
+        propagate_transforms(&mut world);
+        assert_transforms_near(world.get::<Transform>(child).unwrap(), &child_transform());
+
+        // moving the parent carries the child along
+        world.get_mut::<Transform>(parent).unwrap().setpos_meters(dvec3(5.0, 0.0, 0.0));
+        propagate_transforms(&mut world);
+        let local = world.get::<LocalTransform>(child).unwrap().get().clone();
+        let expected = compose_transforms(world.get::<Transform>(parent).unwrap(), &local);
+        assert_transforms_near(world.get::<Transform>(child).unwrap(), &expected);
+
+        world.remove_parent(child);
+        assert_transforms_near(world.get::<Transform>(child).unwrap(), &expected);
+    }
+
+    #[test]
+    fn unchanged_trees_are_skipped() {
+        let mut world = World::new();
+        let parent = world.build_entity().with(parent_transform()).build();
+        let child = world.build_entity().with(child_transform()).build();
+        world.set_parent(child, parent);
+        propagate_transforms(&mut world);
+
+        // nothing was changed, so a bogus world transform on the child should survive
+        let bogus = Transform::new(I64Vec3::new(7, 7, 7));
+        world.insert(child, bogus.clone());
+        propagate_transforms(&mut world);
+        assert_transforms_near(world.get::<Transform>(child).unwrap(), &bogus);
+
+        world.get_mut::<LocalTransform>(child).unwrap().get_mut();
+        propagate_transforms(&mut world);
+        assert_transforms_near(world.get::<Transform>(child).unwrap(), &child_transform());
+    }
+}
@@ -0,0 +1,14 @@
+mod entity;
+pub use entity::*;
+mod storage;
+pub use storage::*;
+mod world;
+pub use world::*;
+mod components;
+pub use components::*;
+mod systems;
+pub use systems::*;
+mod hierarchy;
+pub use hierarchy::*;
+mod snapshot;
+pub use snapshot::*;
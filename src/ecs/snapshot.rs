@@ -0,0 +1,360 @@
+// Compact binary copy of a World's dynamic state, for save games, instant replay, rewinding and comparing two deterministic runs.
+// Unlike scene files this only holds what physics changes every frame (transforms and rigidbody velocities), keyed by entity id,
+// so restoring one puts the existing entities back how they were rather than spawning anything.
+// The physics has no sleeping and rebuilds its contacts from scratch every step, so there's no sleep state or contact cache to save;
+// transforms + velocities are the whole state and restoring a snapshot then stepping gives exactly the same result as the original run
+// (in the same world. The SAS isn't saved, and it still has bodies where they were inserted, so a freshly spawned world can play out differently).
+//
+// Layout, all little endian:
+//   "IG2S" magic, u32 version, u64 frame, u32 number of records, then per record:
+//   u32 entity index, u32 entity generation, u8 flags,
+//   i64 x3 pos (micrometers), f32 x4 rot (w, x, y, z), f32 x3 scl,
+//   if FLAG_RIGIDBODY: i64 x3 velocity (micrometers/sec), f32 x3 angular velocity
+// For entities with a Parent (FLAG_LOCAL) the transform is the LocalTransform, since the world one gets recalculated from it.
+
+use std::collections::VecDeque;
+
+use glm::{vec3, Quat};
+
+use crate::transform::*;
+
+use super::*;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"IG2S";
+const SNAPSHOT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 4 + 4 + 8 + 4;
+const FLAG_RIGIDBODY: u8 = 1;
+const FLAG_LOCAL: u8 = 2;
+
+pub const SNAPSHOTS_PER_SECOND: usize = 60; // physics_system() runs once per frame at 60fps
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct WorldSnapshot {
+    bytes: Vec<u8>,
+}
+
+impl WorldSnapshot {
+    pub fn new() -> Self {
+        return Self { bytes: Vec::new() };
+    }
+
+    // only entities with a RigidBodyComponent or a Parent, which is everything physics/the hierarchy moves.
+    // static scenery can be tens of thousands of transforms that never change, so per-frame captures leave it out
+    pub fn capture(world: &World, frame: u64) -> Self {
+        let mut snapshot = Self::new();
+        snapshot.recapture(world, frame, false);
+        return snapshot;
+    }
+
+    // every entity with a Transform, for save games where gameplay code might have moved anything
+    pub fn capture_all(world: &World, frame: u64) -> Self {
+        let mut snapshot = Self::new();
+        snapshot.recapture(world, frame, true);
+        return snapshot;
+    }
+
+    // overwrites this snapshot with the world's current state, reusing its buffer so capturing every frame doesn't allocate
+    pub fn recapture(&mut self, world: &World, frame: u64, include_static: bool) {
+        let bytes = &mut self.bytes;
+        bytes.clear();
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&frame.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // record count, filled in at the end
+
+        let mut n_records: u32 = 0;
+        for (entity, world_transform) in world.query::<Transform>() {
+            let local = world.get::<LocalTransform>(entity).filter(|_| world.has::<Parent>(entity));
+            let rigidbody = world.get::<RigidBodyComponent>(entity);
+            if !include_static && rigidbody.is_none() && local.is_none() {
+                continue;
+            }
+            let flags = (if rigidbody.is_some() {FLAG_RIGIDBODY} else {0}) | (if local.is_some() {FLAG_LOCAL} else {0});
+            let transform = match local {
+                Some(local) => local.get(),
+                None => world_transform
+            };
+
+            bytes.extend_from_slice(&entity.index.to_le_bytes());
+            bytes.extend_from_slice(&entity.generation.to_le_bytes());
+            bytes.push(flags);
+            let pos = transform.pos();
+            let rot = transform.rot_quat();
+            let scl = transform.scl();
+            for v in [pos.x, pos.y, pos.z] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            for v in [rot.w, rot.i, rot.j, rot.k, scl.x, scl.y, scl.z] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            if let Some(rigidbody) = rigidbody {
+                for v in [rigidbody.velocity.x, rigidbody.velocity.y, rigidbody.velocity.z] {
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                for v in [rigidbody.angular_velocity.x, rigidbody.angular_velocity.y, rigidbody.angular_velocity.z] {
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            n_records += 1;
+        }
+        bytes[HEADER_SIZE - 4..HEADER_SIZE].copy_from_slice(&n_records.to_le_bytes());
+    }
+
+    pub fn frame(&self) -> u64 {
+        return u64::from_le_bytes(self.bytes[8..16].try_into().unwrap());
+    }
+
+    pub fn n_records(&self) -> usize {
+        return u32::from_le_bytes(self.bytes[16..20].try_into().unwrap()) as usize;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        return &self.bytes;
+    }
+
+    // checks the whole thing so restore() can't run off the end of a truncated/corrupted save
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != SNAPSHOT_MAGIC {
+            return Err(String::from("not a world snapshot"));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("snapshot is version {}, expected {}", version, SNAPSHOT_VERSION));
+        }
+        let snapshot = Self { bytes };
+        let mut reader = SnapshotReader { bytes: &snapshot.bytes, pos: HEADER_SIZE };
+        for i in 0..snapshot.n_records() {
+            if reader.record().is_none() {
+                return Err(format!("snapshot is truncated at record {}", i));
+            }
+        }
+        if reader.pos != snapshot.bytes.len() {
+            return Err(String::from("snapshot has extra data at the end"));
+        }
+        return Ok(snapshot);
+    }
+
+    pub fn save_to_file(&self, path: &str) -> std::io::Result<()> {
+        return std::fs::write(path, &self.bytes);
+    }
+
+    pub fn load_from_file(path: &str) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        return Self::from_bytes(bytes).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err));
+    }
+
+    // puts every entity in the snapshot that's still alive back how it was, returns how many were restored.
+    // entities that were despawned since are skipped, and ones spawned since are left alone.
+    // rigidbody state is only restored onto entities that still have a RigidBodyComponent.
+    pub fn restore(&self, world: &mut World) -> usize {
+        let mut reader = SnapshotReader { bytes: &self.bytes, pos: HEADER_SIZE };
+        let mut n_restored = 0;
+        for _ in 0..self.n_records() {
+            let record = reader.record().unwrap();
+            if !world.is_alive(record.entity) {
+                continue;
+            }
+            if record.flags & FLAG_LOCAL != 0 {
+                if let Some(local) = world.get_mut::<LocalTransform>(record.entity) {
+                    local.set(record.transform);
+                }
+            }
+            else if let Some(transform) = world.get_mut::<Transform>(record.entity) {
+                *transform = record.transform;
+            }
+            if let (Some((velocity, angular_velocity)), Some(rigidbody)) = (record.rigidbody, world.get_mut::<RigidBodyComponent>(record.entity)) {
+                rigidbody.velocity = velocity;
+                rigidbody.angular_velocity = angular_velocity;
+            }
+            n_restored += 1;
+        }
+        propagate_transforms(world);
+        return n_restored;
+    }
+
+    // for comparing deterministic runs, returns the first entity whose state differs (or None if they match).
+    // entities only in one of the two snapshots count as differing.
+    pub fn first_difference(&self, other: &WorldSnapshot) -> Option<Entity> {
+        let mut a = SnapshotReader { bytes: &self.bytes, pos: HEADER_SIZE };
+        let mut b = SnapshotReader { bytes: &other.bytes, pos: HEADER_SIZE };
+        for _ in 0..self.n_records().max(other.n_records()) {
+            let a_start = a.pos;
+            let b_start = b.pos;
+            match (a.record(), b.record()) {
+                (Some(ra), Some(_)) => {
+                    if a.bytes[a_start..a.pos] != b.bytes[b_start..b.pos] {
+                        return Some(ra.entity);
+                    }
+                }
+                (Some(ra), None) => return Some(ra.entity),
+                (None, Some(rb)) => return Some(rb.entity),
+                (None, None) => break
+            }
+        }
+        return None;
+    }
+}
+
+struct SnapshotRecord {
+    entity: Entity,
+    flags: u8,
+    transform: Transform,
+    rigidbody: Option<(glm::I64Vec3, glm::Vec3)>,
+}
+
+struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let taken = self.bytes.get(self.pos..self.pos + N)?.try_into().ok()?;
+        self.pos += N;
+        return Some(taken);
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        return Some(u32::from_le_bytes(self.take()?));
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        return Some(i64::from_le_bytes(self.take()?));
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        return Some(f32::from_le_bytes(self.take()?));
+    }
+
+    fn record(&mut self) -> Option<SnapshotRecord> {
+        let entity = Entity { index: self.u32()?, generation: self.u32()? };
+        let flags = self.take::<1>()?[0];
+        let pos = i64vec3(self.i64()?, self.i64()?, self.i64()?);
+        let rot = Quat::new(self.f32()?, self.f32()?, self.f32()?, self.f32()?);
+        let scl = vec3(self.f32()?, self.f32()?, self.f32()?);
+        let rigidbody = if flags & FLAG_RIGIDBODY != 0 {
+            Some((i64vec3(self.i64()?, self.i64()?, self.i64()?), vec3(self.f32()?, self.f32()?, self.f32()?)))
+        } else {
+            None
+        };
+        return Some(SnapshotRecord { entity, flags, transform: Transform::from_parts(pos, rot, scl), rigidbody });
+    }
+}
+
+// ring buffer of the last few seconds of snapshots, for rewind/instant replay.
+// once full, capturing a new frame reuses the oldest snapshot's memory.
+pub struct SnapshotHistory {
+    snapshots: VecDeque<WorldSnapshot>, // oldest first
+    capacity: usize,
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "SnapshotHistory needs room for at least one snapshot");
+        return Self { snapshots: VecDeque::with_capacity(capacity), capacity };
+    }
+
+    pub fn with_seconds(seconds: f32) -> Self {
+        return Self::new(((seconds * SNAPSHOTS_PER_SECOND as f32).ceil() as usize).max(1));
+    }
+
+    pub fn capture(&mut self, world: &World, frame: u64) {
+        let mut snapshot = if self.snapshots.len() == self.capacity {self.snapshots.pop_front().unwrap()} else {WorldSnapshot::new()};
+        snapshot.recapture(world, frame, false);
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.snapshots.len();
+    }
+
+    pub fn latest(&self) -> Option<&WorldSnapshot> {
+        return self.snapshots.back();
+    }
+
+    // frames_ago = 0 is the latest snapshot
+    pub fn get(&self, frames_ago: usize) -> Option<&WorldSnapshot> {
+        if frames_ago >= self.snapshots.len() {
+            return None;
+        }
+        return self.snapshots.get(self.snapshots.len() - 1 - frames_ago);
+    }
+
+    // oldest to newest, restore() each one per frame for an instant replay
+    pub fn iter(&self) -> impl Iterator<Item = &WorldSnapshot> {
+        return self.snapshots.iter();
+    }
+
+    // restores the world to how it was seconds ago (or as far back as the history goes), and forgets everything after that.
+    // returns the frame rewound to, or None if there's no history yet
+    pub fn rewind(&mut self, world: &mut World, seconds: f32) -> Option<u64> {
+        let frames_ago = ((seconds * SNAPSHOTS_PER_SECOND as f32) as usize).min(self.snapshots.len().checked_sub(1)?);
+        for _ in 0..frames_ago {
+            self.snapshots.pop_back();
+        }
+        let snapshot = self.snapshots.back()?;
+        snapshot.restore(world);
+        return Some(snapshot.frame());
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameobjects::ColliderType;
+    use crate::phys::PhysicsMode;
+
+    use super::*;
+
+    // a floor, a tilted box falling onto it, a ball falling onto the box and a child riding on the box
+    fn tumbling_world(mode: PhysicsMode) -> World {
+        let mut world = World::new();
+        world.insert_resource(PhysicsState::new(mode));
+        let mut floor = Transform::meters(dvec3(0.0, -0.5, 0.0));
+        floor.setscl(vec3(20.0, 1.0, 20.0));
+        world.build_entity().with(floor).with(ColliderComponent::new(ColliderType::Box)).build();
+        let mut cube = Transform::meters(dvec3(0.0, 2.0, 0.0));
+        cube.set_rot_quat(glm::quat_angle_axis(0.4, &glm::normalize(&vec3(1.0, 0.0, 1.0))));
+        let cube = world.build_entity().with(cube).with(ColliderComponent::new(ColliderType::Box)).with(RigidBodyComponent::new()).build();
+        world.build_entity().with(Transform::meters(dvec3(0.2, 4.0, 0.1))).with(ColliderComponent::new(ColliderType::Sphere)).with(RigidBodyComponent::new()).build();
+        let child = world.spawn();
+        world.set_parent_local(child, cube, Transform::meters(dvec3(0.0, 1.0, 0.0)));
+        return world;
+    }
+
+    // rewinding, or quickloading into the world that saved it like main.rs does, and loading into a freshly spawned one
+    #[test]
+    fn restoring_then_stepping_matches_the_original_run() {
+        for mode in [PhysicsMode::Float, PhysicsMode::Deterministic] {
+            let mut world = tumbling_world(mode);
+            for _ in 0..90 {
+                physics_system(&mut world);
+            }
+            // through bytes, like a quicksave file
+            let saved = WorldSnapshot::from_bytes(WorldSnapshot::capture_all(&world, 90).as_bytes().to_vec()).unwrap();
+            for _ in 0..120 {
+                physics_system(&mut world);
+            }
+            let uninterrupted = WorldSnapshot::capture_all(&world, 210);
+
+            assert_eq!(saved.restore(&mut world), 4);
+            for _ in 0..120 {
+                physics_system(&mut world);
+            }
+            let restored = WorldSnapshot::capture_all(&world, 210);
+            assert!(restored == uninterrupted, "{:?} run after restoring differs from the uninterrupted one at {:?}", mode, restored.first_difference(&uninterrupted));
+            assert!(restored.first_difference(&saved).is_some(), "nothing moved after the snapshot, so this didn't test anything");
+
+            let mut fresh = tumbling_world(mode);
+            assert_eq!(saved.restore(&mut fresh), 4);
+            for _ in 0..120 {
+                physics_system(&mut fresh);
+            }
+            let fresh = WorldSnapshot::capture_all(&fresh, 210);
+            assert!(fresh == uninterrupted, "{:?} run after restoring into a new world differs from the uninterrupted one at {:?}", mode, fresh.first_difference(&uninterrupted));
+        }
+    }
+}
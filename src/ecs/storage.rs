@@ -0,0 +1,93 @@
+// Sparse set storage: components are packed tightly in a Vec so systems iterate over them without chasing pointers,
+// and a sparse array indexed by entity index says where in that Vec each entity's component lives.
+
+use std::any::Any;
+
+use super::Entity;
+
+const EMPTY: usize = usize::MAX;
+
+pub struct ComponentStorage<T> {
+    sparse: Vec<usize>, // entity index -> index into entities/components, or EMPTY
+    entities: Vec<Entity>,
+    components: Vec<T>,
+}
+
+impl<T> ComponentStorage<T> {
+    pub fn new() -> Self {
+        return Self { sparse: Vec::new(), entities: Vec::new(), components: Vec::new() };
+    }
+
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let i = *self.sparse.get(entity.index())?;
+        if i == EMPTY || self.entities[i] != entity { // entities[i] could be an older/newer generation of the same index
+            return None;
+        }
+        return Some(i);
+    }
+
+    // returns the component the entity already had, if any
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(i) = self.dense_index(entity) {
+            return Some(std::mem::replace(&mut self.components[i], component));
+        }
+        if self.sparse.len() <= entity.index() {
+            self.sparse.resize(entity.index() + 1, EMPTY);
+        }
+        self.sparse[entity.index()] = self.components.len();
+        self.entities.push(entity);
+        self.components.push(component);
+        return None;
+    }
+
+    // O(1), the last component gets moved into the removed one's place
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let i = self.dense_index(entity)?;
+        self.sparse[entity.index()] = EMPTY;
+        self.entities.swap_remove(i);
+        let component = self.components.swap_remove(i);
+        if i < self.entities.len() {
+            self.sparse[self.entities[i].index()] = i;
+        }
+        return Some(component);
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        return self.dense_index(entity).map(|i| &self.components[i]);
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        return self.dense_index(entity).map(|i| &mut self.components[i]);
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        return self.dense_index(entity).is_some();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.components.len();
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        return &self.entities;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        return self.entities.iter().copied().zip(self.components.iter());
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        return self.entities.iter().copied().zip(self.components.iter_mut());
+    }
+}
+
+// lets World keep storages of different component types in one map and still remove a despawned entity from all of them
+pub trait AnyStorage: Any {
+    fn remove_entity(&mut self, entity: Entity);
+}
+
+impl<T: 'static> AnyStorage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+}
@@ -0,0 +1,260 @@
+// Built in systems. Rendering lives in GraphicsEngine::update_entities() since it needs the gl context.
+
+use std::{rc::Rc, cell::RefCell, collections::BTreeMap};
+
+use crate::gameobjects::{Collides, RigidBody};
+use crate::transform::*;
+use crate::phys::{PhysBody, PhysicsMode, RigidBodyList, SpatialAccelerationStructure, do_physics, assert_deterministic_collider};
+use crate::graphics::GraphicsEngine;
+
+use super::*;
+
+// the physics side of every entity with a ColliderComponent, kept on the World as a resource so bodies live as long as their entities instead of being rebuilt every tick.
+// physics_system() adds one in PhysicsMode::Float if the world doesn't have one yet, insert your own before the first tick to pick the mode:
+// world.insert_resource(PhysicsState::new(PhysicsMode::Deterministic))
+pub struct PhysicsState {
+    pub mode: PhysicsMode,
+    sas: SpatialAccelerationStructure,
+    rigidbodies: RigidBodyList, // the bodies physics moves, colliders without a RigidBodyComponent are only in the SAS
+    bodies: BTreeMap<Entity, EntityBody>, // BTree so bodies get added/removed in the same order every run, since that's the order do_physics() steps them in
+}
+
+struct EntityBody {
+    body: Rc<RefCell<PhysBody>>,
+    moving: bool, // whether it's in rigidbodies
+}
+
+impl PhysicsState {
+    pub fn new(mode: PhysicsMode) -> Self {
+        return Self { mode, sas: SpatialAccelerationStructure::new(), rigidbodies: RigidBodyList::new(), bodies: BTreeMap::new() };
+    }
+
+    pub fn n_bodies(&self) -> usize {
+        return self.bodies.len();
+    }
+
+    pub fn n_moving_bodies(&self) -> usize {
+        return self.rigidbodies.len();
+    }
+
+    pub fn has_body(&self, entity: Entity) -> bool {
+        return self.bodies.contains_key(&entity);
+    }
+
+    fn add(&mut self, entity: Entity, body: PhysBody, moving: bool) {
+        if self.mode == PhysicsMode::Deterministic {
+            assert_deterministic_collider(body.get_collider_type());
+        }
+        let body = Rc::new(RefCell::new(body));
+        self.sas.insert(body.clone());
+        if moving {
+            self.rigidbodies.insert(body.clone());
+        }
+        self.bodies.insert(entity, EntityBody { body, moving });
+    }
+
+    // moves bodies to where they are now in the SAS, only the ones physics moves if moving_only
+    fn update_sas(&mut self, moving_only: bool) {
+        for entry in self.bodies.values() {
+            if entry.moving || !moving_only {
+                let collides: Rc<RefCell<dyn Collides>> = entry.body.clone();
+                self.sas.update(&collides);
+            }
+        }
+    }
+
+    // takes the entity's body out of the broadphase and the rigidbody list, returns false if it didn't have one
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let entry = match self.bodies.remove(&entity) {
+            Some(entry) => entry,
+            None => return false
+        };
+        let collides: Rc<RefCell<dyn Collides>> = entry.body.clone();
+        self.sas.remove(&collides);
+        if entry.moving {
+            let rigidbody: Rc<RefCell<dyn RigidBody>> = entry.body;
+            self.rigidbodies.remove(&rigidbody);
+        }
+        return true;
+    }
+}
+
+// one physics tick (1/60th of a second) for every entity with a Transform and ColliderComponent, through do_physics() in the PhysicsState's mode.
+// components are copied into the bodies before the step (so teleporting an entity or setting its velocity just works) and back out after it,
+// and the SAS is told where every body is both times, since it only looks at positions when asked to.
+// entities with a Parent get collided with but are moved by their parent, not by physics.
+pub fn physics_system(world: &mut World) {
+    propagate_transforms(world);
+    let mut physics = world.remove_resource::<PhysicsState>().unwrap_or_else(|| PhysicsState::new(PhysicsMode::Float));
+
+    // forget bodies whose entity was despawned or isn't solid anymore
+    let gone: Vec<Entity> = physics.bodies.keys().copied().filter(|entity| !world.has::<ColliderComponent>(*entity) || !world.has::<Transform>(*entity)).collect();
+    for entity in gone {
+        physics.remove(entity);
+    }
+
+    for (entity, collider, transform) in world.query2::<ColliderComponent, Transform>() {
+        let rigidbody = if world.has::<Parent>(entity) {None} else {world.get::<RigidBodyComponent>(entity)};
+        let moving = rigidbody.is_some();
+        let changed = physics.bodies.get(&entity).map(|entry| entry.moving != moving || entry.body.borrow().get_collider_type() != collider.collider_type);
+        if changed == Some(true) {
+            physics.remove(entity);
+        }
+        if changed != Some(false) {
+            let mut body = PhysBody::new(collider.collider_type);
+            body.transform = transform.clone(); // the SAS needs to know where it is when it's inserted
+            body.anchored = !moving;
+            physics.add(entity, body, moving);
+        }
+
+        let mut body = physics.bodies[&entity].body.borrow_mut();
+        body.friction = collider.friction;
+        body.elasticity = collider.elasticity;
+        body.transform = transform.clone();
+        if let Some(rigidbody) = rigidbody {
+            body.density = rigidbody.density;
+            body.velocity = rigidbody.velocity;
+            body.angular_velocity = rigidbody.angular_velocity;
+        }
+    }
+    physics.update_sas(false);
+
+    do_physics(&mut physics.sas, physics.rigidbodies.bodies(), physics.mode);
+    physics.update_sas(true);
+
+    for (entity, entry) in physics.bodies.iter() {
+        if !entry.moving {
+            continue;
+        }
+        let body = entry.body.borrow();
+        *world.get_mut::<Transform>(*entity).unwrap() = body.transform.clone();
+        let rigidbody = world.get_mut::<RigidBodyComponent>(*entity).unwrap();
+        rigidbody.velocity = body.velocity;
+        rigidbody.angular_velocity = body.angular_velocity;
+    }
+    world.insert_resource(physics);
+}
+
+// despawns the entity and everything attached to it, stops drawing them and takes their bodies out of physics (the SAS and the rigidbody list).
+// Use this instead of World::despawn() for anything with a RenderComponent or ColliderComponent.
+pub fn despawn(world: &mut World, graphics: &mut GraphicsEngine, entity: Entity) {
+    for child in world.children(entity).to_vec() {
+        despawn(world, graphics, child);
+    }
+    if let Some(draw_id) = world.get::<RenderComponent>(entity).and_then(|render| render.draw_id()) {
+        graphics.remove_renderable(draw_id);
+    }
+    if let Some(physics) = world.resource_mut::<PhysicsState>() {
+        physics.remove(entity);
+    }
+    world.despawn_recursive(entity);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameobjects::ColliderType;
+    use crate::graphics::{Mesh, RecordingDevice};
+
+    use super::*;
+
+    // a floor, a box dropped onto it and a sphere dropped onto the box
+    fn stacking_world(mode: PhysicsMode) -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.insert_resource(PhysicsState::new(mode));
+        let mut floor = Transform::meters(dvec3(0.0, -0.5, 0.0));
+        floor.setscl(glm::vec3(20.0, 1.0, 20.0));
+        world.build_entity().with(floor).with(ColliderComponent::new(ColliderType::Box)).build();
+        let cube = world.build_entity().with(Transform::meters(dvec3(0.0, 2.0, 0.0))).with(ColliderComponent::new(ColliderType::Box)).with(RigidBodyComponent::new()).build();
+        let ball = world.build_entity().with(Transform::meters(dvec3(0.1, 4.0, 0.0))).with(ColliderComponent::new(ColliderType::Sphere)).with(RigidBodyComponent::new()).build();
+        return (world, cube, ball);
+    }
+
+    #[test]
+    fn deterministic_mode_runs_from_the_ecs() {
+        let (mut a, cube, ball) = stacking_world(PhysicsMode::Deterministic);
+        let (mut b, _, _) = stacking_world(PhysicsMode::Deterministic);
+        for _ in 0..600 {
+            physics_system(&mut a);
+            physics_system(&mut b);
+        }
+        for entity in [cube, ball] {
+            let (ta, tb) = (a.get::<Transform>(entity).unwrap(), b.get::<Transform>(entity).unwrap());
+            assert_eq!(ta.pos(), tb.pos());
+            assert_eq!(ta.rot_quat().coords.map(|x| x.to_bits()), tb.rot_quat().coords.map(|x| x.to_bits()));
+            assert_eq!(a.get::<RigidBodyComponent>(entity).unwrap().velocity, b.get::<RigidBodyComponent>(entity).unwrap().velocity);
+        }
+
+        let height = a.get::<Transform>(cube).unwrap().pos().y;
+        assert!((height - 500000).abs() < 20000, "Box should be resting on the floor, but it's {}um up.", height);
+    }
+
+    // two balls rolling at each other have to bounce off each other, which needs the SAS to know they moved away from where they spawned
+    #[test]
+    fn moving_bodies_collide() {
+        for mode in [PhysicsMode::Float, PhysicsMode::Deterministic] {
+            let mut world = World::new();
+            world.insert_resource(PhysicsState::new(mode));
+            let mut floor = Transform::meters(dvec3(0.0, -0.5, 0.0));
+            floor.setscl(glm::vec3(40.0, 1.0, 40.0));
+            world.build_entity().with(floor).with(ColliderComponent::new(ColliderType::Box)).build();
+            let mut balls = Vec::new();
+            for side in [-1.0, 1.0] {
+                let mut rigidbody = RigidBodyComponent::new();
+                rigidbody.velocity = i64vec3(-side as i64 * 3_000_000, 0, 0);
+                balls.push(world.build_entity().with(Transform::meters(dvec3(side * 5.0, 0.5, 0.0))).with(ColliderComponent::new(ColliderType::Sphere)).with(rigidbody).build());
+            }
+            for _ in 0..180 {
+                physics_system(&mut world);
+            }
+            let (left, right) = (world.get::<Transform>(balls[0]).unwrap().pos().x, world.get::<Transform>(balls[1]).unwrap().pos().x);
+            assert!(left < right, "{:?}: the balls went through each other, they ended up at {}um and {}um", mode, left, right);
+        }
+    }
+
+    #[test]
+    fn bodies_live_as_long_as_their_entities() {
+        let (mut world, cube, ball) = stacking_world(PhysicsMode::Float);
+        physics_system(&mut world);
+        let physics = world.resource::<PhysicsState>().unwrap();
+        assert_eq!((physics.n_bodies(), physics.n_moving_bodies()), (3, 2));
+        let cube_body = physics.bodies[&cube].body.clone();
+
+        physics_system(&mut world);
+        assert!(Rc::ptr_eq(&cube_body, &world.resource::<PhysicsState>().unwrap().bodies[&cube].body), "the cube's body got rebuilt");
+
+        world.remove::<RigidBodyComponent>(ball); // now it's just something to collide with
+        world.despawn(cube);
+        physics_system(&mut world);
+        let physics = world.resource::<PhysicsState>().unwrap();
+        assert!(!physics.has_body(cube));
+        assert_eq!((physics.n_bodies(), physics.n_moving_bodies()), (2, 0));
+    }
+
+    #[test]
+    fn despawn_removes_everything() {
+        let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+        let mesh = Mesh::from_obj("models/icosphere.obj", 0, graphics.world_shader_id).unwrap();
+        let (mut world, cube, ball) = stacking_world(PhysicsMode::Float);
+        world.insert(cube, RenderComponent::new(mesh));
+        world.set_parent(ball, cube);
+        physics_system(&mut world);
+        graphics.update_entities(&mut world);
+
+        let draw_id = world.get::<RenderComponent>(cube).unwrap().draw_id().unwrap();
+        let physics = world.resource::<PhysicsState>().unwrap();
+        let (cube_body, ball_body) = (physics.bodies[&cube].body.clone(), physics.bodies[&ball].body.clone());
+
+        despawn(&mut world, &mut graphics, cube);
+        assert!(!world.is_alive(cube) && !world.is_alive(ball));
+        assert!(!graphics.remove_renderable(draw_id), "the cube is still in its meshpool");
+        let physics = world.resource_mut::<PhysicsState>().unwrap();
+        assert_eq!((physics.n_bodies(), physics.n_moving_bodies()), (1, 0));
+        for body in [cube_body, ball_body] {
+            let collides: Rc<RefCell<dyn Collides>> = body.clone();
+            assert!(!physics.sas.remove(&collides), "a despawned body is still in the SAS");
+            let rigidbody: Rc<RefCell<dyn RigidBody>> = body;
+            assert!(!physics.rigidbodies.remove(&rigidbody), "a despawned body is still in the rigidbody list");
+        }
+        graphics.cleanup();
+    }
+}
@@ -0,0 +1,221 @@
+// Holds every entity and all of their components. Any 'static type can be a component, so giving an object a new capability
+// is just inserting another component instead of writing a new struct (like RigidMeshObject) and implementing a pile of traits for it.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use super::*;
+
+pub struct World {
+    entities: EntityAllocator,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    resources: HashMap<TypeId, Box<dyn Any>>, // things there's only one of per world instead of one per entity, like PhysicsState
+}
+
+impl World {
+    pub fn new() -> Self {
+        return Self { entities: EntityAllocator::new(), storages: HashMap::new(), resources: HashMap::new() };
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        return self.entities.allocate();
+    }
+
+    // spawns an entity and adds components to it, like world.build_entity().with(transform).with(RenderComponent::new(mesh)).build()
+    pub fn build_entity(&mut self) -> EntityBuilder<'_> {
+        let entity = self.spawn();
+        return EntityBuilder { world: self, entity };
+    }
+
+    // removes the entity and all its components, returns false if it was already dead
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.free(entity) {
+            return false;
+        }
+        for (_, storage) in self.storages.iter_mut() {
+            storage.remove_entity(entity);
+        }
+        return true;
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        return self.entities.is_alive(entity);
+    }
+
+    pub fn n_entities(&self) -> usize {
+        return self.entities.n_alive();
+    }
+
+    pub fn storage<T: 'static>(&self) -> Option<&ComponentStorage<T>> {
+        let storage = self.storages.get(&TypeId::of::<T>())?;
+        return (&**storage as &dyn Any).downcast_ref::<ComponentStorage<T>>();
+    }
+
+    // creates the storage if no component of this type has been added yet
+    pub fn storage_mut<T: 'static>(&mut self) -> &mut ComponentStorage<T> {
+        let storage = self.storages.entry(TypeId::of::<T>()).or_insert_with(|| Box::new(ComponentStorage::<T>::new()));
+        return (&mut **storage as &mut dyn Any).downcast_mut::<ComponentStorage<T>>().unwrap();
+    }
+
+    // like storage_mut(), but returns None instead of creating it
+    fn existing_storage_mut<T: 'static>(&mut self) -> Option<&mut ComponentStorage<T>> {
+        let storage = self.storages.get_mut(&TypeId::of::<T>())?;
+        return (&mut **storage as &mut dyn Any).downcast_mut::<ComponentStorage<T>>();
+    }
+
+    // returns the component the entity already had, if any. panics if the entity is dead
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        assert!(self.is_alive(entity), "Tried to add a component to dead entity {:?}", entity);
+        return self.storage_mut::<T>().insert(entity, component);
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        return self.existing_storage_mut::<T>()?.remove(entity);
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        return self.storage::<T>()?.get(entity);
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        return self.existing_storage_mut::<T>()?.get_mut(entity);
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        return self.get::<T>(entity).is_some();
+    }
+
+    // every entity with a T
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        return self.storage::<T>().into_iter().flat_map(|storage| storage.iter());
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        return self.existing_storage_mut::<T>().into_iter().flat_map(|storage| storage.iter_mut());
+    }
+
+    // every entity with both an A and a B
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Entity, &A, &B)> {
+        let b = self.storage::<B>();
+        return self.query::<A>().filter_map(move |(entity, a)| Some((entity, a, b?.get(entity)?)));
+    }
+
+    // calls f on every entity with both an A and a B, with both components mutable
+    pub fn for_each2_mut<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(Entity, &mut A, &mut B)) {
+        assert!(TypeId::of::<A>() != TypeId::of::<B>(), "for_each2_mut needs two different component types");
+        // take B's storage out of the map for a moment so we can borrow both storages mutably
+        let mut b = match self.storages.remove(&TypeId::of::<B>()) {
+            Some(b) => b,
+            None => return
+        };
+        let b_storage = (&mut *b as &mut dyn Any).downcast_mut::<ComponentStorage<B>>().unwrap();
+        for (entity, a) in self.query_mut::<A>() {
+            if let Some(b) = b_storage.get_mut(entity) {
+                f(entity, a, b);
+            }
+        }
+        self.storages.insert(TypeId::of::<B>(), b);
+    }
+
+    // returns the resource of this type there already was, if any
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> Option<T> {
+        let old = self.resources.insert(TypeId::of::<T>(), Box::new(resource))?;
+        return Some(*old.downcast::<T>().unwrap());
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        return Some(*resource.downcast::<T>().unwrap());
+    }
+
+    pub fn resource<T: 'static>(&self) -> Option<&T> {
+        return self.resources.get(&TypeId::of::<T>())?.downcast_ref::<T>();
+    }
+
+    pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        return self.resources.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>();
+    }
+}
+
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity: Entity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: 'static>(self, component: T) -> Self {
+        self.world.insert(self.entity, component);
+        return self;
+    }
+
+    pub fn build(self) -> Entity {
+        return self.entity;
+    }
+}
+
+// a list of systems to run in order every tick
+pub struct Schedule {
+    systems: Vec<Box<dyn FnMut(&mut World)>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        return Self { systems: Vec::new() };
+    }
+
+    pub fn add_system(&mut self, system: impl FnMut(&mut World) + 'static) {
+        self.systems.push(Box::new(system));
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        for system in self.systems.iter_mut() {
+            system(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Health(i32);
+
+    #[test]
+    fn despawned_entity_stays_dead_after_its_slot_is_reused() {
+        let mut world = World::new();
+        let old = world.build_entity().with(Health(10)).build();
+        assert!(world.despawn(old));
+        let new = world.build_entity().with(Health(20)).build();
+        assert_eq!(new.index(), old.index(), "the freed slot should have been reused");
+        assert_ne!(new.generation(), old.generation());
+
+        assert!(!world.is_alive(old));
+        assert!(world.get::<Health>(old).is_none());
+        assert!(world.get_mut::<Health>(old).is_none());
+        assert!(world.remove::<Health>(old).is_none());
+        assert!(!world.despawn(old));
+        assert_eq!(world.get::<Health>(new).unwrap().0, 20, "using the old id must not have touched the new entity");
+        assert_eq!(world.n_entities(), 1);
+    }
+
+    #[test]
+    fn lookups_dont_create_storages() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        assert!(world.get_mut::<Health>(entity).is_none());
+        assert!(world.remove::<Health>(entity).is_none());
+        assert_eq!(world.query_mut::<Health>().count(), 0);
+        assert!(world.storage::<Health>().is_none());
+    }
+
+    #[test]
+    fn resources() {
+        let mut world = World::new();
+        assert!(world.resource::<Health>().is_none());
+        assert!(world.insert_resource(Health(1)).is_none());
+        world.resource_mut::<Health>().unwrap().0 += 1;
+        assert_eq!(world.insert_resource(Health(5)).unwrap().0, 2);
+        assert_eq!(world.remove_resource::<Health>().unwrap().0, 5);
+        assert!(world.resource::<Health>().is_none());
+    }
+}
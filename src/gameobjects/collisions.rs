@@ -6,7 +6,7 @@ use glm::{I64Vec3, Vec3, vec3, vec4, Mat4};
 use crate::transform::*;
 
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ColliderType {
     Sphere,
     Convex,
@@ -81,7 +81,7 @@ pub fn collision_SAT(obj1: &(dyn Collides), obj2: &(dyn Collides)) -> Option<Col
     for x in [-0.5, 0.5] {
         for y in [-0.5, 0.5] {
             for z in [-0.5, 0.5] {
-                verts2.push(transformed_obj2_rel_pos + (mat * obj2.transform().rotscalemat *vec4(x, y, z, 1.0)).xyz());
+                verts2.push(transformed_obj2_rel_pos + (mat * obj2.transform().rotscalemat() *vec4(x, y, z, 1.0)).xyz());
                 //i += 1;
             }
         }
@@ -117,7 +117,7 @@ pub fn collision_SAT(obj1: &(dyn Collides), obj2: &(dyn Collides)) -> Option<Col
         // if obj2.transform().pos().y < 0 {p.normal.y *= -1.0};
         // if obj2.transform().pos().z < 0 {p.normal.z *= -1.0};
         //p.pos.component_mul_assign(&obj2.transform().scl()); //println!("multiply by {:?}", obj2.transform().scl());
-        p.pos = transformed_obj2_rel_pos + (mat * obj2.transform().rotscalemat *vec4(p.pos.x, p.pos.y, p.pos.z, 1.0)).xyz();
+        p.pos = transformed_obj2_rel_pos + (mat * obj2.transform().rotscalemat() *vec4(p.pos.x, p.pos.y, p.pos.z, 1.0)).xyz();
         planes2.push(p);
     }
 
@@ -568,7 +568,7 @@ macro_rules! impl_collides {
                 }
                 
                 // sphere to box
-                else if (self.collider_type == crate::gameobjects::ColliderType::Sphere && other.get_collider_type() == crate::gameobjects::ColliderType::Box) || (self.collider_type == crate::gameobjects::collisions::ColliderType::Box && other.get_collider_type() == crate::gameobjects::collisions::ColliderType::Sphere) { 
+                else if (self.collider_type == crate::gameobjects::ColliderType::Sphere && other.get_collider_type() == crate::gameobjects::ColliderType::Box) || (self.collider_type == crate::gameobjects::ColliderType::Box && other.get_collider_type() == crate::gameobjects::ColliderType::Sphere) { 
                     // transform both cube and sphere by same matrix so cube is at origin and unrotated 
                     let cube: &dyn crate::gameobjects::Collides;
                     let sphere: &dyn crate::gameobjects::Collides;
@@ -620,8 +620,7 @@ macro_rules! impl_collides {
                             normal.z = -1.0;
                         }
                         let hitpos = crate::transform::i64vec3_from_vec3(&(mat2 * vec4(closest.x, closest.y, closest.z, 1.0)).xyz()) + cube.transform().pos();
-                        println!("Extents were {:?} so pos is {:?}", extents, hitpos);
-                        let info = crate::gameobjects::collisions::CollisionInfo {
+                        let info = crate::gameobjects::CollisionInfo {
                             normal: (mat2 * glm::vec4(normal.x, normal.y, normal.z, 1.0)).xyz(),
                             collision_points: vec![(hitpos, (distance_squared as f64).sqrt() as i64 - ((sphere.transform().scl().x/2.0) as f64 * crate::transform::UNITS_PER_METER as f64) as i64)]
                         };
@@ -633,14 +632,18 @@ macro_rules! impl_collides {
                 }
 
                 // sphere to sphere
-                else if self.collider_type == crate::gameobjects::collisions::ColliderType::Sphere && other.get_collider_type() == crate::gameobjects::collisions::ColliderType::Sphere { 
-                    let min_distance_squared = (self.transform.scl().x/2.0 + other.transform().scl().x/2.0).powf(2.0) as i64 * (crate::transform::UNITS_PER_METER).pow(2);
+                else if self.collider_type == crate::gameobjects::ColliderType::Sphere && other.get_collider_type() == crate::gameobjects::ColliderType::Sphere { 
+                    let radius = self.transform.scl().x as f64/2.0 * crate::transform::UNITS_PER_METER as f64;
+                    let min_distance = radius + other.transform().scl().x as f64/2.0 * crate::transform::UNITS_PER_METER as f64;
                     let v = self.transform.pos() - other.transform().pos();
-                    let distance_squared = v.x*v.x + v.y*v.y + v.z*v.z; //println!("Distance squared was {} out of {}", distance_squared, min_distance_squared);
-                    if min_distance_squared >= distance_squared {
-                        let info = crate::gameobjects::collisions::CollisionInfo {
-                            normal: crate::transform::vec3_from_i64vec3(&v),
-                            collision_points: vec![(crate::transform::i64vec3_from_vec3(&(crate::transform::vec3_from_i64vec3(&v) * ((self.transform.scl().x/2.0)))) + self.transform.pos(), (distance_squared as f64).sqrt() as i64 - (self.transform.scl().x/2.0 + other.transform().scl().x/2.0) as i64 * (crate::transform::UNITS_PER_METER))]
+                    let distance = crate::transform::dvec3_from_i64vec3(&v).magnitude() * crate::transform::UNITS_PER_METER as f64; // doubles so the squares don't overflow
+                    if min_distance >= distance {
+                        // normal must be a unit vector pointing from other to self, if they're exactly on top of each other any direction works
+                        let normal = if distance == 0.0 {glm::vec3(0.0, 1.0, 0.0)} else {crate::transform::vec3_from_i64vec3(&v).normalize()};
+                        let hitpos = self.transform.pos() - crate::transform::i64vec3_from_vec3(&(normal * self.transform.scl().x/2.0));
+                        let info = crate::gameobjects::CollisionInfo {
+                            normal: normal,
+                            collision_points: vec![(hitpos, (min_distance - distance) as i64)]
                         };
                         return Some(info)
                     }
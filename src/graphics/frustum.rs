@@ -0,0 +1,42 @@
+// The part of space a camera can see, as 6 planes, for skipping instances that are off screen before they're drawn.
+// Because of floating origin, model matrices are relative to the camera, so the frustum is too: it comes straight from proj * camera,
+// and never has to deal with huge world coordinates.
+
+use glm::{Mat4, Vec3, Vec4};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vec4; 6], // left, right, bottom, top, near, far. xyz is the normal (pointing inside), w the distance, all normalized
+}
+
+impl Frustum {
+    // from the matrix that takes (camera relative) positions to clip space, usually proj * camera
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let row = |i: usize| -> Vec4 { m.row(i).transpose() };
+        let mut planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ];
+        for plane in planes.iter_mut() {
+            let length = plane.xyz().norm();
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+        return Self { planes };
+    }
+
+    // conservative, a sphere just outside a corner of the frustum can still count as inside
+    pub fn intersects_sphere(&self, center: &Vec3, radius: f32) -> bool {
+        for plane in self.planes.iter() {
+            if plane.xyz().dot(center) + plane.w < -radius {
+                return false;
+            }
+        }
+        return true;
+    }
+}
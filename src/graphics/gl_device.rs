@@ -0,0 +1,352 @@
+// The opengl 4.6 RenderDevice.
+
+use std::{cell::{Cell, RefCell}, collections::HashMap};
+
+use gl46::*;
+use crate::graphics::*;
+
+pub struct GlDevice {
+    gl: gl46::GlFns,
+    fences: RefCell<HashMap<GLuint, GLsync>>, // opengl's fences are pointers, so they get ids of our own
+    last_fence: Cell<GLuint>,
+}
+
+impl GlDevice {
+    pub fn new(gl: gl46::GlFns) -> Self {
+        unsafe {
+            // make opengl track errors for us
+            gl.Enable(gl46::GL_DEBUG_OUTPUT);
+            gl.DebugMessageCallback(Some(opengl_debug_callback), std::ptr::null());
+        }
+        return Self { gl, fences: RefCell::new(HashMap::new()), last_fence: Cell::new(0) };
+    }
+
+    // for anything the RenderDevice trait doesn't cover yet
+    pub fn gl(&self) -> &gl46::GlFns {
+        return &self.gl;
+    }
+
+    fn compile_shader(&self, stage: GLenum, source: &str) -> Result<GLuint, String> {
+        unsafe {
+            let shader = self.gl.CreateShader(stage);
+            let length: GLint = source.len().try_into().unwrap();
+            let ptr = source.as_ptr();
+            self.gl.ShaderSource(shader, 1, &ptr, &length);
+            self.gl.CompileShader(shader);
+
+            let mut success: GLint = -1;
+            self.gl.GetShaderiv(shader, GL_COMPILE_STATUS, &mut success);
+            if success == 0 {
+                let mut length: GLint = 0;
+                self.gl.GetShaderiv(shader, GL_INFO_LOG_LENGTH, &mut length);
+                let mut info_log: Vec<u8> = vec![0; length.max(1) as usize];
+                let mut written: GLint = 0;
+                self.gl.GetShaderInfoLog(shader, length, &mut written, info_log.as_mut_ptr());
+                self.gl.DeleteShader(shader);
+                return Err(String::from_utf8_lossy(&info_log[..written.max(0) as usize]).to_string());
+            }
+            return Ok(shader);
+        }
+    }
+}
+
+fn gl_target(target: BufferTarget) -> GLenum {
+    return match target {
+        BufferTarget::Vertex => GL_ARRAY_BUFFER,
+        BufferTarget::Index => GL_ELEMENT_ARRAY_BUFFER,
+        BufferTarget::DrawIndirect => GL_DRAW_INDIRECT_BUFFER,
+        BufferTarget::ShaderStorage => GL_SHADER_STORAGE_BUFFER,
+    };
+}
+
+fn gl_texture_target(kind: TextureKind) -> GLenum {
+    return match kind {
+        TextureKind::Tex2D => GL_TEXTURE_2D,
+        TextureKind::Tex2DArray => GL_TEXTURE_2D_ARRAY,
+    };
+}
+
+impl RenderDevice for GlDevice {
+    fn create_buffer(&self) -> GLuint {
+        let mut buffer: GLuint = 0;
+        unsafe { self.gl.GenBuffers(1, &mut buffer); }
+        return buffer;
+    }
+
+    fn buffer_static_data(&self, target: BufferTarget, buffer: GLuint, data: &[u8]) {
+        unsafe {
+            self.gl.BindBuffer(gl_target(target), buffer);
+            self.gl.BufferData(gl_target(target), data.len() as isize, data.as_ptr() as *const c_void, GL_STATIC_DRAW);
+        }
+    }
+
+    // TODO: should check opengl version and not use coherent_bit/persistent_bit and use glBufferSubData if persistent mapping is unsupported
+    fn create_mapped_buffer(&self, target: BufferTarget, nbytes: isize) -> (GLuint, *mut c_void) {
+        let flags = GL_MAP_PERSISTENT_BIT|GL_MAP_COHERENT_BIT|GL_MAP_WRITE_BIT;
+        let buffer = self.create_buffer();
+        unsafe {
+            // Because we said glBufferStorage, the buffer can't be resized, only replaced
+            self.gl.BindBuffer(gl_target(target), buffer);
+            self.gl.BufferStorage(gl_target(target), nbytes, std::ptr::null(), flags);
+            let ptr = self.gl.MapBufferRange(gl_target(target), 0, nbytes, flags);
+            return (buffer, ptr);
+        }
+    }
+
+    fn bind_buffer(&self, target: BufferTarget, buffer: GLuint) {
+        unsafe { self.gl.BindBuffer(gl_target(target), buffer); }
+    }
+
+    fn bind_buffer_base(&self, target: BufferTarget, binding: GLuint, buffer: GLuint) {
+        unsafe { self.gl.BindBufferBase(gl_target(target), binding, buffer); }
+    }
+
+    fn delete_buffer(&self, buffer: GLuint) {
+        unsafe { self.gl.DeleteBuffers(1, &buffer); }
+    }
+
+    fn create_vertex_array(&self) -> GLuint {
+        let mut vao: GLuint = 0;
+        unsafe { self.gl.GenVertexArrays(1, &mut vao); }
+        return vao;
+    }
+
+    fn bind_vertex_array(&self, vao: GLuint) {
+        self.gl.BindVertexArray(vao);
+    }
+
+    fn delete_vertex_array(&self, vao: GLuint) {
+        unsafe { self.gl.DeleteVertexArrays(1, &vao); }
+    }
+
+    fn vertex_attrib(&self, index: GLuint, n_floats: i32, buffer: GLuint, offset: usize, stride: i32, divisor: GLuint) {
+        unsafe {
+            self.gl.BindBuffer(GL_ARRAY_BUFFER, buffer);
+            self.gl.EnableVertexAttribArray(index);
+            self.gl.VertexAttribPointer(index, n_floats, GL_FLOAT, false as u8, stride, offset as *const c_void);
+            self.gl.VertexAttribDivisor(index, divisor); // this divisor thing makes the attribute per instance/mesh instead of per vertex
+        }
+    }
+
+    fn create_texture(&self, kind: TextureKind, format: TextureFormat, sampling: TextureSampling, width: u32, height: u32, layers: u32, data: Option<&[u8]>) -> GLuint {
+        let target = gl_texture_target(kind);
+        let (internal_format, source_format, source_type) = match format {
+            TextureFormat::Rgba8 => (GL_RGBA8, GL_RGBA, GL_UNSIGNED_BYTE),
+            TextureFormat::Rgb8 => (GL_RGBA8, GL_RGB, GL_UNSIGNED_BYTE),
+            TextureFormat::Depth32 => (GL_DEPTH_COMPONENT32, GL_DEPTH_COMPONENT, GL_FLOAT),
+        };
+        let pixels = data.map_or(std::ptr::null(), |d| d.as_ptr() as *const c_void);
+
+        let mut tex: GLuint = 0;
+        unsafe {
+            self.gl.GenTextures(1, &mut tex);
+            self.gl.BindTexture(target, tex);
+            if kind == TextureKind::Tex2D {
+                self.gl.TexImage2D(target, 0, internal_format.0 as i32, width as i32, height as i32, 0, source_format, source_type, pixels);
+            }
+            else {
+                self.gl.TexStorage3D(target, 1, internal_format, width as i32, height as i32, layers as i32);
+                if data.is_some() {
+                    self.gl.TexSubImage3D(target, 0, 0, 0, 0, width as i32, height as i32, layers as i32, source_format, source_type, pixels);
+                }
+            }
+
+            match sampling {
+                TextureSampling::Tiled => {
+                    self.gl.TexParameteri(target, GL_TEXTURE_WRAP_S, GL_REPEAT.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_WRAP_T, GL_REPEAT.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_MIN_FILTER, GL_LINEAR.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_MAG_FILTER, GL_LINEAR.0 as i32);
+                },
+                TextureSampling::Nearest => {
+                    self.gl.TexParameteri(target, GL_TEXTURE_MAG_FILTER, GL_NEAREST.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_MIN_FILTER, GL_NEAREST.0 as i32);
+                },
+                TextureSampling::DepthBorder => {
+                    self.gl.TexParameteri(target, GL_TEXTURE_MAG_FILTER, GL_NEAREST.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_MIN_FILTER, GL_NEAREST.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_BORDER.0 as i32);
+                    self.gl.TexParameteri(target, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_BORDER.0 as i32);
+                    self.gl.TexParameterfv(target, GL_TEXTURE_BORDER_COLOR, [1.0, 1.0, 1.0, 1.0].as_ptr());
+                },
+            }
+        }
+        return tex;
+    }
+
+    fn bind_texture(&self, unit: GLuint, kind: TextureKind, texture: GLuint) {
+        unsafe {
+            self.gl.ActiveTexture(GLenum(GL_TEXTURE0.0 + unit));
+            self.gl.BindTexture(gl_texture_target(kind), texture);
+        }
+    }
+
+    fn delete_texture(&self, texture: GLuint) {
+        unsafe { self.gl.DeleteTextures(1, &texture); }
+    }
+
+    fn create_framebuffer(&self, color: Option<GLuint>, depth: Option<GLuint>) -> GLuint {
+        let mut buffer: GLuint = 0;
+        unsafe {
+            self.gl.GenFramebuffers(1, &mut buffer);
+            self.gl.BindFramebuffer(GL_FRAMEBUFFER, buffer);
+            if let Some(color) = color {
+                self.gl.FramebufferTexture2D(GL_FRAMEBUFFER, GL_COLOR_ATTACHMENT0, GL_TEXTURE_2D, color, 0);
+            }
+            if let Some(depth) = depth {
+                self.gl.FramebufferTexture(GL_FRAMEBUFFER, GL_DEPTH_ATTACHMENT, depth, 0);
+            }
+            if color.is_none() {
+                self.gl.DrawBuffer(GL_NONE);
+                self.gl.ReadBuffer(GL_NONE);
+            }
+            if self.gl.CheckFramebufferStatus(GL_FRAMEBUFFER) != GL_FRAMEBUFFER_COMPLETE {
+                panic!("Failure to create framebuffer. So sad.");
+            }
+            self.gl.BindFramebuffer(GL_FRAMEBUFFER, 0);
+        }
+        return buffer;
+    }
+
+    fn bind_framebuffer(&self, framebuffer: GLuint) {
+        unsafe { self.gl.BindFramebuffer(GL_FRAMEBUFFER, framebuffer); }
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        unsafe { self.gl.Viewport(x, y, width, height); }
+    }
+
+    fn delete_framebuffer(&self, framebuffer: GLuint) {
+        unsafe { self.gl.DeleteFramebuffers(1, &framebuffer); }
+    }
+
+    fn create_program(&self, vertex_source: &str, fragment_source: &str) -> Result<GLuint, String> {
+        let vertex = self.compile_shader(GL_VERTEX_SHADER, vertex_source).map_err(|log| format!("vertex shader: {}", log))?;
+        let fragment = match self.compile_shader(GL_FRAGMENT_SHADER, fragment_source) {
+            Ok(fragment) => fragment,
+            Err(log) => {
+                self.gl.DeleteShader(vertex);
+                return Err(format!("fragment shader: {}", log));
+            }
+        };
+        unsafe {
+            let program = self.gl.CreateProgram();
+            self.gl.AttachShader(program, vertex);
+            self.gl.AttachShader(program, fragment);
+            self.gl.BindFragDataLocation(program, 0, b"color\0".as_ptr());
+            self.gl.LinkProgram(program);
+            // the program keeps what it needs, the shaders can go
+            self.gl.DeleteShader(vertex);
+            self.gl.DeleteShader(fragment);
+
+            let mut success: GLint = -1;
+            self.gl.GetProgramiv(program, GL_LINK_STATUS, &mut success);
+            if success == 0 {
+                self.gl.DeleteProgram(program);
+                return Err(String::from("failed to link shader program"));
+            }
+            return Ok(program);
+        }
+    }
+
+    fn use_program(&self, program: GLuint) {
+        self.gl.UseProgram(program);
+    }
+
+    fn uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        unsafe { return self.gl.GetUniformLocation(program, (name.to_owned() + "\0").as_ptr()); }
+    }
+
+    fn set_uniform_i32(&self, location: GLint, value: i32) {
+        unsafe { self.gl.Uniform1i(location, value); }
+    }
+
+    fn set_uniform_mat4(&self, location: GLint, matrix: &glm::Mat4, transpose: bool) {
+        unsafe { self.gl.UniformMatrix4fv(location, 1, transpose as u8, matrix.as_ptr()); }
+    }
+
+    fn delete_program(&self, program: GLuint) {
+        self.gl.DeleteProgram(program);
+    }
+
+    fn set_clear_color(&self, rgba: [f32; 4]) {
+        unsafe { self.gl.ClearColor(rgba[0], rgba[1], rgba[2], rgba[3]); }
+    }
+
+    fn clear(&self, color: bool, depth: bool) {
+        let mut mask = GLbitfield(0);
+        if color {mask = mask|GL_COLOR_BUFFER_BIT;}
+        if depth {mask = mask|GL_DEPTH_BUFFER_BIT;}
+        unsafe { self.gl.Clear(mask); }
+    }
+
+    fn set_depth_test(&self, enabled: bool) {
+        unsafe { if enabled {self.gl.Enable(GL_DEPTH_TEST)} else {self.gl.Disable(GL_DEPTH_TEST)} }
+    }
+
+    fn set_backface_culling(&self, enabled: bool) {
+        unsafe {
+            if enabled {
+                self.gl.Enable(GL_CULL_FACE);
+                self.gl.CullFace(GL_BACK);
+            }
+            else {
+                self.gl.Disable(GL_CULL_FACE);
+            }
+        }
+    }
+
+    fn draw_arrays(&self, first: i32, count: i32) {
+        unsafe { self.gl.DrawArrays(GL_TRIANGLES, first, count); }
+    }
+
+    fn draw_elements_instanced(&self, n_indices: i32, first_index_byte: usize, n_instances: i32, base_vertex: i32, base_instance: GLuint) {
+        unsafe { self.gl.DrawElementsInstancedBaseVertexBaseInstance(GL_TRIANGLES, n_indices, GL_UNSIGNED_INT, first_index_byte as *const c_void, n_instances, base_vertex, base_instance); }
+    }
+
+    fn multi_draw_elements_indirect(&self, n_commands: i32, offset: usize) {
+        unsafe { self.gl.MultiDrawElementsIndirect(GL_TRIANGLES, GL_UNSIGNED_INT, offset as *const c_void, n_commands, 0); }
+    }
+
+    fn flush(&self) {
+        unsafe { self.gl.Flush(); }
+    }
+
+    fn create_fence(&self) -> GLuint {
+        let sync = unsafe { self.gl.FenceSync(GL_SYNC_GPU_COMMANDS_COMPLETE, GLbitfield(0)) };
+        self.last_fence.set(self.last_fence.get() + 1);
+        self.fences.borrow_mut().insert(self.last_fence.get(), sync);
+        return self.last_fence.get();
+    }
+
+    fn wait_fence(&self, fence: GLuint) {
+        let sync = match self.fences.borrow_mut().remove(&fence) {
+            Some(sync) => sync,
+            None => return
+        };
+        unsafe {
+            // the flush bit makes sure the fence actually gets to the gpu, otherwise this could wait forever
+            loop {
+                let status = self.gl.ClientWaitSync(GLsync(sync.0), GL_SYNC_FLUSH_COMMANDS_BIT, 1_000_000);
+                if status != GL_TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            self.gl.DeleteSync(sync);
+        }
+    }
+
+    fn read_pixels(&self, framebuffer: GLuint, width: u32, height: u32) -> Option<RgbaImage> {
+        let mut image = RgbaImage::new(width, height);
+        unsafe {
+            self.gl.BindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+            self.gl.ReadPixels(0, 0, width as i32, height as i32, GL_RGBA, GL_UNSIGNED_BYTE, image.pixels.as_mut_ptr() as *mut c_void);
+            self.gl.BindFramebuffer(GL_FRAMEBUFFER, 0);
+        }
+        // opengl's rows go bottom to top
+        let row_nbytes = (width * 4) as usize;
+        let flipped: Vec<u8> = image.pixels.chunks_exact(row_nbytes.max(1)).rev().flatten().copied().collect();
+        image.pixels = flipped;
+        return Some(image);
+    }
+}
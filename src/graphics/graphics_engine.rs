@@ -2,30 +2,66 @@ use crate::Renderable;
 use crate::graphics::*;
 use crate::windowing::*;
 use crate::transform::*;
+use crate::ecs::*;
+use crate::animation::{SkeletalAnimator, MAX_JOINTS};
 use std::time::Duration;
 use std::time::Instant;
 use std::{cell::RefCell, rc::Rc};
 use std::collections::HashMap;
-use gl46::*;
 use glm::Vec2;
 use glm::vec2;
 use glfw::Key;
 use glm::vec3;
+use glm::I64Vec3;
 
 // there are many different uuids here so for clarification:
 // mesh_uuid: each Mesh has one
-// draw_uuid: each gameobject implementing Renderable has one
-// shaders, textures, & framebuffers have their own ids as well but opengl takes care of that for us
+// draw_uuid: each gameobject implementing Renderable (or entity with a RenderComponent) has one
+// shaders, textures, & framebuffers have their own ids as well but the RenderDevice takes care of that for us
 
 
 
+// why GraphicsEngine couldn't change a dynamic mesh
+#[derive(Debug, PartialEq)]
+pub enum MeshUpdateError {
+    NoSuchMesh(usize), // there's no mesh with this uuid in LOADED_MESHES
+    NotDynamic(usize), // the mesh with this uuid wasn't made dynamic, so it could be instanced and changing it would change every instance
+    PartialVertex(usize), // this many floats isn't a whole number of vertices
+    IndexOutOfRange(u32, usize), // an index is past the end of the (this many) vertices
+    OutOfRange(usize, usize), // a partial update would go up to the first number, but there are only the second number of vertices/indices
+    SkinnedVertexCount(usize, usize), // skinned meshes keep their joints and weights per vertex, so they need to keep their (first number of) vertices, not get the second number
+}
+
+impl std::fmt::Display for MeshUpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshUpdateError::NoSuchMesh(id) => write!(f, "there's no mesh {}", id),
+            MeshUpdateError::NotDynamic(id) => write!(f, "mesh {} isn't dynamic", id),
+            MeshUpdateError::PartialVertex(n_floats) => write!(f, "{} floats isn't a whole number of {} float vertices", n_floats, N_FLOATS_PER_VERTEX),
+            MeshUpdateError::IndexOutOfRange(index, n_vertices) => write!(f, "index {} is past the end of the {} vertices", index, n_vertices),
+            MeshUpdateError::OutOfRange(end, len) => write!(f, "the update goes up to {} but there are only {}", end, len),
+            MeshUpdateError::SkinnedVertexCount(expected, actual) => write!(f, "skinned meshes can't change how many vertices they have ({}), but got {}", expected, actual),
+        }
+    }
+}
+
 pub struct GraphicsEngine {
-    gl: gl46::GlFns,
+    device: Box<dyn RenderDevice>, // GlDevice normally, or a RecordingDevice to run without a gpu
 
     resolution: (u32, u32),
     
     pub world_shader_id: u32,
+    pub skinned_shader_id: u32, // for meshes made with Mesh::skinned()
     pub camera: Camera,
+    pub camera_entity: Option<Entity>, // if set, the camera follows this entity's (world) Transform, so it can be attached to a player/vehicle with World::set_parent()
+
+    pub frustum_culling: bool, // skip drawing instances the camera can't see
+    frustum: Frustum, // camera relative, updated with the camera matrices
+    n_instances_drawn: usize, // last frame's
+    n_instances: usize,
+
+    pub lod_hysteresis: f32, // how far (as a fraction) past a level of detail's screen size an entity has to get before it switches, see select_lod()
+    mesh_lods: HashMap<usize, (f32, Vec<MeshLod>)>, // key is mesh uuid, value is (bounding radius, lods), cached the first time an entity with that mesh is seen
 
     pub freecam_override_enabled: bool,
     freecam_transform: Transform,
@@ -41,11 +77,14 @@ pub struct GraphicsEngine {
     pools: HashMap<GLuint /*(shader program id)*/, HashMap<u32 /*(texture/texture array id)*/, Vec<MeshPool>>>,
     shaders: HashMap<u32, ShaderProgram>, // key is program id
     textures: HashMap<u32, Texture>, // key is gl texture id
+    texture_paths: HashMap<u32, String>, // key is gl texture id, value is the file it was loaded from
     framebuffers: HashMap<u32, Framebuffer>, // key is gl framebuffer id
 
     // tells how to get to the drawing data for a particular object from its draw id
     object_drawing_data_locations: HashMap<usize, (u32, u32, usize, i32, i32)>, // tuple is (programid, textureid, index in vector, slot within meshpool, instance offset)
 
+    instance_owners: HashMap<(u32, u32, usize, i32), Vec<usize>>, // key is the first 4 fields of an object_drawing_data_locations value, value is the draw_id of each instance in that slot, so remove_renderable() can fix up whichever one it moves
+    dynamic_mesh_draws: HashMap<usize, Vec<usize>>, // key is the uuid of a dynamic mesh, value is the draw_id of everything drawing it (each in its own meshpool slot), so changing the mesh can change all of them
     cached_meshes_to_add: HashMap<usize, (u32, Vec<usize>)>, // key is [meshuuid or -1], value is (count, vec<keys of mesh_locations that need to get filled out>)
                             // dynamic meshes don't go in here and are added immediately as they cannot be instanced
                                 // faster than sorting it when we add meshes
@@ -61,15 +100,24 @@ pub struct GraphicsEngine {
 }
 
 impl GraphicsEngine {
-    pub fn new(gl_context: gl46::GlFns, resolution: (u32, u32)) -> Self {
+    pub fn new(device: Box<dyn RenderDevice>, resolution: (u32, u32)) -> Self {
         let mut ge = Self {
-            gl: gl_context,
+            device: device,
 
             resolution: resolution,
 
             world_shader_id: 0,
+            skinned_shader_id: 0,
             camera: Camera::new(),
 
+            frustum_culling: true,
+            frustum: Frustum::from_matrix(&glm::identity()),
+            n_instances_drawn: 0,
+            n_instances: 0,
+
+            lod_hysteresis: 0.1,
+            mesh_lods: HashMap::new(),
+
             freecam_override_enabled: false,
             freecam_pitchyaw: vec2(0.0, 0.0),
             freecam_speed: 0,
@@ -79,13 +127,17 @@ impl GraphicsEngine {
             postproc_shader_id: 0,
 
             renderable_gameobjects: HashMap::new(),
+            camera_entity: None,
 
             pools: HashMap::new(),
             shaders: HashMap::new(),
             textures: HashMap::new(),
+            texture_paths: HashMap::new(),
             framebuffers: HashMap::new(),
             object_drawing_data_locations: HashMap::new(),
 
+            instance_owners: HashMap::new(),
+            dynamic_mesh_draws: HashMap::new(),
             cached_meshes_to_add: HashMap::new(),
 
             last_draw_uuid: 1,
@@ -95,29 +147,23 @@ impl GraphicsEngine {
         };
         
         ge.postproc_shader_id = ge.load_shader("shaders/postproc_vertex.glsl", "shaders/postproc_fragment.glsl", vec!["screenTexture"]);
-        let postproc_framebuffer = Framebuffer::new(&ge.gl, resolution.0, resolution.1, true, true);
+        let postproc_framebuffer = Framebuffer::new(&*ge.device, resolution.0, resolution.1, true, true);
         ge.postproc_framebuffer_id = postproc_framebuffer.gl_framebuffer;
         ge.load_framebuffer(postproc_framebuffer);
         ge.world_shader_id = ge.load_shader("shaders/world_vertex.glsl", "shaders/world_fragment.glsl", vec!["textures", "shadowmap"]);
+        ge.skinned_shader_id = ge.load_shader("shaders/skinned_world_vertex.glsl", "shaders/world_fragment.glsl", vec!["textures", "shadowmap"]);
         ge.setup_screen_quad();
 
         
-        unsafe {
-            // make opengl track errors for us
-            ge.gl.Enable(gl46::GL_DEBUG_OUTPUT);
-            ge.gl.DebugMessageCallback(Some(opengl_debug_callback), std::ptr::null());
+        // tell opengl how to combine colors
+        //ge.gl.Enable(GL_BLEND);
+        //ge.gl.BlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
 
-            // tell opengl how to combine colors
-            //ge.gl.Enable(GL_BLEND);
-            //ge.gl.BlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
+        // make sky pretty
+        ge.device.set_clear_color([0.5, 0.5, 0.8, 1.0]);
 
-            // make sky pretty
-            ge.gl.ClearColor(0.5, 0.5, 0.8, 1.0);
-
-            // backface culling
-            ge.gl.Enable(GL_CULL_FACE);
-            ge.gl.CullFace(GL_BACK);
-        }
+        // backface culling
+        ge.device.set_backface_culling(true);
 
         return ge;
     }
@@ -132,32 +178,28 @@ impl GraphicsEngine {
     
      // Sets up vbo/vao for a quad that covers the screen, so we can render a texture to it for postproc
     pub fn setup_screen_quad(&mut self) {
-        unsafe {
-            self.gl.GenBuffers(1, &mut self.screen_quad_vbo as *const u32 as *mut u32);
-            self.gl.GenVertexArrays(1, &mut self.screen_quad_vao as *const u32 as *mut u32);
-            self.gl.BindBuffer(GL_ARRAY_BUFFER, self.screen_quad_vbo);
-            self.gl.BindVertexArray(self.screen_quad_vao);
+        self.screen_quad_vbo = self.device.create_buffer();
+        self.screen_quad_vao = self.device.create_vertex_array();
+        self.device.bind_vertex_array(self.screen_quad_vao);
 
-            self.gl.BufferData(GL_ARRAY_BUFFER, 24*4, &Self::SCREEN_QUAD_VERTS[0] as *const f32 as *const c_void, GL_STATIC_DRAW);
-            
-            self.gl.EnableVertexAttribArray(0);
-            self.gl.VertexAttribPointer(0, 2, GL_FLOAT, false as u8, 16, std::ptr::null());
-            self.gl.EnableVertexAttribArray(1);
-            self.gl.VertexAttribPointer(1, 2, GL_FLOAT, false as u8, 16, 8 as *const c_void);
-        }
+        let bytes: Vec<u8> = Self::SCREEN_QUAD_VERTS.iter().flat_map(|f| f.to_ne_bytes()).collect();
+        self.device.buffer_static_data(BufferTarget::Vertex, self.screen_quad_vbo, &bytes);
+        
+        self.device.vertex_attrib(0, 2, self.screen_quad_vbo, 0, 16, 0);
+        self.device.vertex_attrib(1, 2, self.screen_quad_vbo, 8, 16, 0);
     }
 
     pub fn update_resolution(&mut self, resolution: (u32, u32)) {
         self.resolution = resolution;
-        self.framebuffers[&self.postproc_framebuffer_id].cleanup(&self.gl);
-        let postproc_framebuffer = Framebuffer::new(&self.gl, resolution.0, resolution.1, true, false);
+        self.framebuffers[&self.postproc_framebuffer_id].cleanup(&*self.device);
+        let postproc_framebuffer = Framebuffer::new(&*self.device, resolution.0, resolution.1, true, false);
         self.postproc_framebuffer_id = postproc_framebuffer.gl_framebuffer;
         self.load_framebuffer(postproc_framebuffer);
     }
 
     // returns shader id
     pub fn load_shader(&mut self, vertex_path : &'static str, fragment_path : &'static str, texture_names: Vec<&str>) -> u32 {
-        let shader_program = ShaderProgram::new(&self.gl, vertex_path, fragment_path, texture_names);
+        let shader_program = ShaderProgram::new(&*self.device, vertex_path, fragment_path, texture_names);
         let id = shader_program.program;
         self.shaders.insert(id, shader_program);
         return id; 
@@ -165,13 +207,36 @@ impl GraphicsEngine {
 
     // returns (texture id, texture size)
     pub fn load_texture_from_file(&mut self, path: &str, ttype: TextureType) -> (u32, (i32, i32)) {
-        let texture = Texture::from_file(&self.gl, path, ttype);
+        let texture = Texture::from_file(&*self.device, path, ttype);
         let id = texture.gl_texture;
         let size = texture.size;
         self.textures.insert(id, texture);
+        self.texture_paths.insert(id, path.to_string());
         return (id, size);
     }
 
+    // returns (texture id, texture size) for an image file's bytes. source_path is the file they came from if there is one, so texture_path() can find it
+    pub fn load_texture_from_memory(&mut self, bytes: &[u8], ttype: TextureType, source_path: Option<&str>) -> Result<(u32, (i32, i32)), String> {
+        let texture = Texture::from_memory(&*self.device, bytes, ttype)?;
+        let id = texture.gl_texture;
+        let size = texture.size;
+        self.textures.insert(id, texture);
+        if let Some(path) = source_path {
+            self.texture_paths.insert(id, path.to_string());
+        }
+        return Ok((id, size));
+    }
+
+    // path the texture was loaded from with load_texture_from_file()
+    pub fn texture_path(&self, texture_id: u32) -> Option<&str> {
+        return self.texture_paths.get(&texture_id).map(|p| p.as_str());
+    }
+
+    // id of an already loaded texture, so the same image file doesn't get loaded twice
+    pub fn texture_id_from_path(&self, path: &str) -> Option<u32> {
+        return self.texture_paths.iter().find(|(_, p)| p.as_str() == path).map(|(id, _)| *id);
+    }
+
     fn load_framebuffer(&mut self, framebuffer: Framebuffer) {
         self.framebuffers.insert(framebuffer.gl_framebuffer, framebuffer);
     }
@@ -186,28 +251,24 @@ impl GraphicsEngine {
         self.update_camera_matrices();
 
         // Update the data on the gpu for objects that have moved/changed color/etc
-        let mut camera_pos = self.camera.transform.pos(); // for floating origin, the objects positions are offset by camera position on the cpu, so camera always at 0,0,0
-        if self.freecam_override_enabled {
-            camera_pos = self.freecam_transform.pos();
-        }
-        camera_pos *= -1;
+        let camera_pos = self.camera_offset();
 
         let start = Instant::now();
         for (_, obj_refcell) in self.renderable_gameobjects.iter_mut() {
             let obj = obj_refcell.borrow();
             let draw_id = obj.get_draw_id();
             let loc = self.object_drawing_data_locations[&draw_id];
-            self.pools.get(&loc.0).unwrap().get(&loc.1).unwrap().get(loc.2).unwrap().set_transform(loc.3, loc.4, &obj.transform().get_model(&camera_pos));
+            self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap().get_mut(loc.2).unwrap().set_transform(loc.3, loc.4, &obj.transform().get_model(&camera_pos));
             if obj.get_color_changed() {
-                self.pools.get(&loc.0).unwrap().get(&loc.1).unwrap().get(loc.2).unwrap().set_rgba(loc.3, loc.4, &obj.get_rgba());
+                self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap().get_mut(loc.2).unwrap().set_rgba(loc.3, loc.4, &obj.get_rgba());
             };
 
             if obj.get_texture_z_changed() {
-                self.pools.get(&loc.0).unwrap().get(&loc.1).unwrap().get(loc.2).unwrap().set_texture_z(loc.3, loc.4, &obj.get_texture_z());
+                self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap().get_mut(loc.2).unwrap().set_texture_z(loc.3, loc.4, &obj.get_texture_z());
             };
         }
         let elapsed = start.elapsed();
-        println!("Updating {} renderables took {:?} ({:?} per renderable)", self.renderable_gameobjects.len(), elapsed, elapsed/(self.renderable_gameobjects.len() as u32));
+        println!("Updating {} renderables took {:?} ({:?} per renderable)", self.renderable_gameobjects.len(), elapsed, elapsed/(self.renderable_gameobjects.len().max(1) as u32));
 
         // ensure that the gpu has the most up-to-date data 
         // unsafe {
@@ -217,16 +278,104 @@ impl GraphicsEngine {
         // }
     }
 
-    pub fn draw(&mut self) {
-        unsafe {
-            self.gl.Clear(GL_COLOR_BUFFER_BIT|GL_DEPTH_BUFFER_BIT);
-            self.gl.Enable(GL_DEPTH_TEST);
+    // for floating origin, the objects positions are offset by camera position on the cpu, so camera always at 0,0,0
+    fn camera_offset(&self) -> I64Vec3 {
+        let mut camera_pos = self.camera.transform.pos();
+        if self.freecam_override_enabled {
+            camera_pos = self.freecam_transform.pos();
         }
-        self.draw_to_framebuffer(&vec![self.world_shader_id], self.postproc_framebuffer_id, self.resolution);
-        unsafe {
-            self.gl.Clear(GL_COLOR_BUFFER_BIT|GL_DEPTH_BUFFER_BIT);
-            self.gl.Disable(GL_DEPTH_TEST);
+        return camera_pos * -1;
+    }
+
+    // moves and turns the camera to camera_entity's world Transform. the camera's rotation is a view rotation (see ROTATION in transform.rs),
+    // so it gets the inverse of the entity's, and the entity's scale is left out.
+    // call once per frame, before update(), or the view matrices will be built from last frame's camera position
+    pub fn sync_camera_entity(&mut self, world: &mut World) {
+        propagate_transforms(world);
+        if let Some(transform) = self.camera_entity.and_then(|entity| world.get::<Transform>(entity)) {
+            self.camera.transform = Transform::from_parts(transform.pos(), transform.rot_quat().conjugate(), vec3(1.0, 1.0, 1.0));
+        }
+    }
+
+    // gives any new entities with a RenderComponent somewhere to be drawn, then uploads every entity's transform and any color/texture changes.
+    // call once per frame, after update() and before draw()
+    pub fn update_entities(&mut self, world: &mut World) {
+        propagate_transforms(world);
+
+        let camera_pos = self.camera_offset();
+        world.for_each2_mut::<RenderComponent, Transform>(|_, render, transform| {
+            // switching level of detail means giving up the instance in the old level's meshpool and getting one in the new one's
+            let (lod, mesh_id) = self.select_entity_lod(render, transform, &camera_pos);
+            if lod != render.lod && render.draw_id.is_some() {
+                self.remove_renderable(render.draw_id.unwrap());
+                render.draw_id = None;
+                render.mark_changed();
+            }
+            render.lod = lod;
+            if render.draw_id.is_none() {
+                render.draw_id = Some(self.add_draw(mesh_id));
+            }
+        });
+        self.add_cached_renderables();
+
+        world.for_each2_mut::<RenderComponent, Transform>(|_, render, transform| {
+            let loc = self.object_drawing_data_locations[&render.draw_id.unwrap()];
+            let pool = self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap().get_mut(loc.2).unwrap();
+            pool.set_transform(loc.3, loc.4, &transform.get_model(&camera_pos));
+            let (color, texture_z) = render.take_changes();
+            if color.is_some() {
+                pool.set_rgba(loc.3, loc.4, &color.unwrap());
+            }
+            if texture_z.is_some() {
+                pool.set_texture_z(loc.3, loc.4, &texture_z.unwrap());
+            }
+        });
+
+        // skinned meshes' vertices were scaled into 1m^3 but their skeletons weren't, so the joint matrices get scaled the same way
+        if world.query::<SkeletalAnimator>().next().is_some() {
+            let meshes = LOADED_MESHES.lock().unwrap();
+            for (_, render, animator) in world.query2::<RenderComponent, SkeletalAnimator>() {
+                let mesh = match meshes.get(&render.mesh_id()) {
+                    Some(mesh) if mesh.skin.is_some() => mesh,
+                    _ => continue
+                };
+                let loc = self.object_drawing_data_locations[&render.draw_id.unwrap()];
+                let pool = self.pools.get(&loc.0).unwrap().get(&loc.1).unwrap().get(loc.2).unwrap();
+                let inverse_normalization = mesh.normalization.try_inverse().unwrap_or(glm::identity());
+                let matrices: Vec<glm::Mat4> = animator.joint_matrices().iter().map(|m| mesh.normalization * m * inverse_normalization).collect();
+                pool.set_joint_matrices(loc.3, loc.4, &matrices);
+            }
+        }
+    }
+
+    // (level of detail, mesh uuid to draw) for an entity, by how big its mesh's bounding sphere is on screen
+    fn select_entity_lod(&mut self, render: &RenderComponent, transform: &Transform, camera_pos: &I64Vec3) -> (usize, usize) {
+        let mesh_id = render.mesh_id();
+        if !self.mesh_lods.contains_key(&mesh_id) {
+            let meshes = LOADED_MESHES.lock().unwrap();
+            let mesh = &meshes[&mesh_id];
+            self.mesh_lods.insert(mesh_id, (mesh.bounding_radius(), mesh.lods.clone()));
         }
+        let (radius, lods) = &self.mesh_lods[&mesh_id];
+        if lods.is_empty() {
+            return (0, mesh_id);
+        }
+
+        let model = transform.get_model(camera_pos);
+        let scale = (0..3).map(|i| model.fixed_view::<3, 1>(0, i).norm()).fold(0.0, f32::max);
+        let distance = model.fixed_view::<3, 1>(0, 3).norm();
+        let world_radius = radius * scale;
+        let screen_size = if distance <= world_radius {f32::INFINITY} else {world_radius * self.camera.get_proj()[(1, 1)] / distance};
+        let lod = select_lod(lods, screen_size, render.lod, self.lod_hysteresis);
+        return (lod, if lod == 0 {mesh_id} else {lods[lod - 1].mesh_id});
+    }
+
+    pub fn draw(&mut self) {
+        self.device.clear(true, true);
+        self.device.set_depth_test(true);
+        self.draw_to_framebuffer(&vec![self.world_shader_id, self.skinned_shader_id], self.postproc_framebuffer_id, self.resolution);
+        self.device.clear(true, true);
+        self.device.set_depth_test(false);
         self.present_framebuffer(self.postproc_framebuffer_id, self.postproc_shader_id);
 
         // calling this somehow prevents weird artifacts created by moving objects (which thanks to floating origin, happens whenever camera moves)
@@ -235,30 +384,62 @@ impl GraphicsEngine {
         // them up with future commands. I think that before, there were a few draw commands left over at the end of each frame that weren't being processed until
         // more draw commands were given next frame, and thus those every frame a few objects were being drawn using the positions from last frame. 
         // Maybe. But it works now so idc.
-        unsafe {
-            self.gl.Flush(); 
+        self.device.flush();
+    }
 
-            
+    // (instances drawn, instances there are) in the last draw(), to see how much frustum culling saved
+    pub fn culling_stats(&self) -> (usize, usize) {
+        return (self.n_instances_drawn, self.n_instances);
+    }
+
+    // how much memory every meshpool is using, wasting and has free, as (shader id, texture id, stats). see MeshPool::stats()
+    pub fn meshpool_stats(&self) -> Vec<(GLuint, u32, MeshPoolStats)> {
+        let mut stats = Vec::new();
+        for (shader_id, pools_by_texture) in self.pools.iter() {
+            for (texture_id, pools) in pools_by_texture.iter() {
+                for pool in pools.iter() {
+                    stats.push((*shader_id, *texture_id, pool.stats()));
+                }
+            }
         }
+        stats.sort_by_key(|(shader_id, texture_id, _)| (*shader_id, *texture_id)); // instead of whatever order the hash maps are in
+        return stats;
+    }
+
+    // moves everything in every meshpool to the front of its buffers, so the memory removed meshes left behind is in one piece again.
+    // pools also do this by themselves when they'd otherwise have to grow, this is for doing it at a convenient time (like after unloading a level)
+    pub fn defragment_meshpools(&mut self) {
+        for pools_by_texture in self.pools.values_mut() {
+            for pools in pools_by_texture.values_mut() {
+                for pool in pools.iter_mut() {
+                    pool.defragment();
+                }
+            }
+        }
+    }
+
+    // what's on the screen after draw(), None if the device can't read it back (RecordingDevice)
+    pub fn screenshot(&self) -> Option<RgbaImage> {
+        return self.device.read_pixels(0, self.resolution.0, self.resolution.1);
     }
 
     pub fn cleanup(&mut self) {
         for (_, texture) in self.textures.iter() {
-            texture.cleanup(&self.gl);
+            texture.cleanup(&*self.device);
         }
 
         for (_, shader) in self.shaders.iter() {
-            shader.cleanup(&self.gl);
+            shader.cleanup(&*self.device);
         }
 
         for (_, framebuffer) in self.framebuffers.iter() {
-            framebuffer.cleanup(&self.gl);
+            framebuffer.cleanup(&*self.device);
         }
 
         for (_, hashmapthing) in self.pools.iter_mut() {
             for (_, vecofpools) in hashmapthing.iter_mut() {
                 for pool in vecofpools.iter_mut() {
-                    pool.cleanup(&self.gl);
+                    pool.cleanup(&*self.device);
                 }  
             }   
         }
@@ -268,33 +449,38 @@ impl GraphicsEngine {
     // Sets obj draw_id.
     pub fn add_renderable(&mut self, obj_refcell: Rc<RefCell<dyn Renderable>>)  {
         let mut obj = obj_refcell.borrow_mut();
-
-        let draw_id = self.last_draw_uuid;
-        self.last_draw_uuid += 1;
+        let draw_id = self.add_draw(obj.get_mesh_id());
         obj.set_draw_id(draw_id);
-
-        let mesh_id = obj.get_mesh_id();
-        
         drop(obj);
 
         self.renderable_gameobjects.insert(draw_id, obj_refcell); 
+    }
+
+    // reserves an instance of the mesh (added to a meshpool next update unless the mesh is dynamic) and returns its draw_id
+    fn add_draw(&mut self, mesh_id: usize) -> usize {
+        let draw_id = self.last_draw_uuid;
+        self.last_draw_uuid += 1;
 
         let mut instance_offset = 0;
-        let mesh = &LOADED_MESHES.lock().unwrap()[&mesh_id];
+        let dynamic = LOADED_MESHES.lock().unwrap()[&mesh_id].dynamic; // not kept locked, add_mesh_to_pool() locks it too
 
-        if mesh.dynamic { // can't instance a dynamic mesh since its vertices could change at any time, so no point caching (TODO SEE mesh.rs)
+        if dynamic { // can't instance a dynamic mesh since its vertices could change at any time, so no point caching (TODO SEE mesh.rs)
+            self.object_drawing_data_locations.insert(draw_id, (0, 0, 0, 0, 0)); // add_mesh_to_pool fills this out
+            self.dynamic_mesh_draws.entry(mesh_id).or_insert_with(Vec::new).push(draw_id);
             self.add_mesh_to_pool(mesh_id, 1, vec!(draw_id));
+            return draw_id;
         }
         else if !self.cached_meshes_to_add.contains_key(&mesh_id) {
-            self.cached_meshes_to_add.insert(mesh.uuid, (1, vec!(draw_id)));
+            self.cached_meshes_to_add.insert(mesh_id, (1, vec!(draw_id)));
         }
         else {
-            instance_offset = self.cached_meshes_to_add[&mesh.uuid].0;
-            self.cached_meshes_to_add.get_mut(&mesh.uuid).unwrap().0 += 1;
-            self.cached_meshes_to_add.get_mut(&mesh.uuid).unwrap().1.push(draw_id);
+            instance_offset = self.cached_meshes_to_add[&mesh_id].0;
+            self.cached_meshes_to_add.get_mut(&mesh_id).unwrap().0 += 1;
+            self.cached_meshes_to_add.get_mut(&mesh_id).unwrap().1.push(draw_id);
         }
 
         self.object_drawing_data_locations.insert(draw_id, (0, 0, 0, 0, instance_offset as i32)); // this gets filled out in add_mesh_to_pool, except for the instance offset
+        return draw_id;
     }
 
     fn add_cached_renderables(&mut self) {
@@ -306,6 +492,8 @@ impl GraphicsEngine {
 
     fn add_mesh_to_pool(&mut self, mesh_id: usize, count: u32, location_keys: Vec<usize>) {
         let mesh = &LOADED_MESHES.lock().unwrap()[&mesh_id];
+        let vertices = mesh.gpu_vertices(); // skinned meshes have joints/weights interleaved in
+        let skinned = mesh.skin.is_some();
 
         // if our shader/texture has not been used yet, then create meshpool storage for them
         if !self.pools.contains_key(&mesh.shader_id) {
@@ -315,106 +503,217 @@ impl GraphicsEngine {
             self.pools.get_mut(&mesh.shader_id).unwrap().insert(mesh.texture_id, Vec::new());
         }
 
-        //find smallest pool which will hold mesh
-        let mut found_pool = false;   
+        // pools hold meshes of any size, so there's one per vertex layout
         let vec: &mut Vec<MeshPool> = self.pools.get_mut(&mesh.shader_id).unwrap().get_mut(&mesh.texture_id).unwrap();
-        //println!("There are already {:?} pools here", vec.len());
-        let mut i = 0;
-        for pool in vec.iter_mut() {
-            if pool.mesh_vertex_nbytes as usize >= mesh.vertices.len() * 4 { // if the pool is big enough
-                if (pool.mesh_vertex_nbytes as usize) <= mesh.vertices.len() * 16 { // but also if the pool is more than 4 times as big then it needs to be, skip it
-                    found_pool = true;
-                    let slot = pool.add_mesh(&self.gl, mesh.uuid as i32, &mesh.vertices, &mesh.indices, count, mesh.dynamic);
-                    for k in location_keys.iter() {
-                        let loc = self.object_drawing_data_locations.get_mut(k).unwrap();
-                        loc.0 = mesh.shader_id;
-                        loc.1 = mesh.texture_id;
-                        loc.2 = i;
-                        loc.3 = slot.0;
-                        loc.4 += slot.1;
-                        //println!("Added stuff to location! {:?}, slot.1 was {}", loc, slot.1);
-                    }
-                    
-                    break;
-                }
+        let i = match vec.iter().position(|pool| pool.skinned == skinned) {
+            Some(i) => i,
+            None => {
+                // a new pool starts with about TARGET_MESHPOOL_BASE_SIZE bytes of vertices and of indices and a 16th of that for instances (joint matrices included), and grows if it needs more
+                let instance_nbytes = INSTANCE_NBYTES + if skinned {MAX_JOINTS * 64} else {0};
+                let (n_vertices, n_indices, n_instances) = (TARGET_MESHPOOL_BASE_SIZE as usize / (mesh.floats_per_vertex() * 4), TARGET_MESHPOOL_BASE_SIZE as usize / 4, TARGET_MESHPOOL_BASE_SIZE as usize / 16 / instance_nbytes);
+                vec.push(if skinned {
+                    MeshPool::new_skinned(&*self.device, n_vertices, n_indices, n_instances)
+                } else {
+                    MeshPool::new(&*self.device, n_vertices, n_indices, n_instances)
+                });
+                vec.len() - 1
             }
-            else {
-                //println!("skipping ")
+        };
+        let slot = vec[i].add_mesh(&*self.device, mesh.uuid as i32, &vertices, &mesh.indices, count, mesh.dynamic);
+        for k in location_keys.iter() {
+            let loc = self.object_drawing_data_locations.get_mut(k).unwrap();
+            loc.0 = mesh.shader_id;
+            loc.1 = mesh.texture_id;
+            loc.2 = i;
+            loc.3 = slot.0;
+            loc.4 += slot.1;
+            //println!("Added stuff to location! {:?}, slot.1 was {}", loc, slot.1);
+        }
+
+        for k in location_keys {
+            let loc = self.object_drawing_data_locations[&k];
+            let owners = self.instance_owners.entry((loc.0, loc.1, loc.2, loc.3)).or_insert_with(Vec::new);
+            if owners.len() <= loc.4 as usize {
+                owners.resize(loc.4 as usize + 1, usize::MAX);
             }
-            i += 1;
+            owners[loc.4 as usize] = k;
+        }
+    }
+
+    // Stops drawing the object with the given draw_id (from Renderable::get_draw_id() or RenderComponent::draw_id()), freeing its instance in its meshpool.
+    // O(1): the last instance of the same mesh gets moved into the freed instance's place.
+    // Gameobjects also need to be taken out of the SAS (SpatialAccelerationStructure::remove()) and the RigidBodyList if they were added to them;
+    // for entities, ecs::despawn() does all of it.
+    pub fn remove_renderable(&mut self, draw_id: usize) -> bool {
+        let loc = match self.object_drawing_data_locations.remove(&draw_id) {
+            Some(loc) => loc,
+            None => return false
+        };
+        self.renderable_gameobjects.remove(&draw_id);
+        for draw_ids in self.dynamic_mesh_draws.values_mut() {
+            draw_ids.retain(|id| *id != draw_id);
         }
 
-        // if no pool was found, create one
-        if !found_pool {
-            //println!("No available meshpool for mesh with {} vertices; creating new pool", mesh.vertices.len()/N_FLOATS_PER_VERTEX);
-            let mut pool_size: usize = N_FLOATS_PER_VERTEX * 4;
-            while pool_size < mesh.vertices.len() * 4 {
-                pool_size*=2;
+        // if it never made it into a meshpool it's still waiting in the cache
+        let mut cached = None;
+        for (mesh_id, (_, draw_ids)) in self.cached_meshes_to_add.iter() {
+            if draw_ids.get(loc.4 as usize) == Some(&draw_id) {
+                cached = Some(*mesh_id);
+                break;
             }
+        }
+        if cached.is_some() {
+            let (count, draw_ids) = self.cached_meshes_to_add.get_mut(&cached.unwrap()).unwrap();
+            draw_ids.swap_remove(loc.4 as usize);
+            *count -= 1;
+            if draw_ids.len() > loc.4 as usize {
+                self.object_drawing_data_locations.get_mut(&draw_ids[loc.4 as usize]).unwrap().4 = loc.4;
+            }
+            if *count == 0 {
+                self.cached_meshes_to_add.remove(&cached.unwrap());
+            }
+            return true;
+        }
 
-            // TODO: instance capacity should REALLY depend (for cubes, instance mcuh > meshes, while for big meshes its about equal)
-            let capacity = f32::ceil(TARGET_MESHPOOL_BASE_SIZE as f32/pool_size as f32) as isize;
-            // println!("Capacity {}", capacity);
-            let mut new_pool = MeshPool::new(&self.gl, capacity, capacity, pool_size as isize, pool_size as isize); // TODO: more intelligently set base capacity OR more intelliently resize pool
-            let slot = new_pool.add_mesh(&self.gl, mesh.uuid as i32, &mesh.vertices, &mesh.indices, count, mesh.dynamic);
-            self.pools.get_mut(&mesh.shader_id).unwrap().get_mut(&mesh.texture_id).unwrap().push(new_pool);
-            for k in location_keys {
-                let loc = self.object_drawing_data_locations.get_mut(&k).unwrap();
-                loc.0 = mesh.shader_id;
-                loc.1 = mesh.texture_id;
-                loc.2 = self.pools[&mesh.shader_id][&mesh.texture_id].len() - 1;
-                loc.3 = slot.0;
-                loc.4 += slot.1;
-                //println!("Added stuff to location! {:?}, slot.1 was {}", loc, slot.1);
+        let key = (loc.0, loc.1, loc.2, loc.3);
+        let moved = self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap()[loc.2].remove_instance(loc.3, loc.4);
+        let owners = self.instance_owners.get_mut(&key).unwrap();
+        if moved.is_some() {
+            let moved_owner = owners[moved.unwrap() as usize];
+            owners[loc.4 as usize] = moved_owner;
+            self.object_drawing_data_locations.get_mut(&moved_owner).unwrap().4 = loc.4;
+        }
+        owners.pop();
+        if owners.is_empty() {
+            self.instance_owners.remove(&key);
+        }
+        return true;
+    }
+
+    // Replaces the vertices and indices of a mesh made with dynamic = true, for everything drawing it. vertices are laid out like Mesh::vertices and in the units
+    // the mesh was made from; they get the same scaling the original ones got (see Mesh::normalization) instead of being scaled into 1m^3 again, so objects keep their size.
+    // if the mesh got bigger, its meshpools find room for it somewhere else in their buffers.
+    pub fn set_dynamic_mesh(&mut self, mesh_id: usize, mut vertices: Vec<f32>, indices: Vec<u32>) -> Result<(), MeshUpdateError> {
+        if vertices.len() % N_FLOATS_PER_VERTEX != 0 {
+            return Err(MeshUpdateError::PartialVertex(vertices.len()));
+        }
+        let n_vertices = vertices.len() / N_FLOATS_PER_VERTEX;
+        if let Some(index) = indices.iter().find(|i| **i as usize >= n_vertices) {
+            return Err(MeshUpdateError::IndexOutOfRange(*index, n_vertices));
+        }
+        let gpu_vertices = {
+            let mut meshes = LOADED_MESHES.lock().unwrap();
+            let mesh = dynamic_mesh(&mut meshes, mesh_id)?;
+            if mesh.skin.is_some() && n_vertices != mesh.vertices.len() / N_FLOATS_PER_VERTEX {
+                return Err(MeshUpdateError::SkinnedVertexCount(mesh.vertices.len() / N_FLOATS_PER_VERTEX, n_vertices));
             }
-            
+            mesh.normalize_vertices(&mut vertices);
+            mesh.vertices = vertices;
+            mesh.indices = indices.clone();
+            mesh.gpu_vertices().into_owned()
+        };
+        self.mesh_lods.remove(&mesh_id); // its bounding radius changed
+
+        for draw_id in self.dynamic_mesh_draws.get(&mesh_id).cloned().unwrap_or_default() {
+            let loc = self.object_drawing_data_locations[&draw_id];
+            self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap()[loc.2].update_mesh(&*self.device, loc.3, &gpu_vertices, &indices);
+        }
+        return Ok(());
+    }
+
+    // Overwrites some of a dynamic mesh's vertices, starting at first_vertex, without changing how many there are. same layout and units as set_dynamic_mesh()
+    pub fn update_dynamic_vertices(&mut self, mesh_id: usize, first_vertex: usize, vertices: &[f32]) -> Result<(), MeshUpdateError> {
+        if vertices.len() % N_FLOATS_PER_VERTEX != 0 {
+            return Err(MeshUpdateError::PartialVertex(vertices.len()));
         }
+        let (gpu_vertices, floats_per_vertex) = {
+            let mut meshes = LOADED_MESHES.lock().unwrap();
+            let mesh = dynamic_mesh(&mut meshes, mesh_id)?;
+            let (start, end) = (first_vertex * N_FLOATS_PER_VERTEX, first_vertex * N_FLOATS_PER_VERTEX + vertices.len());
+            if end > mesh.vertices.len() {
+                return Err(MeshUpdateError::OutOfRange(end / N_FLOATS_PER_VERTEX, mesh.vertices.len() / N_FLOATS_PER_VERTEX));
+            }
+            let mut vertices = vertices.to_vec();
+            mesh.normalize_vertices(&mut vertices);
+            mesh.vertices[start..end].copy_from_slice(&vertices);
+            (mesh.gpu_vertices().into_owned(), mesh.floats_per_vertex())
+        };
+        self.mesh_lods.remove(&mesh_id);
 
+        let changed = &gpu_vertices[first_vertex * floats_per_vertex..(first_vertex + vertices.len() / N_FLOATS_PER_VERTEX) * floats_per_vertex];
+        for draw_id in self.dynamic_mesh_draws.get(&mesh_id).cloned().unwrap_or_default() {
+            let loc = self.object_drawing_data_locations[&draw_id];
+            self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap()[loc.2].update_vertices(loc.3, first_vertex, changed);
+        }
+        return Ok(());
+    }
+
+    // Overwrites some of a dynamic mesh's indices, starting at first_index, without changing how many there are
+    pub fn update_dynamic_indices(&mut self, mesh_id: usize, first_index: usize, indices: &[u32]) -> Result<(), MeshUpdateError> {
+        {
+            let mut meshes = LOADED_MESHES.lock().unwrap();
+            let mesh = dynamic_mesh(&mut meshes, mesh_id)?;
+            if first_index + indices.len() > mesh.indices.len() {
+                return Err(MeshUpdateError::OutOfRange(first_index + indices.len(), mesh.indices.len()));
+            }
+            let n_vertices = mesh.vertices.len() / N_FLOATS_PER_VERTEX;
+            if let Some(index) = indices.iter().find(|i| **i as usize >= n_vertices) {
+                return Err(MeshUpdateError::IndexOutOfRange(*index, n_vertices));
+            }
+            mesh.indices[first_index..first_index + indices.len()].copy_from_slice(indices);
+        }
+
+        for draw_id in self.dynamic_mesh_draws.get(&mesh_id).cloned().unwrap_or_default() {
+            let loc = self.object_drawing_data_locations[&draw_id];
+            self.pools.get_mut(&loc.0).unwrap().get_mut(&loc.1).unwrap()[loc.2].update_indices(loc.3, first_index, indices);
+        }
+        return Ok(());
     }
 
     // Given the id of a framebuffer that covers the WHOLE screen, it will draw a quad with that framebuffer's color texture over the screen using the given shader 
     fn present_framebuffer(&self, buffer_id: u32, shader_id: u32) {
         let buffer = &self.framebuffers[&buffer_id];
 
-        unsafe {
-            self.shaders[&shader_id].r#use(&self.gl);
-            buffer.color.as_ref().unwrap().r#use(&self.gl, 0);
-            self.gl.BindVertexArray(self.screen_quad_vao);
-            self.gl.DrawArrays(GL_TRIANGLES, 0, 6);
-        }
+        self.shaders[&shader_id].r#use(&*self.device);
+        buffer.color.as_ref().unwrap().r#use(&*self.device, 0);
+        self.device.bind_vertex_array(self.screen_quad_vao);
+        self.device.draw_arrays(0, 6);
     }
 
     // draws everything associated with the given shader programs into the given framebuffer.
     // shaders should probably actually be compatible with the given framebuffer
     fn draw_to_framebuffer(&mut self, shader_ids: &Vec<GLuint>, buffer_id: u32, resolution: (u32, u32)) { 
-        unsafe {
-            self.gl.CullFace(GL_BACK);
-        }
+        self.device.set_backface_culling(true);
+        self.n_instances_drawn = 0;
+        self.n_instances = 0;
         let buffer = &self.framebuffers[&buffer_id];
-        buffer.begin_render(&self.gl);
+        buffer.begin_render(&*self.device);
         for id in shader_ids {
-            self.shaders[id].r#use(&self.gl);
+            self.shaders[id].r#use(&*self.device);
             if self.shaders[id].shadowmap_texture_index != -1 {
                 //println!("deploying shadowmap at loc {}", self.shaders.get_mut(id).unwrap().shadowmap_texture_index);
                 //self.shaders.get_mut(id).unwrap().matrix4x4(&"modelToLightSpace".to_string(), &self.spotlights[0].get_model_to_light_space(), false); // TODO: USE SSBO HERE SO WE CAN HAVE MORE THAN ONE LIGHT LOL
                 //self.shadowmap.depth.as_ref().unwrap().r#use(self.shaders.get_mut(id).unwrap().shadowmap_texture_index as u32);
             }
-            let map = &self.pools[&id];
+            let map = match self.pools.get(id) {
+                Some(map) => map,
+                None => continue // nothing uses this shader yet
+            };
             for (texture_id, vec) in map {
                 if *texture_id != 0 {
-                    self.textures[texture_id].r#use(&self.gl, 0); 
+                    self.textures[texture_id].r#use(&*self.device, 0); 
+                }
+                else {
+                    self.device.bind_texture(0, TextureKind::Tex2D, 0);
+                    self.device.bind_texture(0, TextureKind::Tex2DArray, 0);
                 }
-                else { unsafe {
-                    //println!("skipping texture binding here lol");
-                    self.gl.BindTexture(GL_TEXTURE_2D, 0);
-                    self.gl.BindTexture(GL_TEXTURE_2D_ARRAY, 0);
-                }}
                 for pool in vec {
-                    pool.draw(&self.gl); 
+                    self.n_instances_drawn += pool.draw(&*self.device, if self.frustum_culling {Some(&self.frustum)} else {None}); 
+                    self.n_instances += pool.n_instances();
                 }
             }
         }
-        buffer.finish_render(&self.gl, resolution.0, resolution.1)
+        buffer.finish_render(&*self.device, resolution.0, resolution.1)
     }
 
     fn update_camera_matrices(&mut self) {
@@ -422,19 +721,24 @@ impl GraphicsEngine {
         {
             self.camera.perspective(self.resolution.0 as f32/self.resolution.1 as f32, 70.0, 0.1, 4096.0);
         }
-        let mut cam_mat = self.camera.transform.get_model(&self.camera.transform.pos());
-
         self.update_freecam();
-        cam_mat = self.freecam_transform.get_model(&self.freecam_transform.pos());
+        let cam_mat = if self.freecam_override_enabled {
+            self.freecam_transform.get_model(&self.freecam_transform.pos())
+        }
+        else {
+            self.camera.transform.get_model(&self.camera.transform.pos())
+        };
+
+        self.frustum = Frustum::from_matrix(&(self.camera.get_proj() * cam_mat));
 
         for program in self.shaders.iter_mut() {
             if program.1.auto_cam {
                 //println!("autocamming {}", program.0);
-                program.1.matrix4x4(&self.gl, &String::from("camera"), &cam_mat, false);
+                program.1.matrix4x4(&*self.device, &String::from("camera"), &cam_mat, false);
             }
             if program.1.auto_proj && self.camera.proj_changed {
                 
-                program.1.matrix4x4(&self.gl, &String::from("proj"), self.camera.get_proj(), false);
+                program.1.matrix4x4(&*self.device, &String::from("proj"), self.camera.get_proj(), false);
             }
         }
     }
@@ -449,7 +753,7 @@ impl GraphicsEngine {
             self.freecam_transform.setpos(self.freecam_transform.pos() - (i64vec3_from_vec3(&(&(right/100.0 + forward/100.0 + up/100.0))) * self.freecam_speed));
             self.freecam_pitchyaw += INPUT.mouse_delta().yx() * 0.005;
             self.freecam_pitchyaw.x = self.freecam_pitchyaw.x.clamp(-89.0f32.to_radians(), 89.0f32.to_radians());
-            self.freecam_transform.setrotyxz(vec3(self.freecam_pitchyaw.x, self.freecam_pitchyaw.y, 0.0));
+            self.freecam_transform.set_euler(EulerAngles::radians(self.freecam_pitchyaw.x, self.freecam_pitchyaw.y, 0.0), EulerOrder::XYZ); // view rotation, so XYZ (see ROTATION in transform.rs)
             
             if INPUT.is_pressed(Key::A) || INPUT.is_pressed(Key::D) || INPUT.is_pressed(Key::S) || INPUT.is_pressed(Key::W) || INPUT.is_pressed(Key::Q) || INPUT.is_pressed(Key::E) {
                 self.freecam_speed += 1;
@@ -462,4 +766,12 @@ impl GraphicsEngine {
         }
     }
 
-}
\ No newline at end of file
+}
+
+fn dynamic_mesh(meshes: &mut HashMap<usize, Mesh>, mesh_id: usize) -> Result<&mut Mesh, MeshUpdateError> {
+    let mesh = meshes.get_mut(&mesh_id).ok_or(MeshUpdateError::NoSuchMesh(mesh_id))?;
+    if !mesh.dynamic {
+        return Err(MeshUpdateError::NotDynamic(mesh_id));
+    }
+    return Ok(mesh);
+}
@@ -0,0 +1,110 @@
+// Levels of detail: lower resolution copies of a mesh, made with simplify(), that GraphicsEngine::update_entities() swaps in for entities that are small on screen.
+// Each level is a normal Mesh in LOADED_MESHES with its own uuid, so it gets pooled and instanced like any other mesh.
+
+use crate::graphics::*;
+
+// how to make one level of detail
+#[derive(Clone, Copy, Debug)]
+pub struct LodLevel {
+    pub triangle_ratio: f32, // fraction of the full mesh's triangles to keep
+    pub screen_size: f32, // used once the mesh is smaller than this on screen (its bounding sphere's diameter as a fraction of the screen's height)
+}
+
+pub const DEFAULT_LOD_LEVELS: [LodLevel; 3] = [
+    LodLevel { triangle_ratio: 0.5, screen_size: 0.3 },
+    LodLevel { triangle_ratio: 0.25, screen_size: 0.12 },
+    LodLevel { triangle_ratio: 0.08, screen_size: 0.04 },
+];
+
+// one generated level of a mesh, see Mesh::lods
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshLod {
+    pub mesh_id: usize,
+    pub screen_size: f32,
+}
+
+// which level to draw something screen_size big (see LodLevel::screen_size) that's currently drawn at level current, where level 0 is the full mesh and level i is lods[i - 1].
+// with hysteresis (a fraction, like 0.1), the size has to get that much past a threshold before the level changes, so something sitting right on one doesn't flicker back and forth
+pub fn select_lod(lods: &[MeshLod], screen_size: f32, current: usize, hysteresis: f32) -> usize {
+    let mut level = current.min(lods.len());
+    while level < lods.len() && screen_size < lods[level].screen_size * (1.0 - hysteresis) {
+        level += 1;
+    }
+    while level > 0 && screen_size > lods[level - 1].screen_size * (1.0 + hysteresis) {
+        level -= 1;
+    }
+    return level;
+}
+
+impl Mesh {
+    // simplifies the mesh in LOADED_MESHES into the given levels (most detailed first), stores them in its lods and returns their mesh ids.
+    // skinned and dynamic meshes don't get any, since the simplified copies wouldn't follow their skeleton/vertex changes.
+    // call before anything that draws the mesh is added, GraphicsEngine only looks for a mesh's lods the first time it sees it
+    pub fn generate_lods(mesh_id: usize, levels: &[LodLevel]) -> Vec<usize> {
+        let mut meshes = LOADED_MESHES.lock().unwrap();
+        let base = &meshes[&mesh_id];
+        if base.skin.is_some() || base.dynamic {
+            return Vec::new();
+        }
+        let (texture_id, shader_id, original_size, normalization) = (base.texture_id, base.shader_id, base.original_size, base.normalization);
+        let n_triangles = base.indices.len() / 3;
+        let (mut vertices, mut indices) = (base.vertices.clone(), base.indices.clone());
+        let mut lods = Vec::new();
+        for level in levels {
+            // each level starts from the last one, which is quicker than simplifying the full mesh every time
+            (vertices, indices) = simplify(&vertices, &indices, (n_triangles as f32 * level.triangle_ratio) as usize);
+            let mut lod = Mesh {
+                vertices: vertices.clone(),
+                indices: indices.clone(),
+                texture_id,
+                shader_id,
+                uuid: LAST_MESH_UUID.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+                dynamic: false,
+                original_size, // already in range, and scaling it again would make it pop when it's swapped in
+                normalization,
+                source_path: None,
+                skin: None,
+                lods: Vec::new(),
+            };
+            lod.optimize(); // simplifying leaves the triangles in no useful order
+            lods.push(MeshLod { mesh_id: lod.uuid, screen_size: level.screen_size });
+            println!("Created level of detail with {} triangles.", indices.len() / 3);
+            meshes.insert(lod.uuid, lod);
+        }
+        meshes.get_mut(&mesh_id).unwrap().lods = lods.clone();
+        return lods.iter().map(|lod| lod.mesh_id).collect();
+    }
+
+    // from_obj() then generate_lods()
+    pub fn from_obj_with_lods(path: &str, texture_id: u32, shader_id: u32, levels: &[LodLevel]) -> Result<usize, ObjError> {
+        let mesh_id = Mesh::from_obj(path, texture_id, shader_id)?;
+        Mesh::generate_lods(mesh_id, levels);
+        return Ok(mesh_id);
+    }
+
+    // distance from the origin to the farthest vertex, for estimating how big the mesh is on screen
+    pub fn bounding_radius(&self) -> f32 {
+        return self.vertices.chunks_exact(N_FLOATS_PER_VERTEX).map(|v| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()).fold(0.0, f32::max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lod_selection_has_hysteresis() {
+        let lods = [MeshLod { mesh_id: 1, screen_size: 0.3 }, MeshLod { mesh_id: 2, screen_size: 0.1 }];
+        let cases = [
+            // (screen size, current level, expected level)
+            (0.5, 0, 0), (0.2, 0, 1), (0.01, 0, 2), (0.5, 2, 0),
+            (0.28, 0, 0), // just under the threshold, but not by enough
+            (0.32, 1, 1), // just over it going the other way
+            (0.26, 0, 1), (0.34, 1, 0),
+        ];
+        for (screen_size, current, expected) in cases {
+            let level = select_lod(&lods, screen_size, current, 0.1);
+            assert!(level == expected, "something {} of the screen big at level {} should be drawn at level {}, but select_lod() said {}", screen_size, current, expected, level);
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use nalgebra_glm::{Vec3, vec3};
+use nalgebra_glm::{Vec3, vec3, Mat4};
+
+use crate::graphics::{MeshLod, bounding_box};
+use crate::transform::{Transform, i64vec3_from_vec3};
 
 // WAIT: WHY DON'T WE INSTANCE DYNAMIC MESHES? IF SOMEONE WANTS TO MODIFY TWO CURRENTLY IDENTICAL CUBES IN DIFFERENT WAYS, THEY SHOULD JUST CLONE THE MESHES, RIGHT?
 // TODO: GET THAT WORKING
@@ -11,70 +14,82 @@ pub struct Mesh {
     pub shader_id: u32,   // todo: need method to do that
 
     pub uuid: usize, // if meshmaster knows two non-dynamic meshes are equuivalent, it will try to instance them for optimization
-    pub dynamic: bool,
+    pub dynamic: bool, // never instanced, so its vertices can be changed after it's drawn (GraphicsEngine::set_dynamic_mesh() and co.)
 
-    pub original_size: Vec3 // Collision detection relies on meshes being 1m^3 and their size being changed solely through the scale property of transform
+    pub original_size: Vec3, // Collision detection relies on meshes being 1m^3 and their size being changed solely through the scale property of transform
                             // Thus, when a mesh is made its vertex positions are scaled into the range -0.5 to 0.5
                             // To make your mesh the right size, you can set its scale to this automatically set property
+    pub normalization: Mat4, // what scale_vertices_into_range() did to the vertices, its inverse puts them back where they were in the file
+
+    pub source_path: Option<String>, // file the mesh was loaded from, if any, so scenes can refer to it when saved
+
+    pub skin: Option<Skin>, // only for meshes animated by a skeleton (see animation::SkeletalAnimator)
+
+    pub lods: Vec<MeshLod>, // lower detail versions, most detailed first, made by Mesh::generate_lods() (see lod.rs)
+}
+
+// which joints move each vertex of a skinned mesh, and how much. stored next to the vertices instead of in them so everything that reads Mesh::vertices can keep assuming N_FLOATS_PER_VERTEX
+pub struct Skin {
+    pub joints: Vec<[u16; 4]>, // one per vertex, indices into the skeleton's joints
+    pub weights: Vec<[f32; 4]>, // one per vertex, should add up to 1. unused joints should have weight 0
+    // joint matrices are in the mesh's original units, so the gpu gets Mesh::normalization * joint * normalization^-1
+}
+
+impl Skin {
+    pub fn clone(&self) -> Skin {
+        return Skin { joints: self.joints.clone(), weights: self.weights.clone() };
+    }
 }
 
 pub const N_FLOATS_PER_VERTEX: usize = 8; 
+pub const N_FLOATS_PER_SKINNED_VERTEX: usize = N_FLOATS_PER_VERTEX + 4 + 4; // plus joint indices and weights
 pub const N_VERTEX_ATTRIBS: usize = 3;
 
-static LAST_MESH_UUID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+pub(crate) static LAST_MESH_UUID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
 // TODO: if locking the mutex slows GraphicsEngine::add_renderable(), use unsyncronized version of stuff
 pub static LOADED_MESHES: once_cell::sync::Lazy<std::sync::Mutex<std::collections::HashMap<usize, Mesh>>> = once_cell::sync::Lazy::new(|| {std::sync::Mutex::new(std::collections::HashMap::new())}); // key is mesh uuid, value is mesh
 
-// returns value to put into original size
-// makes all vertex coords in range -0.5 to 0.5
-fn scale_vertices_into_range(vertices: &mut Vec<f32>) -> Vec3 {
-    let (mut minx, mut miny, mut minz, mut maxx, mut maxy, mut maxz, mut i) = (0.0f32, 0.0f32, 0.0f32, 0.0f32, 0.0f32, 0.0f32, 0);
-
-    // figure out how big mesh is so we know how much to divide positions by
-    for v in vertices.iter_mut() {
-        if i % N_FLOATS_PER_VERTEX == 0 { // x pos
-            minx = minx.min(*v);
-            maxx = maxx.max(*v);
-        }
-        else if i % N_FLOATS_PER_VERTEX == 1 { // y pos
-            miny = miny.min(*v);
-            maxy =maxy.max(*v);
+// returns value to put into original size, and the matrix that does the same thing to a point as this did to the vertices
+// makes all vertex coords in range -0.5 to 0.5, each axis scaled by its own size. an axis the mesh is flat along (like a plane's y) is only centered,
+// and its original size is 1 so that scaling by original_size still gives the mesh back
+fn scale_vertices_into_range(vertices: &mut Vec<f32>) -> (Vec3, Mat4) {
+    let bounds = match bounding_box(vertices, N_FLOATS_PER_VERTEX) {
+        Some(bounds) => bounds,
+        None => return (vec3(1.0, 1.0, 1.0), Mat4::identity())
+    };
+    let size = bounds.size().map(|s| if s > 0.0 {s} else {1.0});
+    let center = bounds.center();
+    for vertex in vertices.chunks_exact_mut(N_FLOATS_PER_VERTEX) {
+        for axis in 0..3 {
+            vertex[axis] = (vertex[axis] - center[axis]) / size[axis];
         }
-        else if i % N_FLOATS_PER_VERTEX == 2 { // z pos
-            minz = minz.min(*v);
-            maxz = maxz.max(*v);
-        }
-
-        i+=1;
-        if i == 8 {i = 0}
     }
+    let normalization = glm::scaling(&size.map(|s| 1.0 / s)) * glm::translation(&-center);
+    return (size, normalization);
+}
 
-    
-
-    i = 0;
-    for v in vertices {
-        if i % N_FLOATS_PER_VERTEX == 0 { // x pos
-            *v = 1.0*(*v-minx)/(maxx-minx) - 0.5; // i don't really know how this bit works i got it from stack overflow and modified it
-        }
-        else if i % N_FLOATS_PER_VERTEX == 1 { // y pos
-            *v = 1.0*(*v-minx)/(maxx-minx) - 0.5;
-        }
-        else if i % N_FLOATS_PER_VERTEX == 2 { // z pos
-            *v = 1.0*(*v-minx)/(maxx-minx) - 0.5;
-        }
-
-        i+=1;
-        if i == 8 {i = 0}
+// splits vertices in a skinned mesh's gpu layout (see Mesh::gpu_vertices()) into normal vertices, joint indices and weights
+pub fn split_skinned_vertices(gpu_vertices: &[f32]) -> (Vec<f32>, Vec<[u16; 4]>, Vec<[f32; 4]>) {
+    let n_vertices = gpu_vertices.len()/N_FLOATS_PER_SKINNED_VERTEX;
+    let (mut vertices, mut joints, mut weights) = (Vec::with_capacity(n_vertices * N_FLOATS_PER_VERTEX), Vec::with_capacity(n_vertices), Vec::with_capacity(n_vertices));
+    for vertex in gpu_vertices.chunks_exact(N_FLOATS_PER_SKINNED_VERTEX) {
+        vertices.extend_from_slice(&vertex[0..N_FLOATS_PER_VERTEX]);
+        joints.push([0, 1, 2, 3].map(|k| vertex[N_FLOATS_PER_VERTEX + k] as u16));
+        weights.push([0, 1, 2, 3].map(|k| vertex[N_FLOATS_PER_VERTEX + 4 + k]));
     }
+    return (vertices, joints, weights);
+}
 
-    let size = vec3((maxx-minx).abs(), (maxy-miny).abs(), (maxz-minz).abs());
-    return size;
+// the transform that undoes a mesh's normalization, ie. puts its vertices back where they were in the file. for parenting a mesh under whatever its file placed it relative to
+pub fn normalization_offset(normalization: &Mat4) -> Transform {
+    let inverse = normalization.try_inverse().unwrap_or(glm::identity());
+    return Transform::from_parts(i64vec3_from_vec3(&inverse.fixed_view::<3, 1>(0, 3).into_owned()), glm::quat_identity(), vec3(inverse[(0, 0)], inverse[(1, 1)], inverse[(2, 2)]));
 }
 
 impl Mesh {
     pub fn from_vertices(mut vertices: Vec<f32>, indices: Vec<u32>, texture_id: u32, shader_id: u32, dynamic: bool) -> Mesh {
-        let og_size = scale_vertices_into_range(&mut vertices);
+        let (og_size, normalization) = scale_vertices_into_range(&mut vertices);
         return Mesh {
             vertices: vertices,
             indices: indices,
@@ -82,52 +97,71 @@ impl Mesh {
             shader_id: shader_id,
             uuid: LAST_MESH_UUID.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
             dynamic: dynamic,
-            original_size: og_size
+            original_size: og_size,
+            normalization,
+            source_path: None,
+            skin: None,
+            lods: Vec::new(),
+        }
+    }
+
+    // joints/weights have one entry per vertex (vertices.len()/N_FLOATS_PER_VERTEX). vertices should be in the same units as the skeleton the mesh was bound to.
+    // shader_id should be GraphicsEngine::skinned_shader_id, anything else will draw it in its bind pose
+    pub fn skinned(mut vertices: Vec<f32>, indices: Vec<u32>, joints: Vec<[u16; 4]>, weights: Vec<[f32; 4]>, texture_id: u32, shader_id: u32) -> Mesh {
+        let n_vertices = vertices.len()/N_FLOATS_PER_VERTEX;
+        assert!(joints.len() == n_vertices && weights.len() == n_vertices, "Skinned mesh has {} vertices but {} joint indices and {} weights", n_vertices, joints.len(), weights.len());
+        let (og_size, normalization) = scale_vertices_into_range(&mut vertices);
+        return Mesh {
+            vertices: vertices,
+            indices: indices,
+            texture_id: texture_id,
+            shader_id: shader_id,
+            uuid: LAST_MESH_UUID.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+            dynamic: false,
+            original_size: og_size,
+            normalization,
+            source_path: None,
+            skin: Some(Skin { joints, weights }),
+            lods: Vec::new(),
         }
     }
 
     pub fn clone(&self) -> Mesh {
-        return Mesh { vertices: self.vertices.clone(), indices: self.indices.clone(), texture_id: self.texture_id, shader_id: self.shader_id, uuid: self.uuid, dynamic: self.dynamic, original_size: self.original_size }
+        return Mesh { vertices: self.vertices.clone(), indices: self.indices.clone(), texture_id: self.texture_id, shader_id: self.shader_id, uuid: self.uuid, dynamic: self.dynamic, original_size: self.original_size, normalization: self.normalization, source_path: self.source_path.clone(), skin: self.skin.as_ref().map(|s| s.clone()), lods: self.lods.clone() }
     }
 
-    // returns mesh uuid, mesh itself can be accessed as needed (like if it's a dynamic mesh) by using uuid to index into LOADED_MESHES
-    pub fn from_obj(path: &str, textureId: u32, shaderId: u32) -> usize {
-        let options = tobj::LoadOptions {single_index: true, triangulate: true, ignore_points: true, ignore_lines: true};
-        let (models, _materials) = tobj::load_obj(path, &options).expect(&("Failed to load OBJ file at path ".to_owned() + path));
-        let model = &models[0];
-        
-        // TODO: THIS IS PROBABLY SLOW
-        // this exists because obj file format be like POSPOSPOSPOSPOS NORMALNORMALNORMAL TEXTURETEXTURETEXTURE and we be like POSNORMALTEXTURE POSNORMALTEXTURE
-        let mut verts = Vec::new();
-        let mut vert_index = 0;
-        let mut pos_index = 0;
-        let mut normal_index = 0;
-        let mut tex_index = 0; 
-        let mut vert_attrib = 0;
-        let total = model.mesh.positions.len() * N_FLOATS_PER_VERTEX / N_VERTEX_ATTRIBS;
-        while vert_index < total {
-            if vert_attrib == N_FLOATS_PER_VERTEX {vert_attrib = 0;}
-            if vert_attrib < 3 {verts.push(model.mesh.positions[pos_index]); pos_index += 1;}
-            else if vert_attrib < 6 {verts.push(model.mesh.normals[normal_index]); normal_index += 1;}
-            else if vert_attrib < 8 {verts.push(model.mesh.texcoords[tex_index]); tex_index += 1;}
-            //else {verts.push(textureZ);}
-            vert_index += 1;
-            vert_attrib += 1;
-        }
+    pub fn floats_per_vertex(&self) -> usize {
+        return if self.skin.is_some() {N_FLOATS_PER_SKINNED_VERTEX} else {N_FLOATS_PER_VERTEX};
+    }
 
-        println!("Created mesh with {} vertices.", verts.len()/N_FLOATS_PER_VERTEX);
-        let og_size = scale_vertices_into_range(&mut verts);
-        let m = Mesh {
-            vertices: verts,
-            indices: model.mesh.indices.clone(),
-            texture_id: textureId,
-            shader_id: shaderId,
-            uuid: LAST_MESH_UUID.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
-            dynamic: false,
-            original_size: og_size
+    // vertices the way a meshpool wants them: as is for normal meshes, with joint indices and weights stuck on the end of each vertex for skinned ones
+    pub fn gpu_vertices(&self) -> std::borrow::Cow<'_, Vec<f32>> {
+        let skin = match &self.skin {
+            Some(skin) => skin,
+            None => return std::borrow::Cow::Borrowed(&self.vertices)
         };
-        let uuid = m.uuid;
-        LOADED_MESHES.lock().unwrap().insert(uuid, m);
-        return uuid;
+        let mut verts = Vec::with_capacity(self.vertices.len()/N_FLOATS_PER_VERTEX * N_FLOATS_PER_SKINNED_VERTEX);
+        for (i, vertex) in self.vertices.chunks_exact(N_FLOATS_PER_VERTEX).enumerate() {
+            verts.extend_from_slice(vertex);
+            verts.extend(skin.joints[i].iter().map(|j| *j as f32));
+            verts.extend_from_slice(&skin.weights[i]);
+        }
+        return std::borrow::Cow::Owned(verts);
+    }
+
+    // the other way around from gpu_vertices(), for putting vertices back after they've been processed in that layout
+    pub fn set_gpu_vertices(&mut self, gpu_vertices: Vec<f32>) {
+        match &mut self.skin {
+            Some(skin) => (self.vertices, skin.joints, skin.weights) = split_skinned_vertices(&gpu_vertices),
+            None => self.vertices = gpu_vertices
+        }
+    }
+
+    // does to vertices (N_FLOATS_PER_VERTEX each, in the units the mesh was made from) what making the mesh did to its vertices, so they line up with them
+    pub fn normalize_vertices(&self, vertices: &mut [f32]) {
+        for vertex in vertices.chunks_exact_mut(N_FLOATS_PER_VERTEX) {
+            let position = self.normalization * glm::vec4(vertex[0], vertex[1], vertex[2], 1.0);
+            vertex[0..3].copy_from_slice(&[position.x, position.y, position.z]);
+        }
     }
 }
\ No newline at end of file
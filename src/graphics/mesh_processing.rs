@@ -0,0 +1,549 @@
+// CPU side mesh processing on vertex/index arrays like Mesh's: welding, making up normals, uvs and tangents, reordering triangles and vertices for the gpu, and bounds.
+// Everything takes floats_per_vertex and only looks at the first N_FLOATS_PER_VERTEX floats of each vertex (position, normal, uv), so skinned meshes can go through
+// it in their gpu layout (see Mesh::gpu_vertices()) and keep their joints and weights with the right vertices.
+// prepare_imported_mesh() is the pipeline every importer runs its meshes through.
+
+use std::collections::HashMap;
+
+use glm::{Vec3, vec3};
+
+use crate::graphics::*;
+
+// what normals meshes without any get
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GeneratedNormals {
+    Smooth, // averaged over the triangles around each position, for curved surfaces
+    Flat, // every triangle gets its own vertices, for hard edges
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn size(&self) -> Vec3 {
+        return self.max - self.min;
+    }
+
+    pub fn center(&self) -> Vec3 {
+        return (self.min + self.max) / 2.0;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+const VERTEX_CACHE_SIZE: usize = 32; // what optimize_vertex_cache() assumes, most gpus' post transform caches act about this big
+const OVERDRAW_CACHE_SIZE: usize = 16; // for finding where optimize_overdraw() can cut the triangles into clusters without losing much cache use
+
+fn position(vertices: &[f32], floats_per_vertex: usize, i: usize) -> Vec3 {
+    return vec3(vertices[i * floats_per_vertex], vertices[i * floats_per_vertex + 1], vertices[i * floats_per_vertex + 2]);
+}
+
+// None if there aren't any vertices
+pub fn bounding_box(vertices: &[f32], floats_per_vertex: usize) -> Option<Aabb> {
+    let n_vertices = vertices.len() / floats_per_vertex;
+    if n_vertices == 0 {
+        return None;
+    }
+    let mut bounds = Aabb { min: position(vertices, floats_per_vertex, 0), max: position(vertices, floats_per_vertex, 0) };
+    for i in 1..n_vertices {
+        let p = position(vertices, floats_per_vertex, i);
+        bounds.min = bounds.min.inf(&p);
+        bounds.max = bounds.max.sup(&p);
+    }
+    return Some(bounds);
+}
+
+// Ritter's sphere, which is within a few percent of the smallest one, or the box's sphere if that happens to be smaller. None if there aren't any vertices
+pub fn bounding_sphere(vertices: &[f32], floats_per_vertex: usize) -> Option<BoundingSphere> {
+    let bounds = bounding_box(vertices, floats_per_vertex)?;
+    let n_vertices = vertices.len() / floats_per_vertex;
+    let farthest_from = |from: Vec3| (0..n_vertices).map(|i| position(vertices, floats_per_vertex, i)).fold(from, |best, p| if (p - from).norm() > (best - from).norm() {p} else {best});
+
+    // start with the sphere between two far apart points, then grow it just enough to fit every point outside it
+    let a = farthest_from(position(vertices, floats_per_vertex, 0));
+    let b = farthest_from(a);
+    let mut sphere = BoundingSphere { center: (a + b) / 2.0, radius: (b - a).norm() / 2.0 };
+    for i in 0..n_vertices {
+        let p = position(vertices, floats_per_vertex, i);
+        let distance = (p - sphere.center).norm();
+        if distance > sphere.radius {
+            let radius = (sphere.radius + distance) / 2.0;
+            sphere.center += (p - sphere.center) * ((radius - sphere.radius) / distance);
+            sphere.radius = radius;
+        }
+    }
+
+    let box_center = bounds.center();
+    let box_radius = (0..n_vertices).map(|i| (position(vertices, floats_per_vertex, i) - box_center).norm()).fold(0.0, f32::max);
+    if box_radius < sphere.radius {
+        return Some(BoundingSphere { center: box_center, radius: box_radius });
+    }
+    return Some(sphere);
+}
+
+// merges vertices that are the same (every float within tolerance, snapped to a grid that size, so 0 only merges exact copies), drops vertices nothing uses
+// and triangles that end up with two of the same corner. returns the new vertices and indices
+pub fn weld_vertices(vertices: &[f32], indices: &[u32], floats_per_vertex: usize, tolerance: f32) -> (Vec<f32>, Vec<u32>) {
+    let key = |vertex: &[f32]| -> Vec<i64> {
+        return vertex.iter().map(|f| if tolerance > 0.0 {(f / tolerance).round() as i64} else {(f + 0.0).to_bits() as i64}).collect(); // + 0.0 so -0.0 is 0.0
+    };
+    let mut welded_vertices = Vec::new();
+    let mut new_index: HashMap<Vec<i64>, u32> = HashMap::new();
+    let mut welded_indices = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let corners = [triangle[0], triangle[1], triangle[2]].map(|i| {
+            let vertex = &vertices[i as usize * floats_per_vertex..(i as usize + 1) * floats_per_vertex];
+            return *new_index.entry(key(vertex)).or_insert_with(|| {
+                welded_vertices.extend_from_slice(vertex);
+                return (welded_vertices.len() / floats_per_vertex - 1) as u32;
+            });
+        });
+        if corners[0] != corners[1] && corners[1] != corners[2] && corners[2] != corners[0] {
+            welded_indices.extend_from_slice(&corners);
+        }
+    }
+    return (welded_vertices, welded_indices);
+}
+
+// sets every vertex's normal to the average of the triangles around its position, weighted by their area, so uv seams don't show. for files that don't come with normals
+pub fn generate_smooth_normals(vertices: &mut [f32], indices: &[u32], floats_per_vertex: usize) {
+    let position_key = |vertices: &[f32], i: usize| [0, 1, 2].map(|axis| (vertices[i * floats_per_vertex + axis] + 0.0).to_bits());
+    let mut normals: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| position(vertices, floats_per_vertex, triangle[k] as usize));
+        let normal = (b - a).cross(&(c - a)); // length is twice the area
+        for i in triangle {
+            *normals.entry(position_key(vertices, *i as usize)).or_insert(vec3(0.0, 0.0, 0.0)) += normal;
+        }
+    }
+    for i in 0..vertices.len() / floats_per_vertex {
+        let normal = normals.get(&position_key(vertices, i)).copied().unwrap_or(vec3(0.0, 0.0, 0.0));
+        let normal = if normal.norm() > 0.0 {normal.normalize()} else {vec3(0.0, 1.0, 0.0)};
+        vertices[i * floats_per_vertex + 3..i * floats_per_vertex + 6].copy_from_slice(normal.as_slice());
+    }
+}
+
+// gives every triangle its own 3 vertices with the triangle's normal, so edges stay sharp. returns the new vertices and indices, new vertex i is a copy of old vertex indices[i]
+pub fn generate_flat_normals(vertices: &[f32], indices: &[u32], floats_per_vertex: usize) -> (Vec<f32>, Vec<u32>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len() * floats_per_vertex);
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| position(vertices, floats_per_vertex, triangle[k] as usize));
+        let normal = (b - a).cross(&(c - a));
+        let normal = if normal.norm() > 0.0 {normal.normalize()} else {vec3(0.0, 1.0, 0.0)};
+        for i in triangle {
+            let start = flat_vertices.len();
+            flat_vertices.extend_from_slice(&vertices[*i as usize * floats_per_vertex..(*i as usize + 1) * floats_per_vertex]);
+            flat_vertices[start + 3..start + 6].copy_from_slice(normal.as_slice());
+        }
+    }
+    return (flat_vertices, (0..indices.len() as u32).collect());
+}
+
+// texture coordinates for files that don't have any: each vertex is projected onto the axis plane its normal faces most, one texture repeat per unit.
+// call after the normals are set
+pub fn generate_box_uvs(vertices: &mut [f32], floats_per_vertex: usize) {
+    for vertex in vertices.chunks_exact_mut(floats_per_vertex) {
+        let (nx, ny, nz) = (vertex[3].abs(), vertex[4].abs(), vertex[5].abs());
+        let (u, v) = if nx >= ny && nx >= nz {(vertex[2], vertex[1])} else if ny >= nz {(vertex[0], vertex[2])} else {(vertex[0], vertex[1])};
+        vertex[6] = u;
+        vertex[7] = v;
+    }
+}
+
+// a tangent (xyz) and handedness (w) per vertex for normal mapping, where the bitangent is w * cross(normal, tangent). done the way MikkTSpace does it:
+// each triangle's tangent and bitangent come from which way its uvs run, are added to its corners weighted by the corner's angle,
+// and then each vertex's tangent is made perpendicular to its normal. vertices with no usable uvs get any tangent perpendicular to their normal
+pub fn generate_tangents(vertices: &[f32], indices: &[u32], floats_per_vertex: usize) -> Vec<[f32; 4]> {
+    let n_vertices = vertices.len() / floats_per_vertex;
+    let uv = |i: usize| glm::vec2(vertices[i * floats_per_vertex + 6], vertices[i * floats_per_vertex + 7]);
+    let mut tangents = vec![vec3(0.0, 0.0, 0.0); n_vertices];
+    let mut bitangents = vec![vec3(0.0, 0.0, 0.0); n_vertices];
+    for triangle in indices.chunks_exact(3) {
+        let corners = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let [p0, p1, p2] = corners.map(|i| position(vertices, floats_per_vertex, i));
+        let [t0, t1, t2] = corners.map(uv);
+        let (e1, e2, d1, d2) = (p1 - p0, p2 - p0, t1 - t0, t2 - t0);
+        let determinant = d1.x * d2.y - d2.x * d1.y;
+        if determinant.abs() < 1e-12 {
+            continue;
+        }
+        let tangent = (e1 * d2.y - e2 * d1.y) / determinant;
+        let bitangent = (e2 * d1.x - e1 * d2.x) / determinant;
+        if tangent.norm() == 0.0 || bitangent.norm() == 0.0 {
+            continue;
+        }
+        let (tangent, bitangent) = (tangent.normalize(), bitangent.normalize());
+        let p = [p0, p1, p2];
+        for k in 0..3 {
+            let (to_next, to_previous) = (p[(k + 1) % 3] - p[k], p[(k + 2) % 3] - p[k]);
+            if to_next.norm() == 0.0 || to_previous.norm() == 0.0 {
+                continue;
+            }
+            let angle = to_next.normalize().dot(&to_previous.normalize()).clamp(-1.0, 1.0).acos();
+            tangents[corners[k]] += tangent * angle;
+            bitangents[corners[k]] += bitangent * angle;
+        }
+    }
+
+    let mut result = Vec::with_capacity(n_vertices);
+    for i in 0..n_vertices {
+        let normal = vec3(vertices[i * floats_per_vertex + 3], vertices[i * floats_per_vertex + 4], vertices[i * floats_per_vertex + 5]);
+        let mut tangent = tangents[i] - normal * normal.dot(&tangents[i]);
+        if tangent.norm() < 1e-6 {
+            // anything perpendicular to the normal will do
+            let other = if normal.x.abs() < 0.9 {vec3(1.0, 0.0, 0.0)} else {vec3(0.0, 1.0, 0.0)};
+            tangent = other - normal * normal.dot(&other);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(&tangent).dot(&bitangents[i]) < 0.0 {-1.0} else {1.0};
+        result.push([tangent.x, tangent.y, tangent.z, handedness]);
+    }
+    return result;
+}
+
+// how many vertices a gpu with a first in first out post transform cache of cache_size vertices has to run the vertex shader on per triangle,
+// 3 is the worst, 0.5 is about the best a big mesh can get
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+    let mut cache: std::collections::VecDeque<u32> = std::collections::VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for index in indices {
+        if !cache.contains(index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(*index);
+        }
+    }
+    return misses as f32 / (indices.len() / 3) as f32;
+}
+
+// how much Forsyth's algorithm wants to use a vertex next: more if it's near the front of the cache, and more if it has few triangles left so it doesn't get left alone
+fn vertex_cache_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        Some(position) if position < 3 => 0.75, // used by the last triangle, so reusing it right away doesn't help as much as it seems
+        Some(position) => (1.0 - (position - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0
+    };
+    return cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5);
+}
+
+// reorders triangles so their vertices are more likely to still be in the gpu's post transform cache (Tom Forsyth's linear speed vertex cache optimisation)
+pub fn optimize_vertex_cache(indices: &[u32], n_vertices: usize) -> Vec<u32> {
+    let n_triangles = indices.len() / 3;
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); n_vertices];
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        for i in triangle {
+            vertex_triangles[*i as usize].push(t);
+        }
+    }
+    let mut remaining: Vec<usize> = vertex_triangles.iter().map(|triangles| triangles.len()).collect();
+    let mut vertex_scores: Vec<f32> = remaining.iter().map(|r| vertex_cache_score(None, *r)).collect();
+    let triangle_score = |t: usize, vertex_scores: &[f32]| indices[t * 3..t * 3 + 3].iter().map(|i| vertex_scores[*i as usize]).sum::<f32>();
+    let mut triangle_scores: Vec<f32> = (0..n_triangles).map(|t| triangle_score(t, &vertex_scores)).collect();
+    let mut emitted = vec![false; n_triangles];
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut optimized = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0; // for when nothing in the cache has triangles left
+
+    for _ in 0..n_triangles {
+        // the best triangle using a vertex in the cache, or failing that the first one that's left
+        let mut best = cache.iter().flat_map(|v| vertex_triangles[*v as usize].iter()).filter(|t| !emitted[**t]).copied()
+            .fold(None, |best: Option<usize>, t| if best.is_none_or(|b| triangle_scores[t] > triangle_scores[b]) {Some(t)} else {best});
+        if best.is_none() {
+            while emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            best = Some(next_unemitted);
+        }
+        let best = best.unwrap();
+        emitted[best] = true;
+        let triangle = &indices[best * 3..best * 3 + 3];
+        optimized.extend_from_slice(triangle);
+
+        // the triangle's vertices go to the front of the cache
+        for i in triangle {
+            remaining[*i as usize] -= 1;
+            vertex_triangles[*i as usize].retain(|t| *t != best);
+        }
+        cache.retain(|v| !triangle.contains(v));
+        for i in triangle.iter().rev() {
+            cache.insert(0, *i);
+        }
+        let evicted: Vec<u32> = if cache.len() > VERTEX_CACHE_SIZE {cache.split_off(VERTEX_CACHE_SIZE)} else {Vec::new()};
+
+        // rescore everything whose cache position changed, and their triangles
+        let changed: Vec<(u32, Option<usize>)> = cache.iter().enumerate().map(|(p, v)| (*v, Some(p))).chain(evicted.iter().map(|v| (*v, None))).collect();
+        for (vertex, cache_position) in changed.iter() {
+            vertex_scores[*vertex as usize] = vertex_cache_score(*cache_position, remaining[*vertex as usize]);
+        }
+        for (vertex, _) in changed.iter() {
+            for t in vertex_triangles[*vertex as usize].iter() {
+                triangle_scores[*t] = triangle_score(*t, &vertex_scores);
+            }
+        }
+    }
+    return optimized;
+}
+
+// reorders clusters of triangles so the ones on the outside of the mesh facing outwards get drawn first, letting the depth test skip more of what's behind them.
+// clusters are cut where the vertex cache would be mostly empty anyway, so this keeps most of what optimize_vertex_cache() did. call after it
+pub fn optimize_overdraw(vertices: &[f32], indices: &[u32], floats_per_vertex: usize) -> Vec<u32> {
+    let n_triangles = indices.len() / 3;
+    if n_triangles == 0 {
+        return indices.to_vec();
+    }
+
+    // a new cluster starts at every triangle none of whose vertices are in the cache
+    let mut cluster_starts = vec![0];
+    let mut cache: std::collections::VecDeque<u32> = std::collections::VecDeque::with_capacity(OVERDRAW_CACHE_SIZE);
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        let misses = triangle.iter().filter(|i| !cache.contains(i)).count();
+        if misses == 3 && t != 0 {
+            cluster_starts.push(t);
+        }
+        for i in triangle {
+            if !cache.contains(i) {
+                if cache.len() == OVERDRAW_CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(*i);
+            }
+        }
+    }
+    cluster_starts.push(n_triangles);
+
+    let center = bounding_box(vertices, floats_per_vertex).map(|bounds| bounds.center()).unwrap_or(vec3(0.0, 0.0, 0.0));
+    let mut clusters: Vec<(f32, usize, usize)> = Vec::new(); // (how far out and outwards facing, first triangle, end triangle)
+    for window in cluster_starts.windows(2) {
+        let (mut area_weighted_center, mut normal, mut area) = (vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), 0.0);
+        for triangle in indices[window[0] * 3..window[1] * 3].chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| position(vertices, floats_per_vertex, triangle[k] as usize));
+            let cross = (b - a).cross(&(c - a));
+            area_weighted_center += (a + b + c) / 3.0 * cross.norm();
+            area += cross.norm();
+            normal += cross;
+        }
+        let score = if area > 0.0 && normal.norm() > 0.0 {(area_weighted_center / area - center).dot(&normal.normalize())} else {0.0};
+        clusters.push((score, window[0], window[1]));
+    }
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+    return clusters.iter().flat_map(|(_, start, end)| indices[start * 3..end * 3].iter().copied()).collect();
+}
+
+// renumbers vertices in the order the triangles first use them, so the gpu reads the vertex buffer mostly front to back, and drops vertices nothing uses.
+// returns the new vertices and indices
+pub fn optimize_vertex_fetch(vertices: &[f32], indices: &[u32], floats_per_vertex: usize) -> (Vec<f32>, Vec<u32>) {
+    let mut new_index = vec![u32::MAX; vertices.len() / floats_per_vertex];
+    let mut fetched_vertices = Vec::with_capacity(vertices.len());
+    let mut fetched_indices = Vec::with_capacity(indices.len());
+    for i in indices {
+        if new_index[*i as usize] == u32::MAX {
+            new_index[*i as usize] = (fetched_vertices.len() / floats_per_vertex) as u32;
+            fetched_vertices.extend_from_slice(&vertices[*i as usize * floats_per_vertex..(*i as usize + 1) * floats_per_vertex]);
+        }
+        fetched_indices.push(new_index[*i as usize]);
+    }
+    return (fetched_vertices, fetched_indices);
+}
+
+// what the importers run every mesh through before making it a Mesh: welds exact copies of vertices, makes up normals and uvs if the file didn't have them,
+// then orders triangles and vertices for the gpu. vertices should already have room for normals and uvs, their values are ignored if the file didn't have them
+pub fn prepare_imported_mesh(vertices: &[f32], indices: &[u32], floats_per_vertex: usize, has_normals: bool, has_uvs: bool, normals: GeneratedNormals) -> (Vec<f32>, Vec<u32>) {
+    let (mut vertices, mut indices) = weld_vertices(vertices, indices, floats_per_vertex, 0.0);
+    if !has_normals {
+        match normals {
+            GeneratedNormals::Smooth => generate_smooth_normals(&mut vertices, &indices, floats_per_vertex),
+            GeneratedNormals::Flat => (vertices, indices) = generate_flat_normals(&vertices, &indices, floats_per_vertex)
+        }
+    }
+    if !has_uvs {
+        generate_box_uvs(&mut vertices, floats_per_vertex);
+    }
+    let indices = optimize_vertex_cache(&indices, vertices.len() / floats_per_vertex);
+    let indices = optimize_overdraw(&vertices, &indices, floats_per_vertex);
+    return optimize_vertex_fetch(&vertices, &indices, floats_per_vertex);
+}
+
+impl Mesh {
+    // optimize_vertex_cache(), optimize_overdraw() and optimize_vertex_fetch() on this mesh, keeping its skin's joints and weights with their vertices.
+    // call before anything draws the mesh, meshpools keep the copy they were given
+    pub fn optimize(&mut self) {
+        let floats_per_vertex = self.floats_per_vertex();
+        let vertices = self.gpu_vertices().into_owned();
+        let indices = optimize_vertex_cache(&self.indices, vertices.len() / floats_per_vertex);
+        let indices = optimize_overdraw(&vertices, &indices, floats_per_vertex);
+        let (vertices, indices) = optimize_vertex_fetch(&vertices, &indices, floats_per_vertex);
+        self.set_gpu_vertices(vertices);
+        self.indices = indices;
+    }
+
+    // in the mesh's own coordinates, so inside -0.5 to 0.5
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        return bounding_box(&self.vertices, N_FLOATS_PER_VERTEX);
+    }
+
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        return bounding_sphere(&self.vertices, N_FLOATS_PER_VERTEX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icosphere() -> (Vec<f32>, Vec<u32>) {
+        let mesh_id = Mesh::from_obj("models/icosphere.obj", 0, 0).unwrap();
+        let meshes = LOADED_MESHES.lock().unwrap();
+        return (meshes[&mesh_id].vertices.clone(), meshes[&mesh_id].indices.clone());
+    }
+
+    // the triangles as sets of corner positions, so meshes can be compared after their vertices have been renumbered
+    fn triangle_corners(vertices: &[f32], indices: &[u32], floats_per_vertex: usize) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<[[u32; 3]; 3]> = indices.chunks_exact(3).map(|triangle| {
+            let mut corners = [0, 1, 2].map(|k| [0, 1, 2].map(|axis| vertices[triangle[k] as usize * floats_per_vertex + axis].to_bits()));
+            // rotate the smallest corner to the front, which keeps the winding
+            let smallest = (0..3).min_by_key(|k| corners[*k]).unwrap();
+            corners.rotate_left(smallest);
+            return corners;
+        }).collect();
+        triangles.sort();
+        return triangles;
+    }
+
+    // a 4x2 plane off to the side: every axis gets its own scale, and the flat one is only moved
+    #[test]
+    fn scale_into_range_is_per_axis() {
+        let mut vertices = Vec::new();
+        for (x, y) in [(1.0, 3.0), (5.0, 3.0), (5.0, 5.0), (1.0, 5.0)] {
+            vertices.extend_from_slice(&[x, y, 7.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        }
+        let mesh = Mesh::from_vertices(vertices.clone(), vec![0, 1, 2, 0, 2, 3], 0, 0, true);
+        assert!(mesh.original_size == glm::vec3(4.0, 2.0, 1.0), "expected an original size of (4, 2, 1), got {:?}", mesh.original_size);
+        for (i, vertex) in mesh.vertices.chunks_exact(N_FLOATS_PER_VERTEX).enumerate() {
+            assert!(vertex[0].abs() == 0.5 && vertex[1].abs() == 0.5 && vertex[2] == 0.0, "expected every corner at (+-0.5, +-0.5, 0), but one is at {:?}", &vertex[0..3]);
+            let original = glm::vec4(vertices[i * N_FLOATS_PER_VERTEX], vertices[i * N_FLOATS_PER_VERTEX + 1], vertices[i * N_FLOATS_PER_VERTEX + 2], 1.0);
+            assert!(((mesh.normalization * original).xyz() - glm::vec3(vertex[0], vertex[1], vertex[2])).norm() <= 1e-5, "normalization takes {:?} to {:?}, but the vertex ended up at {:?}", original.xyz(), (mesh.normalization * original).xyz(), &vertex[0..3]);
+        }
+    }
+
+    #[test]
+    fn weld_merges_copies() {
+        let (vertices, indices) = icosphere();
+        let n_vertices = vertices.len() / N_FLOATS_PER_VERTEX;
+        // every triangle with its own copies of its corners, plus a triangle that's just a point
+        let mut split_vertices = Vec::new();
+        for i in indices.iter() {
+            split_vertices.extend_from_slice(&vertices[*i as usize * N_FLOATS_PER_VERTEX..(*i as usize + 1) * N_FLOATS_PER_VERTEX]);
+        }
+        let mut split_indices: Vec<u32> = (0..indices.len() as u32).collect();
+        split_indices.extend_from_slice(&[0, 0, 0]);
+
+        let (welded_vertices, welded_indices) = weld_vertices(&split_vertices, &split_indices, N_FLOATS_PER_VERTEX, 0.0);
+        assert!(welded_vertices.len() / N_FLOATS_PER_VERTEX == n_vertices && welded_indices.len() == indices.len(), "expected welding to get back to {} vertices and {} triangles, got {} and {}", n_vertices, indices.len() / 3, welded_vertices.len() / N_FLOATS_PER_VERTEX, welded_indices.len() / 3);
+        assert!(triangle_corners(&welded_vertices, &welded_indices, N_FLOATS_PER_VERTEX) == triangle_corners(&vertices, &indices, N_FLOATS_PER_VERTEX), "welding changed the triangles");
+
+        // nudging one copy of a vertex by less than the tolerance still welds it to the others
+        let most_used = (0..n_vertices as u32).max_by_key(|v| indices.iter().filter(|i| *i == v).count()).unwrap();
+        let copy = indices.iter().position(|i| *i == most_used).unwrap();
+        let mut nudged = split_vertices.clone();
+        nudged[copy * N_FLOATS_PER_VERTEX] += 1e-5;
+        let (tolerant, _) = weld_vertices(&nudged, &split_indices, N_FLOATS_PER_VERTEX, 1e-3);
+        let (exact, _) = weld_vertices(&nudged, &split_indices, N_FLOATS_PER_VERTEX, 0.0);
+        assert!(tolerant.len() / N_FLOATS_PER_VERTEX <= n_vertices + 1 && exact.len() / N_FLOATS_PER_VERTEX == n_vertices + 1, "expected a nudged vertex to weld with a tolerance but not without, got {} and {} vertices", tolerant.len() / N_FLOATS_PER_VERTEX, exact.len() / N_FLOATS_PER_VERTEX);
+    }
+
+    // the icosphere's triangles shuffled should come out of optimize_vertex_cache() needing far fewer vertex shader runs, with the same triangles facing the same way
+    #[test]
+    fn vertex_cache_order_helps() {
+        let (vertices, indices) = icosphere();
+        let mut triangles: Vec<&[u32]> = indices.chunks_exact(3).collect();
+        let mut random = 12345u64;
+        for i in (1..triangles.len()).rev() {
+            random = random.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            triangles.swap(i, (random >> 33) as usize % (i + 1));
+        }
+        let shuffled: Vec<u32> = triangles.concat();
+        let n_vertices = vertices.len() / N_FLOATS_PER_VERTEX;
+
+        let optimized = optimize_vertex_cache(&shuffled, n_vertices);
+        let (before, after) = (average_cache_miss_ratio(&shuffled, 16), average_cache_miss_ratio(&optimized, 16));
+        assert!(after <= before * 0.6 && after <= 1.0, "expected optimizing to bring the cache miss ratio well down, but it went from {} to {}", before, after);
+        let overdraw = optimize_overdraw(&vertices, &optimized, N_FLOATS_PER_VERTEX);
+        let overdraw_ratio = average_cache_miss_ratio(&overdraw, 16);
+        assert!(overdraw_ratio <= after * 1.2, "expected optimize_overdraw() to keep most of the cache order, but the miss ratio went from {} to {}", after, overdraw_ratio);
+        let (fetched_vertices, fetched_indices) = optimize_vertex_fetch(&vertices, &overdraw, N_FLOATS_PER_VERTEX);
+        assert!(fetched_indices.iter().scan(0, |next, i| { let in_order = *i <= *next; *next = (*next).max(*i + 1); Some(in_order) }).all(|in_order| in_order), "expected optimize_vertex_fetch() to number vertices in the order they're first used");
+        let expected = triangle_corners(&vertices, &indices, N_FLOATS_PER_VERTEX);
+        for (what, vertices, indices) in [("optimize_vertex_cache()", &vertices, &optimized), ("optimize_overdraw()", &vertices, &overdraw), ("optimize_vertex_fetch()", &fetched_vertices, &fetched_indices)] {
+            assert!(triangle_corners(vertices, indices, N_FLOATS_PER_VERTEX) == expected, "{} changed the triangles", what);
+        }
+    }
+
+    // a square facing +z with u going along +x and v along +y has tangent +x, and flipping u makes it -x with the handedness flipped too
+    #[test]
+    fn tangents_follow_uvs() {
+        let square = |flip_u: bool| {
+            let mut vertices = Vec::new();
+            for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                vertices.extend_from_slice(&[x, y, 0.0, 0.0, 0.0, 1.0, if flip_u {1.0 - x} else {x}, y]);
+            }
+            return generate_tangents(&vertices, &[0, 1, 2, 0, 2, 3], N_FLOATS_PER_VERTEX);
+        };
+        for (flip_u, expected) in [(false, [1.0, 0.0, 0.0, 1.0]), (true, [-1.0, 0.0, 0.0, -1.0])] {
+            let tangents = square(flip_u);
+            assert!(tangents.iter().all(|tangent| tangent.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() <= 1e-5)), "expected every tangent to be {:?}, got {:?}", expected, tangents);
+        }
+
+        // on the icosphere every tangent should be unit length and perpendicular to its normal
+        let (vertices, indices) = icosphere();
+        for (tangent, vertex) in generate_tangents(&vertices, &indices, N_FLOATS_PER_VERTEX).iter().zip(vertices.chunks_exact(N_FLOATS_PER_VERTEX)) {
+            let (t, n) = (glm::vec3(tangent[0], tangent[1], tangent[2]), glm::vec3(vertex[3], vertex[4], vertex[5]));
+            assert!((t.norm() - 1.0).abs() <= 1e-4 && t.dot(&n).abs() <= 1e-3 && tangent[3].abs() == 1.0, "tangent {:?} isn't a unit vector perpendicular to normal {:?}", tangent, n);
+        }
+    }
+
+    #[test]
+    fn bounding_volumes() {
+        let (vertices, _) = icosphere();
+        let bounds = bounding_box(&vertices, N_FLOATS_PER_VERTEX).expect("no box around the icosphere");
+        assert!((bounds.size() - glm::vec3(1.0, 1.0, 1.0)).abs().max() <= 1e-5 && bounds.center().norm() <= 1e-5, "expected the icosphere's box to be 1m^3 around the origin, got {:?}", bounds);
+        // an icosphere is flattened a little by being scaled into range, so its sphere is at most the box's corner away
+        let sphere = bounding_sphere(&vertices, N_FLOATS_PER_VERTEX).expect("no sphere around the icosphere");
+        assert!(sphere.center.norm() <= 0.02 && sphere.radius >= 0.5 && sphere.radius <= 0.53, "expected a sphere of about 0.5m around the origin, got {:?}", sphere);
+        for vertex in vertices.chunks_exact(N_FLOATS_PER_VERTEX) {
+            assert!((glm::vec3(vertex[0], vertex[1], vertex[2]) - sphere.center).norm() <= sphere.radius + 1e-5, "vertex {:?} is outside the bounding sphere {:?}", &vertex[0..3], sphere);
+        }
+        assert!(bounding_box(&[], N_FLOATS_PER_VERTEX).is_none() && bounding_sphere(&[], N_FLOATS_PER_VERTEX).is_none(), "expected no bounds for no vertices");
+    }
+
+    // every vertex of a skinned mesh follows the joint at its height. after optimizing, that should still be true
+    #[test]
+    fn optimize_keeps_skin_with_vertices() {
+        let (vertices, indices) = icosphere();
+        let n_vertices = vertices.len() / N_FLOATS_PER_VERTEX;
+        let joint_for = |y: f32| if y > 0.0 {1} else {0};
+        let joints: Vec<[u16; 4]> = vertices.chunks_exact(N_FLOATS_PER_VERTEX).map(|v| [joint_for(v[1]), 0, 0, 0]).collect();
+        let mut mesh = Mesh::skinned(vertices, indices, joints, vec![[1.0, 0.0, 0.0, 0.0]; n_vertices], 0, 0);
+        let expected = triangle_corners(&mesh.vertices, &mesh.indices, N_FLOATS_PER_VERTEX);
+        mesh.optimize();
+        let skin = mesh.skin.as_ref().unwrap();
+        assert!(skin.joints.len() == mesh.vertices.len() / N_FLOATS_PER_VERTEX && triangle_corners(&mesh.vertices, &mesh.indices, N_FLOATS_PER_VERTEX) == expected, "optimizing changed the skinned mesh's triangles or lost joints");
+        for (vertex, joints) in mesh.vertices.chunks_exact(N_FLOATS_PER_VERTEX).zip(skin.joints.iter()) {
+            assert!(joints[0] == joint_for(vertex[1]), "the vertex at {:?} ended up with joint {} after optimizing", &vertex[0..3], joints[0]);
+        }
+    }
+}
@@ -1,619 +1,657 @@
-use std::collections::{HashMap, VecDeque};
+use std::{cell::Cell, collections::HashMap};
 use crate::graphics::*;
-use gl46::*;
+use crate::animation::MAX_JOINTS;
 
 pub const TARGET_MESHPOOL_BASE_SIZE: isize = (2 as isize).pow(24); // ~16MB
+pub const JOINT_MATRICES_BINDING: GLuint = 4; // ssbo binding the skinned vertex shader reads joint matrices from
+pub const INSTANCE_NBYTES: usize = 64 + 16 + 4; // size of mat 4x4 + size of vec4 color/transparency + sizeof float for texture z
+
+// the three buffers a meshpool sub-allocates, each with its own RangeAllocator. used as an index into MeshPool::allocators and each slot's allocations
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PoolMemory {
+    Vertices = 0,
+    Indices = 1,
+    Instances = 2,
+}
+
+// the part of one of a meshpool's buffers that belongs to a slot, counted in elements of that buffer (vertices, indices or instances)
+#[derive(Clone, Copy, Debug)]
+struct Allocation {
+    offset: usize,
+    capacity: usize, // how much the allocator gave it
+    len: usize, // how much of that holds something. the rest is wasted until the slot grows into it or the pool is defragmented
+}
+
+impl Allocation {
+    fn empty() -> Self {
+        return Self { offset: 0, capacity: 0, len: 0 };
+    }
+}
+
+// what MeshPool::stats() says about one of its buffers
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolMemoryStats {
+    pub used_bytes: usize,
+    pub wasted_bytes: usize, // given to a slot but not holding anything, like room for more instances of a static mesh or a dynamic mesh that shrank
+    pub free_bytes: usize,
+    pub largest_free_bytes: usize, // biggest thing that fits without growing the buffer. a lot less than free_bytes means it's fragmented
+    pub n_free_ranges: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeshPoolStats {
+    pub n_meshes: usize,
+    pub vertices: PoolMemoryStats,
+    pub indices: PoolMemoryStats,
+    pub instances: PoolMemoryStats,
+}
 
-// Contains a bunch of meshes of the same size for rapid drawing with special opengl stuff. internally used by MeshMaster
+// Holds a bunch of meshes (of any size) and their instances for rapid drawing with special opengl stuff. internally used by GraphicsEngine.
+// vertex, index and instance memory are each one big buffer, split up between the meshes by a RangeAllocator. buffers grow when they run out of room,
+// and defragment() (or running out of room while there's enough free space, just not in one piece) moves everything to the front of them.
+// slots are what the rest of the engine refers to meshes by, they don't change when the memory behind them moves
 pub struct MeshPool {
-    pub mesh_vertex_nbytes: isize,
-    pub mesh_index_nbytes: isize,
-    pub base_mesh_capacity: isize,
-    pub mesh_capacity: isize,
-    pub base_instanced_data_capacity: isize,
     pub instance_nbytes: isize,
-    pub instanced_data_capacity: isize,
+    pub skinned: bool, // skinned pools hold vertices with joint indices/weights (N_FLOATS_PER_SKINNED_VERTEX) and MAX_JOINTS joint matrices per instance
+    floats_per_vertex: usize,
 
-    draw_commands: Vec<IndirectDrawCommand>,
-    drawcount: i32,
-    // model_matrices: Vec<glm::Mat4>, // todo: potentially convert to vector of floats sp we can instance texture array index / color vertex attributes
+    draw_commands: Vec<IndirectDrawCommand>, // one per slot, made from its allocations by update_command()
+    allocations: Vec<Option<[Allocation; 3]>>, // per slot, indexed by PoolMemory. None if the slot is free
+    allocators: [RangeAllocator; 3], // indexed by PoolMemory
+    free_slots: Vec<i32>,
+    command_capacity: usize, // how many draw commands the indbo has room for
 
     vbo : GLuint,  // hold vertices
-    mvbo : GLuint, // secondary vbo with an instanced arrays of model matrices, color, texturez in that order
+    mvbo : GLuint, // secondary vbo with an instanced arrays of model matrices, color, texturez in that order. refilled by draw() with only the visible instances
+    mvbo_fence: Cell<GLuint>, // passed once the gpu is done with the last draw()'s instances, so the next one doesn't write over them while they're being read
     ibo : GLuint,  // holds indices
     vao : GLuint,  // tells opengl how vertices are formatted
     indbo: GLuint, // stores rendering commands
-    //ssbo: GLuint, // (shader storage buffer object) stores one model matrix per mesh
+    jbo: GLuint, // ssbo with MAX_JOINTS joint matrices per instance, only for skinned pools
+    joint_capacity: usize, // how many instances jbo has room for
 
     pool_vertices : *mut ::libc::c_void,
     pool_instanced_data : *mut ::libc::c_void,
     pool_indices : *mut ::libc::c_void,
     pool_commands: *mut ::libc::c_void,
+    pool_joints: *mut ::libc::c_void,
 
-    available_slots : VecDeque<i32>, // represent portions of memory in the vbo where there is room for a mesh
-                                     // supposed to let us not syncronize stuff,
-    // available_instanced_data_slots : VecDeque<i32>, // same but for mvbo; ok deque is slow, i'm just going to manually sync mvbo instead of using deque
-    available_instanced_data_slots_vec : Vec<bool>, // indices are instance slots, values are true if they are free
-                                                    // used for quickly finding continuous portions of memory in mvbo
-    n_available_instance_slots: usize,
+    instances: Vec<u8>, // what set_transform() etc. write to, instance_nbytes per instance. draw() copies the ones the camera can see into the mvbo
+    instance_bounds: Vec<glm::Vec4>, // per instance, xyz is the model's (camera relative) position and w its biggest scale, for frustum culling
+    slot_radii: Vec<f32>, // per slot, how far the mesh's furthest vertex is from its origin
 
-    vertex_slots_to_instanced_slots : HashMap<i32, i32>, // should probably be a vector but im but lazy
-    slot_contents: HashMap<i32, i32>, // key is slot, value is mesh uuid; only contains static meshes
+    static_slots: HashMap<i32, i32>, // key is mesh uuid, value is the slot all its instances are in; only contains static meshes
+    slot_contents: HashMap<i32, i32>, // the other way around
 }
 
 impl MeshPool {
-    pub fn new(gl: &gl46::GlFns, n_meshes: isize, n_instances: isize, sizeof_verts: isize, sizeof_indices: isize) -> Self { // should probably make indbo/ssbo here instead of in expand()
-        // println!("capacity {}, bytes per is {}", n_meshes, sizeof_verts);
-        
+    // capacities are where the buffers start, they double whenever they run out
+    pub fn new(device: &dyn RenderDevice, n_vertices: usize, n_indices: usize, n_instances: usize) -> Self {
+        return MeshPool::with_layout(device, n_vertices, n_indices, n_instances, false);
+    }
+
+    // for meshes with a Skin, which have to be given as Mesh::gpu_vertices()
+    pub fn new_skinned(device: &dyn RenderDevice, n_vertices: usize, n_indices: usize, n_instances: usize) -> Self {
+        return MeshPool::with_layout(device, n_vertices, n_indices, n_instances, true);
+    }
+
+    fn with_layout(device: &dyn RenderDevice, n_vertices: usize, n_indices: usize, n_instances: usize, skinned: bool) -> Self {
         let mut new_pool = Self {
-            mesh_vertex_nbytes: sizeof_verts,
-            mesh_index_nbytes: sizeof_indices, 
-            base_mesh_capacity: n_meshes,
-            mesh_capacity: 0,
-            base_instanced_data_capacity: n_instances,
-            instance_nbytes: 64 + 16 + 4, // size of mat 4x4 + size of vec4 color/transparency + sizeof float for texture z
-            instanced_data_capacity: 0,
+            instance_nbytes: INSTANCE_NBYTES as isize,
+            skinned: skinned,
+            floats_per_vertex: if skinned {N_FLOATS_PER_SKINNED_VERTEX} else {N_FLOATS_PER_VERTEX},
 
             draw_commands: Vec::new(),
-            drawcount: 0,
-            
-            // model_matrices: Vec::new(),
+            allocations: Vec::new(),
+            allocators: [RangeAllocator::new(0), RangeAllocator::new(0), RangeAllocator::new(0)], // grow() gives them their capacity
+            free_slots: Vec::new(),
+            command_capacity: 0,
 
             vbo: 0,
             mvbo: 0,
+            mvbo_fence: Cell::new(0),
             ibo: 0,
             vao: 0,
             indbo: 0,
-            //ssbo: 0,
+            jbo: 0,
+            joint_capacity: 0,
 
             pool_vertices: std::ptr::null_mut(),
             pool_instanced_data: std::ptr::null_mut(),
             pool_indices: std::ptr::null_mut(),
             pool_commands: std::ptr::null_mut(),
+            pool_joints: std::ptr::null_mut(),
 
-            available_slots: VecDeque::new(), 
-            // available_instanced_data_slots: VecDeque::new(),
-            available_instanced_data_slots_vec: Vec::new(),
-            n_available_instance_slots: 0,
+            instances: Vec::new(),
+            instance_bounds: Vec::new(),
+            slot_radii: Vec::new(),
 
-            vertex_slots_to_instanced_slots: HashMap::new(),
+            static_slots: HashMap::new(),
             slot_contents: HashMap::new()
         };
-        new_pool.expand_vbo(gl);
-        MeshPool::expand_mvbo(gl, 1, &mut new_pool.instanced_data_capacity, new_pool.base_instanced_data_capacity, &mut new_pool.n_available_instance_slots, /*&mut new_pool.available_instanced_data_slots,*/ new_pool.mesh_capacity, &mut new_pool.mvbo, &mut new_pool.pool_instanced_data, new_pool.instance_nbytes, &mut new_pool.available_instanced_data_slots_vec);
+        // opengl doesn't like buffers with no storage
+        new_pool.grow(device, PoolMemory::Vertices, n_vertices.max(1));
+        new_pool.grow(device, PoolMemory::Indices, n_indices.max(1));
+        new_pool.grow(device, PoolMemory::Instances, n_instances.max(1));
+        new_pool.grow_commands(device, 1);
         return new_pool;
     }
 
-    pub fn cleanup(&mut self, gl: &gl46::GlFns) {
-        self.del_vertex_buffers(gl);
-        MeshPool::del_instanced_buffers(gl, &mut self.mvbo);
+    pub fn cleanup(&mut self, device: &dyn RenderDevice) {
+        device.wait_fence(self.mvbo_fence.replace(0));
+        device.delete_vertex_array(self.vao);
+        for buffer in [self.vbo, self.ibo, self.indbo, self.mvbo, self.jbo] {
+            if buffer != 0 {
+                device.delete_buffer(buffer);
+            }
+        }
+        self.vao = 0;
+        (self.vbo, self.ibo, self.indbo, self.mvbo, self.jbo) = (0, 0, 0, 0, 0);
     }
 
-    // mvbo size should be indepedant of vbo/ibo size because instancing can cause many models w/ different matrices to be drawn with same vertices
-    // this one needs a size argument becuase if we add like 20000 instances and the base is 5000, expanding once wouldn't be enough and it would segfault
-    // because of the borrow checker this function doesn't use self and instead requires a bunch of self's members to be passed to it
-    pub fn expand_mvbo(gl: &gl46::GlFns, size: isize, instanced_data_capacity: &mut isize, base_instanced_data_capacity: isize, n_available_instances: &mut usize, /*available_instanced_data_slots: &mut VecDeque<i32>,*/ mesh_capacity: isize, mvbo: &mut GLuint, pool_instanced_data: &mut *mut ::libc::c_void, instance_nbytes: isize, available_instanced_data_slots_vec: &mut Vec<bool>) {
-        // println!("Expanding mvbo by {} * {}", size, base_instanced_data_capacity);
-
-        let old_cap = *instanced_data_capacity;
-
-        // for i in 0..base_instanced_data_capacity*size {
-        //     available_instanced_data_slots.push_back((i + *instanced_data_capacity).try_into().unwrap());
-        // }
-
-        *instanced_data_capacity += base_instanced_data_capacity*size;
-        *n_available_instances += base_instanced_data_capacity as usize*size as usize;
-        available_instanced_data_slots_vec.resize(*instanced_data_capacity as usize, true);
-        let flags = GL_MAP_PERSISTENT_BIT|GL_MAP_COHERENT_BIT|GL_MAP_WRITE_BIT;
-        // self.model_matrices.resize_with(self.instanced_data_capacity as usize, ||{glm::identity()});
+    fn element_nbytes(&self, memory: PoolMemory) -> usize {
+        return match memory {
+            PoolMemory::Vertices => self.floats_per_vertex * core::mem::size_of::<GLfloat>(),
+            PoolMemory::Indices => core::mem::size_of::<GLuint>(),
+            PoolMemory::Instances => self.instance_nbytes as usize,
+        };
+    }
 
+    // Because the buffers are persistently mapped, we can't resize them and must replace them. everything is copied over, so allocations stay where they were.
+    // at least doubles the capacity so growing one mesh at a time isn't quadratic
+    fn grow(&mut self, device: &dyn RenderDevice, memory: PoolMemory, min_capacity: usize) {
+        let old_capacity = self.allocators[memory as usize].capacity();
+        let new_capacity = (old_capacity * 2).max(min_capacity);
+        let nbytes = self.element_nbytes(memory);
         unsafe {
+            match memory {
+                PoolMemory::Vertices => {
+                    let (newvbo, new_pool_vertices) = device.create_mapped_buffer(BufferTarget::Vertex, (new_capacity * nbytes) as isize);
+                    if self.vbo != 0 {
+                        libc::memcpy(new_pool_vertices, self.pool_vertices, old_capacity * nbytes);
+                        device.delete_buffer(self.vbo);
+                    }
+                    (self.vbo, self.pool_vertices) = (newvbo, new_pool_vertices);
+                },
+                PoolMemory::Indices => {
+                    let (newibo, new_pool_indices) = device.create_mapped_buffer(BufferTarget::Index, (new_capacity * nbytes) as isize);
+                    if self.ibo != 0 {
+                        libc::memcpy(new_pool_indices, self.pool_indices, old_capacity * nbytes);
+                        device.delete_buffer(self.ibo);
+                    }
+                    (self.ibo, self.pool_indices) = (newibo, new_pool_indices);
+                },
+                PoolMemory::Instances => {
+                    let (newmvbo, new_instanced_data) = device.create_mapped_buffer(BufferTarget::Vertex, (new_capacity * nbytes) as isize);
+                    if self.mvbo != 0 {
+                        // all of every instance, not just its model matrix, or color and texture z are lost until the next draw()
+                        libc::memcpy(new_instanced_data, self.pool_instanced_data, old_capacity * nbytes);
+                        device.delete_buffer(self.mvbo);
+                    }
+                    (self.mvbo, self.pool_instanced_data) = (newmvbo, new_instanced_data);
+                    self.instances.resize(new_capacity * nbytes, 0);
+                    self.instance_bounds.resize(new_capacity, glm::vec4(0.0, 0.0, 0.0, 0.0));
+                }
+            }
+        }
+        self.allocators[memory as usize].grow(new_capacity);
 
+        if memory == PoolMemory::Instances && self.skinned {
+            self.expand_joints(device);
+        }
+        if memory != PoolMemory::Indices { // the index buffer is bound by draw(), but the vao remembers which buffer each attribute comes from
+            self.bind_attributes(device);
+        }
+    }
 
-            let mut newmvbo: GLuint = 0; 
-            let mvboptr: *mut GLuint = &mut newmvbo;
-            gl.GenBuffers(1, mvboptr);
-
-            // secondary array buffer (mvbo) contains model matrices
-            gl.BindBuffer(GL_ARRAY_BUFFER, newmvbo);
-            gl.BufferStorage(GL_ARRAY_BUFFER, instance_nbytes * *instanced_data_capacity, std::ptr::null(), flags);
-            let new_instanced_data = gl.MapBufferRange(GL_ARRAY_BUFFER, 0, *instanced_data_capacity * instance_nbytes, flags);
-
-            gl.EnableVertexAttribArray(1); // color rgba 
-            gl.VertexAttribPointer(1, 4, GL_FLOAT, 0, instance_nbytes as i32, 64 as *mut _);
-            gl.VertexAttribDivisor(1, 1);
-
-            gl.EnableVertexAttribArray(8); // texture z
-            gl.VertexAttribPointer(8, 1, GL_FLOAT, 0, instance_nbytes as i32, (64 + 16) as *mut _);
-            gl.VertexAttribDivisor(8, 1);
-
-            // each vertex attribute has to be no more than a vec4, so for a whole matrix we do 4 attributes
-            gl.EnableVertexAttribArray(4); // model matrix
-            gl.EnableVertexAttribArray(5); 
-            gl.EnableVertexAttribArray(6); 
-            gl.EnableVertexAttribArray(7);
+    // makes a new vao pointing at the current vbo and mvbo, since the old one still points at the buffers they replaced
+    fn bind_attributes(&mut self, device: &dyn RenderDevice) {
+        if self.vao != 0 {
+            device.delete_vertex_array(self.vao);
+        }
+        self.vao = device.create_vertex_array();
+        device.bind_vertex_array(self.vao);
+
+        // Tell opengl how the vertices inside the vbo are formatted
+        let stride = self.floats_per_vertex as i32 * 4;
+        device.vertex_attrib(0, 3, self.vbo, 0, stride, 0); // First 3 floats of each vertex is the position
+        device.vertex_attrib(2, 3, self.vbo, 12, stride, 0); // Then normals (attrib 1 is taken by color lol)
+        device.vertex_attrib(3, 2, self.vbo, 24, stride, 0); // Last 2 are texture
+
+        // skinned vertices have 4 joint indices (as floats) and 4 weights after the texture coords. attribs 4-8 are instanced data
+        if self.skinned {
+            device.vertex_attrib(9, 4, self.vbo, 32, stride, 0);
+            device.vertex_attrib(10, 4, self.vbo, 48, stride, 0);
+        }
 
-            gl.VertexAttribPointer(4, 4, GL_FLOAT, 0, instance_nbytes as i32, 0 as *mut _);
-            gl.VertexAttribPointer(5, 4, GL_FLOAT, 0, instance_nbytes as i32, 16 as *mut _);
-            gl.VertexAttribPointer(6, 4, GL_FLOAT, 0, instance_nbytes as i32, 32 as *mut _);
-            gl.VertexAttribPointer(7, 4, GL_FLOAT, 0, instance_nbytes as i32, 48 as *mut _);
+        let instance_nbytes = self.instance_nbytes as i32;
+        device.vertex_attrib(1, 4, self.mvbo, 64, instance_nbytes, 1); // color rgba, divisor 1 makes it per instance instead of per vertex
+        device.vertex_attrib(8, 1, self.mvbo, 64 + 16, instance_nbytes, 1); // texture z
 
-            gl.VertexAttribDivisor(4, 1); // this divisor thing makes these attributes per instance/mesh instead of per vertex
-            gl.VertexAttribDivisor(5, 1);
-            gl.VertexAttribDivisor(6, 1);
-            gl.VertexAttribDivisor(7, 1); 
+        // each vertex attribute has to be no more than a vec4, so for a whole matrix we do 4 attributes
+        device.vertex_attrib(4, 4, self.mvbo, 0, instance_nbytes, 1); // model matrix
+        device.vertex_attrib(5, 4, self.mvbo, 16, instance_nbytes, 1);
+        device.vertex_attrib(6, 4, self.mvbo, 32, instance_nbytes, 1);
+        device.vertex_attrib(7, 4, self.mvbo, 48, instance_nbytes, 1);
+    }
 
-            // copy data from old mvbo if needed
-            if *mvbo != 0 {
-                libc::memcpy(new_instanced_data, *pool_instanced_data, (old_cap * 64) as usize);
+    // makes the joint matrix ssbo as big as the mvbo, since every instance gets its own block of MAX_JOINTS matrices.
+    // new instances start out with identity matrices so they draw in their bind pose until set_joint_matrices() is called
+    fn expand_joints(&mut self, device: &dyn RenderDevice) {
+        let capacity = self.allocators[PoolMemory::Instances as usize].capacity();
+        if self.joint_capacity >= capacity {
+            return;
+        }
+        let block_nbytes = MAX_JOINTS * 64;
+        let identity: glm::Mat4 = glm::identity();
+        unsafe {
+            let (newjbo, new_pool_joints) = device.create_mapped_buffer(BufferTarget::ShaderStorage, (block_nbytes * capacity) as isize);
 
-                // Delete old buffers
-                MeshPool::del_instanced_buffers(gl, mvbo);
+            if self.jbo != 0 {
+                libc::memcpy(new_pool_joints, self.pool_joints, self.joint_capacity * block_nbytes);
+                device.delete_buffer(self.jbo);
+            }
+            for i in self.joint_capacity * MAX_JOINTS..capacity * MAX_JOINTS {
+                libc::memcpy(new_pool_joints.offset(i as isize * 64), &identity[0] as *const f32 as *const c_void, 64);
             }
 
-            *mvbo = newmvbo;
-            // println!("Old pointer to instanced data was {:?}", *pool_instanced_data);
-            *pool_instanced_data = new_instanced_data;
-            // println!("New pointer is {:?}", *pool_instanced_data);
+            self.jbo = newjbo;
+            self.pool_joints = new_pool_joints;
+            self.joint_capacity = capacity;
         }
     }
 
-    pub fn set_transform(&self, slot: i32, instance: i32, matrix: &glm::Mat4) {
-        unsafe {
-            let instance_slot = (self.vertex_slots_to_instanced_slots[&slot] + instance) as isize;
-            assert!(instance_slot < self.instanced_data_capacity, "Error: We were told to modify the transform for slot {}, but there are only {} slots.", instance_slot, self.instanced_data_capacity);
-            // if instance != 0 {
-            // println!("For instance {} of slot {}, it's {}", instance, slot, instance_slot); 17164
-            // }
-            //println!("Destination = {:?}", self.pool_instanced_data.offset(instance_slot * self.instance_nbytes));
-            // println!("offset={}", self.instance_nbytes * instance_slot);
-            libc::memcpy(self.pool_instanced_data.offset(instance_slot * self.instance_nbytes), &matrix[0] as *const f32 as *const c_void, 64);
-            //  println!("Transformed slot {} with matrix {:?}", slot, matrix);   
-            //println!("Binding buffer {}", self.ssbo);         
-            //gl.BindBuffer(GL_SHADER_STORAGE_BUFFER, self.ssbo);
-            //gl.BufferSubData(GL_SHADER_STORAGE_BUFFER, slot as isize * 64 , 64, &matrix[0] as *const f32 as *const c_void);
-        }
-    }
-
-    pub fn set_rgba(&self, slot: i32, instance: i32, color: &glm::Vec4) {
+    // the indbo has one command per slot
+    fn grow_commands(&mut self, device: &dyn RenderDevice, min_capacity: usize) {
+        if self.command_capacity >= min_capacity {
+            return;
+        }
+        let new_capacity = (self.command_capacity * 2).max(min_capacity);
         unsafe {
-            let instance_slot = (self.vertex_slots_to_instanced_slots[&slot] + instance) as isize;
-            assert!(instance_slot < self.instanced_data_capacity);
-            libc::memcpy(self.pool_instanced_data.offset(instance_slot * self.instance_nbytes + 64), &color[0] as *const f32 as *const c_void, 16);
+            let (newindbo, new_pool_commands) = device.create_mapped_buffer(BufferTarget::DrawIndirect, 20 * new_capacity as isize);
+            if self.indbo != 0 {
+                libc::memcpy(new_pool_commands, self.pool_commands, 20 * self.command_capacity);
+                device.delete_buffer(self.indbo);
+            }
+            (self.indbo, self.pool_commands) = (newindbo, new_pool_commands);
         }
+        self.command_capacity = new_capacity;
     }
 
-    pub fn set_texture_z(&self, slot: i32, instance: i32, tex: &f32) { 
-        unsafe {
-            let instance_slot = (self.vertex_slots_to_instanced_slots[&slot] + instance) as isize;
-            assert!(instance_slot < self.instanced_data_capacity);
-            libc::memcpy(self.pool_instanced_data.offset(instance_slot * self.instance_nbytes + 64 + 16), tex as *const f32 as *const c_void, 4);
+    // finds room for len elements of memory. if there's enough free space, just not in one piece, the buffer gets compacted; otherwise it grows.
+    // allocations of every slot might move, so anything read from them before calling this is stale
+    fn allocate(&mut self, device: &dyn RenderDevice, memory: PoolMemory, len: usize) -> usize {
+        if let Some(offset) = self.allocators[memory as usize].allocate(len) {
+            return offset;
         }
+        if self.allocators[memory as usize].free_space() >= len {
+            self.compact(memory);
+            if let Some(offset) = self.allocators[memory as usize].allocate(len) {
+                return offset;
+            }
+        }
+        let capacity = self.allocators[memory as usize].capacity();
+        self.grow(device, memory, capacity + len);
+        return self.allocators[memory as usize].allocate(len).unwrap();
     }
 
-    // fn resize_ssbo(&self) {
-    //     unsafe {
-    //         gl.BindBuffer(GL_SHADER_STORAGE_BUFFER, self.ssbo);
-    //         //println!("Buffering ssbo data! {:?}", self.model_matrices);
-    //         gl.BufferData(GL_SHADER_STORAGE_BUFFER, self.mesh_capacity * 64, self.model_matrices.as_ptr() as *const c_void, GL_DYNAMIC_DRAW);
-    //         gl.BindBufferBase(GL_SHADER_STORAGE_BUFFER, 3, self.ssbo);
-    //         check_graphics_errors();
-    //     }
-    // }
-
-    pub fn del_vertex_buffers(&mut self, gl: &gl46::GlFns) {
-        unsafe {
-            let vaoptr: *mut GLuint = &mut self.vao;
-            gl.DeleteVertexArrays(1, vaoptr);
-            let vboptr: *mut GLuint = &mut self.vbo;
-            gl.DeleteBuffers(1, vboptr);    
-            let indboptr: *mut GLuint = &mut self.indbo;
-            gl.DeleteBuffers(1, indboptr);   
-            let iboptr: *mut GLuint = &mut self.ibo;
-            gl.DeleteBuffers(1, iboptr);
-            
+    // moves the part of memory every slot has to the front of the buffer, keeping their order, and gives back what they weren't using.
+    // draws look the same afterwards since draw commands follow their slots
+    fn compact(&mut self, memory: PoolMemory) {
+        let m = memory as usize;
+        let mut slots: Vec<usize> = (0..self.allocations.len()).filter(|slot| self.allocations[*slot].is_some()).collect();
+        slots.sort_by_key(|slot| self.allocations[*slot].unwrap()[m].offset); // front to back, so nothing is moved over something that hasn't moved yet
+        self.allocators[m].clear();
+        for slot in slots {
+            let old = self.allocations[slot].unwrap()[m];
+            let offset = self.allocators[m].allocate(old.len).unwrap(); // everything allocated so far is packed at the front, so this is never after old.offset
+            self.move_elements(memory, old.offset, offset, old.len);
+            self.allocations[slot].as_mut().unwrap()[m] = Allocation { offset: offset, capacity: old.len, len: old.len };
+            self.update_command(slot as i32);
         }
     }
 
-    // *buffer
-    // also not a method because of the borrow checker
-    pub fn del_instanced_buffers(gl: &gl46::GlFns, mvbo: &mut GLuint) {
-        unsafe {
-            let mvboptr: *mut GLuint = mvbo;
-            gl.DeleteBuffers(1, mvboptr);
+    // compacts all of the pool's buffers, see compact(). doesn't make them smaller, it's so there's room for big meshes without growing them
+    pub fn defragment(&mut self) {
+        for memory in [PoolMemory::Vertices, PoolMemory::Indices, PoolMemory::Instances] {
+            self.compact(memory);
         }
     }
 
-    // called when mesh pool runs out of space
-    // TODO: should check opengl version and not use coherent_bit/persistent_bit and use glBufferSubData if persistent mapping is unsupported
-    pub fn expand_vbo(&mut self, gl: &gl46::GlFns) {
-        // println!("Expanding mesh pool.");
-        let old_cap = self.mesh_capacity;
-        for i in 0..self.base_mesh_capacity {
-            self.available_slots.push_back((i + self.mesh_capacity).try_into().unwrap());
+    // moves len elements of memory, the ranges can overlap
+    fn move_elements(&mut self, memory: PoolMemory, from: usize, to: usize, len: usize) {
+        if from == to || len == 0 {
+            return;
         }
-
-        self.mesh_capacity += self.base_mesh_capacity;
-        self.draw_commands.resize_with(self.mesh_capacity as usize, IndirectDrawCommand::new);
-        
-
-        let flags = GL_MAP_PERSISTENT_BIT|GL_MAP_COHERENT_BIT|GL_MAP_WRITE_BIT;
-        
+        let nbytes = self.element_nbytes(memory);
         unsafe {
-
-            //self.resize_ssbo();
-            //self.resize_indbo();
-            // println!("resized");
-
-            // Because we said glBufferStorage, we can't resize our previous buffers and must replace them
-            //todo: should really gen all 4 buffers with one call lol
-            let mut newvao: GLuint = 0;
-            let vaoptr: *mut GLuint = &mut newvao;
-            gl.GenVertexArrays(1, vaoptr); //idk if deleting the vao is neccesary
-
-            let mut newvbo: GLuint = 0;
-            let vboptr: *mut GLuint = &mut newvbo;
-            gl.GenBuffers(1, vboptr);   
-            let mut newibo: GLuint = 0; 
-            let iboptr: *mut GLuint = &mut newibo;
-            gl.GenBuffers(1, iboptr);
-            let mut newindbo: GLuint = 0;
-            let indboptr: *mut GLuint = &mut newindbo;
-            gl.GenBuffers(1, indboptr);
-            // gl.GenBuffers(3, [vboptr, iboptr, mvboptr].as_ptr() as *mut u32);
-            
-            gl.BindVertexArray(newvao);
-
-            // array buffer (vbo) contains mesh vertices
-            gl.BindBuffer(GL_ARRAY_BUFFER, newvbo);
-            gl.BufferStorage(GL_ARRAY_BUFFER, self.mesh_vertex_nbytes * self.mesh_capacity, std::ptr::null(), flags);
-            let new_pool_vertices = gl.MapBufferRange(GL_ARRAY_BUFFER, 0, self.mesh_capacity * self.mesh_vertex_nbytes, flags); //mapbufferrange gives a pointer that we put the vertices/indices in
-
-            // element array buffer (ibo) contains mesh indices
-            gl.BindBuffer(GL_ELEMENT_ARRAY_BUFFER, newibo);
-            gl.BufferStorage(GL_ELEMENT_ARRAY_BUFFER, self.mesh_index_nbytes * self.mesh_capacity, std::ptr::null(), flags);
-            let new_pool_indices = gl.MapBufferRange(GL_ELEMENT_ARRAY_BUFFER, 0, self.mesh_capacity * self.mesh_index_nbytes, flags);
-            
-            // draw indirect buffer (indbo) contains drawing commands
-            gl.BindBuffer(GL_DRAW_INDIRECT_BUFFER, newindbo);
-            gl.BufferStorage(GL_DRAW_INDIRECT_BUFFER, 20 * self.mesh_capacity, std::ptr::null(), flags);
-            let new_pool_commands = gl.MapBufferRange(GL_DRAW_INDIRECT_BUFFER, 0, self.mesh_capacity * 20, flags);
-            
-            // make sure to associate other buffers with vao too? do i care about that?
-            //gl.BindBuffer(GL_SHADER_STORAGE_BUFFER, self.ssbo);
-            //gl.BindBuffer(GL_DRAW_INDIRECT_BUFFER, self.indbo);
-
-            // Tell opengl how the vertices inside the vbo are formatted
-            gl.EnableVertexAttribArray(0);
-            gl.EnableVertexAttribArray(2); // attrib 1 is taken by color lol
-            gl.EnableVertexAttribArray(3);
-
-            gl.VertexAttribFormat(0, 3, GL_FLOAT, false as u8, 0); // First 3 floats of each vertex is the position
-            gl.VertexAttribFormat(2, 3, GL_FLOAT, false as u8, 0); // Then normals
-            gl.VertexAttribFormat(3, 3, GL_FLOAT, false as u8, 0); // Last 2 are texture
-
-            gl.VertexBindingDivisor(0, 0);
-            gl.VertexBindingDivisor(2, 0);
-            gl.VertexBindingDivisor(3, 0);
-
-            gl.VertexAttribBinding(0, 0);
-            gl.VertexAttribBinding(2, 2);
-            gl.VertexAttribBinding(3, 3);
-
-            gl.BindVertexBuffer(0, newvbo, 0, N_FLOATS_PER_VERTEX as i32 * 4);
-            gl.BindVertexBuffer(2, newvbo, 12, N_FLOATS_PER_VERTEX as i32 * 4);
-            gl.BindVertexBuffer(3, newvbo, 24, N_FLOATS_PER_VERTEX as i32 * 4);
-
-            // Move contents of the old vbo/ibo into the new one
-            if self.vao != 0 || self.vbo != 0 || self.ibo != 0 {
-                // println!("Copying old data!");
-                libc::memcpy(new_pool_vertices, self.pool_vertices, (old_cap * self.mesh_vertex_nbytes) as usize);
-                libc::memcpy(new_pool_indices, self.pool_indices, (old_cap * self.mesh_index_nbytes) as usize);
-                libc::memcpy(new_pool_commands, self.pool_commands, (old_cap * 20) as usize);
-                
-                // Delete old buffers
-                self.del_vertex_buffers(gl);
+            match memory {
+                PoolMemory::Vertices => { libc::memmove(self.pool_vertices.offset((to * nbytes) as isize), self.pool_vertices.offset((from * nbytes) as isize), len * nbytes); },
+                PoolMemory::Indices => { libc::memmove(self.pool_indices.offset((to * nbytes) as isize), self.pool_indices.offset((from * nbytes) as isize), len * nbytes); },
+                PoolMemory::Instances => {
+                    self.instances.copy_within(from * nbytes..(from + len) * nbytes, to * nbytes);
+                    self.instance_bounds.copy_within(from..from + len, to);
+                    if self.skinned {
+                        let block_nbytes = MAX_JOINTS * 64;
+                        libc::memmove(self.pool_joints.offset((to * block_nbytes) as isize), self.pool_joints.offset((from * block_nbytes) as isize), len * block_nbytes);
+                    }
+                }
             }
+        }
+    }
 
-            self.vao = newvao;
+    // makes the slot's allocation of memory hold len elements, moving it somewhere bigger (without what was in it) if it's too small. returns where it starts
+    fn reserve(&mut self, device: &dyn RenderDevice, slot: i32, memory: PoolMemory, len: usize) -> usize {
+        let m = memory as usize;
+        let old = self.allocations[slot as usize].unwrap()[m];
+        if len <= old.capacity {
+            self.allocations[slot as usize].as_mut().unwrap()[m].len = len;
+            return old.offset;
+        }
+        self.allocators[m].free(old.offset, old.capacity);
+        self.allocations[slot as usize].as_mut().unwrap()[m] = Allocation::empty(); // so compacting in allocate() doesn't move what we just freed
+        let offset = self.allocate(device, memory, len);
+        self.allocations[slot as usize].as_mut().unwrap()[m] = Allocation { offset: offset, capacity: len, len: len };
+        return offset;
+    }
 
-            self.vbo = newvbo;
-            self.ibo = newibo;         
-            self.indbo = newindbo;
+    // makes room for count more instances of the static mesh in slot, right after its others since a draw command's instances have to be contiguous.
+    // if something's in the way they all move somewhere with room to spare, like a Vec does. returns the (relative) index of the first new one
+    fn extend_instances(&mut self, device: &dyn RenderDevice, slot: i32, count: usize) -> usize {
+        let m = PoolMemory::Instances as usize;
+        let old = self.allocations[slot as usize].unwrap()[m];
+        let len = old.len + count;
+        if len > old.capacity && !self.allocators[m].try_extend(old.offset, old.capacity, len - old.capacity) {
+            let capacity = len.max(old.capacity * 2);
+            let offset = self.allocate(device, PoolMemory::Instances, capacity);
+            let old = self.allocations[slot as usize].unwrap()[m]; // allocate() might have compacted the buffer
+            self.move_elements(PoolMemory::Instances, old.offset, offset, old.len);
+            self.allocators[m].free(old.offset, old.capacity);
+            self.allocations[slot as usize].as_mut().unwrap()[m] = Allocation { offset: offset, capacity: capacity, len: len };
+        }
+        else {
+            let allocation = &mut self.allocations[slot as usize].as_mut().unwrap()[m];
+            allocation.capacity = allocation.capacity.max(len);
+            allocation.len = len;
+        }
+        self.update_command(slot);
+        return old.len;
+    }
 
-            self.pool_vertices = new_pool_vertices;
-            self.pool_indices = new_pool_indices;
-            self.pool_commands = new_pool_commands;
+    // the instance's index in the mvbo (and instances, instance_bounds and the jbo)
+    fn instance_slot(&self, slot: i32, instance: i32) -> usize {
+        let allocation = self.allocations[slot as usize].as_ref().unwrap()[PoolMemory::Instances as usize];
+        assert!((instance as usize) < allocation.len, "Error: We were told to modify instance {} of slot {}, but it only has {} instances.", instance, slot, allocation.len);
+        return allocation.offset + instance as usize;
+    }
 
-            //libc::memmove(dest, src, n)
-            //dealloc(ptr, layout);
+    pub fn set_transform(&mut self, slot: i32, instance: i32, matrix: &glm::Mat4) {
+        let instance_slot = self.instance_slot(slot, instance);
+        self.write_instance_data(instance_slot, 0, matrix.as_slice());
+        let scale = matrix.column(0).xyz().norm().max(matrix.column(1).xyz().norm()).max(matrix.column(2).xyz().norm());
+        self.instance_bounds[instance_slot] = glm::vec4(matrix[12], matrix[13], matrix[14], scale);
+    }
 
-            // println!("Finished expanding mesh pool.");
-            }
+    pub fn set_rgba(&mut self, slot: i32, instance: i32, color: &glm::Vec4) {
+        let instance_slot = self.instance_slot(slot, instance);
+        self.write_instance_data(instance_slot, 64, color.as_slice());
     }
 
-    // TODO: Should indbo use persistently mapped buffers? (and more importantly ssbo)
-    // idk how neccesary this function is
-    // fn resize_indbo(&mut self) {
-    //     unsafe {
-    //         // let mut commands: Vec<IndirectDrawCommand> = Vec::new();
-    //         // for i in 0..self.draw_commands.len() {
-    //         //     if self.draw_commands[i].indice_count != 0 {
-    //         //         commands.push(self.draw_commands[i].clone());
-    //         //     }     
-    //         // }
-    //         //println!("Updating INDBO with contents: {:?}", commands);
+    pub fn set_texture_z(&mut self, slot: i32, instance: i32, tex: &f32) {
+        let instance_slot = self.instance_slot(slot, instance);
+        self.write_instance_data(instance_slot, 64 + 16, std::slice::from_ref(tex));
+    }
 
-    //         //self.draw_count = commands.len() as i32;
+    fn write_instance_data(&mut self, instance_slot: usize, offset: usize, floats: &[f32]) {
+        let start = instance_slot * self.instance_nbytes as usize + offset;
+        for (i, float) in floats.iter().enumerate() {
+            self.instances[start + i * 4..start + i * 4 + 4].copy_from_slice(&float.to_ne_bytes());
+        }
+    }
 
-    //         //println!("Drawcount = {}", self.draw_count);
-    //         //println!("Size = {}", self.draw_count * size_of::<IndirectDrawCommand>() as i32);
-    //         gl.BindBuffer(GL_DRAW_INDIRECT_BUFFER, self.indbo);
-    //         gl.BufferData(GL_DRAW_INDIRECT_BUFFER, (size_of::<IndirectDrawCommand>() as isize * self.mesh_capacity) as isize, self.draw_commands.as_ptr() as *const c_void, GL_DYNAMIC_DRAW);
-    //     } 
-    // }
+    // matrices should be what the vertex shader multiplies skinned vertices by (so already adjusted for Mesh::normalization). at most MAX_JOINTS of them
+    pub fn set_joint_matrices(&self, slot: i32, instance: i32, matrices: &[glm::Mat4]) {
+        assert!(self.skinned, "Tried to set joint matrices in a meshpool that isn't for skinned meshes.");
+        let instance_slot = self.instance_slot(slot, instance);
+        assert!(instance_slot < self.joint_capacity);
+        unsafe {
+            let n = matrices.len().min(MAX_JOINTS);
+            libc::memcpy(self.pool_joints.offset((instance_slot * MAX_JOINTS * 64) as isize), matrices.as_ptr() as *const c_void, n * 64);
+        }
+    }
+
+    // makes the slot's draw command match its allocations and copies it into the indbo
+    fn update_command(&mut self, slot: i32) {
+        let command = match self.allocations[slot as usize] {
+            Some([vertices, indices, instances]) => IndirectDrawCommand {
+                indice_count: indices.len as u32,
+                instance_count: instances.len as u32,
+                start: (indices.offset * core::mem::size_of::<GLuint>()) as u32,
+                base_vertex: vertices.offset as i32,
+                base_instance: instances.offset as u32
+            },
+            None => IndirectDrawCommand { indice_count: 0, instance_count: 0, start: 0, base_vertex: 0, base_instance: 0 } // if n_indices == 0, we treat that as no command
+        };
+        self.draw_commands[slot as usize] = command;
+        self.update_indbo(slot);
+    }
 
     fn update_indbo(&mut self, slot: i32) {
         unsafe {
-            // println!("INDBO: Buffering 20 bytes at offset {}, including a count of {}", slot * 20, &self.draw_commands[slot as usize].indice_count);
-            //gl.BufferSubData(GL_DRAW_INDIRECT_BUFFER, (slot * 20) as isize,size_of::<IndirectDrawCommand>() as isize, self.draw_commands.as_ptr().offset(slot as isize).cast());
             libc::memcpy(self.pool_commands.offset(slot as isize * 20), self.draw_commands.as_ptr().offset(slot as isize).cast(), 20);
         }
     }
 
-    // when adding static meshes, this function will try to merge them with identical static meshes 
-    // returns false if fail
-    fn try_extend_instance(&mut self, gl: &gl46::GlFns, count: u32, mesh_uuid: i32) -> (bool, i32, i32) {
-        // let mut indices_to_pop: Vec<isize> = Vec::with_capacity(count as usize);
-        let mut indices_to_falsify: Vec<usize> = Vec::with_capacity(count as usize);
-
-        'outer: for (slot, uuid) in self.slot_contents.iter() {
-            if mesh_uuid == *uuid { // find slot with identical mesh
-                // see if there's enough room for the additional instance matrices
-                let start = self.vertex_slots_to_instanced_slots[slot] + self.draw_commands[*slot as usize].instance_count as i32;
-                let gap = (start + 1 + count as i32) - self.n_available_instance_slots as i32;
-                // println!("Gap = {} for adding count {} to pool with space {} when starting at {}", gap, count, self.n_available_instance_slots, start);
-                if gap > 0 { // if the mvbo is tiny expand it, this technically will run when it doesn't need to but it would just run in find_instance_slot so its ok
-                    // println!("We gotta expand!");
-                    MeshPool::expand_mvbo(gl, (gap as f32 / self.base_instanced_data_capacity as f32).ceil() as isize, &mut self.instanced_data_capacity, self.base_instanced_data_capacity, &mut self.n_available_instance_slots,  /*&mut self.available_instanced_data_slots,*/ self.mesh_capacity, &mut self.mvbo, &mut self.pool_instanced_data, self.instance_nbytes, &mut self.available_instanced_data_slots_vec);
-                }
-                //println!("expansion successful");
-                'inner: for i in start+1..(start+1+count as i32) {
-                    //println!("stepping {}", i);
-                    let free = self.available_instanced_data_slots_vec[i as usize]; 
-                    if free {
-                        // indices_to_pop.push(slot_index);
-                        indices_to_falsify.push(i as usize);
-                        // print!("b");
-                    }
-                    else {
-                        // println!("a");
-                        // indices_to_pop.clear();
-                        indices_to_falsify.clear();
-                        // println!("fail");
-                        continue 'outer;
-                    }
-                }
-                // println!("iteratino complete");
-                // if it got through the for loop it works!
-
-                // mark slots as taken
-                //remove biggest indices first cuz otherwise indices would change
-                // indices_to_pop.sort_unstable_by(|a, b| b.cmp(a));
-                // for slot in indices_to_pop {
-                //     //println!("popping {}", self.available_instanced_data_slots[slot as usize]);
-                //     self.available_instanced_data_slots.remove(slot as usize);
-                    
-                // }
-
-                self.n_available_instance_slots -= indices_to_falsify.len();
-                for slot in indices_to_falsify {
-                    self.available_instanced_data_slots_vec[slot] = false;
-                }
-                
-
-                self.draw_commands[*slot as usize].instance_count += count;
-                // println!("returning success on extension");
-                return (true, *slot, start);
+    // Adds count identical meshes to pool
+    // if the mesh is not dynamic (meaning its vertices will never be modified), then it's instanced with the copies already in the pool
+    // returns a tuple of (slot, instance) although instance will always be 0 unless it was instanced
+        // if count > 0, instance will be for the first one
+    pub fn add_mesh(&mut self, device: &dyn RenderDevice, mesh_uuid: i32, vertices:&Vec<GLfloat>, indices:&Vec<GLuint>, count: u32, dynamic: bool) -> (i32, i32) {
+        if !dynamic {
+            if let Some(slot) = self.static_slots.get(&mesh_uuid).copied() {
+                let first_new_instance = self.extend_instances(device, slot, count as usize);
+                return (slot, first_new_instance as i32);
             }
         }
-        return (false, -1, -1);
-    }
-
-    // finds first location in the mvbo that will accomodate count instances, expanding the mvbo if needed
-    // removes items from available_instance_data_slots for you
-    // this is an extremely dumb, unoptimized way of doing that but oh well
-    // fortunately the overhead is only there when count > 1 so if ur not instancing its fine
-    fn find_instance_slot(&mut self, gl: &gl46::GlFns, count: u32) -> i32 {
-        //let tail_length = count - 1;
-        //let mut index = 0;
-        // let mut indices_to_pop: Vec<isize> = Vec::with_capacity(count as usize);
-        let mut indices_to_falsify: Vec<usize> = Vec::with_capacity(count as usize);
-        let mut found = -1;
-        let mut slot = -1;
-        let mut gap = 0;
-        'outer: for is_free in &self.available_instanced_data_slots_vec {
-            slot+=1;
-            if *is_free == true {
-                if count == 1 {
-                    found = slot;
-                    // indices_to_pop.push(index);
-                    indices_to_falsify.push(slot as usize);
-                    break 'outer;
-                }
-                else {
-                    'inner: for index2 in 0..count {
-                        if slot as usize + index2 as usize >= self.available_instanced_data_slots_vec.len() {
-                            gap = (slot + index2 as i32 + 1) - self.available_instanced_data_slots_vec.len() as i32;
-                            break 'outer;
-                        }
-                        
-                        if !self.available_instanced_data_slots_vec[slot as usize + index2 as usize] {
-                            slot += 1;
-                            // indices_to_pop.clear();
-                            indices_to_falsify.clear();
-                            continue 'outer;
-                        }
-                        else {
-                            // indices_to_pop.push(index_deque(&self.available_instanced_data_slots, slot + index2 as i32));
-                            indices_to_falsify.push(slot as usize + index2 as usize);
-                            slot += 1;
-                        }
-                    }
-                    found = slot;
-                    break 'outer;
-                    // index += 1;
-                }
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.allocations.push(None);
+                self.draw_commands.push(IndirectDrawCommand::new());
+                self.slot_radii.push(0.0);
+                self.grow_commands(device, self.draw_commands.len());
+                self.draw_commands.len() as i32 - 1
             }
+        };
+        let instance_offset = self.allocate(device, PoolMemory::Instances, count as usize);
+        self.allocations[slot as usize] = Some([Allocation::empty(), Allocation::empty(), Allocation { offset: instance_offset, capacity: count as usize, len: count as usize }]);
+        self.update_mesh(device, slot, vertices, indices);
+
+        if !dynamic {
+            self.static_slots.insert(mesh_uuid, slot);
+            self.slot_contents.insert(slot, mesh_uuid);
         }
+        return (slot, 0);
+    }
 
-        if gap < 0 {
-            // println!("FIS: EXPANDING BY {}", gap);
-            MeshPool::expand_mvbo(gl, (gap as f32 / self.base_instanced_data_capacity as f32).ceil() as isize, &mut self.instanced_data_capacity, self.base_instanced_data_capacity, &mut self.n_available_instance_slots,  /*&mut self.available_instanced_data_slots,*/ self.mesh_capacity, &mut self.mvbo, &mut self.pool_instanced_data, self.instance_nbytes, &mut self.available_instanced_data_slots_vec);
-            return self.find_instance_slot(gl, count);
+    // replaces the vertices and indices of the mesh in slot (for dynamic meshes, every instance of a static one would change).
+    // they're written over the old ones if there's room, otherwise they go somewhere else in the pool
+    pub fn update_mesh(&mut self, device: &dyn RenderDevice, slot:i32, vertices:&[GLfloat], indices:&[GLuint]) {
+        let vertex_offset = self.reserve(device, slot, PoolMemory::Vertices, vertices.len() / self.floats_per_vertex);
+        let index_offset = self.reserve(device, slot, PoolMemory::Indices, indices.len());
+        unsafe {
+            let v_destination = self.pool_vertices.offset((vertex_offset * self.element_nbytes(PoolMemory::Vertices)) as isize);
+            libc::memcpy(v_destination, vertices.as_ptr().cast(), vertices.len() * core::mem::size_of::<GLfloat>());
+            let i_destination = self.pool_indices.offset((index_offset * core::mem::size_of::<GLuint>()) as isize);
+            libc::memcpy(i_destination, indices.as_ptr().cast(), indices.len() * core::mem::size_of::<GLuint>());
         }
 
-        // mark slots as taken
-        //remove biggest indices first cuz otherwise indices would change
-        // indices_to_pop.sort_unstable_by(|a, b| b.cmp(a));
-        // for slot in indices_to_pop {
-        //     //println!("popping {}", self.available_instanced_data_slots[slot as usize]);
-        //     self.available_instanced_data_slots.remove(slot as usize);
-            
-        // }
+        self.slot_radii[slot as usize] = vertices.chunks_exact(self.floats_per_vertex).map(|v| glm::vec3(v[0], v[1], v[2]).norm()).fold(0.0, f32::max);
+        self.update_command(slot);
+    }
 
-        self.n_available_instance_slots -= indices_to_falsify.len();
-        for slot in indices_to_falsify {
-            self.available_instanced_data_slots_vec[slot] = false;
+    // overwrites vertices of the mesh in slot starting at first_vertex, they have to be in this pool's vertex layout and not go past the mesh's last vertex
+    pub fn update_vertices(&mut self, slot: i32, first_vertex: usize, vertices: &[GLfloat]) {
+        let allocation = self.allocations[slot as usize].unwrap()[PoolMemory::Vertices as usize];
+        let n_vertices = vertices.len() / self.floats_per_vertex;
+        assert!(first_vertex + n_vertices <= allocation.len, "Tried to write vertices up to {} of a mesh with {}.", first_vertex + n_vertices, allocation.len);
+        unsafe {
+            let destination = self.pool_vertices.offset(((allocation.offset + first_vertex) * self.element_nbytes(PoolMemory::Vertices)) as isize);
+            libc::memcpy(destination, vertices.as_ptr().cast(), vertices.len() * core::mem::size_of::<GLfloat>());
         }
-        
+        // the old vertices might have been the furthest ones, but a radius that's too big only makes culling a little less tight
+        let radius = vertices.chunks_exact(self.floats_per_vertex).map(|v| glm::vec3(v[0], v[1], v[2]).norm()).fold(0.0, f32::max);
+        self.slot_radii[slot as usize] = self.slot_radii[slot as usize].max(radius);
+    }
 
-        if found == -1 {
-            MeshPool::expand_mvbo(gl, (count as f32 / self.base_instanced_data_capacity as f32).ceil() as isize, &mut self.instanced_data_capacity, self.base_instanced_data_capacity, &mut self.n_available_instance_slots, /*&mut self.available_instanced_data_slots,*/ self.mesh_capacity, &mut self.mvbo, &mut self.pool_instanced_data, self.instance_nbytes, &mut self.available_instanced_data_slots_vec);
-            // println!("Having to expand the mvbo because we found nada.");
-            return self.find_instance_slot(gl, count);
-        }
-        else {
-            return found;
+    // overwrites indices of the mesh in slot starting at first_index, without changing how many it has
+    pub fn update_indices(&mut self, slot: i32, first_index: usize, indices: &[GLuint]) {
+        let allocation = self.allocations[slot as usize].unwrap()[PoolMemory::Indices as usize];
+        assert!(first_index + indices.len() <= allocation.len, "Tried to write indices up to {} of a mesh with {}.", first_index + indices.len(), allocation.len);
+        unsafe {
+            let destination = self.pool_indices.offset(((allocation.offset + first_index) * core::mem::size_of::<GLuint>()) as isize);
+            libc::memcpy(destination, indices.as_ptr().cast(), indices.len() * core::mem::size_of::<GLuint>());
         }
     }
 
-    // Adds count identical meshes to pool
-    // if the mesh is not dynamic (meaning its vertices will never be modified), then it might be instanced for optimization
-    // returns a tuple of (slot, instance) although instance will always be 0 unless it was instanced 
-        // if count > 0, instance will be for the first one
-    pub fn add_mesh(&mut self, gl: &gl46::GlFns, mesh_uuid: i32, vertices:&Vec<GLfloat>, indices:&Vec<GLuint>, count: u32, dynamic: bool) -> (i32, i32) {
-        let now = std::time::Instant::now();
-        let result : (bool, i32, i32) = self.try_extend_instance(gl, count, mesh_uuid);
-        // println!("Attempting to extend the instance took {:?} seconds.", now.elapsed());
-        if dynamic || !result.0 {
-            
-
-            self.drawcount += 1;
-            // println!("Adding mesh, drawcount now {}", self.drawcount);
-            if self.available_slots.len() == 0 {
-                self.expand_vbo(gl);
-            }
-            let vertex_slot = self.available_slots.pop_front().unwrap();
-            let instance_slot = self.find_instance_slot(gl, count); //println!("Found instance slot {} for {} instances.", instance_slot, count);
-
-            self.vertex_slots_to_instanced_slots.insert(vertex_slot, instance_slot);
-            // println!("Vertex slot {} corresponds to instance slot {}", vertex_slot, instance_slot);
-            
-            self.update_mesh(vertex_slot, vertices, indices);
-            self.draw_commands[vertex_slot as usize].instance_count = count;
-            self.draw_commands[vertex_slot as usize].base_instance = instance_slot as u32;
-            // println!("Draw command at slot {} is {:?}", vertex_slot, self.draw_commands[vertex_slot as usize]);
-            
-            if (!dynamic) {
-                self.slot_contents.insert(vertex_slot, mesh_uuid);
-            }
-            return (vertex_slot, 0);
+    // frees the slot and every instance of it
+    pub fn remove_mesh(&mut self, slot:i32) {
+        let allocations = self.allocations[slot as usize].take().unwrap();
+        for (m, allocation) in allocations.iter().enumerate() {
+            self.allocators[m].free(allocation.offset, allocation.capacity);
         }
-        else {
-            // println!("Successfully extended previously existing instanced mesh by count {}.", count);
-            // println!("\tDraw command at slot {} is {:?}", result.1, self.draw_commands[result.1 as usize]);
-            return (result.1, result.2);
+        if let Some(uuid) = self.slot_contents.remove(&slot) {
+            self.static_slots.remove(&uuid);
         }
+        self.free_slots.push(slot);
+        self.update_command(slot);
     }
 
-    fn update_mesh(&mut self, slot:i32, vertices:&Vec<GLfloat>, indices:&Vec<GLuint>) {
-        // println!("Updating slot {}", slot);
-        unsafe {
-            let v_destination = self.pool_vertices.offset(slot as isize * self.mesh_vertex_nbytes as isize);
-            libc::memcpy(v_destination, vertices.as_ptr().cast(), vertices.len() * core::mem::size_of::<GLfloat>());
-            let i_destination = self.pool_indices.offset(slot as isize * self.mesh_index_nbytes as isize);
-            //println!("Offset by {}", slot as isize * self.mesh_index_nbytes as isize);
-            libc::memcpy(i_destination, indices.as_ptr().cast(), indices.len() * core::mem::size_of::<GLuint>());
-            // if slot == 1 {
-            //     println!("Data from ptr: {:?}", Vec::from_raw_parts(i_destination as *mut u32, indices.len() as usize, indices.len() as usize))
-            //     // let ptr = self.pool_vertices as *mut GLfloat {
+    // O(1), removes one instance of the mesh in slot by moving the slot's last instance into its place, so instances stay contiguous.
+    // returns the (relative) index of the instance that got moved into instance's place, None if instance was the last one and nothing moved.
+    // removing the last instance of a slot frees the whole slot. otherwise the room it took stays the slot's, for its next instance
+    pub fn remove_instance(&mut self, slot: i32, instance: i32) -> Option<i32> {
+        let count = self.draw_commands[slot as usize].instance_count as i32;
+        assert!(instance < count, "Tried to remove instance {} of slot {}, which only has {} instances.", instance, slot, count);
+        if count == 1 {
+            self.remove_mesh(slot);
+            return None;
+        }
 
-            //     // }
-            // }
+        let base_instance = self.allocations[slot as usize].unwrap()[PoolMemory::Instances as usize].offset;
+        let last = count - 1;
+        if instance != last {
+            self.move_elements(PoolMemory::Instances, base_instance + last as usize, base_instance + instance as usize, 1);
+        }
+        self.allocations[slot as usize].as_mut().unwrap()[PoolMemory::Instances as usize].len -= 1;
+        self.update_command(slot);
 
-            
+        if instance != last {
+            return Some(last);
         }
+        return None;
+    }
 
-        self.draw_commands[slot as usize].indice_count = indices.len() as u32;
-        self.draw_commands[slot as usize].start = (slot * self.mesh_index_nbytes as i32) as u32;
-        self.draw_commands[slot as usize].base_vertex = slot * (self.mesh_vertex_nbytes/(core::mem::size_of::<GLfloat>() * N_FLOATS_PER_VERTEX) as isize) as i32; 
-        
-        self.update_indbo(slot);
+    pub fn instance_count(&self, slot: i32) -> u32 {
+        return self.draw_commands[slot as usize].instance_count;
     }
 
-    pub fn remove_mesh(&mut self, slot:i32) {
-        self.drawcount -= 1;
-        if self.slot_contents.contains_key(&slot) {
-            self.slot_contents.remove(&slot);
-        }
-        self.draw_commands[slot as usize].indice_count = 0; // if n_indices == 0, we treat that as no command
-        self.available_slots.push_back(slot);
-        self.update_indbo(slot);
+    // how much of each buffer is used, given to meshes but not used, and free
+    pub fn stats(&self) -> MeshPoolStats {
+        let memory_stats = |memory: PoolMemory| {
+            let nbytes = self.element_nbytes(memory);
+            let allocator = &self.allocators[memory as usize];
+            let (mut used, mut allocated) = (0, 0);
+            for allocations in self.allocations.iter().flatten() {
+                used += allocations[memory as usize].len;
+                allocated += allocations[memory as usize].capacity;
+            }
+            assert!(allocated == allocator.used(), "Meshpool slots have {} elements of {:?} but the allocator gave out {}.", allocated, memory, allocator.used());
+            return PoolMemoryStats {
+                used_bytes: used * nbytes,
+                wasted_bytes: (allocated - used) * nbytes,
+                free_bytes: allocator.free_space() * nbytes,
+                largest_free_bytes: allocator.largest_free() * nbytes,
+                n_free_ranges: allocator.n_free_ranges(),
+            };
+        };
+        return MeshPoolStats {
+            n_meshes: self.allocations.iter().flatten().count(),
+            vertices: memory_stats(PoolMemory::Vertices),
+            indices: memory_stats(PoolMemory::Indices),
+            instances: memory_stats(PoolMemory::Instances),
+        };
     }
-    
-    pub fn draw(&self, gl: &gl46::GlFns) { 
-        //println!("Drawing!");
-        unsafe {
-             
-            // gl.PointSize(20.0);
-
-            let now = std::time::Instant::now();
-            gl.BindVertexArray(self.vao); 
-            gl.BindBuffer(GL_DRAW_INDIRECT_BUFFER, self.indbo);
-            gl.BindBuffer(GL_ELEMENT_ARRAY_BUFFER, self.ibo);
-
-            let mut index_counts: Vec<i32> = Vec::new();
-            let mut index_starts: Vec<*const c_void> = Vec::new();
-            let mut base_vertices: Vec<i32> = Vec::new();
-            // let mut index = 0;
-            for command in &self.draw_commands {
-                if command.indice_count != 0 {
-                    index_counts.push(command.indice_count as i32);
-                    index_starts.push(command.start as *const c_void);
-                    base_vertices.push(command.base_vertex as i32);
-                    // gl.DrawElementsIndirect(GL_POINTS, GL_UNSIGNED_INT, index as *const c_void);
-                    // index += 20;
-                    gl.DrawElementsInstancedBaseVertexBaseInstance(GL_TRIANGLES, command.indice_count as i32, GL_UNSIGNED_INT, command.start as *const c_void, command.instance_count as i32, command.base_vertex, command.base_instance);
-                }  
+
+    // draws every mesh in the pool, or with a frustum only the instances inside it (skinned pools are never culled, since their joint matrices are found by instance slot).
+    // the visible instances of each draw command are copied to the start of its range in the mvbo so they stay contiguous,
+    // after waiting for the gpu to finish the previous draw() since the mvbo is persistently mapped.
+    // returns how many instances were drawn
+    pub fn draw(&self, device: &dyn RenderDevice, frustum: Option<&Frustum>) -> usize {
+        device.bind_vertex_array(self.vao);
+        device.bind_buffer(BufferTarget::DrawIndirect, self.indbo);
+        device.bind_buffer(BufferTarget::Index, self.ibo);
+        if self.skinned {
+            device.bind_buffer_base(BufferTarget::ShaderStorage, JOINT_MATRICES_BINDING, self.jbo); // the shader indexes it with gl_BaseInstance + gl_InstanceID
+        }
+        let frustum = if self.skinned {None} else {frustum};
+        device.wait_fence(self.mvbo_fence.replace(0));
+
+        // TODO: one multi_draw_elements_indirect() instead, once start is in indices instead of bytes
+        let mut n_drawn = 0;
+        for (slot, command) in self.draw_commands.iter().enumerate() {
+            if command.indice_count != 0 {
+                let n_visible = self.upload_visible_instances(command, self.slot_radii[slot], frustum);
+                if n_visible != 0 {
+                    device.draw_elements_instanced(command.indice_count as i32, command.start as usize, n_visible as i32, command.base_vertex, command.base_instance);
+                }
+                n_drawn += n_visible;
             }
+        }
+        self.mvbo_fence.set(device.create_fence());
+        return n_drawn;
+    }
 
-            // index_counts.remove(0);
-            // index_starts.remove(0);
-            // base_vertices.remove(0);
-            
-           // gl.BindBuffer(GL_SHADER_STORAGE_BUFFER, self.ssbo);
-            //gl.BindBufferBase(GL_SHADER_STORAGE_BUFFER, 3, self.ssbo);
-            //println!("Count {}", self.drawcount);
-            //gl.DrawElementsInstanced(GL_TRIANGLES, self.mesh_capacity as i32, GL_UNSIGNED_INT, null(), 2);
-            // gl.MultiDrawElementsIndirect(GL_TRIANGLES, GL_UNSIGNED_INT, 0 as *const c_void, self.drawcount as i32, 0);
-            // println!("draw command elapsed {}ms", now.elapsed().as_millis());
-            //println!("counts {:?}", index_counts);
-            //println!("starts {:?}", index_starts);
-            //println!("bases {:?}", base_vertices);
-           //gl.MultiDrawElementsBaseVertex(GL_TRIANGLES, index_counts.as_ptr(), GL_UNSIGNED_INT, index_starts.as_ptr(), index_counts.len() as i32, base_vertices.as_ptr());
-        
+    // copies the command's instances that are inside frustum (all of them if it's None) into the mvbo, returns how many
+    fn upload_visible_instances(&self, command: &IndirectDrawCommand, radius: f32, frustum: Option<&Frustum>) -> usize {
+        let nbytes = self.instance_nbytes as usize;
+        let base = command.base_instance as usize;
+        let count = command.instance_count as usize;
+        if frustum.is_none() {
+            unsafe { libc::memcpy(self.pool_instanced_data.offset((base * nbytes) as isize), self.instances[base * nbytes..].as_ptr() as *const c_void, count * nbytes); }
+            return count;
         }
+
+        let frustum = frustum.unwrap();
+        let mut n_visible = 0;
+        for instance in base..base + count {
+            let bounds = &self.instance_bounds[instance];
+            if frustum.intersects_sphere(&bounds.xyz(), radius * bounds.w) {
+                unsafe { libc::memcpy(self.pool_instanced_data.offset(((base + n_visible) * nbytes) as isize), self.instances[instance * nbytes..].as_ptr() as *const c_void, nbytes); }
+                n_visible += 1;
+            }
+        }
+        return n_visible;
+    }
+
+    pub fn n_instances(&self) -> usize {
+        return self.draw_commands.iter().filter(|c| c.indice_count != 0).map(|c| c.instance_count as usize).sum();
+    }
+
+    // the mvbo, so headless checks can look at instance data through RecordingDevice::buffer_contents()
+    pub fn instanced_data_buffer(&self) -> GLuint {
+        return self.mvbo;
+    }
+
+    // the jbo (0 unless the pool is skinned), for checking set_joint_matrices() writes where the shader reads
+    pub fn joint_buffer(&self) -> GLuint {
+        return self.jbo;
+    }
+
+    // the vbo, for checking vertices end up where draw commands say they are
+    pub fn vertex_buffer(&self) -> GLuint {
+        return self.vbo;
     }
 }
 
@@ -636,4 +674,4 @@ impl IndirectDrawCommand {
     fn clone(&self) -> IndirectDrawCommand {
         return IndirectDrawCommand { indice_count: self.indice_count, instance_count: self.instance_count, start: self.start, base_vertex: self.base_vertex, base_instance: self.base_instance }
     }
-}
\ No newline at end of file
+}
@@ -8,16 +8,44 @@ pub type GLint = c_int;
 pub use gl_error_checking::*;
 pub use graphics_engine::*;
 pub use mesh::*;
+pub use mesh_processing::*;
+pub use primitives::*;
+pub use obj::*;
 pub use meshpool::*;
+pub use range_allocator::*;
+pub use simplify::*;
+pub use lod::*;
 pub use framebuffer::*;
 pub use camera::*;
+pub use frustum::*;
 pub use shader_program::*;
 pub use texture::*;
+pub use render_device::*;
+pub use gl_device::*;
+pub use recording_device::*;
+pub use software_device::*;
+pub use rgba_image::*;
 mod gl_error_checking;
 mod graphics_engine;
 mod mesh;
+mod mesh_processing;
+mod primitives;
+mod obj;
 mod meshpool;
+mod range_allocator;
+mod simplify;
+mod lod;
 mod framebuffer;
 mod camera;
+mod frustum;
 mod shader_program;
-mod texture;
\ No newline at end of file
+mod texture;
+mod render_device;
+mod gl_device;
+mod recording_device;
+mod software_device;
+mod rgba_image;
+#[cfg(test)]
+mod render_golden;
+#[cfg(test)]
+mod render_checks;
\ No newline at end of file
@@ -0,0 +1,94 @@
+// Wavefront OBJ loading. Every object (o) or group (g) in a file is read as its own list of vertices along with which MTL material it uses.
+// Objects go through prepare_imported_mesh(), which among other things makes up normals and texture coordinates for files that don't have them.
+// Mesh::from_obj() puts a whole file into one mesh, scene::ObjModel keeps the objects apart and loads their materials.
+
+use crate::graphics::*;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(String, tobj::LoadError), // the file at this path couldn't be opened or isn't valid
+    Empty(String), // the file at this path has no triangles
+    Texture(String, String), // a material's texture at this path couldn't be loaded, and why
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Load(path, err) => write!(f, "couldn't load {}: {}", path, err),
+            ObjError::Empty(path) => write!(f, "{} doesn't have any triangles", path),
+            ObjError::Texture(path, err) => write!(f, "couldn't load texture {}: {}", path, err),
+        }
+    }
+}
+
+// one object or group from an obj file, with vertices laid out like Mesh::vertices (not scaled into range yet)
+pub struct ObjPart {
+    pub name: String,
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>, // index into the materials read_obj() returns
+}
+
+// reads every object in the file and the materials from its MTL file(s). a missing or broken MTL file isn't an error, the objects just won't have materials
+pub fn read_obj(path: &str, normals: GeneratedNormals) -> Result<(Vec<ObjPart>, Vec<tobj::Material>), ObjError> {
+    let options = tobj::LoadOptions {single_index: true, triangulate: true, ignore_points: true, ignore_lines: true};
+    let (models, materials) = tobj::load_obj(path, &options).map_err(|err| ObjError::Load(path.to_string(), err))?;
+    let materials = materials.unwrap_or_else(|err| {
+        println!("Couldn't load the materials for {}: {}", path, err);
+        return Vec::new();
+    });
+
+    let mut parts = Vec::new();
+    for model in models {
+        if model.mesh.indices.is_empty() {
+            continue;
+        }
+        // obj files be like POSPOSPOS NORMALNORMALNORMAL TEXTURETEXTURETEXTURE and we be like POSNORMALTEXTURE POSNORMALTEXTURE
+        let mesh = &model.mesh;
+        let n_vertices = mesh.positions.len() / 3;
+        let has_normals = mesh.normals.len() == n_vertices * 3;
+        let has_texcoords = mesh.texcoords.len() == n_vertices * 2;
+        let mut vertices = Vec::with_capacity(n_vertices * N_FLOATS_PER_VERTEX);
+        for i in 0..n_vertices {
+            vertices.extend_from_slice(&mesh.positions[i * 3..i * 3 + 3]);
+            match has_normals {
+                true => vertices.extend_from_slice(&mesh.normals[i * 3..i * 3 + 3]),
+                false => vertices.extend_from_slice(&[0.0, 0.0, 0.0])
+            }
+            match has_texcoords {
+                true => vertices.extend_from_slice(&mesh.texcoords[i * 2..i * 2 + 2]),
+                false => vertices.extend_from_slice(&[0.0, 0.0])
+            }
+        }
+
+        let (vertices, indices) = prepare_imported_mesh(&vertices, &mesh.indices, N_FLOATS_PER_VERTEX, has_normals, has_texcoords, normals);
+        let material = mesh.material_id.filter(|m| *m < materials.len());
+        parts.push(ObjPart { name: model.name, vertices, indices, material });
+    }
+
+    if parts.is_empty() {
+        return Err(ObjError::Empty(path.to_string()));
+    }
+    return Ok((parts, materials));
+}
+
+impl Mesh {
+    // loads every object in the file as one mesh, with smooth normals if it doesn't have any.
+    // returns mesh uuid, mesh itself can be accessed as needed (like if it's a dynamic mesh) by using uuid to index into LOADED_MESHES
+    pub fn from_obj(path: &str, texture_id: u32, shader_id: u32) -> Result<usize, ObjError> {
+        let (parts, _) = read_obj(path, GeneratedNormals::Smooth)?;
+        let (mut verts, mut indices) = (Vec::new(), Vec::new());
+        for part in parts {
+            let first = (verts.len() / N_FLOATS_PER_VERTEX) as u32;
+            indices.extend(part.indices.iter().map(|i| i + first));
+            verts.extend(part.vertices);
+        }
+
+        println!("Created mesh with {} vertices.", verts.len()/N_FLOATS_PER_VERTEX);
+        let mut m = Mesh::from_vertices(verts, indices, texture_id, shader_id, false);
+        m.source_path = Some(path.to_string());
+        let uuid = m.uuid;
+        LOADED_MESHES.lock().unwrap().insert(uuid, m);
+        return Ok(uuid);
+    }
+}
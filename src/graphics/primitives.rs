@@ -0,0 +1,320 @@
+// Procedurally generated meshes, for when a shape doesn't need a file under models/.
+// Primitive::generate() gives vertices and indices laid out like Mesh::vertices, ready for Mesh::from_vertices(), or Mesh::from_primitive() does both.
+// Everything is centered on the origin with y up, triangles wind counter clockwise seen from outside, and uvs go 0..1 with v pointing up
+// (except Icosphere's, which go a little past 1 where triangles cross the seam so the texture doesn't smear across them).
+// Mesh::from_vertices() scales meshes into 1m^3, but the sizes here still matter for the proportions, and Mesh::original_size/normalization remember them.
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use glm::{Vec2, Vec3, vec2, vec3};
+
+use crate::graphics::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Primitive {
+    Cube { size: Vec3 },
+    UvSphere { radius: f32, segments: u32, rings: u32 }, // segments around y, rings from pole to pole
+    Icosphere { radius: f32, subdivisions: u32 }, // 0 subdivisions is an icosahedron, every one after that has 4x the triangles
+    Cylinder { radius: f32, height: f32, segments: u32 },
+    Cone { radius: f32, height: f32, segments: u32 }, // the tip is at the top
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 }, // height includes the caps, rings are per cap
+    Plane { size: Vec2, subdivisions: u32 }, // in xz facing +y, subdivisions is how many times each side is cut
+    Torus { major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32 }, // lies in xz, major_radius is to the middle of the tube
+}
+
+impl Primitive {
+    pub fn generate(&self) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        match *self {
+            Primitive::Cube { size } => {
+                let half = size / 2.0;
+                // (normal, u direction, v direction), with u cross v = normal so the faces wind outward
+                let faces = [
+                    (vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0)),
+                    (vec3(-1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0)),
+                    (vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0)),
+                    (vec3(0.0, -1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)),
+                    (vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)),
+                    (vec3(0.0, 0.0, -1.0), vec3(-1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)),
+                ];
+                for (normal, u_dir, v_dir) in faces {
+                    grid(&mut vertices, &mut indices, 1, 1, |u, row| {
+                        let v = row as f32;
+                        let position = (normal + u_dir * (u * 2.0 - 1.0) + v_dir * (v * 2.0 - 1.0)).component_mul(&half);
+                        return (position, normal, v);
+                    });
+                }
+            },
+            Primitive::UvSphere { radius, segments, rings } => {
+                let rings = rings.max(2);
+                grid(&mut vertices, &mut indices, segments.max(3), rings, |u, row| {
+                    let normal = around_y(u, latitude(row, rings));
+                    return (normal * radius, normal, row as f32 / rings as f32);
+                });
+            },
+            Primitive::Icosphere { radius, subdivisions } => icosphere(&mut vertices, &mut indices, radius, subdivisions),
+            Primitive::Cylinder { radius, height, segments } => {
+                let segments = segments.max(3);
+                grid(&mut vertices, &mut indices, segments, 1, |u, row| {
+                    let normal = around_y(u, 0.0);
+                    return (normal * radius + vec3(0.0, (row as f32 - 0.5) * height, 0.0), normal, row as f32);
+                });
+                cap(&mut vertices, &mut indices, radius, height / 2.0, segments, true);
+                cap(&mut vertices, &mut indices, radius, -height / 2.0, segments, false);
+            },
+            Primitive::Cone { radius, height, segments } => {
+                let segments = segments.max(3);
+                let slope = height.atan2(radius); // how steep the side is
+                grid(&mut vertices, &mut indices, segments, 1, |u, row| {
+                    let ring = if row == 0 {around_y(u, 0.0) * radius} else {vec3(0.0, 0.0, 0.0)};
+                    let normal = around_y(u, PI / 2.0 - slope);
+                    return (ring + vec3(0.0, (row as f32 - 0.5) * height, 0.0), normal, row as f32);
+                });
+                cap(&mut vertices, &mut indices, radius, -height / 2.0, segments, false);
+            },
+            Primitive::Capsule { radius, height, segments, rings } => {
+                let rings = rings.max(1);
+                let height = height.max(radius * 2.0);
+                // rows 0..=rings are the bottom cap and rings + 1..=2 * rings + 1 the top one, the cylinder is the gap between them
+                grid(&mut vertices, &mut indices, segments.max(3), 2 * rings + 1, |u, row| {
+                    let (latitude, y) = match row <= rings {
+                        true => (latitude(row, 2 * rings), -(height / 2.0 - radius)),
+                        false => (latitude(row - 1, 2 * rings), height / 2.0 - radius),
+                    };
+                    let normal = around_y(u, latitude);
+                    let position = normal * radius + vec3(0.0, y, 0.0);
+                    return (position, normal, position.y / height + 0.5);
+                });
+            },
+            Primitive::Plane { size, subdivisions } => {
+                let cuts = subdivisions + 1;
+                grid(&mut vertices, &mut indices, cuts, cuts, |u, row| {
+                    let v = row as f32 / cuts as f32;
+                    return (vec3((u - 0.5) * size.x, 0.0, (0.5 - v) * size.y), vec3(0.0, 1.0, 0.0), v);
+                });
+            },
+            Primitive::Torus { major_radius, minor_radius, major_segments, minor_segments } => {
+                let minor_segments = minor_segments.max(3);
+                grid(&mut vertices, &mut indices, major_segments.max(3), minor_segments, |u, row| {
+                    let v = row as f32 / minor_segments as f32;
+                    // v starts on the inside of the ring so the seam is where it's hardest to see
+                    let normal = around_y(u, v * 2.0 * PI - PI);
+                    let center = around_y(u, 0.0) * major_radius;
+                    return (center + normal * minor_radius, normal, v);
+                });
+            },
+        }
+        return (vertices, indices);
+    }
+}
+
+impl Mesh {
+    // returns mesh uuid like from_obj(), the mesh is in LOADED_MESHES
+    pub fn from_primitive(primitive: &Primitive, texture_id: u32, shader_id: u32) -> usize {
+        let (vertices, indices) = primitive.generate();
+        let mesh = Mesh::from_vertices(vertices, indices, texture_id, shader_id, false);
+        let uuid = mesh.uuid;
+        LOADED_MESHES.lock().unwrap().insert(uuid, mesh);
+        return uuid;
+    }
+}
+
+fn push_vertex(vertices: &mut Vec<f32>, position: Vec3, normal: Vec3, uv: Vec2) {
+    vertices.extend_from_slice(&[position.x, position.y, position.z, normal.x, normal.y, normal.z, uv.x, uv.y]);
+}
+
+// direction at longitude u (0..1 around y, starting at +z and heading towards +x) and latitude in radians (0 is the equator, pi/2 straight up)
+fn around_y(u: f32, latitude: f32) -> Vec3 {
+    if latitude.abs() == PI / 2.0 { // cos() isn't quite 0 there
+        return vec3(0.0, latitude.signum(), 0.0);
+    }
+    let angle = u * 2.0 * PI;
+    return vec3(angle.sin() * latitude.cos(), latitude.sin(), angle.cos() * latitude.cos());
+}
+
+// latitude of row out of rows, from the bottom pole to the top one. exact at the poles so their vertices all end up in one spot
+fn latitude(row: u32, rows: u32) -> f32 {
+    return match row {
+        0 => -PI / 2.0,
+        r if r == rows => PI / 2.0,
+        r => r as f32 / rows as f32 * PI - PI / 2.0,
+    };
+}
+
+// a grid of quads, usually wrapped around y: columns + 1 vertices across (the last ones repeat the first ones' positions with u = 1, so the texture doesn't wrap backwards)
+// and rows + 1 up. surface(u, row) gives the position, normal and v. triangles with two corners in the same spot (at poles and tips) are left out
+fn grid(vertices: &mut Vec<f32>, indices: &mut Vec<u32>, columns: u32, rows: u32, surface: impl Fn(f32, u32) -> (Vec3, Vec3, f32)) {
+    let first = (vertices.len() / N_FLOATS_PER_VERTEX) as u32;
+    let mut positions = Vec::new();
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let u = column as f32 / columns as f32;
+            let (position, normal, v) = surface(u, row);
+            push_vertex(vertices, position, normal, vec2(u, v));
+            positions.push(position);
+        }
+    }
+    let index = |column: u32, row: u32| row * (columns + 1) + column;
+    for row in 0..rows {
+        for column in 0..columns {
+            let (a, b, c, d) = (index(column, row), index(column + 1, row), index(column + 1, row + 1), index(column, row + 1));
+            for triangle in [[a, b, c], [a, c, d]] {
+                let [pa, pb, pc] = triangle.map(|i| positions[i as usize]);
+                if pa != pb && pb != pc && pc != pa {
+                    indices.extend(triangle.map(|i| i + first));
+                }
+            }
+        }
+    }
+}
+
+// a flat disc closing one end of a cylinder or cone, facing up if top. its uvs are the disc seen from outside, squashed into 0..1
+fn cap(vertices: &mut Vec<f32>, indices: &mut Vec<u32>, radius: f32, y: f32, segments: u32, top: bool) {
+    let first = (vertices.len() / N_FLOATS_PER_VERTEX) as u32;
+    let normal = vec3(0.0, if top {1.0} else {-1.0}, 0.0);
+    push_vertex(vertices, vec3(0.0, y, 0.0), normal, vec2(0.5, 0.5));
+    for i in 0..segments {
+        let direction = around_y(i as f32 / segments as f32, 0.0);
+        let v_direction = if top {-direction.z} else {direction.z};
+        let uv = vec2(0.5 + direction.x / 2.0, 0.5 + v_direction / 2.0);
+        push_vertex(vertices, direction * radius + vec3(0.0, y, 0.0), normal, uv);
+    }
+    for i in 0..segments {
+        let (a, b) = (first + 1 + i, first + 1 + (i + 1) % segments);
+        indices.extend(if top {[first, a, b]} else {[first, b, a]});
+    }
+}
+
+fn icosphere(vertices: &mut Vec<f32>, indices: &mut Vec<u32>, radius: f32, subdivisions: u32) {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].iter().map(|(x, y, z)| vec3(*x, *y, *z).normalize()).collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| {
+            return *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                return positions.len() as u32 - 1;
+            });
+        };
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for [a, b, c] in triangles {
+            let (ab, bc, ca) = (midpoint(a, b, &mut positions), midpoint(b, c, &mut positions), midpoint(c, a, &mut positions));
+            subdivided.extend([[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        triangles = subdivided;
+    }
+
+    // spherical uvs, with the corners of triangles that cross the seam (behind, at -z) moved past u = 1 so the triangle doesn't span the whole texture,
+    // and the poles (which could be any u) getting the u of the rest of their triangle. those corners need their own vertices
+    let uv = |p: Vec3| vec2(0.5 + p.x.atan2(p.z) / (2.0 * PI), 0.5 + p.y.clamp(-1.0, 1.0).asin() / PI);
+    let is_pole = |p: Vec3| p.x.abs() < 1e-6 && p.z.abs() < 1e-6;
+    let mut vertex_indices: HashMap<(u32, u32), u32> = HashMap::new(); // (position, u's bits) -> vertex
+    for triangle in triangles {
+        let mut uvs = triangle.map(|i| uv(positions[i as usize]));
+        let us: Vec<f32> = (0..3).filter(|c| !is_pole(positions[triangle[*c] as usize])).map(|c| uvs[c].x).collect();
+        if us.iter().cloned().fold(0.0, f32::max) - us.iter().cloned().fold(1.0, f32::min) > 0.5 {
+            for uv in uvs.iter_mut() {
+                if uv.x < 0.5 {
+                    uv.x += 1.0;
+                }
+            }
+        }
+        for c in 0..3 {
+            if is_pole(positions[triangle[c] as usize]) {
+                uvs[c].x = (0..3).filter(|o| *o != c).map(|o| uvs[o].x).sum::<f32>() / 2.0;
+            }
+        }
+        for c in 0..3 {
+            let position = positions[triangle[c] as usize];
+            let index = *vertex_indices.entry((triangle[c], uvs[c].x.to_bits())).or_insert_with(|| {
+                push_vertex(vertices, position * radius, position, uvs[c]);
+                return (vertices.len() / N_FLOATS_PER_VERTEX) as u32 - 1;
+            });
+            indices.push(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (name, primitive, its volume, whether it's closed). the volumes are the real shapes', the meshes only approximate them
+    fn primitives() -> Vec<(&'static str, Primitive, f32, bool)> {
+        let pi = std::f32::consts::PI;
+        return vec![
+            ("cube", Primitive::Cube { size: glm::vec3(1.0, 2.0, 3.0) }, 6.0, true),
+            ("uv sphere", Primitive::UvSphere { radius: 0.5, segments: 32, rings: 16 }, 4.0 / 3.0 * pi * 0.125, true),
+            ("icosphere", Primitive::Icosphere { radius: 0.5, subdivisions: 3 }, 4.0 / 3.0 * pi * 0.125, true),
+            ("cylinder", Primitive::Cylinder { radius: 0.5, height: 2.0, segments: 32 }, pi * 0.25 * 2.0, true),
+            ("cone", Primitive::Cone { radius: 0.5, height: 1.0, segments: 32 }, pi * 0.25 / 3.0, true),
+            ("capsule", Primitive::Capsule { radius: 0.5, height: 2.0, segments: 32, rings: 8 }, pi * 0.25 + 4.0 / 3.0 * pi * 0.125, true),
+            ("plane", Primitive::Plane { size: glm::vec2(2.0, 1.0), subdivisions: 3 }, 0.0, false),
+            ("torus", Primitive::Torus { major_radius: 1.0, minor_radius: 0.25, major_segments: 48, minor_segments: 16 }, 2.0 * pi * pi * 0.0625, true),
+        ];
+    }
+
+    // every triangle faces the way its normals do, closed shapes have no holes and enclose about the right volume (which is negative if they're inside out)
+    #[test]
+    fn primitives_are_closed_and_face_out() {
+        for (name, primitive, volume, closed) in primitives() {
+            let (vertices, indices) = primitive.generate();
+            let vertex = |i: u32| &vertices[i as usize * N_FLOATS_PER_VERTEX..(i as usize + 1) * N_FLOATS_PER_VERTEX];
+            let mut enclosed = 0.0;
+            let mut edges: std::collections::BTreeMap<([i32; 3], [i32; 3]), usize> = std::collections::BTreeMap::new();
+            for triangle in indices.chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| glm::vec3(vertex(i)[0], vertex(i)[1], vertex(i)[2]));
+                let normals = [triangle[0], triangle[1], triangle[2]].map(|i| glm::vec3(vertex(i)[3], vertex(i)[4], vertex(i)[5]));
+                if let Some(normal) = normals.iter().find(|n| (n.norm() - 1.0).abs() > 1e-3) {
+                    panic!("the {} has a normal {:?} that isn't 1 long", name, normal);
+                }
+                assert!((b - a).cross(&(c - a)).dot(&(normals[0] + normals[1] + normals[2])) > 0.0, "the {}'s triangle {:?} winds against its normals", name, [a, b, c]);
+                enclosed += a.dot(&b.cross(&c)) / 6.0;
+                // edges between positions, so seams where vertices are split for their uvs still count as joined
+                let key = |p: glm::Vec3| [p.x, p.y, p.z].map(|f| (f * 1e4).round() as i32);
+                for (p, q) in [(a, b), (b, c), (c, a)] {
+                    *edges.entry((key(p).min(key(q)), key(p).max(key(q)))).or_insert(0) += 1;
+                }
+            }
+            let open_edges = edges.values().filter(|n| **n != 2).count();
+            assert!(!closed || (open_edges == 0 && (enclosed - volume).abs() <= volume * 0.05), "expected the {} to be closed with a volume of about {}, but {} edges aren't shared by 2 triangles and it encloses {}", name, volume, open_edges, enclosed);
+            assert!(closed || edges.values().all(|n| *n <= 2), "the {} has edges shared by more than 2 triangles", name);
+        }
+    }
+
+    // uvs stay in 0..1 (icosphere seam triangles a little past it), and seen from outside the texture is never mirrored. a triangle across a bad seam
+    // stretches back over the whole texture, which mirrors it
+    #[test]
+    fn primitive_uvs_dont_smear() {
+        for (name, primitive, _, _) in primitives() {
+            let (vertices, indices) = primitive.generate();
+            let uv = |i: u32| glm::vec2(vertices[i as usize * N_FLOATS_PER_VERTEX + 6], vertices[i as usize * N_FLOATS_PER_VERTEX + 7]);
+            let max_u = if matches!(primitive, Primitive::Icosphere { .. }) {1.5} else {1.0};
+            for triangle in indices.chunks_exact(3) {
+                let uvs = [triangle[0], triangle[1], triangle[2]].map(uv);
+                if let Some(uv) = uvs.iter().find(|uv| uv.x < 0.0 || uv.x > max_u || uv.y < 0.0 || uv.y > 1.0) {
+                    panic!("the {} has uv {:?} out of range", name, uv);
+                }
+                let (ab, ac) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+                assert!(ab.x * ac.y - ab.y * ac.x > 0.0, "the {}'s triangle with uvs {:?} has the texture backwards", name, uvs);
+            }
+        }
+        // and the mesh remembers the size it was made at
+        let mesh_id = Mesh::from_primitive(&Primitive::Cylinder { radius: 0.5, height: 2.0, segments: 16 }, 0, 0);
+        let original_size = LOADED_MESHES.lock().unwrap()[&mesh_id].original_size;
+        assert!((original_size - glm::vec3(1.0, 2.0, 1.0)).abs().max() <= 1e-5, "expected the cylinder's mesh to remember its 1x2x1 size, got {:?}", original_size);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+// Hands out ranges of a buffer that's capacity elements long (vertices, indices, instances, whatever the caller counts in). used by MeshPool to sub-allocate its buffers.
+// best fit: an allocation takes the smallest free range it fits in (the lowest one if there are several), and freed ranges get merged with free neighbours,
+// so small meshes don't chop up the big holes bigger meshes need. both lookups are O(log n) in the number of free ranges.
+// BTrees instead of hash maps so which range you get never depends on anything but the order of calls.
+pub struct RangeAllocator {
+    capacity: usize,
+    used: usize,
+    free_by_offset: BTreeMap<usize, usize>, // key is where a free range starts, value is its length
+    free_by_size: BTreeSet<(usize, usize)>, // (length, offset) of the same free ranges, for finding the best fit
+}
+
+impl RangeAllocator {
+    pub fn new(capacity: usize) -> Self {
+        let mut allocator = Self {
+            capacity: 0,
+            used: 0,
+            free_by_offset: BTreeMap::new(),
+            free_by_size: BTreeSet::new(),
+        };
+        allocator.grow(capacity);
+        return allocator;
+    }
+
+    // returns the offset of len free elements, or None if no free range is long enough (even if there's that much free space in total, see largest_free())
+    pub fn allocate(&mut self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return Some(0);
+        }
+        let (size, offset) = *self.free_by_size.range((len, 0)..).next()?;
+        self.take_free_range(offset, size);
+        if size > len {
+            self.add_free_range(offset + len, size - len);
+        }
+        self.used += len;
+        return Some(offset);
+    }
+
+    // makes the allocation at offset extra elements longer if the range right after it is free. returns false (and changes nothing) if it isn't
+    pub fn try_extend(&mut self, offset: usize, len: usize, extra: usize) -> bool {
+        if extra == 0 {
+            return true;
+        }
+        let end = offset + len;
+        let size = match self.free_by_offset.get(&end) {
+            Some(size) if *size >= extra => *size,
+            _ => return false
+        };
+        self.take_free_range(end, size);
+        if size > extra {
+            self.add_free_range(end + extra, size - extra);
+        }
+        self.used += extra;
+        return true;
+    }
+
+    // gives back the range allocate() returned. len has to be what was allocated (plus whatever try_extend() added)
+    pub fn free(&mut self, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        assert!(offset + len <= self.capacity, "Tried to free elements {} to {} of a range allocator with room for {}.", offset, offset + len, self.capacity);
+        let (mut start, mut end) = (offset, offset + len);
+
+        // merge with the free range before and after it, if they touch
+        if let Some((&before, &size)) = self.free_by_offset.range(..start).next_back() {
+            assert!(before + size <= start, "Tried to free elements {} to {}, but {} to {} were already free.", offset, offset + len, before, before + size);
+            if before + size == start {
+                self.take_free_range(before, size);
+                start = before;
+            }
+        }
+        if let Some((&after, &size)) = self.free_by_offset.range(start..).next() {
+            assert!(after >= end, "Tried to free elements {} to {}, but {} to {} were already free.", offset, offset + len, after, after + size);
+            if after == end {
+                self.take_free_range(after, size);
+                end = after + size;
+            }
+        }
+        self.add_free_range(start, end - start);
+        self.used -= len;
+    }
+
+    // adds room at the end, for when the buffer it's handing out was replaced with a bigger one
+    pub fn grow(&mut self, new_capacity: usize) {
+        if new_capacity <= self.capacity {
+            return;
+        }
+        let old_capacity = self.capacity;
+        self.capacity = new_capacity;
+        self.used += new_capacity - old_capacity; // free() takes it back off
+        self.free(old_capacity, new_capacity - old_capacity);
+    }
+
+    // frees everything
+    pub fn clear(&mut self) {
+        self.free_by_offset.clear();
+        self.free_by_size.clear();
+        self.used = 0;
+        if self.capacity > 0 {
+            self.add_free_range(0, self.capacity);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.capacity;
+    }
+
+    pub fn used(&self) -> usize {
+        return self.used;
+    }
+
+    pub fn free_space(&self) -> usize {
+        return self.capacity - self.used;
+    }
+
+    // the biggest allocation that would currently succeed. if it's a lot smaller than free_space(), the buffer is fragmented
+    pub fn largest_free(&self) -> usize {
+        return self.free_by_size.iter().next_back().map(|(size, _)| *size).unwrap_or(0);
+    }
+
+    pub fn n_free_ranges(&self) -> usize {
+        return self.free_by_offset.len();
+    }
+
+    fn add_free_range(&mut self, offset: usize, size: usize) {
+        self.free_by_offset.insert(offset, size);
+        self.free_by_size.insert((size, offset));
+    }
+
+    fn take_free_range(&mut self, offset: usize, size: usize) {
+        self.free_by_offset.remove(&offset);
+        self.free_by_size.remove(&(size, offset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0..100, with 10..20, 30..60 and 70..75 free
+    fn fragmented() -> RangeAllocator {
+        let mut allocator = RangeAllocator::new(100);
+        assert!(allocator.allocate(100) == Some(0), "a fresh allocator should hand out all of itself from 0");
+        allocator.free(10, 10);
+        allocator.free(30, 30);
+        allocator.free(70, 5);
+        return allocator;
+    }
+
+    #[test]
+    fn allocate_picks_the_best_fit() {
+        let mut allocator = fragmented();
+        assert!(allocator.allocate(5) == Some(70), "5 elements should go in the 5 long hole, not the first one they fit in");
+        assert!(allocator.allocate(8) == Some(10), "8 elements should go in the 10 long hole, not the 30 long one");
+        assert!(allocator.allocate(2) == Some(18), "2 elements should go in what's left of the 10 long hole");
+        assert!(allocator.allocate(31) == None, "nothing is 31 long, even though there's 30 free");
+        assert!(allocator.allocate(30) == Some(30), "the 30 long hole should still be there");
+        assert!(allocator.free_space() == 0 && allocator.n_free_ranges() == 0, "everything should be used now, but {} elements in {} ranges are free", allocator.free_space(), allocator.n_free_ranges());
+    }
+
+    #[test]
+    fn best_fit_ties_go_to_the_lowest_offset() {
+        let mut allocator = RangeAllocator::new(100);
+        allocator.allocate(100);
+        allocator.free(60, 10);
+        allocator.free(20, 10);
+        assert!(allocator.allocate(10) == Some(20), "of two holes the same size, the lower one should be used");
+    }
+
+    #[test]
+    fn free_merges_with_both_neighbours() {
+        let mut allocator = fragmented();
+        allocator.free(20, 10);
+        assert!(allocator.n_free_ranges() == 2 && allocator.largest_free() == 50, "freeing 20..30 should join 10..20 and 30..60 into one 50 long range, got {} ranges with the biggest {} long", allocator.n_free_ranges(), allocator.largest_free());
+        allocator.free(60, 10);
+        assert!(allocator.n_free_ranges() == 1 && allocator.largest_free() == 65, "freeing 60..70 should join 10..60 and 70..75, got {} ranges with the biggest {} long", allocator.n_free_ranges(), allocator.largest_free());
+        assert!(allocator.allocate(65) == Some(10), "the merged range should be usable as one allocation");
+    }
+
+    #[test]
+    fn try_extend_only_grows_into_free_space_right_after() {
+        let mut allocator = fragmented();
+        assert!(allocator.try_extend(0, 10, 5), "0..10 is followed by 10 free elements, so it should be able to take 5 of them");
+        assert!(allocator.used() == 60, "extending by 5 should take them out of the free space, used is {} instead of 60", allocator.used());
+        assert!(!allocator.try_extend(0, 15, 6), "only 5 free elements are left after 0..15");
+        assert!(allocator.try_extend(0, 15, 5), "exactly the 5 free elements left after 0..15 should fit");
+        assert!(!allocator.try_extend(0, 20, 1), "20..30 is in use");
+        assert!(allocator.n_free_ranges() == 2, "the hole that got used up by extending shouldn't be left behind as an empty range");
+    }
+
+    #[test]
+    fn grow_adds_a_free_range_at_the_end() {
+        let mut allocator = RangeAllocator::new(100);
+        allocator.allocate(90);
+        allocator.grow(150);
+        assert!(allocator.capacity() == 150 && allocator.free_space() == 60, "growing to 150 should leave 60 free, got {} of {}", allocator.free_space(), allocator.capacity());
+        assert!(allocator.n_free_ranges() == 1, "the new room should merge with the free 90..100");
+        assert!(allocator.allocate(60) == Some(90), "the merged 90..150 should be handed out in one piece");
+        allocator.grow(120);
+        assert!(allocator.capacity() == 150, "grow() shouldn't shrink");
+    }
+
+    #[test]
+    #[should_panic(expected = "were already free")]
+    fn double_free_panics() {
+        let mut allocator = fragmented();
+        allocator.free(10, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "were already free")]
+    fn overlapping_free_panics() {
+        let mut allocator = fragmented();
+        allocator.free(25, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "with room for")]
+    fn free_past_the_end_panics() {
+        let mut allocator = fragmented();
+        allocator.free(95, 10);
+    }
+}
@@ -0,0 +1,264 @@
+// A RenderDevice with no gpu behind it. It hands out ids, gives mapped buffers real memory (so MeshPool can write into them like normal),
+// and keeps a log of every call so headless checks can see what would've been drawn. RecordingDevice::null() skips the log, for dedicated servers.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use crate::graphics::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RenderCommand {
+    CreateBuffer { buffer: GLuint },
+    BufferStaticData { target: BufferTarget, buffer: GLuint, nbytes: usize },
+    CreateMappedBuffer { target: BufferTarget, buffer: GLuint, nbytes: isize },
+    BindBuffer { target: BufferTarget, buffer: GLuint },
+    BindBufferBase { target: BufferTarget, binding: GLuint, buffer: GLuint },
+    DeleteBuffer { buffer: GLuint },
+    CreateVertexArray { vao: GLuint },
+    BindVertexArray { vao: GLuint },
+    DeleteVertexArray { vao: GLuint },
+    VertexAttrib { index: GLuint, n_floats: i32, buffer: GLuint, offset: usize, stride: i32, divisor: GLuint },
+    CreateTexture { texture: GLuint, kind: TextureKind, format: TextureFormat, width: u32, height: u32, layers: u32 },
+    BindTexture { unit: GLuint, kind: TextureKind, texture: GLuint },
+    DeleteTexture { texture: GLuint },
+    CreateFramebuffer { framebuffer: GLuint, color: Option<GLuint>, depth: Option<GLuint> },
+    BindFramebuffer { framebuffer: GLuint },
+    Viewport { x: i32, y: i32, width: i32, height: i32 },
+    DeleteFramebuffer { framebuffer: GLuint },
+    CreateProgram { program: GLuint },
+    UseProgram { program: GLuint },
+    SetUniformI32 { location: GLint, value: i32 },
+    SetUniformMat4 { location: GLint, matrix: glm::Mat4 },
+    DeleteProgram { program: GLuint },
+    SetClearColor { rgba: [f32; 4] },
+    Clear { color: bool, depth: bool },
+    SetDepthTest { enabled: bool },
+    SetBackfaceCulling { enabled: bool },
+    DrawArrays { first: i32, count: i32 },
+    DrawElementsInstanced { n_indices: i32, first_index_byte: usize, n_instances: i32, base_vertex: i32, base_instance: GLuint },
+    MultiDrawElementsIndirect { n_commands: i32, offset: usize },
+    Flush,
+    CreateFence { fence: GLuint },
+    WaitFence { fence: GLuint },
+}
+
+pub struct RecordingDevice {
+    record: bool,
+    commands: RefCell<Vec<RenderCommand>>,
+    last_id: Cell<GLuint>,
+    buffers: RefCell<HashMap<GLuint, Box<[u8]>>>, // storage of every live buffer. boxed so mapped pointers stay put when the map grows
+    uniforms: RefCell<HashMap<(GLuint, String), GLint>>, // so the same uniform always gets the same location
+}
+
+impl RecordingDevice {
+    pub fn new() -> Self {
+        return Self { record: true, commands: RefCell::new(Vec::new()), last_id: Cell::new(0), buffers: RefCell::new(HashMap::new()), uniforms: RefCell::new(HashMap::new()) };
+    }
+
+    // doesn't keep a log, so it can run forever without using more and more memory
+    pub fn null() -> Self {
+        let mut device = Self::new();
+        device.record = false;
+        return device;
+    }
+
+    pub fn commands(&self) -> Vec<RenderCommand> {
+        return self.commands.borrow().clone();
+    }
+
+    // returns the log so far and starts a new one
+    pub fn take_commands(&self) -> Vec<RenderCommand> {
+        return std::mem::take(&mut *self.commands.borrow_mut());
+    }
+
+    // just the DrawElementsInstanced/MultiDrawElementsIndirect/DrawArrays commands
+    pub fn draws(&self) -> Vec<RenderCommand> {
+        return self.commands.borrow().iter().filter(|c| matches!(c, RenderCommand::DrawElementsInstanced {..} | RenderCommand::MultiDrawElementsIndirect {..} | RenderCommand::DrawArrays {..})).cloned().collect();
+    }
+
+    // copy of whatever has been written to the buffer, None if it doesn't exist (anymore)
+    pub fn buffer_contents(&self, buffer: GLuint) -> Option<Vec<u8>> {
+        return self.buffers.borrow().get(&buffer).map(|b| b.to_vec());
+    }
+
+    // buffers created and not deleted yet, for finding leaks
+    pub fn n_live_buffers(&self) -> usize {
+        return self.buffers.borrow().len();
+    }
+
+    fn push(&self, command: RenderCommand) {
+        if self.record {
+            self.commands.borrow_mut().push(command);
+        }
+    }
+
+    fn next_id(&self) -> GLuint {
+        self.last_id.set(self.last_id.get() + 1);
+        return self.last_id.get();
+    }
+}
+
+impl RenderDevice for RecordingDevice {
+    fn create_buffer(&self) -> GLuint {
+        let buffer = self.next_id();
+        self.buffers.borrow_mut().insert(buffer, Box::new([]));
+        self.push(RenderCommand::CreateBuffer { buffer });
+        return buffer;
+    }
+
+    fn buffer_static_data(&self, target: BufferTarget, buffer: GLuint, data: &[u8]) {
+        self.buffers.borrow_mut().insert(buffer, data.to_vec().into_boxed_slice());
+        self.push(RenderCommand::BufferStaticData { target, buffer, nbytes: data.len() });
+    }
+
+    fn create_mapped_buffer(&self, target: BufferTarget, nbytes: isize) -> (GLuint, *mut c_void) {
+        let buffer = self.next_id();
+        let mut storage = vec![0u8; nbytes.max(0) as usize].into_boxed_slice();
+        let ptr = storage.as_mut_ptr() as *mut c_void;
+        self.buffers.borrow_mut().insert(buffer, storage);
+        self.push(RenderCommand::CreateMappedBuffer { target, buffer, nbytes });
+        return (buffer, ptr);
+    }
+
+    fn bind_buffer(&self, target: BufferTarget, buffer: GLuint) {
+        self.push(RenderCommand::BindBuffer { target, buffer });
+    }
+
+    fn bind_buffer_base(&self, target: BufferTarget, binding: GLuint, buffer: GLuint) {
+        self.push(RenderCommand::BindBufferBase { target, binding, buffer });
+    }
+
+    fn delete_buffer(&self, buffer: GLuint) {
+        self.buffers.borrow_mut().remove(&buffer);
+        self.push(RenderCommand::DeleteBuffer { buffer });
+    }
+
+    fn create_vertex_array(&self) -> GLuint {
+        let vao = self.next_id();
+        self.push(RenderCommand::CreateVertexArray { vao });
+        return vao;
+    }
+
+    fn bind_vertex_array(&self, vao: GLuint) {
+        self.push(RenderCommand::BindVertexArray { vao });
+    }
+
+    fn delete_vertex_array(&self, vao: GLuint) {
+        self.push(RenderCommand::DeleteVertexArray { vao });
+    }
+
+    fn vertex_attrib(&self, index: GLuint, n_floats: i32, buffer: GLuint, offset: usize, stride: i32, divisor: GLuint) {
+        self.push(RenderCommand::VertexAttrib { index, n_floats, buffer, offset, stride, divisor });
+    }
+
+    fn create_texture(&self, kind: TextureKind, format: TextureFormat, _sampling: TextureSampling, width: u32, height: u32, layers: u32, _data: Option<&[u8]>) -> GLuint {
+        let texture = self.next_id();
+        self.push(RenderCommand::CreateTexture { texture, kind, format, width, height, layers });
+        return texture;
+    }
+
+    fn bind_texture(&self, unit: GLuint, kind: TextureKind, texture: GLuint) {
+        self.push(RenderCommand::BindTexture { unit, kind, texture });
+    }
+
+    fn delete_texture(&self, texture: GLuint) {
+        self.push(RenderCommand::DeleteTexture { texture });
+    }
+
+    fn create_framebuffer(&self, color: Option<GLuint>, depth: Option<GLuint>) -> GLuint {
+        let framebuffer = self.next_id();
+        self.push(RenderCommand::CreateFramebuffer { framebuffer, color, depth });
+        return framebuffer;
+    }
+
+    fn bind_framebuffer(&self, framebuffer: GLuint) {
+        self.push(RenderCommand::BindFramebuffer { framebuffer });
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.push(RenderCommand::Viewport { x, y, width, height });
+    }
+
+    fn delete_framebuffer(&self, framebuffer: GLuint) {
+        self.push(RenderCommand::DeleteFramebuffer { framebuffer });
+    }
+
+    // shaders aren't compiled, so this never fails
+    fn create_program(&self, _vertex_source: &str, _fragment_source: &str) -> Result<GLuint, String> {
+        let program = self.next_id();
+        self.push(RenderCommand::CreateProgram { program });
+        return Ok(program);
+    }
+
+    fn use_program(&self, program: GLuint) {
+        self.push(RenderCommand::UseProgram { program });
+    }
+
+    fn uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let mut uniforms = self.uniforms.borrow_mut();
+        let n = uniforms.len() as GLint;
+        return *uniforms.entry((program, name.to_string())).or_insert(n);
+    }
+
+    fn set_uniform_i32(&self, location: GLint, value: i32) {
+        self.push(RenderCommand::SetUniformI32 { location, value });
+    }
+
+    fn set_uniform_mat4(&self, location: GLint, matrix: &glm::Mat4, transpose: bool) {
+        self.push(RenderCommand::SetUniformMat4 { location, matrix: if transpose {matrix.transpose()} else {*matrix} });
+    }
+
+    fn delete_program(&self, program: GLuint) {
+        self.push(RenderCommand::DeleteProgram { program });
+    }
+
+    fn set_clear_color(&self, rgba: [f32; 4]) {
+        self.push(RenderCommand::SetClearColor { rgba });
+    }
+
+    fn clear(&self, color: bool, depth: bool) {
+        self.push(RenderCommand::Clear { color, depth });
+    }
+
+    fn set_depth_test(&self, enabled: bool) {
+        self.push(RenderCommand::SetDepthTest { enabled });
+    }
+
+    fn set_backface_culling(&self, enabled: bool) {
+        self.push(RenderCommand::SetBackfaceCulling { enabled });
+    }
+
+    fn draw_arrays(&self, first: i32, count: i32) {
+        self.push(RenderCommand::DrawArrays { first, count });
+    }
+
+    fn draw_elements_instanced(&self, n_indices: i32, first_index_byte: usize, n_instances: i32, base_vertex: i32, base_instance: GLuint) {
+        self.push(RenderCommand::DrawElementsInstanced { n_indices, first_index_byte, n_instances, base_vertex, base_instance });
+    }
+
+    fn multi_draw_elements_indirect(&self, n_commands: i32, offset: usize) {
+        self.push(RenderCommand::MultiDrawElementsIndirect { n_commands, offset });
+    }
+
+    fn flush(&self) {
+        self.push(RenderCommand::Flush);
+    }
+
+    fn create_fence(&self) -> GLuint {
+        let fence = self.next_id();
+        self.push(RenderCommand::CreateFence { fence });
+        return fence;
+    }
+
+    // nothing to wait for, but the log shows whether writes were fenced
+    fn wait_fence(&self, fence: GLuint) {
+        if fence != 0 {
+            self.push(RenderCommand::WaitFence { fence });
+        }
+    }
+
+    // nothing was actually drawn
+    fn read_pixels(&self, _framebuffer: GLuint, _width: u32, _height: u32) -> Option<RgbaImage> {
+        return None;
+    }
+}
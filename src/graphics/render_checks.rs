@@ -0,0 +1,445 @@
+// Headless rendering tests. Everything draws into a RecordingDevice (or a SoftwareDevice when pixels matter), so cargo test works without a window or a gpu;
+// the tests look at the draw calls MeshPool emits and at what it wrote into its (fake) mapped buffers.
+
+use crate::ecs::*;
+use crate::transform::*;
+use crate::graphics::*;
+
+const TRIANGLE_VERTEX_NBYTES: usize = 3 * N_FLOATS_PER_VERTEX * 4;
+const TRIANGLE_INDEX_NBYTES: usize = 3 * 4;
+
+fn triangle() -> (Vec<GLfloat>, Vec<GLuint>) {
+    let mut vertices = Vec::new();
+    for pos in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+        vertices.extend_from_slice(&pos);
+        vertices.extend_from_slice(&[0.0, 0.0, 1.0, 0.0, 0.0]); // normal, uv
+    }
+    return (vertices, vec![0, 1, 2]);
+}
+
+// room for 2 triangles and 8 instances
+fn small_pool(device: &RecordingDevice) -> MeshPool {
+    return MeshPool::new(device, 6, 6, 8);
+}
+
+// the (n_indices, n_instances, base_vertex, base_instance) of every indexed draw
+fn pool_draws(device: &RecordingDevice, pool: &MeshPool) -> Vec<(i32, i32, i32, GLuint)> {
+    device.take_commands();
+    pool.draw(device, None);
+    return device.draws().iter().filter_map(|command| match command {
+        RenderCommand::DrawElementsInstanced { n_indices, n_instances, base_vertex, base_instance, .. } => Some((*n_indices, *n_instances, *base_vertex, *base_instance)),
+        _ => None
+    }).collect();
+}
+
+fn expect_draws(what: &str, actual: Vec<(i32, i32, i32, GLuint)>, expected: Vec<(i32, i32, i32, GLuint)>) {
+    assert!(actual == expected, "expected {} to draw (n_indices, n_instances, base_vertex, base_instance) {:?}, but got {:?}", what, expected, actual);
+}
+
+#[test]
+fn add_mesh_draws_once() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let (slot, instance) = pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    assert!((slot, instance) == (0, 0), "first mesh went to slot {} instance {}", slot, instance);
+    expect_draws("one triangle", pool_draws(&device, &pool), vec![(3, 1, 0, 0)]);
+}
+
+#[test]
+fn static_meshes_are_instanced() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    let (slot, instance) = pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    assert!((slot, instance) == (0, 1), "second copy of a static mesh went to slot {} instance {} instead of slot 0 instance 1", slot, instance);
+    expect_draws("two copies of one static mesh", pool_draws(&device, &pool), vec![(3, 2, 0, 0)]);
+}
+
+#[test]
+fn remove_instance_keeps_instances_contiguous() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let (slot, _) = pool.add_mesh(&device, 1, &vertices, &indices, 3, false);
+    for instance in 0..3 {
+        pool.set_transform(slot, instance, &glm::translation(&glm::vec3(instance as f32 + 1.0, 0.0, 0.0)));
+    }
+
+    let moved = pool.remove_instance(slot, 0);
+    assert!(moved == Some(2), "removing instance 0 of 3 should move instance 2 into its place, but remove_instance() returned {:?}", moved);
+    let draws = pool_draws(&device, &pool);
+    assert!(draws.len() == 1 && draws[0].1 == 2, "expected one draw of two remaining instances, but got {:?}", draws);
+    let base_instance = draws[0].3 as usize;
+
+    let data = device.buffer_contents(pool.instanced_data_buffer()).expect("the instanced data buffer doesn't exist");
+    let x_translation = |instance: usize| {
+        let offset = (base_instance + instance) * pool.instance_nbytes as usize + 12 * 4; // column 3, row 0 of the model matrix
+        return f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    };
+    assert!(x_translation(0) == 3.0 && x_translation(1) == 2.0, "expected instances to be at x 3 and 2 after the removal, but they're at {} and {}", x_translation(0), x_translation(1));
+}
+
+#[test]
+fn remove_mesh_frees_slot() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let (first, _) = pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    pool.remove_mesh(first);
+    expect_draws("an empty pool", pool_draws(&device, &pool), vec![]);
+
+    // the next mesh gets the freed slot and the memory it had
+    let (second, _) = pool.add_mesh(&device, 2, &vertices, &indices, 1, false);
+    assert!(second == first, "expected the freed slot {} to be reused, but the next mesh went to slot {}", first, second);
+    assert!(pool.instance_count(first) == 1, "the reused slot has {} instances instead of 1", pool.instance_count(first));
+    expect_draws("a mesh in a reused slot", pool_draws(&device, &pool), vec![(3, 1, 0, 0)]);
+}
+
+#[test]
+fn pool_growth_keeps_meshes() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let mut slots = Vec::new();
+    for uuid in 0..5 {
+        slots.push(pool.add_mesh(&device, uuid, &vertices, &indices, 1, true).0);
+    }
+    let stats = pool.stats();
+    assert!(stats.n_meshes == 5 && stats.vertices.used_bytes == 5 * TRIANGLE_VERTEX_NBYTES && stats.indices.used_bytes == 5 * TRIANGLE_INDEX_NBYTES, "5 meshes were added but the pool says it has {} using {} bytes of vertices and {} of indices", stats.n_meshes, stats.vertices.used_bytes, stats.indices.used_bytes);
+
+    let draws = pool_draws(&device, &pool);
+    // every mesh got its own instance, but which one each got doesn't matter
+    let mut base_instances: Vec<GLuint> = draws.iter().map(|draw| draw.3).collect();
+    base_instances.sort();
+    base_instances.dedup();
+    assert!(base_instances.len() == 5, "expected 5 different base instances, got {:?}", base_instances);
+    let mut actual: Vec<(i32, i32, i32)> = draws.iter().map(|draw| (draw.0, draw.1, draw.2)).collect();
+    let mut expected: Vec<(i32, i32, i32)> = slots.iter().map(|slot| (3, 1, slot * 3)).collect();
+    actual.sort();
+    expected.sort();
+    assert!(actual == expected, "expected (n_indices, n_instances, base_vertex) {:?}, but got {:?}", expected, actual);
+}
+
+// growing the instance buffer, or moving a mesh's instances to make room for more of them, shouldn't lose their color or texture z
+#[test]
+fn pool_growth_keeps_instance_data() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let (slot, _) = pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    let color = glm::vec4(0.25, 0.5, 0.75, 1.0);
+    pool.set_transform(slot, 0, &glm::translation(&glm::vec3(7.0, 0.0, 0.0)));
+    pool.set_rgba(slot, 0, &color);
+    pool.set_texture_z(slot, 0, &3.0);
+    let base_instance = pool_draws(&device, &pool)[0].3 as usize;
+
+    // (x translation, color, texture z) of the instance at base_instance in the mvbo
+    let instance = |pool: &MeshPool, base_instance: usize| -> (f32, glm::Vec4, f32) {
+        let data = device.buffer_contents(pool.instanced_data_buffer()).expect("the instanced data buffer doesn't exist");
+        let float = |i: usize| {
+            let offset = base_instance * pool.instance_nbytes as usize + i * 4;
+            return f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+        };
+        return (float(12), glm::vec4(float(16), float(17), float(18), float(19)), float(20));
+    };
+    let expected = (7.0, color, 3.0);
+
+    // 20 instances of something else don't fit in the 8 the pool started with. nothing is drawn before looking, so the mvbo is what got copied from the old one
+    pool.add_mesh(&device, 2, &vertices, &indices, 20, false);
+    assert!(instance(&pool, base_instance) == expected, "after the instance buffer grew, expected the first mesh's (x translation, color, texture z) to be {:?}, but they're {:?}", expected, instance(&pool, base_instance));
+
+    // the other mesh's instances are right after the first one's, so adding more of the first has to move them
+    let (same_slot, first_new_instance) = pool.add_mesh(&device, 1, &vertices, &indices, 2, false);
+    let draws = pool_draws(&device, &pool);
+    assert!((same_slot, first_new_instance) == (slot, 1) && draws[slot as usize].1 == 3, "expected 2 more copies to be instances 1 and 2 of slot {}, but they're at slot {} instance {} and the draws are {:?}", slot, same_slot, first_new_instance, draws);
+    let moved_base_instance = draws[slot as usize].3 as usize;
+    assert!(moved_base_instance != base_instance, "the first mesh's instances should have moved, but they're still at {}", base_instance);
+    assert!(instance(&pool, moved_base_instance) == expected, "after moving the first mesh's instances, expected them to start with (x translation, color, texture z) {:?}, but it's {:?}", expected, instance(&pool, moved_base_instance));
+}
+
+// used + wasted + free is always the whole buffer, and the only thing that wastes memory here is removing an instance
+#[test]
+fn pool_stats_add_up() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let instance_nbytes = pool.instance_nbytes as usize;
+    let (slot, _) = pool.add_mesh(&device, 1, &vertices, &indices, 3, false);
+    pool.remove_instance(slot, 2);
+
+    let stats = pool.stats();
+    let expected = [
+        ("vertices", stats.vertices, TRIANGLE_VERTEX_NBYTES, 0, TRIANGLE_VERTEX_NBYTES),
+        ("indices", stats.indices, TRIANGLE_INDEX_NBYTES, 0, TRIANGLE_INDEX_NBYTES),
+        ("instances", stats.instances, 2 * instance_nbytes, instance_nbytes, 5 * instance_nbytes),
+    ];
+    for (what, stats, used, wasted, free) in expected {
+        let actual = (stats.used_bytes, stats.wasted_bytes, stats.free_bytes, stats.largest_free_bytes);
+        assert!(actual == (used, wasted, free, free), "expected {} to have (used, wasted, free, largest free) bytes {:?}, but they have {:?}", what, (used, wasted, free, free), actual);
+    }
+
+    // the next copy goes where the removed one was, without moving the others
+    pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+    assert!(pool.stats().instances.wasted_bytes == 0, "adding the removed instance back still wastes {} bytes", pool.stats().instances.wasted_bytes);
+    expect_draws("3 copies of one static mesh", pool_draws(&device, &pool), vec![(3, 3, 0, 0)]);
+
+    // and after removing everything, each buffer should be one free range again
+    pool.remove_mesh(slot);
+    let stats = pool.stats();
+    for (what, stats) in [("vertices", stats.vertices), ("indices", stats.indices), ("instances", stats.instances)] {
+        assert!(stats.used_bytes + stats.wasted_bytes == 0 && stats.largest_free_bytes == stats.free_bytes, "after removing the only mesh, {} are {:?}", what, stats);
+    }
+    assert!(stats.n_meshes == 0, "the empty pool says it has {} meshes", stats.n_meshes);
+}
+
+// each (slot, tag, n_indices) in meshes, in slot order, should draw its own vertices and instance: the x of its first vertex and its x translation are both tag
+fn expect_meshes(device: &RecordingDevice, pool: &MeshPool, meshes: &[(i32, f32, i32)], after: &str) {
+    let draws = pool_draws(device, pool);
+    let vertex_data = device.buffer_contents(pool.vertex_buffer()).expect("the vertex buffer doesn't exist");
+    let instance_data = device.buffer_contents(pool.instanced_data_buffer()).expect("the instanced data buffer doesn't exist");
+    let float = |data: &Vec<u8>, offset: usize| f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    let actual: Vec<(i32, f32, f32)> = draws.iter().map(|draw| (draw.0, float(&vertex_data, draw.2 as usize * N_FLOATS_PER_VERTEX * 4), float(&instance_data, draw.3 as usize * pool.instance_nbytes as usize + 12 * 4))).collect();
+    let expected: Vec<(i32, f32, f32)> = meshes.iter().map(|(_, tag, n_indices)| (*n_indices, *tag, *tag)).collect();
+    assert!(actual == expected, "after {}, expected the meshes' (n_indices, first vertex x, x translation) to be {:?}, but they're {:?}", after, expected, actual);
+}
+
+#[test]
+fn defragment_keeps_meshes() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    // n_triangles copies of triangle(), moved tag meters along x so they can be told apart in the vbo
+    let mesh = |tag: f32, n_triangles: u32| {
+        let (triangle, _) = triangle();
+        let mut vertices = Vec::new();
+        for _ in 0..n_triangles {
+            for vertex in triangle.chunks_exact(N_FLOATS_PER_VERTEX) {
+                vertices.push(vertex[0] + tag);
+                vertices.extend_from_slice(&vertex[1..]);
+            }
+        }
+        return (vertices, (0..3 * n_triangles).collect::<Vec<GLuint>>());
+    };
+    let mut meshes = Vec::new();
+    let add = |pool: &mut MeshPool, meshes: &mut Vec<(i32, f32, i32)>, tag: i32, n_triangles: u32| {
+        let (vertices, indices) = mesh(tag as f32, n_triangles);
+        let (slot, _) = pool.add_mesh(&device, tag, &vertices, &indices, 1, true);
+        pool.set_transform(slot, 0, &glm::translation(&glm::vec3(tag as f32, 0.0, 0.0)));
+        meshes.push((slot, tag as f32, indices.len() as i32));
+        meshes.sort_by_key(|(slot, _, _)| *slot);
+    };
+    for tag in 0..4 {
+        add(&mut pool, &mut meshes, tag, 1);
+    }
+    pool.remove_mesh(meshes[0].0);
+    pool.remove_mesh(meshes[2].0);
+    meshes = vec![meshes[1], meshes[3]];
+    let stats = pool.stats();
+    assert!(stats.vertices.n_free_ranges == 2 && stats.vertices.largest_free_bytes == TRIANGLE_VERTEX_NBYTES && stats.vertices.free_bytes == 2 * TRIANGLE_VERTEX_NBYTES, "removing the first and third of 4 triangles should leave two triangle sized holes, but the vertices are {:?}", stats.vertices);
+    let vertex_capacity = stats.vertices.used_bytes + stats.vertices.wasted_bytes + stats.vertices.free_bytes;
+
+    // two triangles don't fit in either hole, but they do in both together
+    add(&mut pool, &mut meshes, 4, 2);
+    let stats = pool.stats();
+    assert!(stats.vertices.used_bytes + stats.vertices.wasted_bytes + stats.vertices.free_bytes == vertex_capacity, "the vertex buffer grew from {} bytes instead of being compacted", vertex_capacity);
+    expect_meshes(&device, &pool, &meshes, "the pool compacted itself");
+
+    pool.remove_mesh(meshes[0].0);
+    meshes.remove(0);
+    pool.defragment();
+    let stats = pool.stats();
+    for (what, stats) in [("vertices", stats.vertices), ("indices", stats.indices), ("instances", stats.instances)] {
+        assert!(stats.wasted_bytes == 0 && stats.n_free_ranges <= 1 && stats.largest_free_bytes == stats.free_bytes, "after defragmenting, {} are {:?}", what, stats);
+    }
+    expect_meshes(&device, &pool, &meshes, "defragmenting");
+}
+
+// the instance behind the camera shouldn't be drawn, and the two in front should be moved together so one draw covers them
+#[test]
+fn frustum_culling_compacts_instances() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    let (slot, _) = pool.add_mesh(&device, 1, &vertices, &indices, 3, false);
+    let positions = [glm::vec3(0.0, 0.0, -5.0), glm::vec3(0.0, 0.0, 5.0), glm::vec3(1.0, 0.0, -5.0)];
+    for (instance, pos) in positions.iter().enumerate() {
+        pool.set_transform(slot, instance as i32, &glm::translation(pos));
+    }
+
+    let frustum = Frustum::from_matrix(&glm::perspective(1.5, 1.2, 0.1, 100.0));
+    device.take_commands();
+    let n_drawn = pool.draw(&device, Some(&frustum));
+    let draws: Vec<(i32, GLuint)> = device.draws().iter().filter_map(|command| match command {
+        RenderCommand::DrawElementsInstanced { n_instances, base_instance, .. } => Some((*n_instances, *base_instance)),
+        _ => None
+    }).collect();
+    assert!(n_drawn == 2 && draws.len() == 1 && draws[0].0 == 2 && pool.n_instances() == 3, "expected one draw of 2 of the 3 instances, but draw() returned {} and the draws were (n_instances, base_instance) {:?}", n_drawn, draws);
+
+    let data = device.buffer_contents(pool.instanced_data_buffer()).expect("the instanced data buffer doesn't exist");
+    let translation = |instance: usize| {
+        let offset = (draws[0].1 as usize + instance) * pool.instance_nbytes as usize + 12 * 4;
+        let float = |i: usize| f32::from_ne_bytes(data[offset + i * 4..offset + i * 4 + 4].try_into().unwrap());
+        return glm::vec3(float(0), float(1), float(2));
+    };
+    assert!(translation(0) == positions[0] && translation(1) == positions[2], "expected the visible instances to be at {:?} and {:?}, but they're at {:?} and {:?}", positions[0], positions[2], translation(0), translation(1));
+}
+
+// the mvbo is refilled every draw, so each draw has to wait for the gpu to be done with the last one's instances first
+#[test]
+fn draws_wait_for_the_last_draws_instances() {
+    let device = RecordingDevice::new();
+    let mut pool = small_pool(&device);
+    let (vertices, indices) = triangle();
+    pool.add_mesh(&device, 1, &vertices, &indices, 1, false);
+
+    device.take_commands();
+    pool.draw(&device, None);
+    let fence = match device.commands().last() {
+        Some(RenderCommand::CreateFence { fence }) => *fence,
+        other => panic!("expected draw() to end with a fence, but the last command was {:?}", other)
+    };
+    device.take_commands();
+    pool.draw(&device, None);
+    let commands = device.take_commands();
+    let wait = commands.iter().position(|command| *command == RenderCommand::WaitFence { fence });
+    let first_draw = commands.iter().position(|command| matches!(command, RenderCommand::DrawElementsInstanced { .. }));
+    assert!(wait.is_some() && wait < first_draw, "expected the second draw() to wait for fence {} before drawing, but its commands were {:?}", fence, commands);
+
+    pool.cleanup(&device);
+    assert!(matches!(device.commands().first(), Some(RenderCommand::WaitFence { .. })), "expected cleanup() to wait for the last draw first, but it did {:?}", device.commands());
+}
+
+#[test]
+fn cleanup_deletes_buffers() {
+    let device = RecordingDevice::new();
+    let mut pool = MeshPool::new_skinned(&device, 6, 6, 8);
+    assert!(device.n_live_buffers() != 0, "making a pool didn't create any buffers");
+    pool.cleanup(&device);
+    assert!(device.n_live_buffers() == 0, "{} buffers were left after cleanup()", device.n_live_buffers());
+}
+
+// the whole engine, shaders and postprocessing and all, should be able to draw a frame without opengl
+#[test]
+fn engine_runs_headless() {
+    let mut engine = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    engine.update((64, 48));
+    engine.draw();
+    engine.cleanup();
+}
+
+// the camera has to turn with camera_entity, not just move with it: a sphere off to the left is only drawn while the entity faces left
+#[test]
+fn camera_turns_with_its_entity() {
+    let mut engine = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    let mesh = Mesh::from_obj("models/icosphere.obj", 0, engine.world_shader_id).unwrap();
+    let mut world = World::new();
+    world.build_entity().with(Transform::meters(dvec3(-5.0, 0.0, 0.0))).with(RenderComponent::new(mesh)).build();
+    let camera = world.build_entity().with(Transform::meters(dvec3(0.0, 0.0, 0.0))).build();
+    engine.camera_entity = Some(camera);
+
+    // cameras look down -z, so turning left is a positive yaw
+    for (yaw, expected) in [(0.0f32, 0), (90.0, 1), (-90.0, 0), (180.0, 0)] {
+        world.get_mut::<Transform>(camera).unwrap().setrotyxz(glm::vec3(0.0, yaw.to_radians(), 0.0));
+        engine.sync_camera_entity(&mut world);
+        engine.update((64, 48));
+        engine.update_entities(&mut world);
+        engine.draw();
+        let (n_drawn, _) = engine.culling_stats();
+        assert!(n_drawn == expected, "with the camera entity turned {} degrees, expected {} instances to be drawn but {} were", yaw, expected, n_drawn);
+    }
+    engine.cleanup();
+}
+
+// one sphere that moves away from the camera and back should end up on its lowest detail level and then its full mesh again, with only one instance at a time
+#[test]
+fn engine_switches_lods_by_distance() {
+    let mut engine = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    let mesh = Mesh::from_obj_with_lods("models/icosphere.obj", 0, engine.world_shader_id, &DEFAULT_LOD_LEVELS).unwrap();
+    let mut world = World::new();
+    let sphere = world.build_entity().with(Transform::meters(dvec3(0.0, 0.0, -2.0))).with(RenderComponent::new(mesh)).build();
+
+    for (z, expected) in [(-2.0, 0), (-200.0, DEFAULT_LOD_LEVELS.len()), (-2.0, 0)] {
+        world.get_mut::<Transform>(sphere).unwrap().setpos_meters(dvec3(0.0, 0.0, z));
+        engine.update((64, 48));
+        engine.update_entities(&mut world);
+        engine.draw();
+        let lod = world.get::<RenderComponent>(sphere).unwrap().lod();
+        assert!(lod == expected, "expected the sphere to be drawn at level {} from {}m away, but it's at level {}", expected, -z, lod);
+        let (_, n_instances) = engine.culling_stats();
+        assert!(n_instances == 1, "expected 1 instance after switching level of detail, but there are {}", n_instances);
+    }
+    engine.cleanup();
+}
+
+// a dynamic quad in front of the camera changed through the engine: made wider, partly moved back, turned around by its indices
+// (so it's culled), then made into a sphere that's too big for its meshpool slot, which has to keep its color when it moves to a bigger pool
+#[test]
+fn dynamic_mesh_updates() {
+    let resolution = (64, 48);
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(resolution.0, resolution.1)), resolution);
+    let quad = |left: f32, right: f32| {
+        let mut vertices = Vec::new();
+        for (x, y) in [(left, -0.5), (right, -0.5), (right, 0.5), (left, 0.5)] {
+            vertices.extend_from_slice(&[x, y, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        }
+        return vertices;
+    };
+    let mesh = Mesh::from_vertices(quad(-0.5, 0.5), vec![0, 1, 2, 0, 2, 3], 0, engine.world_shader_id, true);
+    let mesh_id = mesh.uuid;
+    LOADED_MESHES.lock().unwrap().insert(mesh_id, mesh);
+
+    let mut world = World::new();
+    let mut render = RenderComponent::new(mesh_id);
+    render.set_rgba(glm::vec4(1.0, 0.0, 0.0, 1.0));
+    render.set_texture_z(-1.0);
+    world.build_entity().with(Transform::meters(dvec3(0.0, 0.0, -3.0))).with(render).build();
+
+    // (what, x, y) of pixels to look at, 0.75m left, right and above the middle
+    let points = [("left", 21, 24), ("middle", 32, 24), ("right", 43, 24), ("top", 32, 13)];
+    let expect_red = |engine: &mut GraphicsEngine, world: &mut World, after: &str, red: [bool; 4]| {
+        engine.update(resolution);
+        engine.update_entities(world);
+        engine.draw();
+        let image = engine.screenshot().expect("the software device didn't give back a frame");
+        for ((what, x, y), red) in points.iter().zip(red) {
+            let pixel = image.pixel(*x, *y);
+            assert!((pixel[0] > 200 && pixel[1] < 50) == red, "after {}, expected the {} pixel {}to be red, but it's {:?}", after, what, if red {""} else {"not "}, pixel);
+        }
+        assert!(engine.culling_stats().1 == 1, "after {}, expected 1 instance but there are {}", after, engine.culling_stats().1);
+    };
+
+    expect_red(&mut engine, &mut world, "adding the quad", [false, true, false, false]);
+    engine.set_dynamic_mesh(mesh_id, quad(-1.0, 1.0), vec![0, 1, 2, 0, 2, 3]).unwrap();
+    expect_red(&mut engine, &mut world, "making it 2m wide", [true, true, true, false]);
+    engine.update_dynamic_vertices(mesh_id, 1, &quad(0.0, 0.0)[N_FLOATS_PER_VERTEX..3 * N_FLOATS_PER_VERTEX]).unwrap();
+    expect_red(&mut engine, &mut world, "moving its right side to the middle", [true, false, false, false]);
+    engine.update_dynamic_indices(mesh_id, 0, &[0, 2, 1, 0, 3, 2]).unwrap();
+    expect_red(&mut engine, &mut world, "turning it around", [false, false, false, false]);
+    let (sphere_vertices, sphere_indices) = Primitive::UvSphere { radius: 1.0, segments: 16, rings: 8 }.generate();
+    engine.set_dynamic_mesh(mesh_id, sphere_vertices, sphere_indices).unwrap();
+    expect_red(&mut engine, &mut world, "making it a sphere", [true, true, true, true]);
+
+    let static_mesh = Mesh::from_primitive(&Primitive::Cube { size: glm::vec3(1.0, 1.0, 1.0) }, 0, engine.world_shader_id);
+    let errors = [
+        (engine.set_dynamic_mesh(static_mesh, quad(0.0, 1.0), vec![0, 1, 2]), MeshUpdateError::NotDynamic(static_mesh)),
+        (engine.set_dynamic_mesh(usize::MAX, quad(0.0, 1.0), vec![0, 1, 2]), MeshUpdateError::NoSuchMesh(usize::MAX)),
+        (engine.set_dynamic_mesh(mesh_id, vec![0.0; 3], vec![]), MeshUpdateError::PartialVertex(3)),
+        (engine.set_dynamic_mesh(mesh_id, quad(0.0, 1.0), vec![0, 1, 4]), MeshUpdateError::IndexOutOfRange(4, 4)),
+        (engine.update_dynamic_vertices(mesh_id, 1000, &quad(0.0, 1.0)), MeshUpdateError::OutOfRange(1004, 17 * 9)),
+        (engine.update_dynamic_indices(mesh_id, 0, &[0, 1, 100000]), MeshUpdateError::IndexOutOfRange(100000, 17 * 9)),
+    ];
+    for (result, expected) in errors {
+        assert!(result.as_ref().err() == Some(&expected), "expected {:?}, got {:?}", expected, result);
+    }
+
+    // shrinking it leaves the sphere's room in the pool, until it's defragmented
+    engine.set_dynamic_mesh(mesh_id, quad(-0.5, 0.5), vec![0, 1, 2, 0, 2, 3]).unwrap();
+    expect_red(&mut engine, &mut world, "making it a quad again", [false, true, false, false]);
+    let wasted_vertex_bytes = |engine: &GraphicsEngine| engine.meshpool_stats().iter().map(|(_, _, stats)| stats.vertices.wasted_bytes).sum::<usize>();
+    assert!(wasted_vertex_bytes(&engine) == (17 * 9 - 4) * N_FLOATS_PER_VERTEX * 4, "expected the sphere's vertices minus the quad's to be wasted, but {} bytes are", wasted_vertex_bytes(&engine));
+    engine.defragment_meshpools();
+    assert!(wasted_vertex_bytes(&engine) == 0, "{} bytes of vertices are still wasted after defragmenting", wasted_vertex_bytes(&engine));
+    expect_red(&mut engine, &mut world, "defragmenting", [false, true, false, false]);
+    engine.cleanup();
+}
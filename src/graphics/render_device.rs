@@ -0,0 +1,97 @@
+// Everything in graphics talks to the gpu through a RenderDevice instead of calling opengl directly, so it can run without a gl context.
+// GlDevice is the real one. RecordingDevice just remembers what it was told to do (and gives mapped buffers some ordinary memory), for headless checks and servers.
+// Ids are plain u32s like opengl's, 0 meaning none. Calls that act on "the bound" something (attribs, uniforms, indirect draws) work like opengl's do.
+
+use crate::graphics::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BufferTarget {
+    Vertex,
+    Index,
+    DrawIndirect,
+    ShaderStorage,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TextureKind {
+    Tex2D,
+    Tex2DArray,
+}
+
+// format of the data given to create_texture()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureFormat {
+    Rgba8,
+    Rgb8,
+    Depth32, // f32 depth
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureSampling {
+    Tiled, // linear filtering, repeats outside 0-1
+    Nearest,
+    DepthBorder, // nearest, and reads 1.0 (the far plane) outside 0-1, for shadowmaps
+}
+
+pub trait RenderDevice {
+    // a buffer with no storage yet, for buffer_static_data()
+    fn create_buffer(&self) -> GLuint;
+    // replaces buffer's storage with data, which won't change afterwards
+    fn buffer_static_data(&self, target: BufferTarget, buffer: GLuint, data: &[u8]);
+    // nbytes of storage that stays mapped for writing for as long as the buffer exists (persistent and coherent, so writes are seen by the gpu without flushing).
+    // the buffer is left bound to target
+    fn create_mapped_buffer(&self, target: BufferTarget, nbytes: isize) -> (GLuint, *mut c_void);
+    fn bind_buffer(&self, target: BufferTarget, buffer: GLuint);
+    // for shader storage buffers, binding is the number in the shader's layout(binding=)
+    fn bind_buffer_base(&self, target: BufferTarget, binding: GLuint, buffer: GLuint);
+    fn delete_buffer(&self, buffer: GLuint);
+
+    fn create_vertex_array(&self) -> GLuint;
+    fn bind_vertex_array(&self, vao: GLuint);
+    fn delete_vertex_array(&self, vao: GLuint);
+    // tells the bound vertex array that attribute index is n_floats floats read from buffer, starting offset bytes in and stride bytes apart.
+    // divisor 0 is one per vertex, 1 is one per instance
+    fn vertex_attrib(&self, index: GLuint, n_floats: i32, buffer: GLuint, offset: usize, stride: i32, divisor: GLuint);
+
+    // data is layers images of width*height stacked on top of each other (layers is 1 for Tex2D), or None to leave it uninitialized
+    fn create_texture(&self, kind: TextureKind, format: TextureFormat, sampling: TextureSampling, width: u32, height: u32, layers: u32, data: Option<&[u8]>) -> GLuint;
+    // unit is 0-7, texture 0 unbinds
+    fn bind_texture(&self, unit: GLuint, kind: TextureKind, texture: GLuint);
+    fn delete_texture(&self, texture: GLuint);
+
+    // panics if the framebuffer isn't complete. with no color texture, nothing is drawn to color at all
+    fn create_framebuffer(&self, color: Option<GLuint>, depth: Option<GLuint>) -> GLuint;
+    // 0 is the screen
+    fn bind_framebuffer(&self, framebuffer: GLuint);
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    fn delete_framebuffer(&self, framebuffer: GLuint);
+
+    // the fragment shader's output should be called color. Err has the compile/link log
+    fn create_program(&self, vertex_source: &str, fragment_source: &str) -> Result<GLuint, String>;
+    fn use_program(&self, program: GLuint);
+    // -1 if the program doesn't have (or doesn't use) that uniform
+    fn uniform_location(&self, program: GLuint, name: &str) -> GLint;
+    // uniform setters act on the program in use
+    fn set_uniform_i32(&self, location: GLint, value: i32);
+    fn set_uniform_mat4(&self, location: GLint, matrix: &glm::Mat4, transpose: bool);
+    fn delete_program(&self, program: GLuint);
+
+    fn set_clear_color(&self, rgba: [f32; 4]);
+    fn clear(&self, color: bool, depth: bool);
+    fn set_depth_test(&self, enabled: bool);
+    fn set_backface_culling(&self, enabled: bool);
+    // triangles from the bound vertex array
+    fn draw_arrays(&self, first: i32, count: i32);
+    // triangles with u32 indices from the bound index buffer, first_index_byte bytes in. shaders see gl_BaseInstance = base_instance
+    fn draw_elements_instanced(&self, n_indices: i32, first_index_byte: usize, n_instances: i32, base_vertex: i32, base_instance: GLuint);
+    // n_commands draws described by the bound draw indirect buffer, starting offset bytes in. each command is 5 u32s:
+    // index count, instance count, first index (in indices, not bytes), base vertex, base instance
+    fn multi_draw_elements_indirect(&self, n_commands: i32, offset: usize);
+    fn flush(&self);
+    // a fence the gpu passes once it's done with every command given before it, for knowing when mapped memory it reads can be written again
+    fn create_fence(&self) -> GLuint;
+    // blocks until the gpu has passed fence, then deletes it. 0 returns straight away
+    fn wait_fence(&self, fence: GLuint);
+    // the bottom left width x height pixels of the framebuffer's color (0 is the screen), None if the device can't read them
+    fn read_pixels(&self, framebuffer: GLuint, width: u32, height: u32) -> Option<RgbaImage>;
+}
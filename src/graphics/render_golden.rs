@@ -0,0 +1,237 @@
+// Golden image tests for GraphicsEngine::draw(), rendered with the SoftwareDevice so they run on machines without a gpu.
+// Every scenario renders a small scene, has to meet its own expectations (the sky is sky colored, the near cube hides the far one, etc.),
+// and the frame has to match the png in RENDER_GOLDEN_DIR give or take a few pixels, since float rounding can move triangle edges a little between platforms.
+// Run with cargo test, or with BLESS=1 set to rewrite the golden images after an intentional change to rendering.
+
+use glm::{vec3, vec4};
+
+use crate::ecs::*;
+use crate::transform::*;
+use crate::graphics::*;
+use crate::animation::*;
+use crate::scene::*;
+
+const RENDER_GOLDEN_DIR: &str = "golden/render";
+const RENDER_GOLDEN_RESOLUTION: (u32, u32) = (96, 64);
+const PIXEL_TOLERANCE: u8 = 8; // per channel
+const MAX_DIFFERENT_PIXELS: usize = 30; // ~0.5% of the image
+
+const SKY: [u8; 4] = [128, 128, 204, 255];
+
+fn expect_pixel(what: &str, image: &RgbaImage, x: u32, y: u32, expected: [u8; 4]) {
+    let actual = image.pixel(x, y);
+    assert!(actual.iter().zip(expected.iter()).all(|(a, b)| a.abs_diff(*b) <= PIXEL_TOLERANCE), "Expected {} at ({}, {}) to be {:?}, but it was {:?}", what, x, y, expected, actual);
+}
+
+// draws one frame of world the way the main loop would
+fn render(engine: &mut GraphicsEngine, world: &mut World) -> RgbaImage {
+    engine.update(RENDER_GOLDEN_RESOLUTION);
+    engine.update_entities(world);
+    engine.draw();
+    let image = engine.screenshot().expect("the software device didn't give back a frame");
+    engine.cleanup();
+    return image;
+}
+
+fn spawn(world: &mut World, mesh: usize, pos: glm::DVec3, color: glm::Vec4, texture_z: f32) -> Entity {
+    let mut render = RenderComponent::new(mesh);
+    render.set_rgba(color);
+    render.set_texture_z(texture_z);
+    return world.build_entity().with(Transform::meters(pos)).with(render).build();
+}
+
+// grass textured spheres in a row, tinted red, white and blue, with the texture array sampled per pixel
+fn textured_icospheres() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let (grass, _) = engine.load_texture_from_file("textures/grass.png", TextureType::TexArray2D);
+    let sphere = Mesh::from_obj("models/icosphere.obj", grass, engine.world_shader_id).unwrap();
+
+    let mut world = World::new();
+    let tints = [vec4(1.0, 0.3, 0.3, 1.0), vec4(1.0, 1.0, 1.0, 1.0), vec4(0.3, 0.3, 1.0, 1.0)];
+    for (i, tint) in tints.iter().enumerate() {
+        spawn(&mut world, sphere, dvec3((i as f64 - 1.0) * 1.5, 0.0, -4.0), *tint, 0.0);
+    }
+    let image = render(&mut engine, &mut world);
+
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    let cy = RENDER_GOLDEN_RESOLUTION.1 / 2;
+    let (left, middle, right) = (image.pixel(22, cy), image.pixel(RENDER_GOLDEN_RESOLUTION.0 / 2, cy), image.pixel(RENDER_GOLDEN_RESOLUTION.0 - 23, cy));
+    // grass.png is grey, so the tint is the only color
+    assert!(middle != SKY && middle[0].abs_diff(middle[2]) <= PIXEL_TOLERANCE, "Expected the middle sphere to be grey, but the center pixel is {:?}", middle);
+    assert!(left[0] > left[2] && right[2] > right[0], "Expected the left sphere to be red and the right one blue, but they're {:?} and {:?}", left, right);
+    return image;
+}
+
+// a red cube in front of a bigger green one. the red one has to hide the green one no matter what order they're drawn in,
+// and with backface culling the cube's insides never show
+fn depth_and_culling() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let cube = Mesh::from_obj("models/rainbowcube.obj", 0, engine.world_shader_id).unwrap();
+
+    let mut world = World::new();
+    let far = spawn(&mut world, cube, dvec3(0.0, 0.0, -6.0), vec4(0.0, 1.0, 0.0, 1.0), -1.0);
+    world.get_mut::<Transform>(far).unwrap().setscl(vec3(3.0, 3.0, 3.0));
+    spawn(&mut world, cube, dvec3(0.0, 0.0, -3.0), vec4(1.0, 0.0, 0.0, 1.0), -1.0);
+    let image = render(&mut engine, &mut world);
+
+    let (cx, cy) = (RENDER_GOLDEN_RESOLUTION.0 / 2, RENDER_GOLDEN_RESOLUTION.1 / 2);
+    expect_pixel("the near red cube", &image, cx, cy, [255, 0, 0, 255]);
+    expect_pixel("the far green cube", &image, cx + 16, cy, [0, 255, 0, 255]);
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    return image;
+}
+
+// the icosphere and each of its default levels of detail, left to right, drawn as normal meshes so they're all big enough to see.
+// simplifying shouldn't punch holes in them or shrink them
+fn icosphere_lods() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let sphere = Mesh::from_obj("models/icosphere.obj", 0, engine.world_shader_id).unwrap();
+    let mut meshes = vec![sphere];
+    meshes.extend(Mesh::generate_lods(sphere, &DEFAULT_LOD_LEVELS));
+
+    let mut world = World::new();
+    for (i, mesh) in meshes.iter().enumerate() {
+        spawn(&mut world, *mesh, dvec3((i as f64 - 1.5) * 1.1, 0.0, -3.0), vec4(1.0, 1.0, 1.0, 1.0), -1.0);
+    }
+    let image = render(&mut engine, &mut world);
+
+    let cy = RENDER_GOLDEN_RESOLUTION.1 / 2;
+    for i in 0..meshes.len() as u32 {
+        let cx = RENDER_GOLDEN_RESOLUTION.0 * (2 * i + 1) / 8;
+        expect_pixel(&format!("level of detail {}", i), &image, cx, cy, [255, 255, 255, 255]);
+    }
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    return image;
+}
+
+// models/gltf_test.gltf halfway through its Bend animation: the red cube with the checkered one on top on the left,
+// and the green bar on the right with its top half bent over by the skeleton
+fn gltf_model() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let model = GltfModel::load("models/gltf_test.gltf", &mut engine, &GltfImportOptions::new()).unwrap();
+
+    let mut world = World::new();
+    let instance = model.spawn(&mut world, &Transform::empty());
+    model.play_animation(&mut world, &instance, model.animation_index("Bend").expect("there's no Bend animation"));
+    animation_system(&mut world, 0.5);
+    skeletal_animation_system(&mut world, 0.5);
+    propagate_transforms(&mut world);
+    let image = render(&mut engine, &mut world);
+
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    expect_pixel("the red cube", &image, 29, 32, [255, 0, 0, 255]);
+    expect_pixel("the bottom of the bar", &image, 68, 37, [0, 255, 0, 255]);
+    // the checker texture is yellow and blue, neither of which is the sky or the red cube
+    let top = image.pixel(30, 13);
+    assert!(top != SKY && top != [255, 0, 0, 255], "Expected the checkered cube on top of the red one, but got {:?}", top);
+    // the bar's top is bent towards -x, so straight above its bottom there's only sky
+    expect_pixel("the sky where the bar would be if it wasn't bent", &image, 68, 26, SKY);
+    return image;
+}
+
+// models/obj_test.obj just below eye level: the grass textured crate tinted orange by its material on the left,
+// the blue gem with generated normals on the right, and the white floor under them
+fn obj_model() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let model = ObjModel::load("models/obj_test.obj", &mut engine, &ObjImportOptions::new()).unwrap();
+
+    let mut world = World::new();
+    model.spawn(&mut world, &Transform::meters(dvec3(0.0, -0.5, -5.0)));
+    propagate_transforms(&mut world);
+    let image = render(&mut engine, &mut world);
+
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    expect_pixel("the floor", &image, 48, 46, [255, 255, 255, 255]);
+    let (crate_color, gem) = (image.pixel(26, 38), image.pixel(69, 41));
+    assert!(crate_color[0] > crate_color[1] && crate_color[1] > crate_color[2], "Expected the crate to be orange, but it's {:?}", crate_color);
+    assert!(gem[2] > gem[1] && gem[1] > gem[0], "Expected the gem to be blue, but it's {:?}", gem);
+    return image;
+}
+
+// every Primitive in its own color, scaled back to its proportions. top row: cube, uv sphere, icosphere, cylinder,
+// bottom row: cone, capsule, plane and torus, those two tilted towards the camera so you can see the sky through the torus
+fn primitives() -> RgbaImage {
+    let mut engine = GraphicsEngine::new(Box::new(SoftwareDevice::new(RENDER_GOLDEN_RESOLUTION.0, RENDER_GOLDEN_RESOLUTION.1)), RENDER_GOLDEN_RESOLUTION);
+    let shapes = [
+        (Primitive::Cube { size: vec3(1.0, 1.0, 1.0) }, [255, 0, 0, 255]),
+        (Primitive::UvSphere { radius: 0.5, segments: 16, rings: 8 }, [0, 255, 0, 255]),
+        (Primitive::Icosphere { radius: 0.5, subdivisions: 2 }, [0, 0, 255, 255]),
+        (Primitive::Cylinder { radius: 0.4, height: 1.0, segments: 16 }, [255, 255, 0, 255]),
+        (Primitive::Cone { radius: 0.5, height: 1.0, segments: 16 }, [255, 0, 255, 255]),
+        (Primitive::Capsule { radius: 0.25, height: 1.0, segments: 16, rings: 4 }, [0, 255, 255, 255]),
+        (Primitive::Plane { size: glm::vec2(1.0, 1.0), subdivisions: 2 }, [255, 255, 255, 255]),
+        (Primitive::Torus { major_radius: 0.35, minor_radius: 0.15, major_segments: 24, minor_segments: 12 }, [255, 128, 0, 255]),
+    ];
+
+    let mut world = World::new();
+    for (i, (primitive, color)) in shapes.iter().enumerate() {
+        let mesh = Mesh::from_primitive(primitive, 0, engine.world_shader_id);
+        let size = LOADED_MESHES.lock().unwrap()[&mesh].original_size;
+        let (column, row) = ((i % 4) as f64, (i / 4) as f64);
+        let entity = spawn(&mut world, mesh, dvec3((column - 1.5) * 1.1, 0.55 - row * 1.1, -3.0), glm::make_vec4(&color.map(|c| c as f32 / 255.0)), -1.0);
+        let transform = world.get_mut::<Transform>(entity).unwrap();
+        transform.setscl(size * 0.9 / size.max());
+        if matches!(primitive, Primitive::Plane { .. } | Primitive::Torus { .. }) {
+            transform.rotate_around_axis(vec3(1.0, 0.0, 0.0), 60f32.to_radians());
+        }
+    }
+    let image = render(&mut engine, &mut world);
+
+    expect_pixel("the sky", &image, 0, 0, SKY);
+    for (i, (primitive, color)) in shapes.iter().enumerate() {
+        let (x, y) = (RENDER_GOLDEN_RESOLUTION.0 * (2 * (i as u32 % 4) + 1) / 8, RENDER_GOLDEN_RESOLUTION.1 / 2 - 12 + 24 * (i as u32 / 4));
+        let expected = if matches!(primitive, Primitive::Torus { .. }) {SKY} else {*color};
+        expect_pixel(&format!("the middle of {:?}", primitive), &image, x, y, expected);
+    }
+    return image;
+}
+
+// renders the scenario (which checks its own expectations), and compares the frame to golden/render/<name>.png (or rewrites it if BLESS is set)
+fn check_golden(name: &str, run: fn() -> RgbaImage) {
+    let image = run();
+    let path = format!("{}/{}.png", RENDER_GOLDEN_DIR, name);
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::create_dir_all(RENDER_GOLDEN_DIR).unwrap();
+        image.save_png(&path).unwrap();
+        println!("wrote {}", path);
+        return;
+    }
+    let golden = RgbaImage::load_png(&path).unwrap_or_else(|err| panic!("{} (run with BLESS=1 to create it)", err));
+    assert!(golden.width == image.width && golden.height == image.height, "Frame is {}x{} but {} is {}x{}", image.width, image.height, path, golden.width, golden.height);
+    let n_different = image.n_different_pixels(&golden, PIXEL_TOLERANCE);
+    if n_different > MAX_DIFFERENT_PIXELS {
+        let actual_path = format!("{}/{}.actual.png", RENDER_GOLDEN_DIR, name);
+        let _ = image.save_png(&actual_path);
+        panic!("{} pixels differ from {}, the frame was written to {}", n_different, path, actual_path);
+    }
+}
+
+#[test]
+fn textured_icospheres_golden() {
+    check_golden("textured_icospheres", textured_icospheres);
+}
+
+#[test]
+fn depth_and_culling_golden() {
+    check_golden("depth_and_culling", depth_and_culling);
+}
+
+#[test]
+fn icosphere_lods_golden() {
+    check_golden("icosphere_lods", icosphere_lods);
+}
+
+#[test]
+fn gltf_model_golden() {
+    check_golden("gltf_model", gltf_model);
+}
+
+#[test]
+fn obj_model_golden() {
+    check_golden("obj_model", obj_model);
+}
+
+#[test]
+fn primitives_golden() {
+    check_golden("primitives", primitives);
+}
@@ -0,0 +1,115 @@
+// 8 bit rgba pixels in memory, what RenderDevice::read_pixels() gives back. Rows go top to bottom like an image file (opengl's go bottom to top).
+// Can be saved as a png for thumbnails/golden images, and loaded back with stb_image.
+
+use stb_image::image::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>, // width*height*4 bytes
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        return Self { width, height, pixels: vec![0; (width * height * 4) as usize] };
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        return [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]];
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    pub fn load_png(path: &str) -> Result<Self, String> {
+        return match load_with_depth(path, 4, false) {
+            LoadResult::Error(message) => Err(format!("couldn't load {}: {}", path, message)),
+            LoadResult::ImageU8(image) => Ok(Self { width: image.width as u32, height: image.height as u32, pixels: image.data }),
+            LoadResult::ImageF32(..) => Err(format!("{} is a float image", path))
+        };
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        return std::fs::write(path, self.to_png()).map_err(|err| format!("couldn't write {}: {}", path, err));
+    }
+
+    // uncompressed png, good enough for small images and it's not worth a dependency
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.pixels.len() + self.height as usize);
+        for row in self.pixels.chunks_exact((self.width * 4).max(1) as usize) {
+            raw.push(0); // filter type none
+            raw.extend_from_slice(row);
+        }
+
+        // zlib stream made of "stored" deflate blocks, which can each hold up to 65535 bytes
+        let mut zlib = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(65535).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            zlib.push(if blocks.peek().is_none() {1} else {0});
+            zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bits per channel, rgba, deflate, no filtering, not interlaced
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib);
+        png_chunk(&mut png, b"IEND", &[]);
+        return png;
+    }
+
+    // biggest difference between any channel of any pixel, None if the sizes don't match
+    pub fn max_difference(&self, other: &RgbaImage) -> Option<u8> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        return Some(self.pixels.iter().zip(other.pixels.iter()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0));
+    }
+
+    // how many pixels have some channel more than tolerance off from other's
+    pub fn n_different_pixels(&self, other: &RgbaImage, tolerance: u8) -> usize {
+        return self.pixels.chunks_exact(4).zip(other.pixels.chunks_exact(4)).filter(|(a, b)| a.iter().zip(b.iter()).any(|(a, b)| a.abs_diff(*b) > tolerance)).count();
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {(crc >> 1) ^ 0xedb88320} else {crc >> 1};
+        }
+    }
+    return !crc;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
@@ -0,0 +1,345 @@
+// Quadric error mesh simplification (Garland & Heckbert), for making lower detail versions of meshes. See lod.rs.
+// Vertices that share a position are welded together while simplifying, so uv/normal seams don't stop edges from collapsing.
+// Every vertex that survives keeps its own normal and uv, only its position moves.
+// Ordered maps and sets are used on purpose: iterating hash maps would make the result change from run to run.
+
+use std::collections::{BinaryHeap, BTreeMap, BTreeSet};
+use std::cmp::Ordering;
+use glm::DVec3;
+
+use crate::transform::dvec3;
+
+use crate::graphics::N_FLOATS_PER_VERTEX;
+
+// open edges (holes, the rim of a plane) get an extra plane through them this many times as important as a triangle's,
+// otherwise the outline of the mesh would shrink first since collapsing along it costs nothing
+const BORDER_WEIGHT: f64 = 100.0;
+
+// collapses that turn a triangle more than this far (cos of the angle) are skipped, so the mesh doesn't fold over itself
+const MIN_NORMAL_DOT: f64 = 0.2;
+
+// symmetric 4x4 matrix, stored as its upper triangle: a2 ab ac ad b2 bc bd c2 cd d2
+#[derive(Clone, Copy)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn zero() -> Self {
+        return Self([0.0; 10]);
+    }
+
+    // squared distance to the plane ax + by + cz + d = 0, times weight
+    fn from_plane(normal: &DVec3, d: f64, weight: f64) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        return Self([a*a, a*b, a*c, a*d, b*b, b*c, b*d, c*c, c*d, d*d].map(|x| x * weight));
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..10 {
+            self.0[i] += other.0[i];
+        }
+    }
+
+    fn error(&self, p: &DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        return q[0]*x*x + 2.0*q[1]*x*y + 2.0*q[2]*x*z + 2.0*q[3]*x + q[4]*y*y + 2.0*q[5]*y*z + 2.0*q[6]*y + q[7]*z*z + 2.0*q[8]*z + q[9];
+    }
+
+    // the point with the least error, if there is only one
+    fn optimal_point(&self) -> Option<DVec3> {
+        let q = &self.0;
+        let m = glm::DMat3::new(q[0], q[1], q[2], q[1], q[4], q[5], q[2], q[5], q[7]);
+        if m.determinant().abs() < 1e-12 {
+            return None;
+        }
+        return m.try_inverse().map(|inverse| -(inverse * dvec3(q[3], q[6], q[8])));
+    }
+}
+
+// a possible edge collapse in the queue. versions are the endpoints' versions when it was queued, if either has changed since it's outdated
+struct Collapse {
+    cost: f64,
+    a: usize,
+    b: usize,
+    versions: (u32, u32),
+    target: DVec3,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cost == other.cost;
+    }
+}
+impl Eq for Collapse {}
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+impl Ord for Collapse {
+    // reversed, so the BinaryHeap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal);
+    }
+}
+
+struct Simplifier {
+    positions: Vec<DVec3>, // per welded position
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    collapsed_into: Vec<usize>, // itself unless it was collapsed
+    triangles: Vec<[usize; 3]>, // welded positions, not vertices
+    alive: Vec<bool>,
+    position_triangles: Vec<Vec<usize>>, // triangles touching each position, can include dead ones
+}
+
+impl Simplifier {
+    fn find(&self, mut position: usize) -> usize {
+        while self.collapsed_into[position] != position {
+            position = self.collapsed_into[position];
+        }
+        return position;
+    }
+
+    fn normal(&self, triangle: &[usize; 3]) -> DVec3 {
+        let [a, b, c] = triangle.map(|p| self.positions[p]);
+        return (b - a).cross(&(c - a));
+    }
+
+    fn neighbors(&self, position: usize) -> BTreeSet<usize> {
+        let mut neighbors = BTreeSet::new();
+        for t in self.position_triangles[position].iter() {
+            if self.alive[*t] {
+                neighbors.extend(self.triangles[*t].iter().filter(|p| **p != position));
+            }
+        }
+        return neighbors;
+    }
+
+    fn queue_collapse(&self, heap: &mut BinaryHeap<Collapse>, a: usize, b: usize) {
+        let mut quadric = self.quadrics[a];
+        quadric.add(&self.quadrics[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let mut candidates = vec![pa, pb, (pa + pb) * 0.5];
+        if let Some(optimal) = quadric.optimal_point() {
+            // the optimum can be far away for almost flat areas, only trust it near the edge
+            if (optimal - (pa + pb) * 0.5).norm() <= (pb - pa).norm() {
+                candidates.push(optimal);
+            }
+        }
+        let (mut target, mut cost) = (pa, f64::INFINITY);
+        for candidate in candidates {
+            let error = quadric.error(&candidate);
+            if error < cost {
+                target = candidate;
+                cost = error;
+            }
+        }
+        heap.push(Collapse { cost: cost.max(0.0), a, b, versions: (self.versions[a], self.versions[b]), target });
+    }
+
+    // false if moving a and b to target would flip a triangle, or if they share more than the 2 neighbors an interior edge should (which would make the mesh non-manifold)
+    fn can_collapse(&self, a: usize, b: usize, target: &DVec3) -> bool {
+        if self.neighbors(a).intersection(&self.neighbors(b)).count() > 2 {
+            return false;
+        }
+        for position in [a, b] {
+            for t in self.position_triangles[position].iter() {
+                let triangle = self.triangles[*t];
+                if !self.alive[*t] || (triangle.contains(&a) && triangle.contains(&b)) {
+                    continue; // these ones disappear
+                }
+                let before = self.normal(&triangle);
+                let corners = triangle.map(|p| if p == position {*target} else {self.positions[p]});
+                let after = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+                let lengths = before.norm() * after.norm();
+                if lengths == 0.0 || before.dot(&after) < MIN_NORMAL_DOT * lengths {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    // moves b into a
+    fn collapse(&mut self, a: usize, b: usize, target: DVec3) -> usize {
+        let mut n_removed = 0;
+        self.positions[a] = target;
+        let quadric = self.quadrics[b];
+        self.quadrics[a].add(&quadric);
+        self.collapsed_into[b] = a;
+        self.versions[a] += 1;
+        self.versions[b] += 1;
+
+        let moved = std::mem::take(&mut self.position_triangles[b]);
+        for t in moved {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.triangles[t].contains(&a) {
+                self.alive[t] = false;
+                n_removed += 1;
+                continue;
+            }
+            for p in self.triangles[t].iter_mut() {
+                if *p == b {
+                    *p = a;
+                }
+            }
+            self.position_triangles[a].push(t);
+        }
+        let alive = &self.alive;
+        self.position_triangles[a].retain(|t| alive[*t]);
+        return n_removed;
+    }
+}
+
+// returns new vertices and indices with at most target_n_triangles triangles, or as close as it could get without folding the mesh over itself.
+// vertices are N_FLOATS_PER_VERTEX floats each, like Mesh::vertices
+pub fn simplify(vertices: &[f32], indices: &[u32], target_n_triangles: usize) -> (Vec<f32>, Vec<u32>) {
+    let n_vertices = vertices.len() / N_FLOATS_PER_VERTEX;
+
+    // weld vertices by position
+    let mut welded = BTreeMap::new();
+    let mut vertex_positions = Vec::with_capacity(n_vertices);
+    let mut positions = Vec::new();
+    for vertex in vertices.chunks_exact(N_FLOATS_PER_VERTEX) {
+        let key = [vertex[0].to_bits(), vertex[1].to_bits(), vertex[2].to_bits()];
+        let position = *welded.entry(key).or_insert_with(|| {
+            positions.push(dvec3(vertex[0] as f64, vertex[1] as f64, vertex[2] as f64));
+            return positions.len() - 1;
+        });
+        vertex_positions.push(position);
+    }
+
+    let n_positions = positions.len();
+    let mut simplifier = Simplifier {
+        positions,
+        quadrics: vec![Quadric::zero(); n_positions],
+        versions: vec![0; n_positions],
+        collapsed_into: (0..n_positions).collect(),
+        triangles: Vec::new(),
+        alive: Vec::new(),
+        position_triangles: vec![Vec::new(); n_positions],
+    };
+
+    // the vertices (not positions) of each triangle, for putting the output back together
+    let mut triangle_vertices = Vec::new();
+    for triangle in indices.chunks_exact(3) {
+        let vertex_triangle = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let position_triangle = vertex_triangle.map(|v| vertex_positions[v]);
+        if position_triangle[0] == position_triangle[1] || position_triangle[1] == position_triangle[2] || position_triangle[0] == position_triangle[2] {
+            continue; // already degenerate
+        }
+        let t = simplifier.triangles.len();
+        for p in position_triangle {
+            simplifier.position_triangles[p].push(t);
+        }
+        simplifier.triangles.push(position_triangle);
+        simplifier.alive.push(true);
+        triangle_vertices.push(vertex_triangle);
+    }
+    let mut n_alive = simplifier.triangles.len();
+
+    // every position starts with the planes of the triangles around it, weighted by area so tiny triangles don't count as much as big ones
+    let mut edge_count: BTreeMap<(usize, usize), (usize, usize)> = BTreeMap::new(); // edge -> (how many triangles use it, one of them)
+    for (t, triangle) in simplifier.triangles.iter().enumerate() {
+        let normal = simplifier.normal(triangle);
+        let area = normal.norm() * 0.5;
+        if area == 0.0 {
+            continue;
+        }
+        let normal = normal.normalize();
+        let quadric = Quadric::from_plane(&normal, -normal.dot(&simplifier.positions[triangle[0]]), area);
+        for p in triangle {
+            simplifier.quadrics[*p].add(&quadric);
+        }
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            let entry = edge_count.entry((a.min(b), a.max(b))).or_insert((0, t));
+            entry.0 += 1;
+        }
+    }
+    for ((a, b), (count, t)) in edge_count.iter() {
+        if *count != 1 {
+            continue;
+        }
+        let face_normal = simplifier.normal(&simplifier.triangles[*t]).normalize();
+        let edge = simplifier.positions[*b] - simplifier.positions[*a];
+        let border_normal = edge.cross(&face_normal);
+        if border_normal.norm() == 0.0 {
+            continue;
+        }
+        let border_normal = border_normal.normalize();
+        let quadric = Quadric::from_plane(&border_normal, -border_normal.dot(&simplifier.positions[*a]), edge.norm_squared() * BORDER_WEIGHT);
+        simplifier.quadrics[*a].add(&quadric);
+        simplifier.quadrics[*b].add(&quadric);
+    }
+
+    let mut heap = BinaryHeap::new();
+    for (a, b) in edge_count.keys() {
+        simplifier.queue_collapse(&mut heap, *a, *b);
+    }
+
+    while n_alive > target_n_triangles {
+        let collapse = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break
+        };
+        let (a, b) = (collapse.a, collapse.b);
+        if simplifier.collapsed_into[a] != a || simplifier.collapsed_into[b] != b || collapse.versions != (simplifier.versions[a], simplifier.versions[b]) {
+            continue; // outdated
+        }
+        if !simplifier.can_collapse(a, b, &collapse.target) {
+            continue;
+        }
+        n_alive -= simplifier.collapse(a, b, collapse.target);
+        for neighbor in simplifier.neighbors(a) {
+            simplifier.queue_collapse(&mut heap, a, neighbor);
+        }
+    }
+
+    // put the surviving triangles back together out of the original vertices, at their new positions
+    let mut new_vertices = Vec::new();
+    let mut new_indices = Vec::with_capacity(n_alive * 3);
+    let mut remap: BTreeMap<usize, u32> = BTreeMap::new();
+    for (t, triangle) in triangle_vertices.iter().enumerate() {
+        if !simplifier.alive[t] {
+            continue;
+        }
+        for v in triangle {
+            let index = *remap.entry(*v).or_insert_with(|| {
+                let position = simplifier.positions[simplifier.find(vertex_positions[*v])];
+                new_vertices.extend_from_slice(&[position.x as f32, position.y as f32, position.z as f32]);
+                new_vertices.extend_from_slice(&vertices[v * N_FLOATS_PER_VERTEX + 3..(v + 1) * N_FLOATS_PER_VERTEX]);
+                return (new_vertices.len() / N_FLOATS_PER_VERTEX - 1) as u32;
+            });
+            new_indices.push(index);
+        }
+    }
+    return (new_vertices, new_indices);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::*;
+
+    // a quarter of the icosphere's triangles should still look like the icosphere: about as big, and not collapsed to a point
+    #[test]
+    fn simplify_keeps_shape() {
+        let mesh_id = Mesh::from_obj("models/icosphere.obj", 0, 0).unwrap();
+        let (vertices, indices) = {
+            let meshes = LOADED_MESHES.lock().unwrap();
+            (meshes[&mesh_id].vertices.clone(), meshes[&mesh_id].indices.clone())
+        };
+        let target = indices.len() / 3 / 4;
+        let (new_vertices, new_indices) = simplify(&vertices, &indices, target);
+        let n_vertices = new_vertices.len() / N_FLOATS_PER_VERTEX;
+        assert!(new_indices.len() / 3 <= target && new_indices.len() / 3 >= target / 2, "asked for {} triangles out of {}, but got {}", target, indices.len() / 3, new_indices.len() / 3);
+        assert!(new_indices.iter().all(|index| (*index as usize) < n_vertices), "an index is past the {} vertices", n_vertices);
+        for vertex in new_vertices.chunks_exact(N_FLOATS_PER_VERTEX) {
+            let distance = glm::vec3(vertex[0], vertex[1], vertex[2]).norm();
+            assert!(distance >= 0.35 && distance <= 0.55, "expected every vertex to stay on the sphere (0.5 from the center), but one is {} away", distance);
+        }
+    }
+}
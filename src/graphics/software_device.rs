@@ -0,0 +1,643 @@
+// A RenderDevice that rasterizes on the CPU, for golden image checks on machines without a gpu and for rendering thumbnails on servers.
+// It can't run glsl, so create_program() recognizes the engine's own shaders from their source and runs a rust copy of them instead:
+//     - world_vertex/skinned_world_vertex + world_fragment: instanced model matrices (and joint matrices), vertex color times the texture array, alpha < 0.1 discarded
+//     - postproc: copies screenTexture to the screen (the postproc kernel only has the center tap, so that's all it does anyway)
+//     - anything else links fine but draws nothing
+// Framebuffer 0 (the screen) is width x height, read it back with read_pixels(). Slow, but it doesn't have to be fast.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use glm::{Mat4, Vec4, vec4};
+
+use crate::animation::MAX_JOINTS;
+use crate::graphics::*;
+
+struct SoftTexture {
+    format: TextureFormat,
+    sampling: TextureSampling,
+    width: u32,
+    height: u32,
+    layers: u32,
+    texels: Vec<[f32; 4]>, // bottom row first like opengl, depth textures keep depth in r
+}
+
+#[derive(Clone, Copy)]
+struct SoftAttrib {
+    n_floats: i32,
+    buffer: GLuint,
+    offset: usize,
+    stride: i32,
+    divisor: GLuint,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SoftProgram {
+    World { skinned: bool },
+    Blit,
+    Unsupported,
+}
+
+// one vertex after the "vertex shader"
+#[derive(Clone, Copy)]
+struct ShadedVertex {
+    clip: Vec4,
+    varyings: [f32; 6], // world: rgb + texture coords (u, v, layer). blit: texture coords (u, v)
+}
+
+// a vertex in window coordinates, ready to be rasterized
+#[derive(Clone, Copy)]
+struct WindowVertex {
+    x: f32,
+    y: f32,
+    z: f32, // 0-1 depth
+    inv_w: f32,
+    varyings: [f32; 6],
+}
+
+struct SoftState {
+    buffers: HashMap<GLuint, Box<[u8]>>, // boxed so mapped pointers stay put when the map grows
+    vertex_arrays: HashMap<GLuint, HashMap<GLuint, SoftAttrib>>,
+    textures: HashMap<GLuint, SoftTexture>,
+    framebuffers: HashMap<GLuint, (Option<GLuint>, Option<GLuint>)>, // (color, depth) textures
+    programs: HashMap<GLuint, SoftProgram>,
+    uniform_locations: HashMap<(GLuint, String), GLint>,
+    uniform_matrices: HashMap<GLint, Mat4>, // locations are unique across programs, so no need to key these by program too
+
+    vertex_array: GLuint,
+    index_buffer: GLuint,
+    indirect_buffer: GLuint,
+    storage_buffers: HashMap<GLuint, GLuint>, // binding -> buffer
+    bound_textures: HashMap<(GLuint, TextureKind), GLuint>, // (unit, kind) -> texture
+    program: GLuint,
+    framebuffer: GLuint,
+    viewport: (i32, i32, i32, i32),
+    clear_color: [f32; 4],
+    depth_test: bool,
+    backface_culling: bool,
+}
+
+pub struct SoftwareDevice {
+    state: RefCell<SoftState>,
+    last_id: Cell<GLuint>,
+    n_triangles: Cell<usize>,
+}
+
+impl SoftwareDevice {
+    pub fn new(width: u32, height: u32) -> Self {
+        let device = Self {
+            state: RefCell::new(SoftState {
+                buffers: HashMap::new(),
+                vertex_arrays: HashMap::new(),
+                textures: HashMap::new(),
+                framebuffers: HashMap::new(),
+                programs: HashMap::new(),
+                uniform_locations: HashMap::new(),
+                uniform_matrices: HashMap::new(),
+
+                vertex_array: 0,
+                index_buffer: 0,
+                indirect_buffer: 0,
+                storage_buffers: HashMap::new(),
+                bound_textures: HashMap::new(),
+                program: 0,
+                framebuffer: 0,
+                viewport: (0, 0, width as i32, height as i32),
+                clear_color: [0.0, 0.0, 0.0, 0.0],
+                depth_test: false,
+                backface_culling: false,
+            }),
+            last_id: Cell::new(0),
+            n_triangles: Cell::new(0),
+        };
+
+        // the screen is just another framebuffer with id 0
+        let color = device.create_texture(TextureKind::Tex2D, TextureFormat::Rgba8, TextureSampling::Nearest, width, height, 1, None);
+        let depth = device.create_texture(TextureKind::Tex2D, TextureFormat::Depth32, TextureSampling::DepthBorder, width, height, 1, None);
+        device.state.borrow_mut().framebuffers.insert(0, (Some(color), Some(depth)));
+        return device;
+    }
+
+    // triangles that made it past clipping and culling since the device was made, to check something was actually drawn
+    pub fn n_triangles_rasterized(&self) -> usize {
+        return self.n_triangles.get();
+    }
+
+    fn next_id(&self) -> GLuint {
+        self.last_id.set(self.last_id.get() + 1);
+        return self.last_id.get();
+    }
+
+    // runs the bound program over the given (vertex, instance) pairs three at a time, instance being gl_BaseInstance + gl_InstanceID
+    fn draw_triangles(&self, vertices: &[(u32, u32)]) {
+        let mut state = self.state.borrow_mut();
+        let program = *state.programs.get(&state.program).unwrap_or(&SoftProgram::Unsupported);
+        if program == SoftProgram::Unsupported {
+            return;
+        }
+        let (color_id, depth_id) = state.framebuffers.get(&state.framebuffer).copied().unwrap_or((None, None));
+
+        // take the render targets out of the map while drawing, so the program can still sample every other texture
+        let mut color = color_id.and_then(|id| state.textures.remove(&id));
+        let mut depth = if state.depth_test {depth_id.and_then(|id| state.textures.remove(&id))} else {None};
+
+        let shaded: Vec<ShadedVertex> = vertices.iter().map(|(vertex, instance)| shade_vertex(&state, program, *vertex, *instance)).collect();
+        for triangle in shaded.chunks_exact(3) {
+            for clipped in clip_near(triangle).chunks_exact(3) {
+                if self.rasterize(&state, program, clipped, color.as_mut(), depth.as_mut()) {
+                    self.n_triangles.set(self.n_triangles.get() + 1);
+                }
+            }
+        }
+
+        if let (Some(id), Some(texture)) = (color_id, color) {
+            state.textures.insert(id, texture);
+        }
+        if let (Some(id), Some(texture)) = (depth_id, depth) {
+            state.textures.insert(id, texture);
+        }
+    }
+
+    // returns false if the triangle was culled
+    fn rasterize(&self, state: &SoftState, program: SoftProgram, triangle: &[ShadedVertex], mut color: Option<&mut SoftTexture>, mut depth: Option<&mut SoftTexture>) -> bool {
+        let (vx, vy, vw, vh) = state.viewport;
+        let window: Vec<WindowVertex> = triangle.iter().map(|v| {
+            let inv_w = 1.0 / v.clip.w;
+            return WindowVertex {
+                x: snap(vx as f32 + (v.clip.x * inv_w * 0.5 + 0.5) * vw as f32),
+                y: snap(vy as f32 + (v.clip.y * inv_w * 0.5 + 0.5) * vh as f32),
+                z: v.clip.z * inv_w * 0.5 + 0.5,
+                inv_w: inv_w,
+                varyings: v.varyings,
+            };
+        }).collect();
+
+        // counter clockwise is the front, like opengl's default
+        let (mut a, mut b, c) = (window[0], window[1], window[2]);
+        let mut area = edge(&a, &b, c.x, c.y);
+        if area == 0.0 || (state.backface_culling && area < 0.0) {
+            return false;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut a, &mut b);
+            area = -area;
+        }
+
+        // only the part inside both the viewport and the target
+        let (target_w, target_h) = match (&color, &depth) {
+            (Some(t), _) | (None, Some(t)) => (t.width as i32, t.height as i32),
+            (None, None) => return true
+        };
+        let min_x = (a.x.min(b.x).min(c.x).floor() as i32).max(vx).max(0);
+        let max_x = (a.x.max(b.x).max(c.x).ceil() as i32).min(vx + vw).min(target_w);
+        let min_y = (a.y.min(b.y).min(c.y).floor() as i32).max(vy).max(0);
+        let max_y = (a.y.max(b.y).max(c.y).ceil() as i32).min(vy + vh).min(target_h);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let (w_a, w_b, w_c) = (edge(&b, &c, px, py), edge(&c, &a, px, py), edge(&a, &b, px, py));
+                if !covers(w_a, &b, &c) || !covers(w_b, &c, &a) || !covers(w_c, &a, &b) {
+                    continue;
+                }
+                let (l_a, l_b, l_c) = (w_a / area, w_b / area, w_c / area);
+
+                let z = l_a * a.z + l_b * b.z + l_c * c.z;
+                if z < 0.0 || z > 1.0 {
+                    continue;
+                }
+                let texel = (y as u32 * target_w as u32 + x as u32) as usize;
+                if let Some(depth) = depth.as_deref_mut() {
+                    if z >= depth.texels[texel][0] {
+                        continue;
+                    }
+                }
+
+                // perspective correct interpolation
+                let (p_a, p_b, p_c) = (l_a * a.inv_w, l_b * b.inv_w, l_c * c.inv_w);
+                let sum = p_a + p_b + p_c;
+                let mut varyings = [0.0; 6];
+                for i in 0..6 {
+                    varyings[i] = (p_a * a.varyings[i] + p_b * b.varyings[i] + p_c * c.varyings[i]) / sum;
+                }
+
+                let rgba = match shade_fragment(state, program, &varyings) {
+                    Some(rgba) => rgba,
+                    None => continue // discarded, so it doesn't write depth either
+                };
+                if let Some(depth) = depth.as_deref_mut() {
+                    depth.texels[texel][0] = z;
+                }
+                if let Some(color) = color.as_deref_mut() {
+                    color.texels[texel] = store(color.format, rgba);
+                }
+            }
+        }
+        return true;
+    }
+}
+
+// gpus snap vertices to a grid of 1/256th of a pixel, which keeps edge math exact enough that triangles sharing an edge don't leave gaps between them
+fn snap(coord: f32) -> f32 {
+    return (coord * 256.0).round() / 256.0;
+}
+
+// twice the signed area of abp, positive if p is left of a->b.
+// always worked out from the same end of the edge, so the triangles on either side of it get exactly opposite values
+fn edge(a: &WindowVertex, b: &WindowVertex, px: f32, py: f32) -> f32 {
+    if (a.x, a.y) > (b.x, b.y) {
+        return -edge(b, a, px, py);
+    }
+    return (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x);
+}
+
+// top-left rule, so pixels exactly on an edge shared by two triangles only get drawn once
+fn covers(w: f32, a: &WindowVertex, b: &WindowVertex) -> bool {
+    if w != 0.0 {
+        return w > 0.0;
+    }
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    return dy < 0.0 || (dy == 0.0 && dx < 0.0);
+}
+
+// cuts off whatever's in front of the near plane (z < -w), returns a list of triangles.
+// the other planes are left to the rasterizer, which only looks at pixels inside the viewport anyway
+fn clip_near(triangle: &[ShadedVertex]) -> Vec<ShadedVertex> {
+    let distance = |v: &ShadedVertex| v.clip.z + v.clip.w;
+    let mut polygon = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (current, next) = (&triangle[i], &triangle[(i + 1) % 3]);
+        let (d_current, d_next) = (distance(current), distance(next));
+        if d_current >= 0.0 {
+            polygon.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            let mut varyings = [0.0; 6];
+            for j in 0..6 {
+                varyings[j] = current.varyings[j] + (next.varyings[j] - current.varyings[j]) * t;
+            }
+            polygon.push(ShadedVertex { clip: current.clip + (next.clip - current.clip) * t, varyings });
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for i in 1..polygon.len().saturating_sub(1) {
+        triangles.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+    }
+    return triangles;
+}
+
+fn read_f32(state: &SoftState, buffer: GLuint, byte: usize) -> f32 {
+    return match state.buffers.get(&buffer).and_then(|data| data.get(byte..byte + 4)) {
+        Some(bytes) => f32::from_ne_bytes(bytes.try_into().unwrap()),
+        None => 0.0
+    };
+}
+
+fn read_u32(state: &SoftState, buffer: GLuint, byte: usize) -> u32 {
+    return match state.buffers.get(&buffer).and_then(|data| data.get(byte..byte + 4)) {
+        Some(bytes) => u32::from_ne_bytes(bytes.try_into().unwrap()),
+        None => 0
+    };
+}
+
+// like opengl, attributes that aren't set up are (0, 0, 0, 1) and missing components are filled in from that
+fn attrib(state: &SoftState, index: GLuint, vertex: u32, instance: u32) -> Vec4 {
+    let attrib = match state.vertex_arrays.get(&state.vertex_array).and_then(|attribs| attribs.get(&index)) {
+        Some(attrib) => attrib,
+        None => return vec4(0.0, 0.0, 0.0, 1.0)
+    };
+    let stride = if attrib.stride == 0 {attrib.n_floats as usize * 4} else {attrib.stride as usize};
+    let element = if attrib.divisor == 0 {vertex} else {instance / attrib.divisor};
+    let start = attrib.offset + element as usize * stride;
+    let mut value = vec4(0.0, 0.0, 0.0, 1.0);
+    for i in 0..attrib.n_floats.min(4) as usize {
+        value[i] = read_f32(state, attrib.buffer, start + i * 4);
+    }
+    return value;
+}
+
+fn uniform_matrix(state: &SoftState, name: &str) -> Mat4 {
+    return state.uniform_locations.get(&(state.program, name.to_string())).and_then(|location| state.uniform_matrices.get(location)).copied().unwrap_or(glm::identity());
+}
+
+fn shade_vertex(state: &SoftState, program: SoftProgram, vertex: u32, instance: u32) -> ShadedVertex {
+    match program {
+        SoftProgram::World { skinned } => {
+            let pos = attrib(state, 0, vertex, instance);
+            let color = attrib(state, 1, vertex, instance);
+            let tex_coords = attrib(state, 3, vertex, instance);
+            let model = Mat4::from_columns(&[attrib(state, 4, vertex, instance), attrib(state, 5, vertex, instance), attrib(state, 6, vertex, instance), attrib(state, 7, vertex, instance)]);
+            let texture_z = attrib(state, 8, vertex, instance).x;
+
+            let mut skin: Mat4 = glm::identity();
+            if skinned {
+                let joints = attrib(state, 9, vertex, instance);
+                let weights = attrib(state, 10, vertex, instance);
+                let buffer = state.storage_buffers.get(&JOINT_MATRICES_BINDING).copied().unwrap_or(0);
+                skin = Mat4::zeros();
+                for i in 0..4 {
+                    let start = (instance as usize * MAX_JOINTS + joints[i] as usize) * 64;
+                    let joint = Mat4::from_iterator((0..16).map(|j| read_f32(state, buffer, start + j * 4)));
+                    skin += joint * weights[i];
+                }
+            }
+
+            let clip = uniform_matrix(state, "proj") * uniform_matrix(state, "camera") * model * skin * vec4(pos.x, pos.y, pos.z, 1.0);
+            return ShadedVertex { clip, varyings: [color.x, color.y, color.z, tex_coords.x, tex_coords.y, texture_z] };
+        }
+        SoftProgram::Blit => {
+            let pos = attrib(state, 0, vertex, instance);
+            let tex_coords = attrib(state, 1, vertex, instance);
+            return ShadedVertex { clip: vec4(pos.x, pos.y, 0.0, 1.0), varyings: [tex_coords.x, tex_coords.y, 0.0, 0.0, 0.0, 0.0] };
+        }
+        SoftProgram::Unsupported => return ShadedVertex { clip: vec4(0.0, 0.0, 0.0, 1.0), varyings: [0.0; 6] }
+    }
+}
+
+// None means discard
+fn shade_fragment(state: &SoftState, program: SoftProgram, varyings: &[f32; 6]) -> Option<[f32; 4]> {
+    match program {
+        SoftProgram::World {..} => {
+            let tx = if varyings[5] == -1.0 {
+                [1.0, 1.0, 1.0, 1.0]
+            }
+            else {
+                sample(state, 0, TextureKind::Tex2DArray, varyings[3], varyings[4], varyings[5])
+            };
+            if tx[3] < 0.1 {
+                return None;
+            }
+            return Some([varyings[0] * tx[0], varyings[1] * tx[1], varyings[2] * tx[2], 0.0]);
+        }
+        SoftProgram::Blit => {
+            let tx = sample(state, 0, TextureKind::Tex2D, varyings[0], varyings[1], 0.0);
+            return Some([tx[0], tx[1], tx[2], 1.0]);
+        }
+        SoftProgram::Unsupported => return None
+    }
+}
+
+// reading a texture that isn't there gives (0, 0, 0, 1), same as an incomplete texture in opengl
+fn sample(state: &SoftState, unit: GLuint, kind: TextureKind, u: f32, v: f32, layer: f32) -> [f32; 4] {
+    let texture = match state.bound_textures.get(&(unit, kind)).and_then(|id| state.textures.get(id)) {
+        Some(texture) => texture,
+        None => return [0.0, 0.0, 0.0, 1.0]
+    };
+    let layer = (layer + 0.5).floor().clamp(0.0, (texture.layers - 1) as f32) as u32;
+    let (w, h) = (texture.width as i32, texture.height as i32);
+    let texel = |x: i32, y: i32| -> [f32; 4] {
+        if texture.sampling == TextureSampling::DepthBorder && (x < 0 || y < 0 || x >= w || y >= h) {
+            return [1.0, 1.0, 1.0, 1.0];
+        }
+        let (x, y) = (x.rem_euclid(w), y.rem_euclid(h));
+        return texture.texels[((layer * texture.height + y as u32) * texture.width + x as u32) as usize];
+    };
+
+    if texture.sampling != TextureSampling::Tiled {
+        return texel((u * w as f32).floor() as i32, (v * h as f32).floor() as i32);
+    }
+    // bilinear
+    let (x, y) = (u * w as f32 - 0.5, v * h as f32 - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let (t00, t10, t01, t11) = (texel(x0, y0), texel(x0 + 1, y0), texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+    let mut result = [0.0; 4];
+    for i in 0..4 {
+        let bottom = t00[i] + (t10[i] - t00[i]) * fx;
+        let top = t01[i] + (t11[i] - t01[i]) * fx;
+        result[i] = bottom + (top - bottom) * fy;
+    }
+    return result;
+}
+
+// what actually ends up in a texture of that format
+fn store(format: TextureFormat, rgba: [f32; 4]) -> [f32; 4] {
+    return match format {
+        TextureFormat::Depth32 => rgba,
+        _ => rgba.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() / 255.0)
+    };
+}
+
+impl RenderDevice for SoftwareDevice {
+    fn create_buffer(&self) -> GLuint {
+        let buffer = self.next_id();
+        self.state.borrow_mut().buffers.insert(buffer, Box::new([]));
+        return buffer;
+    }
+
+    fn buffer_static_data(&self, _target: BufferTarget, buffer: GLuint, data: &[u8]) {
+        self.state.borrow_mut().buffers.insert(buffer, data.to_vec().into_boxed_slice());
+    }
+
+    fn create_mapped_buffer(&self, target: BufferTarget, nbytes: isize) -> (GLuint, *mut c_void) {
+        let buffer = self.next_id();
+        let mut storage = vec![0u8; nbytes.max(0) as usize].into_boxed_slice();
+        let ptr = storage.as_mut_ptr() as *mut c_void;
+        self.state.borrow_mut().buffers.insert(buffer, storage);
+        self.bind_buffer(target, buffer);
+        return (buffer, ptr);
+    }
+
+    fn bind_buffer(&self, target: BufferTarget, buffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        match target {
+            BufferTarget::Index => state.index_buffer = buffer,
+            BufferTarget::DrawIndirect => state.indirect_buffer = buffer,
+            _ => {}
+        }
+    }
+
+    fn bind_buffer_base(&self, target: BufferTarget, binding: GLuint, buffer: GLuint) {
+        if target == BufferTarget::ShaderStorage {
+            self.state.borrow_mut().storage_buffers.insert(binding, buffer);
+        }
+    }
+
+    fn delete_buffer(&self, buffer: GLuint) {
+        self.state.borrow_mut().buffers.remove(&buffer);
+    }
+
+    fn create_vertex_array(&self) -> GLuint {
+        let vao = self.next_id();
+        self.state.borrow_mut().vertex_arrays.insert(vao, HashMap::new());
+        return vao;
+    }
+
+    fn bind_vertex_array(&self, vao: GLuint) {
+        self.state.borrow_mut().vertex_array = vao;
+    }
+
+    fn delete_vertex_array(&self, vao: GLuint) {
+        self.state.borrow_mut().vertex_arrays.remove(&vao);
+    }
+
+    fn vertex_attrib(&self, index: GLuint, n_floats: i32, buffer: GLuint, offset: usize, stride: i32, divisor: GLuint) {
+        let mut state = self.state.borrow_mut();
+        let vao = state.vertex_array;
+        if let Some(attribs) = state.vertex_arrays.get_mut(&vao) {
+            attribs.insert(index, SoftAttrib { n_floats, buffer, offset, stride, divisor });
+        }
+    }
+
+    fn create_texture(&self, _kind: TextureKind, format: TextureFormat, sampling: TextureSampling, width: u32, height: u32, layers: u32, data: Option<&[u8]>) -> GLuint {
+        let texture = self.next_id();
+        let n_texels = (width * height * layers) as usize;
+        let texels = match (format, data) {
+            (TextureFormat::Rgba8, Some(data)) => data.chunks_exact(4).take(n_texels).map(|p| [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]).collect(),
+            (TextureFormat::Rgb8, Some(data)) => data.chunks_exact(3).take(n_texels).map(|p| [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, 1.0]).collect(),
+            (TextureFormat::Depth32, Some(data)) => data.chunks_exact(4).take(n_texels).map(|d| [f32::from_ne_bytes(d.try_into().unwrap()), 0.0, 0.0, 1.0]).collect(),
+            (TextureFormat::Depth32, None) => vec![[1.0, 0.0, 0.0, 1.0]; n_texels],
+            (_, None) => vec![[0.0, 0.0, 0.0, 0.0]; n_texels],
+        };
+        self.state.borrow_mut().textures.insert(texture, SoftTexture { format, sampling, width, height, layers: layers.max(1), texels });
+        return texture;
+    }
+
+    fn bind_texture(&self, unit: GLuint, kind: TextureKind, texture: GLuint) {
+        self.state.borrow_mut().bound_textures.insert((unit, kind), texture);
+    }
+
+    fn delete_texture(&self, texture: GLuint) {
+        self.state.borrow_mut().textures.remove(&texture);
+    }
+
+    fn create_framebuffer(&self, color: Option<GLuint>, depth: Option<GLuint>) -> GLuint {
+        let framebuffer = self.next_id();
+        self.state.borrow_mut().framebuffers.insert(framebuffer, (color, depth));
+        return framebuffer;
+    }
+
+    fn bind_framebuffer(&self, framebuffer: GLuint) {
+        self.state.borrow_mut().framebuffer = framebuffer;
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.state.borrow_mut().viewport = (x, y, width, height);
+    }
+
+    fn delete_framebuffer(&self, framebuffer: GLuint) {
+        if framebuffer != 0 {
+            self.state.borrow_mut().framebuffers.remove(&framebuffer);
+        }
+    }
+
+    fn create_program(&self, vertex_source: &str, fragment_source: &str) -> Result<GLuint, String> {
+        let program = self.next_id();
+        let kind = if fragment_source.contains("screenTexture") {
+            SoftProgram::Blit
+        }
+        else if vertex_source.contains("in mat4 model") && fragment_source.contains("sampler2DArray textures") {
+            SoftProgram::World { skinned: vertex_source.contains("joint_matrices") }
+        }
+        else {
+            SoftProgram::Unsupported
+        };
+        self.state.borrow_mut().programs.insert(program, kind);
+        return Ok(program);
+    }
+
+    fn use_program(&self, program: GLuint) {
+        self.state.borrow_mut().program = program;
+    }
+
+    fn uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let mut state = self.state.borrow_mut();
+        let n = state.uniform_locations.len() as GLint;
+        return *state.uniform_locations.entry((program, name.to_string())).or_insert(n);
+    }
+
+    // the only samplers are the engine's own, which always use unit 0
+    fn set_uniform_i32(&self, _location: GLint, _value: i32) {}
+
+    fn set_uniform_mat4(&self, location: GLint, matrix: &glm::Mat4, transpose: bool) {
+        self.state.borrow_mut().uniform_matrices.insert(location, if transpose {matrix.transpose()} else {*matrix});
+    }
+
+    fn delete_program(&self, program: GLuint) {
+        self.state.borrow_mut().programs.remove(&program);
+    }
+
+    fn set_clear_color(&self, rgba: [f32; 4]) {
+        self.state.borrow_mut().clear_color = rgba;
+    }
+
+    fn clear(&self, color: bool, depth: bool) {
+        let mut state = self.state.borrow_mut();
+        let (color_id, depth_id) = state.framebuffers.get(&state.framebuffer).copied().unwrap_or((None, None));
+        let clear_color = state.clear_color;
+        if let Some(texture) = color_id.filter(|_| color).and_then(|id| state.textures.get_mut(&id)) {
+            let value = store(texture.format, clear_color);
+            texture.texels.fill(value);
+        }
+        if let Some(texture) = depth_id.filter(|_| depth).and_then(|id| state.textures.get_mut(&id)) {
+            texture.texels.fill([1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    fn set_depth_test(&self, enabled: bool) {
+        self.state.borrow_mut().depth_test = enabled;
+    }
+
+    fn set_backface_culling(&self, enabled: bool) {
+        self.state.borrow_mut().backface_culling = enabled;
+    }
+
+    fn draw_arrays(&self, first: i32, count: i32) {
+        let vertices: Vec<(u32, u32)> = (first..first + count).map(|v| (v as u32, 0)).collect();
+        self.draw_triangles(&vertices);
+    }
+
+    fn draw_elements_instanced(&self, n_indices: i32, first_index_byte: usize, n_instances: i32, base_vertex: i32, base_instance: GLuint) {
+        let mut vertices = Vec::with_capacity((n_indices * n_instances.max(0)) as usize);
+        {
+            let state = self.state.borrow();
+            let indices: Vec<u32> = (0..n_indices as usize).map(|i| read_u32(&state, state.index_buffer, first_index_byte + i * 4)).collect();
+            for instance in 0..n_instances as u32 {
+                vertices.extend(indices.iter().map(|index| ((*index as i32 + base_vertex) as u32, base_instance + instance)));
+            }
+        }
+        self.draw_triangles(&vertices);
+    }
+
+    fn multi_draw_elements_indirect(&self, n_commands: i32, offset: usize) {
+        let commands: Vec<[u32; 5]> = {
+            let state = self.state.borrow();
+            (0..n_commands as usize).map(|c| {
+                let start = offset + c * 20;
+                return [0, 1, 2, 3, 4].map(|i| read_u32(&state, state.indirect_buffer, start + i * 4));
+            }).collect()
+        };
+        for [n_indices, n_instances, first_index, base_vertex, base_instance] in commands {
+            if n_indices != 0 {
+                self.draw_elements_instanced(n_indices as i32, first_index as usize * 4, n_instances as i32, base_vertex as i32, base_instance);
+            }
+        }
+    }
+
+    // everything already happened
+    fn flush(&self) {}
+
+    // every draw is finished by the time it returns, so there's never anything to wait for
+    fn create_fence(&self) -> GLuint {
+        return self.next_id();
+    }
+
+    fn wait_fence(&self, _fence: GLuint) {}
+
+    fn read_pixels(&self, framebuffer: GLuint, width: u32, height: u32) -> Option<RgbaImage> {
+        let state = self.state.borrow();
+        let texture = state.framebuffers.get(&framebuffer).and_then(|(color, _)| *color).and_then(|id| state.textures.get(&id))?;
+        let (width, height) = (width.min(texture.width), height.min(texture.height));
+        let mut image = RgbaImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let texel = texture.texels[((height - 1 - y) * texture.width + x) as usize];
+                image.set_pixel(x, y, texel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+            }
+        }
+        return Some(image);
+    }
+}
@@ -1,5 +1,4 @@
 use stb_image::image::*;
-use gl46::*;
 use crate::graphics::*;
 
 #[derive(PartialEq)]
@@ -16,66 +15,50 @@ pub struct Texture {
 
 // TODO: Options to disable tiling and use GL_NEAREST instead of GL_LINEAR
 impl Texture {
-    pub fn from_file(gl: &GlFns, path: &str, ttype: TextureType) -> Self {
-        // check_graphics_errors(gl);
-        unsafe {
-            let result = load(path.clone());
-            match result {
-                LoadResult::Error(message) => {panic!("Failure to load image with path {}, loader said {}", path, message);}
-                LoadResult::ImageU8(image) => {
-                    //println!("ok");
-                    
-                    let mut tex: u32 = 0;
-                    gl.GenTextures(1, &tex as *const u32 as *mut u32); // check_graphics_errors(gl);
-                    let target = if ttype == TextureType::Tex2D {GL_TEXTURE_2D} else {GL_TEXTURE_2D_ARRAY};
-                    //figure out if its rgba or rgb by calculating how many many bytes per pixel
-                    let source_format = if image.width * image.height * 4 == image.data.len() {GL_RGBA} else {GL_RGB};
-
-                    // check_graphics_errors(gl);
-                    gl.BindTexture(target, tex); 
-                    // check_graphics_errors(gl);
-                    if target == GL_TEXTURE_2D {
-                        gl.TexImage2D(target, 0, GL_RGBA.0 as i32, image.width as i32, image.height as i32, 0, GL_RGBA, GL_UNSIGNED_BYTE, image.data.as_ptr() as *const c_void);
-                    }     
-                    else {
-                        gl.TexStorage3D(target, 1, GL_RGBA8, image.width as i32, image.width as i32, (image.height/image.width) as i32);
-                        // check_graphics_errors(gl);
-                        gl.TexSubImage3D(target, 0, 0, 0, 0, image.width as i32, image.width as i32, (image.height/image.width) as i32, source_format, GL_UNSIGNED_BYTE, image.data.as_ptr() as *const c_void);
-                    }               
-                    // check_graphics_errors(gl);
+    pub fn from_file(device: &dyn RenderDevice, path: &str, ttype: TextureType) -> Self {
+        let result = load(path.clone());
+        match result {
+            LoadResult::Error(message) => {panic!("Failure to load image with path {}, loader said {}", path, message);}
+            LoadResult::ImageU8(image) => {return Self::from_image(device, image, ttype);}
+            LoadResult::ImageF32(..) => {panic!("bruh shut up image i dont want floats at path {}", path);}
+        }
+    }
 
-                    gl.TexParameteri(target, GL_TEXTURE_WRAP_S, GL_REPEAT.0 as i32);	
-                    gl.TexParameteri(target, GL_TEXTURE_WRAP_T, GL_REPEAT.0 as i32);
-                    gl.TexParameteri(target, GL_TEXTURE_MIN_FILTER, GL_LINEAR.0 as i32);
-                    gl.TexParameteri(target, GL_TEXTURE_MAG_FILTER, GL_LINEAR.0 as i32);
+    // an image file (png, jpg, etc.) that's already in memory, like one embedded in a glTF model
+    pub fn from_memory(device: &dyn RenderDevice, bytes: &[u8], ttype: TextureType) -> Result<Self, String> {
+        match load_from_memory(bytes) {
+            LoadResult::Error(message) => Err(message),
+            LoadResult::ImageU8(image) => Ok(Self::from_image(device, image, ttype)),
+            LoadResult::ImageF32(..) => Err(String::from("float images aren't supported")),
+        }
+    }
 
-                    let texture = Self {
-                        gl_texture: tex,
-                        tex_type: ttype,
-                        size: (image.width as i32, image.height as i32)
-                    };
+    fn from_image(device: &dyn RenderDevice, image: Image<u8>, ttype: TextureType) -> Self {
+        //figure out if its rgba or rgb by calculating how many many bytes per pixel
+        let format = if image.width * image.height * 4 == image.data.len() {TextureFormat::Rgba8} else {TextureFormat::Rgb8};
+        let tex = if ttype == TextureType::Tex2D {
+            device.create_texture(TextureKind::Tex2D, format, TextureSampling::Tiled, image.width as u32, image.height as u32, 1, Some(&image.data))
+        }
+        else if image.height % image.width == 0 {
+            // texture arrays are stored as square layers stacked vertically in the image
+            device.create_texture(TextureKind::Tex2DArray, format, TextureSampling::Tiled, image.width as u32, image.width as u32, (image.height/image.width) as u32, Some(&image.data))
+        }
+        else {
+            // not made of square layers, so the whole thing is one layer
+            device.create_texture(TextureKind::Tex2DArray, format, TextureSampling::Tiled, image.width as u32, image.height as u32, 1, Some(&image.data))
+        };
 
-                    return texture;
-                }
-                LoadResult::ImageF32(..) => {panic!("bruh shut up image i dont want floats at path {}", path);}
-            }
+        let texture = Self {
+            gl_texture: tex,
+            tex_type: ttype,
+            size: (image.width as i32, image.height as i32)
+        };
 
-            
-        }
+        return texture;
     }
 
-    pub fn empty_depth(gl: &GlFns, width: u32, height: u32) -> Self {
-        let mut tex: u32 = 0;
-        unsafe{
-            gl.GenTextures(1, &tex as *const u32 as *mut u32);
-            gl.BindTexture(GL_TEXTURE_2D, tex);
-            gl.TexImage2D(GL_TEXTURE_2D, 0,GL_DEPTH_COMPONENT32.0 as i32, width as i32, height as i32, 0,GL_DEPTH_COMPONENT, GL_FLOAT, std::ptr::null());
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST.0 as i32);
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST.0 as i32);
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_BORDER.0 as i32);	
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_BORDER.0 as i32);
-            gl.TexParameterfv(GL_TEXTURE_2D, GL_TEXTURE_BORDER_COLOR, [1.0, 1.0, 1.0, 1.0].as_ptr());
-        }
+    pub fn empty_depth(device: &dyn RenderDevice, width: u32, height: u32) -> Self {
+        let tex = device.create_texture(TextureKind::Tex2D, TextureFormat::Depth32, TextureSampling::DepthBorder, width, height, 1, None);
         return Self {
             gl_texture: tex,
             tex_type: TextureType::Tex2D,
@@ -84,15 +67,8 @@ impl Texture {
 
     }
 
-    pub fn empty_color(gl: &GlFns, width: u32, height: u32, multisampled: bool) -> Self {
-        let mut tex: u32 = 0;
-        unsafe{
-            gl.GenTextures(1, &tex as *const u32 as *mut u32);
-            gl.BindTexture(GL_TEXTURE_2D, tex);
-            gl.TexImage2D(GL_TEXTURE_2D, 0, GL_RGBA.0 as i32, width as i32, height as i32, 0,GL_RGBA, GL_UNSIGNED_BYTE, std::ptr::null());
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST.0 as i32);
-            gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST.0 as i32);
-        }
+    pub fn empty_color(device: &dyn RenderDevice, width: u32, height: u32) -> Self {
+        let tex = device.create_texture(TextureKind::Tex2D, TextureFormat::Rgba8, TextureSampling::Nearest, width, height, 1, None);
         return Self {
             gl_texture: tex,
             tex_type: TextureType::Tex2D,
@@ -101,25 +77,12 @@ impl Texture {
     }
   
     // location is int from 0 to 7 (inclusive), use different locations to bind multiple textures at once
-    pub fn r#use(&self, gl: &GlFns, location: u32) {
-        unsafe {gl.ActiveTexture(gl46::GLenum(GL_TEXTURE0.0 + location))}
-        if self.tex_type == TextureType::Tex2D {
-            unsafe {
-                //println!("location {}", location);
-                gl.BindTexture(GL_TEXTURE_2D, self.gl_texture);
-            }
-        }
-        else {
-            unsafe {
-                gl.BindTexture(GL_TEXTURE_2D_ARRAY, self.gl_texture);
-            }
-        }
+    pub fn r#use(&self, device: &dyn RenderDevice, location: u32) {
+        let kind = if self.tex_type == TextureType::Tex2D {TextureKind::Tex2D} else {TextureKind::Tex2DArray};
+        device.bind_texture(location, kind, self.gl_texture);
     }
 
-    pub fn cleanup(&self, gl: &GlFns) {
-        unsafe {
-            gl.DeleteTextures(1, &self.gl_texture);
-        }
+    pub fn cleanup(&self, device: &dyn RenderDevice) {
+        device.delete_texture(self.gl_texture);
     }
 }
-
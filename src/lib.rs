@@ -0,0 +1,16 @@
+#![allow(non_snake_case)]
+
+extern crate nalgebra_glm as glm;
+
+use crate::gameobjects::{GameObject, Renderable};
+
+pub mod transform;
+pub mod graphics;
+pub mod windowing;
+pub mod gameobjects;
+pub mod phys;
+pub mod ecs;
+pub mod scene;
+pub mod animation;
+
+pub const WINDOW_NAME: &str = "IG2";
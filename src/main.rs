@@ -2,69 +2,68 @@
 
 extern crate nalgebra_glm as glm;
 
-use std::{cell::RefCell, rc::Rc};
-
-use glm::{vec3, vec4};
-
-use crate::{gameobjects::{GameObject, Renderable, RigidBody, Collides, PhysMeshObject}, graphics::{Mesh, Texture}, transform::dvec3};
-
-mod transform;
-mod graphics;
-mod windowing;
-mod gameobjects;
-mod phys;
-
-pub const WINDOW_NAME: &str = "IG2";
+use IG2::{ecs, graphics, windowing, phys, scene, animation};
+use IG2::{graphics::Mesh, transform::{Transform, dvec3}};
 
 fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
     application();
 }
 
-// URGENT TODO: some structure for rapid removal of gameobjects from these vectors
 fn application() {
     println!("Initializing application.");
-    let mut RIGIDBODIES: Vec<Rc<RefCell<dyn RigidBody>>> = Vec::new();
-    let mut SAS = phys::SpatialAccelerationStructure::new();
+    let mut WORLD = ecs::World::new();
+    WORLD.insert_resource(ecs::PhysicsState::new(phys::PhysicsMode::Float)); // PhysicsMode::Deterministic for lockstep networking/replays
     let mut WINDOW = windowing::Window::new(String::from("POG"));
-    let mut GE = graphics::GraphicsEngine::new(WINDOW.create_opengl_context(), WINDOW.resolution as (u32, u32));
+    let mut GE = graphics::GraphicsEngine::new(Box::new(graphics::GlDevice::new(WINDOW.create_opengl_context())), WINDOW.resolution as (u32, u32));
     GE.freecam_override_enabled = true;
 
     println!("Starting main loop");
 
     let (grass_id, _size) = GE.load_texture_from_file("textures/grass.png", graphics::TextureType::TexArray2D);
-    let mesh = Mesh::from_obj("models/icosphere.obj", grass_id, GE.world_shader_id);
+    let mesh = Mesh::from_obj_with_lods("models/icosphere.obj", grass_id, GE.world_shader_id, &graphics::DEFAULT_LOD_LEVELS).expect("couldn't load the icosphere");
     for x in -1..100 {
         for y in -1..100 {
             for z in 5..10 {
-                let test = Rc::new(RefCell::new(gameobjects::MeshObject::new(mesh)));
-                GE.add_renderable(test.clone());
-                test.borrow_mut().transform.setpos_meters(dvec3(x as f64 * 3.0, y as f64 * 3.0, z as f64 * 3.0));
-                test.borrow_mut().set_texture_z(0.0);
+                let mut render = ecs::RenderComponent::new(mesh);
+                render.set_texture_z(0.0);
+                WORLD.build_entity()
+                    .with(Transform::meters(dvec3(x as f64 * 3.0, y as f64 * 3.0, z as f64 * 3.0)))
+                    .with(render)
+                    .build();
             }
         }
     }
 
-    
-    let mesh2 = Mesh::from_obj("models/rainbowcube.obj", grass_id, GE.world_shader_id);
-    let floor = Rc::new(RefCell::new(PhysMeshObject::new(mesh2, gameobjects::ColliderType::Box)));
-    floor.borrow_mut().set_rgba(vec4(0.4, 0.6, 0.4, 1.0));
-    floor.borrow_mut().transform.setpos_meters(dvec3(0.0, -10.0, 0.0));
-    floor.borrow_mut().transform.setscl(vec3(10.0, 1.0, 10.0));
-    floor.borrow_mut().set_texture_z(0.0);
-    SAS.insert(floor.clone());
-    GE.add_renderable(floor);
-    
-    //GAMEOBJECTS.push(test.clone());
+    let mut PREFABS = scene::PrefabLibrary::new();
+    PREFABS.load_dir("prefabs").unwrap();
+    PREFABS.spawn_prefab("Floor", Transform::meters(dvec3(0.0, -10.0, 0.0)), &mut WORLD, &mut GE).unwrap();
     
     //GE.camera.transform.setpos_meters(dvec3(0.0, 0.0, 10.0));
 
+    let DEBUG_KEYS = std::env::args().any(|arg| arg == "--debug");
+    let mut HISTORY = ecs::SnapshotHistory::with_seconds(5.0);
+    let mut frame: u64 = 0;
+
     while !WINDOW.should_close() {
         WINDOW.update();
 
-        phys::do_physics(&mut SAS, &RIGIDBODIES);
+        // debug keys (run with --debug): backspace rewinds the last 5 seconds, f5/f9 quicksave/quickload
+        if DEBUG_KEYS {
+            debug_keys(&mut WORLD, &mut HISTORY, &mut frame);
+        }
+
+        animation::animation_system(&mut WORLD, 1.0/60.0);
+        animation::skeletal_animation_system(&mut WORLD, 1.0/60.0);
+        ecs::physics_system(&mut WORLD);
+        frame += 1;
+        if DEBUG_KEYS {
+            HISTORY.capture(&WORLD, frame);
+        }
 
+        GE.sync_camera_entity(&mut WORLD);
         GE.update(WINDOW.resolution);
+        GE.update_entities(&mut WORLD);
         GE.draw();
 
         WINDOW.swap_buffers(); // will block because vsync
@@ -76,4 +75,27 @@ fn application() {
     WINDOW.cleanup();
 
     println!("Application ran successfully! :)")
-}
\ No newline at end of file
+}
+
+fn debug_keys(WORLD: &mut ecs::World, HISTORY: &mut ecs::SnapshotHistory, frame: &mut u64) {
+    if windowing::INPUT.did_press_begin(glfw::Key::Backspace) {
+        if let Some(rewound_to) = HISTORY.rewind(WORLD, 5.0) {
+            *frame = rewound_to;
+        }
+    }
+    if windowing::INPUT.did_press_begin(glfw::Key::F5) {
+        if let Err(err) = ecs::WorldSnapshot::capture_all(WORLD, *frame).save_to_file("quicksave.ig2s") {
+            println!("Failed to quicksave: {}", err);
+        }
+    }
+    if windowing::INPUT.did_press_begin(glfw::Key::F9) {
+        match ecs::WorldSnapshot::load_from_file("quicksave.ig2s") {
+            Ok(snapshot) => {
+                snapshot.restore(WORLD);
+                *frame = snapshot.frame();
+                HISTORY.clear();
+            }
+            Err(err) => println!("Failed to quickload: {}", err)
+        }
+    }
+}
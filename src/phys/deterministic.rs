@@ -0,0 +1,428 @@
+// Fixed point versions of the narrowphase, collision response and integration.
+// Used by do_physics when PhysicsMode::Deterministic is selected, so that identical inputs produce bit-identical Transforms on every run and every platform.
+// Floats are only touched when reading/writing Transform and RigidBody (whose storage is still f32), and those conversions are exact/well defined.
+
+use glm::I64Vec3;
+
+use crate::gameobjects::{Collides, ColliderType, RigidBody};
+use crate::transform::*;
+
+use super::fixed::*;
+
+pub struct FixedCollisionInfo {
+    pub normal: FixedVec3, // points from the other object towards the object that was tested
+    pub collision_points: Vec<(I64Vec3, i64)> // vec of (hitPos, hitPenetration), both in micrometers
+}
+
+// physics runs at 60hz
+const STEP_DIVISOR: i64 = 60;
+
+// half the size of a collider along each of its local axes, in micrometers
+fn half_extents_um(obj: &dyn Collides) -> [i64; 3] {
+    let scl = FixedVec3::from_vec3(&obj.transform().scl());
+    return [(scl.x * Fixed::HALF).to_um(), (scl.y * Fixed::HALF).to_um(), (scl.z * Fixed::HALF).to_um()];
+}
+
+fn radius_um(obj: &dyn Collides) -> i64 {
+    return half_extents_um(obj)[0];
+}
+
+fn rotation(obj: &dyn Collides) -> FixedQuat {
+    return FixedQuat::from_quat(&obj.transform().rot_quat());
+}
+
+// length of a micrometer vector, summed in a u128 so the squares can't overflow
+fn length_um(v: &I64Vec3) -> i64 {
+    return isqrt(sum_of_squares(&[v.x, v.y, v.z])).min(i64::MAX as u128) as i64;
+}
+
+// unit vector pointing the same way as a micrometer vector
+fn direction_um(v: &I64Vec3, length: i64) -> FixedVec3 {
+    return FixedVec3::new(Fixed::ratio(v.x, length), Fixed::ratio(v.y, length), Fixed::ratio(v.z, length));
+}
+
+// there's no fixed point narrowphase for convex colliders yet, so worlds in this mode refuse them instead of letting them fall through everything
+pub fn assert_deterministic_collider(collider_type: ColliderType) {
+    assert!(collider_type != ColliderType::Convex, "Convex colliders aren't supported in PhysicsMode::Deterministic.");
+}
+
+// returns None if no collision
+pub fn collides_deterministic(obj1: &dyn Collides, obj2: &dyn Collides) -> Option<FixedCollisionInfo> {
+    match (obj1.get_collider_type(), obj2.get_collider_type()) {
+        (ColliderType::Sphere, ColliderType::Sphere) => sphere_sphere(obj1, obj2),
+        (ColliderType::Sphere, ColliderType::Box) => sphere_box(obj1, obj2, false),
+        (ColliderType::Box, ColliderType::Sphere) => sphere_box(obj2, obj1, true),
+        (ColliderType::Box, ColliderType::Box) => box_box(obj1, obj2),
+        (ColliderType::Convex, _) | (_, ColliderType::Convex) => panic!("Convex colliders aren't supported in PhysicsMode::Deterministic.")
+    }
+}
+
+fn sphere_sphere(obj1: &dyn Collides, obj2: &dyn Collides) -> Option<FixedCollisionInfo> {
+    let r1 = radius_um(obj1);
+    let r2 = radius_um(obj2);
+    let v = obj1.transform().pos() - obj2.transform().pos();
+    let distance = length_um(&v);
+    if distance > r1 + r2 {
+        return None;
+    }
+
+    // if they're exactly on top of each other any direction works, so just pick up
+    let normal = if distance == 0 {FixedVec3::new(Fixed::ZERO, Fixed::ONE, Fixed::ZERO)} else {direction_um(&v, distance)};
+    let hitpos = obj1.transform().pos() - normal.scale_int(r1);
+    return Some(FixedCollisionInfo { normal, collision_points: vec![(hitpos, r1 + r2 - distance)] });
+}
+
+// sphere_is_obj2 flips the normal so it always points towards obj1
+fn sphere_box(sphere: &dyn Collides, cube: &dyn Collides, sphere_is_obj2: bool) -> Option<FixedCollisionInfo> {
+    let radius = radius_um(sphere);
+    let extents = half_extents_um(cube);
+    let axes = rotation(cube).axes();
+    let rel = sphere.transform().pos() - cube.transform().pos();
+
+    // sphere center in the box's local space (still micrometers), and the closest point on the box to it
+    let local = [axes[0].dot_int(&rel), axes[1].dot_int(&rel), axes[2].dot_int(&rel)];
+    let closest = [local[0].clamp(-extents[0], extents[0]), local[1].clamp(-extents[1], extents[1]), local[2].clamp(-extents[2], extents[2])];
+    let diff = i64vec3(local[0] - closest[0], local[1] - closest[1], local[2] - closest[2]);
+    let distance = length_um(&diff);
+    if distance > radius {
+        return None;
+    }
+
+    let local_normal;
+    let penetration;
+    if distance > 0 {
+        local_normal = direction_um(&diff, distance);
+        penetration = radius - distance;
+    }
+    else { // center is inside the box, so push out through the nearest face
+        let mut best_axis = 0;
+        let mut best_depth = i64::MAX;
+        for i in 0..3 {
+            let depth = extents[i] - local[i].abs();
+            if depth < best_depth {
+                best_depth = depth;
+                best_axis = i;
+            }
+        }
+        let mut n = [Fixed::ZERO; 3];
+        n[best_axis] = if local[best_axis] < 0 {-Fixed::ONE} else {Fixed::ONE};
+        local_normal = FixedVec3::new(n[0], n[1], n[2]);
+        penetration = radius + best_depth;
+    }
+
+    let mut normal = axes[0].scale(local_normal.x) + axes[1].scale(local_normal.y) + axes[2].scale(local_normal.z);
+    let hitpos = cube.transform().pos() + axes[0].scale_int(closest[0]) + axes[1].scale_int(closest[1]) + axes[2].scale_int(closest[2]);
+    if sphere_is_obj2 {
+        normal = -normal;
+    }
+    return Some(FixedCollisionInfo { normal, collision_points: vec![(hitpos, penetration)] });
+}
+
+// world space corners of a box, in micrometers
+fn box_corners(obj: &dyn Collides) -> Vec<I64Vec3> {
+    let extents = half_extents_um(obj);
+    let axes = rotation(obj).axes();
+    let mut corners = Vec::with_capacity(8);
+    for x in [-1, 1] {
+        for y in [-1, 1] {
+            for z in [-1, 1] {
+                corners.push(obj.transform().pos() + axes[0].scale_int(extents[0] * x) + axes[1].scale_int(extents[1] * y) + axes[2].scale_int(extents[2] * z));
+            }
+        }
+    }
+    return corners;
+}
+
+// finds which of verts are inside of cube, returning the outward normal of the face of cube each corner is closest to
+fn corners_inside_box(verts: &Vec<I64Vec3>, cube: &dyn Collides, least_normal: &mut FixedVec3, least_penetration: &mut i64, collision_points: &mut Vec<(I64Vec3, i64)>) {
+    let extents = half_extents_um(cube);
+    let axes = rotation(cube).axes();
+    'outer: for v in verts.iter() {
+        let rel = v - cube.transform().pos();
+        let mut best_depth = i64::MAX;
+        let mut best_normal = FixedVec3::ZERO;
+        for i in 0..3 {
+            let d = axes[i].dot_int(&rel);
+            let depth = extents[i] - d.abs();
+            if depth < 0 {
+                continue 'outer;
+            }
+            if depth < best_depth {
+                best_depth = depth;
+                best_normal = if d < 0 {-axes[i]} else {axes[i]};
+            }
+        }
+
+        if best_depth < *least_penetration {
+            *least_penetration = best_depth;
+            *least_normal = best_normal;
+        }
+        collision_points.push((*v, best_depth));
+    }
+}
+
+fn box_box(obj1: &dyn Collides, obj2: &dyn Collides) -> Option<FixedCollisionInfo> {
+    let mut collision_points = Vec::new();
+
+    let mut obj1_normal = FixedVec3::ZERO;
+    let mut obj1_penetration = i64::MAX;
+    corners_inside_box(&box_corners(obj1), obj2, &mut obj1_normal, &mut obj1_penetration, &mut collision_points);
+    let n_obj1_points = collision_points.len();
+
+    let mut obj2_normal = FixedVec3::ZERO;
+    let mut obj2_penetration = i64::MAX;
+    corners_inside_box(&box_corners(obj2), obj1, &mut obj2_normal, &mut obj2_penetration, &mut collision_points);
+
+    if collision_points.is_empty() {
+        return None;
+    }
+
+    // obj2's corners being inside obj1 means obj1 gets pushed the opposite way of obj1's face normal
+    let normal = if n_obj1_points == 0 {-obj2_normal} else {obj1_normal};
+    return Some(FixedCollisionInfo { normal, collision_points });
+}
+
+pub fn fixed_mass(obj: &dyn RigidBody) -> Fixed {
+    let scl = FixedVec3::from_vec3(&obj.transform().scl());
+    let volume = scl.x * scl.y * scl.z;
+    let mass = Fixed::from_f32(obj.density()) * volume;
+    return match obj.get_collider_type() {
+        ColliderType::Sphere => mass * Fixed::ratio(4, 3) * Fixed::ratio(31415, 10000), // same approximation of pi as RigidBody::mass()
+        _ => mass
+    };
+}
+
+// fixed point version of phys::moment_of_inertia, None for convex colliders
+pub fn fixed_moment_of_inertia(collider_type: ColliderType, size: FixedVec3, mass: Fixed) -> Option<FixedVec3> {
+    match collider_type {
+        ColliderType::Sphere => {
+            let radius = size.x * Fixed::HALF;
+            let i = Fixed::ratio(2, 5) * mass * radius * radius;
+            Some(FixedVec3::new(i, i, i))
+        }
+        ColliderType::Box => {
+            let k = Fixed::ratio(1, 12) * mass;
+            Some(FixedVec3::new(k * (size.y * size.y + size.z * size.z), k * (size.x * size.x + size.z * size.z), k * (size.y * size.y + size.x * size.x)))
+        }
+        ColliderType::Convex => None
+    }
+}
+
+// velocity (um/s) of a point (relative position in um) on a rigidbody
+fn velocity_at_point(velocity: &I64Vec3, angular_velocity: &FixedVec3, rel_pos: &I64Vec3) -> I64Vec3 {
+    return velocity + angular_velocity.cross_int(rel_pos);
+}
+
+// how much force (in newtons, fixed point) applied at rel_pos (in um) changes angular velocity by
+fn angular_change_from_force_at_pos(inertia: &FixedVec3, force: &FixedVec3, rel_pos: &I64Vec3) -> FixedVec3 {
+    // avoid dividing by zero for massless/flat objects
+    if inertia.x.0 == 0 || inertia.y.0 == 0 || inertia.z.0 == 0 {
+        return FixedVec3::ZERO;
+    }
+    let torque = FixedVec3::from_um(rel_pos).cross(force);
+    return torque.component_div(inertia);
+}
+
+// how much a unit impulse (kg m/s) along normal at rel_pos (in um) changes the speed of that point along normal, counting the spin it gives as well as the push
+fn impulse_response(inertia: &FixedVec3, mass: Fixed, normal: &FixedVec3, rel_pos: &I64Vec3) -> Fixed {
+    if mass == Fixed::ZERO {
+        return Fixed::ZERO;
+    }
+    let spin = angular_change_from_force_at_pos(inertia, normal, rel_pos);
+    return Fixed::ONE / mass + FixedVec3::from_um(&spin.cross_int(rel_pos)).dot(normal);
+}
+
+fn inertia_of(obj: &dyn RigidBody, mass: Fixed) -> FixedVec3 {
+    return fixed_moment_of_inertia(obj.get_collider_type(), FixedVec3::from_vec3(&obj.transform().scl()), mass).unwrap_or(FixedVec3::ZERO); // angular_change_from_force_at_pos() ignores zero inertia
+}
+
+// something a body can run into while it's stepped
+pub enum Obstacle<'a> {
+    Anchored(&'a dyn Collides),
+    // bodies are stepped one at a time, so by the time a moving obstacle is stepped the body that hit it has already moved out of the way.
+    // it gets its side of the contact (its part of the push, and the opposite impulse) straight away instead
+    Moving(&'a mut dyn RigidBody)
+}
+
+impl Obstacle<'_> {
+    fn collider(&self) -> &dyn Collides {
+        return match self {
+            Obstacle::Anchored(other) => *other,
+            Obstacle::Moving(other) => &**other
+        };
+    }
+}
+
+// deterministic version of do_physics()'s per-object step.
+// convex bodies aren't supported (see assert_deterministic_collider())
+pub fn step_rigidbody_deterministic(obj: &mut dyn RigidBody, possible_colliding: &mut Vec<Obstacle>) {
+    let mut velocity = obj.velocity();
+    let mut angular_velocity = FixedVec3::from_vec3(&obj.angular_velocity());
+    let mass = fixed_mass(obj);
+    let inertia = inertia_of(obj, mass);
+
+    // gravity
+    velocity += i64vec3(0, super::GRAVITY, 0);
+
+    for obstacle in possible_colliding.iter_mut() {
+        let collision = collides_deterministic(obj, obstacle.collider());
+        if collision.is_none() {
+            continue;
+        }
+        let info = collision.unwrap();
+
+        let mut greatest_penetration = 0;
+        let mut total_pos = i64vec3(0, 0, 0);
+        for point in info.collision_points.iter() {
+            greatest_penetration = greatest_penetration.max(point.1.abs());
+            total_pos += point.0;
+        }
+        let hitpos = total_pos / info.collision_points.len() as i64;
+        let e = Fixed::from_f32(obj.elasticity()) * Fixed::from_f32(obstacle.collider().elasticity()) + Fixed::ONE;
+
+        // the push out is split with a moving obstacle by mass, so the lighter one moves further. pushing both all the way out meant a box
+        // on the floor with a ball on it got pushed up by the floor and all the way back down by the ball every step until it sank through
+        let mut obstacle_motion = None;
+        let mut share = Fixed::ONE;
+        if let Obstacle::Moving(other) = obstacle {
+            let other_mass = fixed_mass(&**other);
+            if mass + other_mass != Fixed::ZERO {
+                share = other_mass / (mass + other_mass);
+            }
+            *other.transform_mut().pos_mut() -= info.normal.scale_int((Fixed::ONE - share).scale_int(greatest_penetration));
+            let other_rel = hitpos - other.transform().pos();
+            obstacle_motion = Some((other.velocity(), FixedVec3::from_vec3(&other.angular_velocity()), other_mass, inertia_of(&**other, other_mass), other_rel));
+        }
+        *obj.transform_mut().pos_mut() += info.normal.scale_int(share.scale_int(greatest_penetration));
+
+        let rel = hitpos - obj.transform().pos();
+        let mut v = velocity_at_point(&velocity, &angular_velocity, &rel);
+        let mut response = impulse_response(&inertia, mass, &info.normal, &rel);
+        if let Some((other_velocity, other_angular_velocity, other_mass, other_inertia, other_rel)) = &obstacle_motion {
+            v -= velocity_at_point(other_velocity, other_angular_velocity, other_rel);
+            response += impulse_response(other_inertia, *other_mass, &info.normal, other_rel);
+        }
+
+        // only react if we're actually moving into the other object
+        let speed_into_normal = info.normal.dot_int(&v);
+        if speed_into_normal >= 0 || response == Fixed::ZERO {
+            continue;
+        }
+
+        // the impulse that makes the contact points separate at elasticity times the speed they closed at (relative to a moving obstacle).
+        // it has to count the spin it gives as well as the push, or a tumbling box bounces off the floor faster than it came in
+        let normal_impulse = e * Fixed::from_um(-speed_into_normal) / response;
+        let mut impulse = info.normal.scale(normal_impulse);
+
+        // friction against the sliding of the contact point: at most enough to stop it, and at most friction times the push apart
+        let slip = v - info.normal.scale_int(speed_into_normal);
+        let slip_speed = length_um(&slip);
+        if slip_speed > 0 {
+            let tangent = direction_um(&slip, slip_speed);
+            let mut tangent_response = impulse_response(&inertia, mass, &tangent, &rel);
+            if let Some((_, _, other_mass, other_inertia, other_rel)) = &obstacle_motion {
+                tangent_response += impulse_response(other_inertia, *other_mass, &tangent, other_rel);
+            }
+            let friction = (Fixed::from_f32(obj.friction()) * Fixed::from_f32(obstacle.collider().friction())).sqrt();
+            if tangent_response > Fixed::ZERO {
+                impulse = impulse - tangent.scale((Fixed::from_um(slip_speed) / tangent_response).min(friction * normal_impulse));
+            }
+        }
+
+        if mass != Fixed::ZERO {
+            velocity += impulse.scale(Fixed::ONE / mass).to_um();
+        }
+        angular_velocity += angular_change_from_force_at_pos(&inertia, &impulse, &rel);
+        if let (Obstacle::Moving(other), Some((other_velocity, other_angular_velocity, other_mass, other_inertia, other_rel))) = (obstacle, obstacle_motion) {
+            if other_mass != Fixed::ZERO {
+                other.set_velocity(other_velocity - impulse.scale(Fixed::ONE / other_mass).to_um());
+            }
+            other.set_angular_velocity((other_angular_velocity + angular_change_from_force_at_pos(&other_inertia, &-impulse, &other_rel)).to_vec3());
+        }
+    }
+
+    // velocity step
+    angular_velocity = angular_velocity.scale(Fixed::ratio(99, 100));
+    *obj.transform_mut().pos_mut() += velocity / STEP_DIVISOR;
+    let rot = rotation(obj).integrate(&angular_velocity, Fixed::ratio(1, STEP_DIVISOR));
+    obj.transform_mut().set_rot_quat(rot.to_quat());
+
+    obj.set_velocity(velocity);
+    obj.set_angular_velocity(angular_velocity.to_vec3());
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::{vec3, quat_angle_axis};
+
+    use crate::transform::*;
+    use crate::gameobjects::ColliderType;
+    use crate::phys::*;
+    use crate::phys::golden::expect_golden_snapshot;
+
+    fn tumbling_scene() -> PhysicsWorld {
+        let mut cube = PhysBody::new(ColliderType::Box);
+        cube.name = String::from("Cube");
+        cube.transform.setpos_meters(dvec3(0.3, 2.0, -0.2));
+        cube.transform.set_rot_quat(quat_angle_axis(0.6, &vec3(1.0, 0.5, 0.25).normalize()));
+        cube.angular_velocity = vec3(0.5, -1.0, 2.0);
+
+        let mut ball = PhysBody::new(ColliderType::Sphere);
+        ball.name = String::from("Ball");
+        ball.transform.setpos_meters(dvec3(-0.4, 3.5, 0.1));
+        ball.velocity = i64vec3(250_000, 0, -100_000);
+
+        return PhysicsWorldBuilder::new().mode(PhysicsMode::Deterministic).floor(0.0, 20.0).body(cube).body(ball).build();
+    }
+
+    #[test]
+    fn runs_are_bit_identical() {
+        let mut a = tumbling_scene();
+        let mut b = tumbling_scene();
+        for _ in 0..4 {
+            a.step_n(60);
+            b.step_n(60);
+            assert_eq!(a.snapshot().to_text(), b.snapshot().to_text());
+        }
+        // and identical to every other build, not just to this process
+        expect_golden_snapshot("deterministic_tumbling", &a.snapshot());
+
+        // a world restored to a snapshot has to carry on exactly like the one it was taken from
+        let saved = a.snapshot();
+        a.step_n(30);
+        let mut c = tumbling_scene();
+        c.restore(&saved);
+        c.step_n(30);
+        assert_eq!(a.snapshot().to_text(), c.snapshot().to_text());
+    }
+
+    // a ball thrown along the floor has to settle into rolling, not have friction spin it up until the maths overflows
+    #[test]
+    fn sliding_sphere_spin_stays_bounded() {
+        let mut ball = PhysBody::new(ColliderType::Sphere);
+        ball.name = String::from("Ball");
+        ball.transform.setpos_meters(dvec3(0.0, 0.5, 0.0));
+        ball.velocity = i64vec3(3_000_000, 0, 0);
+        let mut world = PhysicsWorldBuilder::new().mode(PhysicsMode::Deterministic).floor(0.0, 100.0).body(ball).build();
+
+        for step in 0..600 {
+            world.step();
+            let ball = world.bodies().get(BodyHandle(1));
+            let ball = ball.read().unwrap();
+            let spin = ball.angular_velocity.magnitude();
+            assert!(spin < 20.0, "Sliding ball is spinning at {}rad/s after {} steps.", spin, step + 1);
+            assert!(ball.transform.pos().y > 400_000, "Sliding ball sank into the floor, it's at {}um.", ball.transform.pos().y);
+        }
+        let ball = world.bodies().get(BodyHandle(1));
+        let ball = ball.read().unwrap();
+        assert!(ball.velocity.x > 0 && ball.angular_velocity.z < 0.0, "Ball should still be rolling forwards, but its velocity is {:?} and spin {:?}.", ball.velocity, ball.angular_velocity);
+    }
+
+    #[test]
+    #[should_panic(expected = "Convex colliders aren't supported")]
+    fn convex_bodies_are_rejected() {
+        let mut blob = PhysBody::new(ColliderType::Convex);
+        blob.transform.setpos_meters(dvec3(0.0, 1.0, 0.0));
+        PhysicsWorldBuilder::new().mode(PhysicsMode::Deterministic).floor(0.0, 20.0).body(blob).build();
+    }
+}
@@ -0,0 +1,381 @@
+// Fixed point maths for the deterministic physics mode.
+// Floats give different results depending on platform, compiler flags and the order operations happen in, which breaks lockstep networking and replays.
+// Everything in here is plain integer maths, so identical inputs always give bit-identical outputs.
+
+use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign};
+
+use glm::{Vec3, vec3, Quat, I64Vec3};
+
+use crate::transform::*;
+
+pub const FIXED_FRAC_BITS: u32 = 16;
+pub const FIXED_ONE: i64 = 1 << FIXED_FRAC_BITS;
+
+// 48.16 fixed point number. arithmetic saturates instead of overflowing, so a body that gets flung absurdly fast ends up pinned at the limit rather than panicking
+// with 16 fractional bits, anything in the range +-256 converts to an f32 exactly, so quaternions and angular velocities survive being stored in Transform/RigidBody as floats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed(pub i64);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(FIXED_ONE);
+    pub const HALF: Fixed = Fixed(FIXED_ONE / 2);
+
+    pub fn from_int(n: i64) -> Self {
+        return Fixed(n << FIXED_FRAC_BITS);
+    }
+
+    // multiplying by a power of two is exact for floats and the cast truncates towards zero on every platform, so this is deterministic
+    pub fn from_f32(f: f32) -> Self {
+        return Fixed((f * FIXED_ONE as f32) as i64);
+    }
+
+    pub fn to_f32(self) -> f32 {
+        return self.0 as f32 / FIXED_ONE as f32;
+    }
+
+    // n/d without going through floats
+    pub fn ratio(n: i64, d: i64) -> Self {
+        return Fixed(saturate(((n as i128) << FIXED_FRAC_BITS) / d as i128));
+    }
+
+    // converts micrometers to meters
+    pub fn from_um(um: i64) -> Self {
+        return Fixed::ratio(um, UNITS_PER_METER);
+    }
+
+    // converts meters to micrometers
+    pub fn to_um(self) -> i64 {
+        return saturate((self.0 as i128 * UNITS_PER_METER as i128) >> FIXED_FRAC_BITS);
+    }
+
+    // multiplies an integer (like a position in micrometers) by this
+    pub fn scale_int(self, n: i64) -> i64 {
+        return saturate((self.0 as i128 * n as i128) >> FIXED_FRAC_BITS);
+    }
+
+    pub fn abs(self) -> Self {
+        return Fixed(self.0.saturating_abs());
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        return if self.0 < other.0 {self} else {other};
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        return if self.0 > other.0 {self} else {other};
+    }
+
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        return self.max(min).min(max);
+    }
+
+    pub fn signum(self) -> Self {
+        return Fixed::from_int(self.0.signum());
+    }
+
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        return Fixed(isqrt((self.0 as u128) << FIXED_FRAC_BITS) as i64); // below 2^40, so it fits
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, rhs: Fixed) -> Fixed {
+        return Fixed(self.0.saturating_add(rhs.0));
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, rhs: Fixed) -> Fixed {
+        return Fixed(self.0.saturating_sub(rhs.0));
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, rhs: Fixed) -> Fixed {
+        return Fixed(saturate((self.0 as i128 * rhs.0 as i128) >> FIXED_FRAC_BITS));
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, rhs: Fixed) -> Fixed {
+        assert!(rhs.0 != 0, "Fixed point division by zero.");
+        return Fixed(saturate(((self.0 as i128) << FIXED_FRAC_BITS) / rhs.0 as i128));
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        return Fixed(self.0.saturating_neg());
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Fixed) {
+        *self = *self - rhs;
+    }
+}
+
+// clamps an intermediate result back into an i64
+fn saturate(n: i128) -> i64 {
+    return n.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+}
+
+// sum of the squares of some i64s, in a u128 so it can't overflow (each square is below 2^126)
+pub fn sum_of_squares(values: &[i64]) -> u128 {
+    return values.iter().map(|v| v.unsigned_abs() as u128 * v.unsigned_abs() as u128).fold(0u128, |total, sq| total.saturating_add(sq));
+}
+
+// integer square root (floor), bit by bit so there's no float involved
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut result: u128 = 0;
+    let mut bit: u128 = 1 << ((127 - n.leading_zeros()) & !1); // highest power of 4 <= n
+    let mut rem = n;
+    while bit != 0 {
+        if rem >= result + bit {
+            rem -= result + bit;
+            result = (result >> 1) + bit;
+        }
+        else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    return result;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct FixedVec3 {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+
+impl FixedVec3 {
+    pub const ZERO: FixedVec3 = FixedVec3 {x: Fixed::ZERO, y: Fixed::ZERO, z: Fixed::ZERO};
+
+    pub fn new(x: Fixed, y: Fixed, z: Fixed) -> Self {
+        return Self {x, y, z};
+    }
+
+    pub fn from_vec3(v: &Vec3) -> Self {
+        return Self::new(Fixed::from_f32(v.x), Fixed::from_f32(v.y), Fixed::from_f32(v.z));
+    }
+
+    pub fn to_vec3(&self) -> Vec3 {
+        return vec3(self.x.to_f32(), self.y.to_f32(), self.z.to_f32());
+    }
+
+    // micrometers -> meters
+    pub fn from_um(v: &I64Vec3) -> Self {
+        return Self::new(Fixed::from_um(v.x), Fixed::from_um(v.y), Fixed::from_um(v.z));
+    }
+
+    // meters -> micrometers
+    pub fn to_um(&self) -> I64Vec3 {
+        return i64vec3(self.x.to_um(), self.y.to_um(), self.z.to_um());
+    }
+
+    // multiplies each component of an integer vector (like a position in micrometers) by this
+    pub fn scale_int(&self, n: i64) -> I64Vec3 {
+        return i64vec3(self.x.scale_int(n), self.y.scale_int(n), self.z.scale_int(n));
+    }
+
+    // dot product of this (usually a unit direction) with an integer vector, giving an integer (so um stays um)
+    pub fn dot_int(&self, v: &I64Vec3) -> i64 {
+        let dot = (self.x.0 as i128 * v.x as i128).saturating_add(self.y.0 as i128 * v.y as i128).saturating_add(self.z.0 as i128 * v.z as i128);
+        return saturate(dot >> FIXED_FRAC_BITS);
+    }
+
+    // cross product of this with an integer vector, giving an integer vector
+    pub fn cross_int(&self, v: &I64Vec3) -> I64Vec3 {
+        let (ax, ay, az) = (self.x.0 as i128, self.y.0 as i128, self.z.0 as i128);
+        let (bx, by, bz) = (v.x as i128, v.y as i128, v.z as i128);
+        return i64vec3(saturate((ay * bz).saturating_sub(az * by) >> FIXED_FRAC_BITS), saturate((az * bx).saturating_sub(ax * bz) >> FIXED_FRAC_BITS), saturate((ax * by).saturating_sub(ay * bx) >> FIXED_FRAC_BITS));
+    }
+
+    pub fn dot(&self, other: &FixedVec3) -> Fixed {
+        return self.x * other.x + self.y * other.y + self.z * other.z;
+    }
+
+    pub fn cross(&self, other: &FixedVec3) -> FixedVec3 {
+        return FixedVec3::new(self.y * other.z - self.z * other.y, self.z * other.x - self.x * other.z, self.x * other.y - self.y * other.x);
+    }
+
+    pub fn scale(&self, s: Fixed) -> FixedVec3 {
+        return FixedVec3::new(self.x * s, self.y * s, self.z * s);
+    }
+
+    pub fn component_div(&self, other: &FixedVec3) -> FixedVec3 {
+        return FixedVec3::new(self.x / other.x, self.y / other.y, self.z / other.z);
+    }
+
+    pub fn magnitude(&self) -> Fixed {
+        return Fixed(saturate(isqrt(sum_of_squares(&[self.x.0, self.y.0, self.z.0])) as i128));
+    }
+
+    // returns zero vector if the vector has no length
+    pub fn normalize(&self) -> FixedVec3 {
+        let mag = self.magnitude();
+        if mag.0 == 0 {
+            return FixedVec3::ZERO;
+        }
+        return FixedVec3::new(self.x / mag, self.y / mag, self.z / mag);
+    }
+}
+
+impl Add for FixedVec3 {
+    type Output = FixedVec3;
+    fn add(self, rhs: FixedVec3) -> FixedVec3 {
+        return FixedVec3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z);
+    }
+}
+
+impl Sub for FixedVec3 {
+    type Output = FixedVec3;
+    fn sub(self, rhs: FixedVec3) -> FixedVec3 {
+        return FixedVec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z);
+    }
+}
+
+impl Neg for FixedVec3 {
+    type Output = FixedVec3;
+    fn neg(self) -> FixedVec3 {
+        return FixedVec3::new(-self.x, -self.y, -self.z);
+    }
+}
+
+impl AddAssign for FixedVec3 {
+    fn add_assign(&mut self, rhs: FixedVec3) {
+        *self = *self + rhs;
+    }
+}
+
+// unit quaternion in fixed point, same conventions as glm's quaternions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedQuat {
+    pub w: Fixed,
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+
+impl FixedQuat {
+    pub fn identity() -> Self {
+        return Self {w: Fixed::ONE, x: Fixed::ZERO, y: Fixed::ZERO, z: Fixed::ZERO};
+    }
+
+    pub fn from_quat(q: &Quat) -> Self {
+        return Self {w: Fixed::from_f32(q.w), x: Fixed::from_f32(q.i), y: Fixed::from_f32(q.j), z: Fixed::from_f32(q.k)};
+    }
+
+    pub fn to_quat(&self) -> Quat {
+        return Quat::new(self.w.to_f32(), self.x.to_f32(), self.y.to_f32(), self.z.to_f32());
+    }
+
+    pub fn conjugate(&self) -> Self {
+        return Self {w: self.w, x: -self.x, y: -self.y, z: -self.z};
+    }
+
+    // hamilton product, self * other
+    pub fn mul(&self, other: &FixedQuat) -> Self {
+        return Self {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        };
+    }
+
+    pub fn normalize(&self) -> Self {
+        let mag = Fixed(saturate(isqrt(sum_of_squares(&[self.w.0, self.x.0, self.y.0, self.z.0])) as i128));
+        if mag.0 == 0 {
+            return FixedQuat::identity();
+        }
+        return Self {w: self.w / mag, x: self.x / mag, y: self.y / mag, z: self.z / mag};
+    }
+
+    // rotates v by this quaternion (q * v * q^-1)
+    pub fn rotate(&self, v: &FixedVec3) -> FixedVec3 {
+        let p = FixedQuat {w: Fixed::ZERO, x: v.x, y: v.y, z: v.z};
+        let r = self.mul(&p).mul(&self.conjugate());
+        return FixedVec3::new(r.x, r.y, r.z);
+    }
+
+    // local x, y and z axes of something rotated by this quaternion, in world space
+    pub fn axes(&self) -> [FixedVec3; 3] {
+        return [
+            self.rotate(&FixedVec3::new(Fixed::ONE, Fixed::ZERO, Fixed::ZERO)),
+            self.rotate(&FixedVec3::new(Fixed::ZERO, Fixed::ONE, Fixed::ZERO)),
+            self.rotate(&FixedVec3::new(Fixed::ZERO, Fixed::ZERO, Fixed::ONE)),
+        ];
+    }
+
+    // advances the rotation by angular velocity * dt using dq/dt = 0.5 * w * q, so no trig is needed
+    pub fn integrate(&self, angular_velocity: &FixedVec3, dt: Fixed) -> Self {
+        let w = FixedQuat {w: Fixed::ZERO, x: angular_velocity.x * dt, y: angular_velocity.y * dt, z: angular_velocity.z * dt};
+        let dq = w.mul(self);
+        return Self {
+            w: self.w + dq.w * Fixed::HALF,
+            x: self.x + dq.x * Fixed::HALF,
+            y: self.y + dq.y * Fixed::HALF,
+            z: self.z + dq.z * Fixed::HALF,
+        }.normalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isqrt_is_exact() {
+        for n in 0..2000u128 {
+            let root = isqrt(n);
+            assert!(root * root <= n && (root + 1) * (root + 1) > n, "isqrt({}) gave {}", n, root);
+        }
+        for n in [1u128 << 20, 1 << 40, 1 << 63, u64::MAX as u128, 12345678901234567] {
+            assert_eq!(isqrt(n * n), n);
+            assert_eq!(isqrt(n * n - 1), n - 1);
+        }
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+    }
+
+    // these are the exact bits every platform has to produce, if one changes then saved replays stop matching
+    #[test]
+    fn fixed_bits_are_pinned() {
+        assert_eq!(Fixed::from_int(3).0, 196608);
+        assert_eq!(Fixed::from_f32(0.1).0, 6553);
+        assert_eq!(Fixed::from_f32(-1.75).0, -114688);
+        assert_eq!(Fixed::ratio(1, 3).0, 21845);
+        assert_eq!(Fixed::ratio(-2, 5).0, -26214);
+        assert_eq!((Fixed::ratio(1, 3) * Fixed::ratio(1, 3)).0, 7281);
+        assert_eq!((Fixed::ONE / Fixed::from_int(7)).0, 9362);
+        assert_eq!(Fixed::from_int(2).sqrt().0, 92681);
+        assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+        assert_eq!(Fixed::from_um(1_500_000), Fixed::ratio(3, 2));
+        assert_eq!(Fixed::ratio(3, 2).to_um(), 1_500_000);
+        assert_eq!(Fixed::ratio(1, 3).scale_int(1_000_000), 333328);
+
+        let v = FixedVec3::new(Fixed::from_int(3), Fixed::from_int(4), Fixed::ZERO);
+        assert_eq!(v.magnitude(), Fixed::from_int(5));
+        assert_eq!(v.normalize(), FixedVec3::new(Fixed::ratio(3, 5), Fixed::ratio(4, 5), Fixed::ZERO));
+    }
+}
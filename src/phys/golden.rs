@@ -0,0 +1,163 @@
+// Headless physics regression tests, so CI can catch things like the sphere-sphere normal bug without a window or a GPU.
+// Every scenario runs in both physics modes and has to meet its expectation (resting height, bounce apex, etc.) in each.
+// The deterministic run's final snapshot must also exactly match the golden file in PHYSICS_GOLDEN_DIR.
+// Run with cargo test, or with BLESS=1 set to rewrite the golden files after an intentional change to the physics.
+
+use std::{rc::Rc, cell::RefCell};
+
+use glm::I64Vec3;
+
+use crate::transform::*;
+use crate::gameobjects::*;
+
+use super::*;
+
+const PHYSICS_GOLDEN_DIR: &str = "golden/physics";
+
+fn expect_near(what: &str, actual_um: i64, expected_um: i64, tolerance_um: i64) {
+    assert!((actual_um - expected_um).abs() <= tolerance_um, "Expected {} to be {}m (+-{}m), but it was {}m.", what, expected_um as f64/UNITS_PER_METER as f64, tolerance_um as f64/UNITS_PER_METER as f64, actual_um as f64/UNITS_PER_METER as f64);
+}
+
+fn body_pos(world: &PhysicsWorld, handle: BodyHandle) -> I64Vec3 {
+    return world.bodies().get(handle).read().unwrap().transform.pos();
+}
+
+// runs the scenario in both modes, and compares the deterministic run's final snapshot to golden/physics/<name>.txt
+fn check_golden(name: &str, run: fn(PhysicsMode) -> PhysicsSnapshot) {
+    run(PhysicsMode::Float); // float results are allowed to differ between platforms, so only their expectations are checked
+    expect_golden_snapshot(name, &run(PhysicsMode::Deterministic));
+}
+
+// compares a deterministic snapshot to golden/physics/<name>.txt (or rewrites it if BLESS is set)
+pub(super) fn expect_golden_snapshot(name: &str, snapshot: &PhysicsSnapshot) {
+    let text = snapshot.to_text();
+    let path = format!("{}/{}.txt", PHYSICS_GOLDEN_DIR, name);
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::create_dir_all(PHYSICS_GOLDEN_DIR).unwrap();
+        std::fs::write(&path, &text).unwrap();
+        println!("wrote {}", path);
+        return;
+    }
+    let golden = std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("Could not read {}: {} (run with BLESS=1 to create it)", path, err));
+    assert!(golden == text, "Snapshot differs from {}\n--- golden\n{}--- actual\n{}", path, golden, text);
+}
+
+// a 1m box dropped from 3m should end up sitting on the floor with its center 0.5m up
+fn box_rests_on_floor(mode: PhysicsMode) -> PhysicsSnapshot {
+    let mut cube = PhysBody::new(ColliderType::Box);
+    cube.name = String::from("Box");
+    cube.transform.setpos_meters(dvec3(0.0, 3.0, 0.0));
+
+    let mut world = PhysicsWorldBuilder::new().mode(mode).floor(0.0, 20.0).body(cube).build();
+    world.step_n(600);
+
+    let pos = body_pos(&world, BodyHandle(1));
+    expect_near("box height", pos.y, 500000, 20000);
+    expect_near("box x", pos.x, 0, 1000);
+    expect_near("box z", pos.z, 0, 1000);
+    return world.snapshot();
+}
+
+// with a combined elasticity of e, a ball dropped from height h should come back up to about h * e^2
+fn sphere_bounce_apex(mode: PhysicsMode) -> PhysicsSnapshot {
+    let drop_height = 2.0;
+    let elasticity = 0.8;
+
+    let mut sphere = PhysBody::new(ColliderType::Sphere);
+    sphere.name = String::from("Sphere");
+    sphere.elasticity = elasticity;
+    sphere.transform.setpos_meters(dvec3(0.0, drop_height + 0.5, 0.0));
+
+    let mut world = PhysicsWorldBuilder::new().mode(mode).floor(0.0, 20.0).body(sphere).build();
+    world.bodies().get(BodyHandle(0)).write().unwrap().elasticity = 1.0;
+
+    // fall until it bounces, then rise until it stops going up
+    let bounced = world.step_until(600, |w| w.bodies().get(BodyHandle(1)).read().unwrap().velocity.y > 0);
+    assert!(bounced, "Sphere never bounced.");
+    let mut apex = body_pos(&world, BodyHandle(1)).y;
+    world.step_until(600, |w| w.bodies().get(BodyHandle(1)).read().unwrap().velocity.y <= 0);
+    apex = apex.max(body_pos(&world, BodyHandle(1)).y);
+
+    let expected_apex = ((drop_height * (elasticity as f64).powi(2) + 0.5) * UNITS_PER_METER as f64) as i64;
+    expect_near("bounce apex", apex, expected_apex, 150000);
+    return world.snapshot();
+}
+
+// a sphere dropped straight onto an anchored sphere must be pushed straight up and end up sitting on top of it
+fn sphere_lands_on_sphere(mode: PhysicsMode) -> PhysicsSnapshot {
+    let mut base = PhysBody::new(ColliderType::Sphere);
+    base.name = String::from("BaseSphere");
+    base.anchored = true;
+    base.transform.setpos_meters(dvec3(0.0, 0.5, 0.0));
+
+    let mut sphere = PhysBody::new(ColliderType::Sphere);
+    sphere.name = String::from("Sphere");
+    sphere.elasticity = 0.0;
+    sphere.transform.setpos_meters(dvec3(0.0, 3.0, 0.0));
+
+    let mut world = PhysicsWorldBuilder::new().mode(mode).floor(0.0, 20.0).body(base).body(sphere).build();
+    world.step_n(300);
+
+    let pos = body_pos(&world, BodyHandle(2));
+    expect_near("sphere height", pos.y, 1500000, 30000);
+    expect_near("sphere x", pos.x, 0, 1000);
+    expect_near("sphere z", pos.z, 0, 1000);
+    return world.snapshot();
+}
+
+// same as box_rests_on_floor, but through do_physics() and the SAS like the game does, instead of PhysicsWorld
+fn sas_box_rests_on_floor(mode: PhysicsMode) -> PhysicsSnapshot {
+    let floor = Rc::new(RefCell::new(PhysBody::new(ColliderType::Box)));
+    floor.borrow_mut().name = String::from("Floor");
+    floor.borrow_mut().transform.setscl(glm::vec3(20.0, 1.0, 20.0));
+    floor.borrow_mut().transform.setpos_meters(dvec3(0.0, -0.5, 0.0));
+
+    let cube = Rc::new(RefCell::new(PhysBody::new(ColliderType::Box)));
+    cube.borrow_mut().name = String::from("Box");
+    cube.borrow_mut().transform.setpos_meters(dvec3(0.0, 3.0, 0.0));
+
+    let mut sas = SpatialAccelerationStructure::new();
+    sas.insert(floor.clone());
+    sas.insert(cube.clone());
+    let rigidbodies: Vec<Rc<RefCell<dyn RigidBody>>> = vec![cube.clone()];
+    for _ in 0..600 {
+        do_physics(&mut sas, &rigidbodies, mode);
+    }
+
+    let pos = cube.borrow().transform.pos();
+    expect_near("box height", pos.y, 500000, 20000);
+    expect_near("box x", pos.x, 0, 1000);
+    expect_near("box z", pos.z, 0, 1000);
+
+    let bodies = [floor, cube].iter().map(|body| {
+        let body = body.borrow();
+        return BodySnapshot {
+            name: body.name.clone(),
+            pos: body.transform.pos(),
+            rot: body.transform.rot_quat(),
+            velocity: body.velocity,
+            angular_velocity: body.angular_velocity,
+        };
+    }).collect();
+    return PhysicsSnapshot { steps: 600, bodies };
+}
+
+#[test]
+fn box_rests_on_floor_golden() {
+    check_golden("box_rests_on_floor", box_rests_on_floor);
+}
+
+#[test]
+fn sphere_bounce_apex_golden() {
+    check_golden("sphere_bounce_apex", sphere_bounce_apex);
+}
+
+#[test]
+fn sphere_lands_on_sphere_golden() {
+    check_golden("sphere_lands_on_sphere", sphere_lands_on_sphere);
+}
+
+#[test]
+fn sas_box_rests_on_floor_golden() {
+    check_golden("sas_box_rests_on_floor", sas_box_rests_on_floor);
+}
@@ -3,4 +3,22 @@ pub use inertia_tensor::*;
 mod spatial_acceleration_structure;
 pub use spatial_acceleration_structure::*;
 mod physics_update;
-pub use physics_update::*;
\ No newline at end of file
+pub use physics_update::*;
+mod fixed;
+pub use fixed::*;
+mod deterministic;
+pub use deterministic::*;
+mod parallel_physics;
+pub use parallel_physics::*;
+mod world;
+pub use world::*;
+#[cfg(test)]
+mod golden;
+mod softbody;
+pub use softbody::*;
+mod raycast;
+pub use raycast::*;
+mod vehicle;
+pub use vehicle::*;
+mod rigidbody_list;
+pub use rigidbody_list::*;
@@ -0,0 +1,307 @@
+// Thread-safe rigidbody storage and a physics step that runs narrowphase and island solving across rayon's worker threads.
+// do_physics() works on Rc<RefCell<dyn RigidBody>>, which can't leave the main thread, so bodies here are plain data behind Arc<RwLock<>> and referred to by index.
+
+use std::sync::{Arc, RwLock};
+
+use glm::{I64Vec3, Vec3, vec3, vec4};
+use rayon::prelude::*;
+
+use crate::transform::*;
+use crate::gameobjects::*;
+
+use super::{AABB, GRAVITY, respond_to_collision};
+
+// index into a BodySet, stays valid for as long as the BodySet exists
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BodyHandle(pub usize);
+
+// rigidbody without any of the rendering stuff, so it's Send + Sync
+pub struct PhysBody {
+    pub name: String,
+    pub transform: Transform,
+
+    pub density: f32,
+    pub friction: f32,
+    pub elasticity: f32,
+    pub velocity: I64Vec3,
+    pub angular_velocity: Vec3,
+
+    pub anchored: bool, // anchored bodies (like the floor) get collided with but never move
+
+    collider_type: ColliderType,
+}
+
+impl PhysBody {
+    pub fn new(collider_type: ColliderType) -> Self {
+        return Self {
+            name: String::from("PhysBody"),
+            transform: Transform::empty(),
+
+            density: 1.0,
+            friction: 0.4,
+            elasticity: 0.3,
+            velocity: i64vec3(0, 0, 0),
+            angular_velocity: vec3(0.0, 0.0, 0.0),
+
+            anchored: false,
+
+            collider_type: collider_type,
+        };
+    }
+}
+
+crate::impl_gameobject!(PhysBody);
+crate::impl_collides!(PhysBody);
+crate::impl_rigidbody!(PhysBody);
+crate::impl_transform!(PhysBody);
+
+pub struct BodySet {
+    bodies: Vec<Arc<RwLock<PhysBody>>>,
+}
+
+// a contact found by the narrowphase; normal points towards a
+struct Contact {
+    a: usize,
+    b: usize,
+    normal: Vec3,
+    collision_points: Vec<(I64Vec3, i64)>,
+}
+
+impl BodySet {
+    pub fn new() -> Self {
+        return Self { bodies: Vec::new() };
+    }
+
+    pub fn insert(&mut self, body: PhysBody) -> BodyHandle {
+        self.bodies.push(Arc::new(RwLock::new(body)));
+        return BodyHandle(self.bodies.len() - 1);
+    }
+
+    pub fn get(&self, handle: BodyHandle) -> Arc<RwLock<PhysBody>> {
+        return self.bodies[handle.0].clone();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.bodies.len();
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = BodyHandle> {
+        return (0..self.bodies.len()).map(BodyHandle);
+    }
+}
+
+// finds every pair of bodies whose AABBs overlap by sorting on x and sweeping, skipping pairs where neither body can move
+// pairs are always (lower index, higher index) and come out in the same order every time
+fn sweep_and_prune(aabbs: &Vec<AABB>, anchored: &Vec<bool>) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..aabbs.len()).collect();
+    order.sort_by_key(|i| (aabbs[*i].min().x, *i));
+
+    let mut pairs = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+    for i in order {
+        active.retain(|j| aabbs[*j].max().x > aabbs[i].min().x);
+        for j in active.iter() {
+            if anchored[i] && anchored[*j] {
+                continue;
+            }
+            if aabbs[i].touches(&aabbs[*j]) {
+                pairs.push((i.min(*j), i.max(*j)));
+            }
+        }
+        active.push(i);
+    }
+    return pairs;
+}
+
+fn find_root(parents: &mut Vec<usize>, mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    return i;
+}
+
+// groups contacts into islands of bodies that touch each other (directly or through other bodies), returning indices into contacts
+// anchored bodies don't join islands together since nothing that happens in one island can move them
+fn build_islands(n_bodies: usize, contacts: &Vec<Contact>, anchored: &Vec<bool>) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..n_bodies).collect();
+    for contact in contacts {
+        if !anchored[contact.a] && !anchored[contact.b] {
+            let root_a = find_root(&mut parents, contact.a);
+            let root_b = find_root(&mut parents, contact.b);
+            parents[root_a.max(root_b)] = root_a.min(root_b);
+        }
+    }
+
+    let mut island_of_root: Vec<usize> = vec![usize::MAX; n_bodies];
+    let mut islands: Vec<Vec<usize>> = Vec::new();
+    for (i, contact) in contacts.iter().enumerate() {
+        let body = if anchored[contact.a] {contact.b} else {contact.a};
+        let root = find_root(&mut parents, body);
+        if island_of_root[root] == usize::MAX {
+            island_of_root[root] = islands.len();
+            islands.push(Vec::new());
+        }
+        islands[island_of_root[root]].push(i);
+    }
+    return islands;
+}
+
+// every non-anchored body in an island belongs to only that island, so islands can be solved at the same time without fighting over locks
+fn solve_island(bodies: &Vec<Arc<RwLock<PhysBody>>>, contacts: &Vec<Contact>, island: &Vec<usize>) {
+    for i in island {
+        let contact = &contacts[*i];
+        let (elasticity_a, anchored_a) = {let a = bodies[contact.a].read().unwrap(); (a.elasticity, a.anchored)};
+        let (elasticity_b, anchored_b) = {let b = bodies[contact.b].read().unwrap(); (b.elasticity, b.anchored)};
+
+        if !anchored_a {
+            respond_to_collision(&mut *bodies[contact.a].write().unwrap(), elasticity_b, &contact.normal, &contact.collision_points);
+        }
+        if !anchored_b {
+            respond_to_collision(&mut *bodies[contact.b].write().unwrap(), elasticity_a, &-contact.normal, &contact.collision_points);
+        }
+    }
+}
+
+pub fn do_physics_parallel(set: &BodySet) {
+    let bodies = &set.bodies;
+
+    // gravity
+    bodies.par_iter().for_each(|body| {
+        let mut body = body.write().unwrap();
+        if !body.anchored {
+            body.velocity += i64vec3(0, GRAVITY, 0);
+        }
+    });
+
+    // broadphase
+    let anchored: Vec<bool> = bodies.par_iter().map(|body| body.read().unwrap().anchored).collect();
+    let aabbs: Vec<AABB> = bodies.par_iter().map(|body| AABB::new(body.read().unwrap().transform())).collect();
+    let pairs = sweep_and_prune(&aabbs, &anchored);
+
+    // narrowphase, every pair is tested once and the response gets applied to both bodies
+    let contacts: Vec<Contact> = pairs.par_iter().filter_map(|(a, b)| {
+        let body_a = bodies[*a].read().unwrap();
+        let body_b = bodies[*b].read().unwrap();
+        return body_a.collides_with(&*body_b).map(|info| Contact {a: *a, b: *b, normal: info.normal, collision_points: info.collision_points});
+    }).collect();
+
+    // collision response
+    let islands = build_islands(bodies.len(), &contacts, &anchored);
+    islands.par_iter().for_each(|island| solve_island(bodies, &contacts, island));
+
+    // velocity step
+    bodies.par_iter().for_each(|body| {
+        let mut obj = body.write().unwrap();
+        if obj.anchored {
+            return;
+        }
+        let v = obj.velocity()/60;
+        *obj.angular_velocity_mut() *= 0.99;
+        *(obj.transform_mut().pos_mut()) += v;
+        let av = obj.angular_velocity()/60.0;
+        obj.transform_mut().rotatex(av.x);
+        obj.transform_mut().rotatey(av.y);
+        obj.transform_mut().rotatez(av.z);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, cell::RefCell};
+
+    use crate::phys::*;
+
+    use super::*;
+
+    fn contact(a: usize, b: usize) -> Contact {
+        return Contact { a, b, normal: vec3(0.0, 1.0, 0.0), collision_points: Vec::new() };
+    }
+
+    // a floor (anchored), a stack of 1 and 2 on it, 3 and 4 touching each other on it, and 5 on its own
+    #[test]
+    fn islands_split_at_anchored_bodies() {
+        let anchored = vec![true, false, false, false, false, false];
+        let contacts = vec![contact(0, 1), contact(0, 3), contact(1, 2), contact(3, 4), contact(0, 5), contact(0, 4)];
+        let mut islands = build_islands(anchored.len(), &contacts, &anchored);
+        for island in islands.iter_mut() {
+            island.sort();
+        }
+        assert!(islands == vec![vec![0, 2], vec![1, 3, 5], vec![4]], "expected the contacts to be grouped into [[0, 2], [1, 3, 5], [4]], but got {:?}", islands);
+    }
+
+    // a box on the floor with a ball on top of it, all already resting
+    fn resting_stack() -> Vec<PhysBody> {
+        let mut floor = PhysBody::new(ColliderType::Box);
+        floor.anchored = true;
+        floor.transform.setscl(vec3(20.0, 1.0, 20.0));
+        floor.transform.setpos_meters(dvec3(0.0, -0.5, 0.0));
+        let mut cube = PhysBody::new(ColliderType::Box);
+        cube.transform.setpos_meters(dvec3(0.0, 0.5, 0.0));
+        let mut ball = PhysBody::new(ColliderType::Sphere);
+        ball.transform.setpos_meters(dvec3(0.0, 1.5, 0.0));
+        return vec![floor, cube, ball];
+    }
+
+    #[test]
+    fn resting_stack_matches_do_physics() {
+        let mut set = BodySet::new();
+        for body in resting_stack() {
+            set.insert(body);
+        }
+
+        let mut sas = SpatialAccelerationStructure::new();
+        let mut rigidbodies: Vec<Rc<RefCell<dyn RigidBody>>> = Vec::new();
+        let sequential: Vec<Rc<RefCell<PhysBody>>> = resting_stack().into_iter().map(|body| Rc::new(RefCell::new(body))).collect();
+        for body in sequential.iter() {
+            sas.insert(body.clone());
+            if !body.borrow().anchored {
+                rigidbodies.push(body.clone());
+            }
+        }
+
+        for _ in 0..300 {
+            do_physics_parallel(&set);
+            do_physics(&mut sas, &rigidbodies, PhysicsMode::Float);
+        }
+        let ball_height = set.get(BodyHandle(2)).read().unwrap().transform.pos().y as f64 / UNITS_PER_METER as f64;
+        assert!((ball_height - 1.5).abs() < 0.05, "the stack didn't stay standing, the ball is at {}m", ball_height);
+        for (i, body) in sequential.iter().enumerate() {
+            let parallel = set.get(BodyHandle(i)).read().unwrap().transform.pos();
+            let sequential = body.borrow().transform.pos();
+            let difference = vec3_from_i64vec3(&(parallel - sequential)).norm();
+            assert!(difference < 0.02, "body {} ended up {}m away from where do_physics() put it ({:?} vs {:?})", i, difference, parallel, sequential);
+        }
+    }
+
+    // a few separate islands (two stacks and some things tumbling into each other), so there's something to share between threads
+    fn busy_world() -> PhysicsWorld {
+        let mut builder = PhysicsWorldBuilder::new().mode(PhysicsMode::Float).floor(0.0, 20.0);
+        for (i, x) in [-6.0, 0.0, 6.0].iter().enumerate() {
+            let mut cube = PhysBody::new(ColliderType::Box);
+            cube.transform.setpos_meters(dvec3(*x, 1.0 + i as f64, 0.0));
+            cube.angular_velocity = vec3(0.5, 1.0 * i as f32, -0.5);
+            let mut ball = PhysBody::new(ColliderType::Sphere);
+            ball.transform.setpos_meters(dvec3(*x + 0.2, 3.0 + i as f64, 0.1));
+            ball.velocity = i64vec3(-200_000 * i as i64, 0, 100_000);
+            builder = builder.body(cube).body(ball);
+        }
+        return builder.build();
+    }
+
+    // islands don't share moving bodies and the narrowphase only reads, so how many threads there are can't change the result
+    #[test]
+    fn thread_count_doesnt_change_results() {
+        let run = |threads: usize| -> String {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            return pool.install(|| {
+                let mut world = busy_world();
+                world.step_n(240);
+                return world.snapshot().to_text();
+            });
+        };
+        let one = run(1);
+        let four = run(4);
+        assert!(one == four, "stepping with 4 threads differs from 1\n--- 1 thread\n{}--- 4 threads\n{}", one, four);
+    }
+}
@@ -7,7 +7,18 @@ use super::SpatialAccelerationStructure;
 
 pub const GRAVITY: i64 = (-1.807 * 0.016 as f64 * UNITS_PER_METER as f64) as i64;
 
-pub fn do_physics(sas: &mut SpatialAccelerationStructure, rigidbodies: &Vec<Rc<RefCell<dyn crate::gameobjects::RigidBody>>>) {
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PhysicsMode {
+    Float, // fast, but results can differ between platforms/builds
+    Deterministic, // fixed point maths, identical inputs give bit-identical Transforms (for lockstep networking and replays)
+}
+
+pub fn do_physics(sas: &mut SpatialAccelerationStructure, rigidbodies: &Vec<Rc<RefCell<dyn crate::gameobjects::RigidBody>>>, mode: PhysicsMode) {
+    if mode == PhysicsMode::Deterministic {
+        do_physics_deterministic(sas, rigidbodies);
+        return;
+    }
+
     //std::thread::sleep(time::Duration::from_millis(500));
     // println!("DOING PHYSICS OH NO");
     //let mut positions = Vec::new();
@@ -31,61 +42,11 @@ pub fn do_physics(sas: &mut SpatialAccelerationStructure, rigidbodies: &Vec<Rc<R
                 continue;
             };
             let other = obj2_cell.borrow_mut();
-            let mass = obj.mass();
             
             let collision = obj.collides_with(&*other); // penetration, normal, hitpos
             if collision.is_some() {
-                let normal =collision.as_ref().unwrap().normal;
-                //dbnormal = normal;
-                let mut greatest_penetration = 0;
-                for point in collision.as_ref().unwrap().collision_points.iter() {
-                    let penetration = point.1;
-                    if penetration > greatest_penetration {
-                        greatest_penetration = penetration;
-                    }
-                    //println!("COLLISION!!!! {}, normal {:?}", penetration, normal);
-                    
-                    //println!("Moving up by {:?} to compensate", (i64vec3_from_vec3(&normal) * penetration.abs())/UNITS_PER_METER);
-                    
-                    //println!("V = {:?} but at point its {:?}", obj.velocity(), v);
-                    //println!("Res:?ponding with force {:?} ", i64vec3_from_vec3(&(normal * (1.0 + e)));//.component_mul(&-v));
-                    //let speed_towards_wall = vec3_from_i64vec3(&v).dot(&normal);
-                
-                    //println!("Collision speed = {}", obj.mass());
-
-                    //let friction_speed = vec3_from_i64vec3(&v) - collision_speed;
-                    //let f = obj.friction() * other.friction();
-                    //let friction_force = -friction_speed * (penetration.abs() as f32 / UNITS_PER_METER as f32) * f;
-                    //obj.impulse_at_pos(friction_force, hitpos - obj.transform().pos());
-                    //positions.push(point.0);
-                }
-                
-                *obj.transform_mut().pos_mut() += (i64vec3_from_vec3(&normal) * greatest_penetration.abs())/UNITS_PER_METER;
-
-                let mut total_pos = i64vec3(0, 0, 0);
-                collision.as_ref().unwrap().collision_points.iter().for_each(|x| total_pos += x.0);
-                let mut hitpos = total_pos/collision.as_ref().unwrap().collision_points.len() as i64;
-                hitpos -= obj.transform().pos();
-               
-                let e = obj.elasticity() * other.elasticity() + 1.0;
-                let v = obj.velocity_at_point(vec3_from_i64vec3(&hitpos));
-                //let collision_speed = -normal * (vec3_from_i64vec3(&v)).component_mul(&normal).magnitude();
-                //let force = -collision_speed * obj.mass() * (1.0 + e);
-                
-                
-                let desired_change_in_velocity = -e * normal * (normal.dot(&&vec3_from_i64vec3(&v))/(&(normal.dot(&normal))) );
-                // println!("Desired change in speed is {:?}", desired_change_in_velocity);
-                println!("OBJ velocity is {:?}, but at point its {:?}", obj.velocity(), v);
-                
-                obj.torque_from_force_at_pos(vec3_from_i64vec3(&-v) * mass, hitpos);
-                obj.impulse_at_pos(desired_change_in_velocity * mass, hitpos);
-                
-                //dbnormal = vec3_from_i64vec3(&-v).normalize();
-                
-                //positions.push(hitpos + obj.transform().pos());
-                
-                //debug_pos_cuz_it_hit = true;
-                
+                let info = collision.unwrap();
+                respond_to_collision(&mut *obj, other.elasticity(), &info.normal, &info.collision_points);
             }
             else {
                 //println!("{} could have been colliding with {}, but it wasn't", obj.name(), other.name());
@@ -122,4 +83,57 @@ pub fn do_physics(sas: &mut SpatialAccelerationStructure, rigidbodies: &Vec<Rc<R
     
     
 
-}
\ No newline at end of file
+}
+
+// pushes obj out of whatever it hit and applies the bounce/spin from the collision
+// normal should point towards obj
+pub fn respond_to_collision(obj: &mut dyn crate::gameobjects::RigidBody, other_elasticity: f32, normal: &Vec3, collision_points: &Vec<(I64Vec3, i64)>) {
+    let mass = obj.mass();
+
+    let mut greatest_penetration = 0;
+    for point in collision_points.iter() {
+        let penetration = point.1;
+        if penetration > greatest_penetration {
+            greatest_penetration = penetration;
+        }
+    }
+    
+    *obj.transform_mut().pos_mut() += (i64vec3_from_vec3(normal) * greatest_penetration.abs())/UNITS_PER_METER;
+
+    // averaged relative to obj so summing positions far from the origin can't overflow
+    let mut total_pos = i64vec3(0, 0, 0);
+    collision_points.iter().for_each(|x| total_pos += x.0 - obj.transform().pos());
+    let hitpos = total_pos/collision_points.len() as i64;
+   
+    let e = obj.elasticity() * other_elasticity + 1.0;
+    let v = obj.velocity_at_point(vec3_from_i64vec3(&hitpos));
+    
+    let desired_change_in_velocity = -e * normal * (normal.dot(&&vec3_from_i64vec3(&v))/(&(normal.dot(normal))) );
+    // println!("Desired change in speed is {:?}", desired_change_in_velocity);
+    // println!("OBJ velocity is {:?}, but at point its {:?}", obj.velocity(), v);
+    
+    obj.torque_from_force_at_pos(vec3_from_i64vec3(&-v) * mass, hitpos);
+    obj.impulse_at_pos(desired_change_in_velocity * mass, hitpos);
+}
+
+// rigidbodies are stepped in the order they appear in the vec and the SAS returns objects in a fixed order, so the only thing left to make deterministic is the maths
+fn do_physics_deterministic(sas: &mut SpatialAccelerationStructure, rigidbodies: &Vec<Rc<RefCell<dyn crate::gameobjects::RigidBody>>>) {
+    for obj_cell in rigidbodies {
+        let mut obj = obj_cell.borrow_mut();
+
+        // whatever the SAS has that isn't being stepped is anchored, the rest get pushed back when obj runs into them
+        let possible_colliding = sas.query_aabb(&super::AABB::new(obj.transform()));
+        let address = |cell: &Rc<RefCell<dyn crate::gameobjects::Collides>>| cell.as_ptr() as *const () as usize;
+        let rigidbody = |cell: &Rc<RefCell<dyn crate::gameobjects::Collides>>| rigidbodies.iter().find(|rb| rb.as_ptr() as *const () as usize == address(cell));
+        let anchored: Vec<_> = possible_colliding.iter().filter(|obj2_cell| rigidbody(obj2_cell).is_none()).map(|obj2_cell| obj2_cell.borrow()).collect();
+        let mut moving: Vec<_> = possible_colliding.iter()
+            .filter(|obj2_cell| obj_cell.as_ptr() as *const () as usize != address(obj2_cell)) // ignore a self-collision
+            .filter_map(|obj2_cell| rigidbody(obj2_cell))
+            .map(|rb| rb.borrow_mut())
+            .collect();
+        let mut others: Vec<super::Obstacle> = anchored.iter().map(|other| super::Obstacle::Anchored(&**other)).collect();
+        others.extend(moving.iter_mut().map(|other| super::Obstacle::Moving(&mut **other)));
+
+        super::step_rigidbody_deterministic(&mut *obj, &mut others);
+    }
+}
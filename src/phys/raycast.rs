@@ -0,0 +1,135 @@
+// Ray queries against the colliders in the SAS, for things like wheels, bullets and line of sight.
+
+use std::{rc::Rc, cell::RefCell};
+
+use glm::{Vec3, vec3, vec4, I64Vec3};
+
+use crate::transform::*;
+use crate::gameobjects::*;
+
+use super::{SpatialAccelerationStructure, AABB};
+
+pub struct RayHit {
+    pub object: Rc<RefCell<dyn Collides>>,
+    pub pos: I64Vec3, // micrometers
+    pub normal: Vec3,
+    pub distance: f32, // meters from the ray's origin
+}
+
+// returns distance along the ray (which must have a normalized direction) to where it enters the collider and the surface normal there
+// origin is relative to the collider's position, in meters
+fn ray_vs_collider(collider_type: ColliderType, transform: &Transform, origin: Vec3, direction: Vec3) -> Option<(f32, Vec3)> {
+    match collider_type {
+        ColliderType::Sphere => {
+            let radius = transform.scl().x / 2.0;
+            let b = origin.dot(&direction);
+            let c = origin.magnitude_squared() - radius * radius;
+            if c > 0.0 && b > 0.0 { // outside and pointing away
+                return None;
+            }
+            let discriminant = b * b - c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let t = (-b - discriminant.sqrt()).max(0.0);
+            let hit = origin + direction * t;
+            // starting at the exact center there's no surface direction to go by, so just face the ray
+            let normal = if hit.magnitude_squared() > 0.0 {hit.normalize()} else {-direction};
+            return Some((t, normal));
+        }
+        ColliderType::Box => {
+            // slab test in the box's local space
+            let local_origin = (transform.unrotate() * vec4(origin.x, origin.y, origin.z, 1.0)).xyz();
+            let local_direction = (transform.unrotate() * vec4(direction.x, direction.y, direction.z, 0.0)).xyz();
+            let extents = transform.scl() / 2.0;
+
+            let mut t_enter = f32::MIN;
+            let mut t_exit = f32::MAX;
+            let mut enter_axis = 0;
+            for axis in 0..3 {
+                if local_direction[axis] == 0.0 {
+                    if local_origin[axis].abs() > extents[axis] {
+                        return None;
+                    }
+                    continue;
+                }
+                let t1 = (-extents[axis] - local_origin[axis]) / local_direction[axis];
+                let t2 = (extents[axis] - local_origin[axis]) / local_direction[axis];
+                if t1.min(t2) > t_enter {
+                    t_enter = t1.min(t2);
+                    enter_axis = axis;
+                }
+                t_exit = t_exit.min(t1.max(t2));
+            }
+            if t_enter > t_exit || t_exit < 0.0 {
+                return None;
+            }
+
+            let mut local_normal = vec3(0.0, 0.0, 0.0);
+            local_normal[enter_axis] = -local_direction[enter_axis].signum();
+            let normal = (transform.rotatemat() * vec4(local_normal.x, local_normal.y, local_normal.z, 0.0)).xyz();
+            return Some((t_enter.max(0.0), normal));
+        }
+        _ => None // TODO: rays against convex/AABB colliders
+    }
+}
+
+impl SpatialAccelerationStructure {
+    // finds the closest Sphere/Box collider hit by a ray from origin (micrometers) along direction, within max_distance meters.
+    // ignore is for skipping the object doing the raycast (compare with Rc::as_ptr() as *const ()), since it's probably mutably borrowed anyway
+    pub fn raycast(&mut self, origin: I64Vec3, direction: Vec3, max_distance: f32, ignore: Option<*const ()>) -> Option<RayHit> {
+        let direction = direction.normalize();
+
+        // broadphase with a box around the whole ray
+        let mut bounds = Transform::new(origin + i64vec3_from_vec3(&(direction * max_distance / 2.0)));
+        bounds.setscl((direction * max_distance).abs().add_scalar(0.001));
+        let candidates = self.query_aabb(&AABB::new(&bounds));
+
+        let mut closest: Option<RayHit> = None;
+        for candidate in candidates {
+            if ignore.is_some() && candidate.as_ptr() as *const () == ignore.unwrap() {
+                continue;
+            }
+
+            let (t, normal) = {
+                let obj = candidate.borrow();
+                let rel_origin = vec3_from_i64vec3(&(origin - obj.transform().pos()));
+                match ray_vs_collider(obj.get_collider_type(), obj.transform(), rel_origin, direction) {
+                    Some(hit) => hit,
+                    None => continue
+                }
+            };
+
+            if t <= max_distance && (closest.is_none() || t < closest.as_ref().unwrap().distance) {
+                closest = Some(RayHit { object: candidate, pos: origin + i64vec3_from_vec3(&(direction * t)), normal, distance: t });
+            }
+        }
+        return closest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere() -> Transform {
+        let mut transform = Transform::new(i64vec3(0, 0, 0));
+        transform.setscl(vec3(2.0, 2.0, 2.0));
+        return transform;
+    }
+
+    #[test]
+    fn ray_hits_sphere_from_outside() {
+        let (t, normal) = ray_vs_collider(ColliderType::Sphere, &sphere(), vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)).expect("the ray should hit the sphere");
+        assert!((t - 4.0).abs() < 1e-5 && (normal - vec3(-1.0, 0.0, 0.0)).norm() < 1e-5, "expected a hit 4m away facing -x, got {}m facing {:?}", t, normal);
+        assert!(ray_vs_collider(ColliderType::Sphere, &sphere(), vec3(-5.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0)).is_none(), "a ray pointing away from the sphere shouldn't hit it");
+    }
+
+    #[test]
+    fn ray_from_sphere_center_has_a_normal() {
+        let direction = vec3(0.0, 0.6, 0.8);
+        let (t, normal) = ray_vs_collider(ColliderType::Sphere, &sphere(), vec3(0.0, 0.0, 0.0), direction).expect("a ray starting inside the sphere should hit it");
+        assert!(t == 0.0 && normal.iter().all(|f| f.is_finite()), "expected a hit right at the start with a finite normal, got {}m facing {:?}", t, normal);
+        assert!((normal + direction).norm() < 1e-6, "expected the normal to face back along the ray, got {:?}", normal);
+    }
+}
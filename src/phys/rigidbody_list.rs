@@ -0,0 +1,43 @@
+// The list of rigidbodies do_physics() steps, with O(1) removal so despawning doesn't have to search it.
+
+use std::{rc::Rc, cell::RefCell, collections::HashMap};
+
+use crate::gameobjects::RigidBody;
+
+pub struct RigidBodyList {
+    bodies: Vec<Rc<RefCell<dyn RigidBody>>>,
+    indices: HashMap<*const (), usize>, // key is the body's address, value is its index in bodies
+}
+
+impl RigidBodyList {
+    pub fn new() -> Self {
+        return Self { bodies: Vec::new(), indices: HashMap::new() };
+    }
+
+    pub fn insert(&mut self, body: Rc<RefCell<dyn RigidBody>>) {
+        self.indices.insert(body.as_ptr() as *const (), self.bodies.len());
+        self.bodies.push(body);
+    }
+
+    // returns false if body wasn't in the list. the last body gets moved into its place, so order isn't kept
+    pub fn remove(&mut self, body: &Rc<RefCell<dyn RigidBody>>) -> bool {
+        let i = match self.indices.remove(&(body.as_ptr() as *const ())) {
+            Some(i) => i,
+            None => return false
+        };
+        self.bodies.swap_remove(i);
+        if i < self.bodies.len() {
+            self.indices.insert(self.bodies[i].as_ptr() as *const (), i);
+        }
+        return true;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.bodies.len();
+    }
+
+    // for passing to do_physics()
+    pub fn bodies(&self) -> &Vec<Rc<RefCell<dyn RigidBody>>> {
+        return &self.bodies;
+    }
+}
@@ -0,0 +1,494 @@
+// Cloth and soft bodies, simulated as particles held together by constraints (position based dynamics, using the XPBD formulation so stiffness doesn't depend on substep count).
+// based on https://matthias-research.github.io/pages/publications/XPBD.pdf and https://matthias-research.github.io/pages/tenMinutePhysics/
+// Particles collide with the Sphere/Box colliders in the SAS, and their positions get written back into a dynamic Mesh for drawing.
+
+use std::collections::HashMap;
+
+use glm::{Vec3, vec3, I64Vec3, vec4};
+
+use crate::transform::*;
+use crate::gameobjects::ColliderType;
+use crate::graphics::{Mesh, LOADED_MESHES, N_FLOATS_PER_VERTEX};
+
+use super::{SpatialAccelerationStructure, AABB};
+
+// keeps particles from sitting exactly on collider surfaces, where they'd flicker in and out of them
+const COLLISION_MARGIN: f32 = 0.01;
+
+struct DistanceConstraint {
+    a: usize,
+    b: usize,
+    rest_length: f32,
+    compliance: f32, // inverse of stiffness, 0 is completely rigid
+}
+
+struct VolumeConstraint {
+    triangles: Vec<[usize; 3]>,
+    rest_volume: f32,
+    compliance: f32,
+}
+
+pub struct SoftBody {
+    pub origin: I64Vec3, // particle positions are in meters relative to this, so they can be f32 without losing precision far from (0, 0, 0)
+    pub gravity: Vec3,
+    pub damping: f32, // fraction of velocity kept each substep
+    pub friction: f32, // 0-1, how much of a particle's sliding is cancelled when it touches a collider
+    pub substeps: u32,
+
+    positions: Vec<Vec3>,
+    prev_positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    inverse_masses: Vec<f32>, // 0 means the particle is pinned in place
+    particle_mass: f32, // what an unpinned particle weighs
+
+    distance_constraints: Vec<DistanceConstraint>,
+    bending_constraints: Vec<DistanceConstraint>,
+    volume_constraints: Vec<VolumeConstraint>,
+    volume_gradients: Vec<Vec3>, // scratch space for solve_volume_constraints(), so it doesn't allocate every substep
+
+    triangles: Vec<[usize; 3]>, // particle indices
+    mesh_id: usize,
+    vertex_particles: Vec<usize>, // for each vertex of the mesh, which particle it follows (vertices get duplicated along uv seams, particles don't)
+}
+
+impl SoftBody {
+    // turns an already loaded mesh into a soft body. the mesh must be dynamic.
+    // size is the size in meters the (unit sized) mesh will be simulated at. volume_compliance is None for open meshes or to let it squash freely
+    // The Renderable drawing the mesh should have its position set to origin and a scale of 1, since the vertices will be written in meters relative to origin.
+    pub fn from_mesh(mesh_id: usize, origin: I64Vec3, size: Vec3, mass: f32, stretch_compliance: f32, bend_compliance: f32, volume_compliance: Option<f32>) -> Self {
+        let meshes = LOADED_MESHES.lock().unwrap();
+        let mesh = &meshes[&mesh_id];
+        assert!(mesh.dynamic, "Soft bodies can only deform dynamic meshes, since static ones may be instanced.");
+
+        // weld vertices that share a position into a single particle, otherwise the mesh would tear along every uv seam
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut vertex_particles = Vec::new();
+        let mut particle_at_pos: HashMap<[u32; 3], usize> = HashMap::new();
+        for v in mesh.vertices.chunks(N_FLOATS_PER_VERTEX) {
+            let key = [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()];
+            let particle = *particle_at_pos.entry(key).or_insert_with(|| {
+                positions.push(vec3(v[0], v[1], v[2]).component_mul(&size));
+                return positions.len() - 1;
+            });
+            vertex_particles.push(particle);
+        }
+
+        let triangles: Vec<[usize; 3]> = mesh.indices.chunks(3).map(|t| [vertex_particles[t[0] as usize], vertex_particles[t[1] as usize], vertex_particles[t[2] as usize]]).collect();
+        drop(meshes);
+
+        let mut body = SoftBody::new(mesh_id, origin, positions, triangles, vertex_particles, mass);
+        body.add_edge_constraints(stretch_compliance, bend_compliance);
+        if volume_compliance.is_some() {
+            body.add_volume_constraint(volume_compliance.unwrap());
+        }
+        return body;
+    }
+
+    // makes a rectangular sheet of cloth hanging in the xy plane (top edge at origin), along with a dynamic mesh for drawing it
+    // call pin() on some particles (like pin_top_edge()) or it'll just fall on the floor
+    pub fn cloth(origin: I64Vec3, width: f32, height: f32, segments_x: usize, segments_y: usize, mass: f32, stretch_compliance: f32, bend_compliance: f32, texture_id: u32, shader_id: u32) -> Self {
+        let mut positions = Vec::new();
+        let mut vertices = Vec::new();
+        for y in 0..=segments_y {
+            for x in 0..=segments_x {
+                let u = x as f32 / segments_x as f32;
+                let v = y as f32 / segments_y as f32;
+                let pos = vec3((u - 0.5) * width, -v * height, 0.0);
+                positions.push(pos);
+                vertices.extend_from_slice(&[pos.x, pos.y, pos.z, 0.0, 0.0, 1.0, u, v]);
+            }
+        }
+
+        let mut indices = Vec::new();
+        let mut triangles = Vec::new();
+        let row = segments_x + 1;
+        for y in 0..segments_y {
+            for x in 0..segments_x {
+                let i = y * row + x;
+                triangles.push([i, i + row, i + 1]);
+                triangles.push([i + 1, i + row, i + row + 1]);
+            }
+        }
+        for t in triangles.iter() {
+            indices.extend(t.iter().map(|i| *i as u32));
+        }
+
+        let mesh = Mesh::from_vertices(vertices, indices, texture_id, shader_id, true);
+        let mesh_id = mesh.uuid;
+        LOADED_MESHES.lock().unwrap().insert(mesh_id, mesh);
+
+        let vertex_particles = (0..positions.len()).collect();
+        let mut body = SoftBody::new(mesh_id, origin, positions, triangles, vertex_particles, mass);
+        body.add_edge_constraints(stretch_compliance, bend_compliance);
+        body.write_to_mesh(); // from_vertices rescaled the vertices, so put the real positions back
+        return body;
+    }
+
+    fn new(mesh_id: usize, origin: I64Vec3, positions: Vec<Vec3>, triangles: Vec<[usize; 3]>, vertex_particles: Vec<usize>, mass: f32) -> Self {
+        let n = positions.len();
+        let particle_mass = mass / n.max(1) as f32;
+        return Self {
+            origin,
+            gravity: vec3(0.0, -9.807, 0.0),
+            damping: 0.999,
+            friction: 0.5,
+            substeps: 10,
+
+            prev_positions: positions.clone(),
+            positions,
+            velocities: vec![vec3(0.0, 0.0, 0.0); n],
+            inverse_masses: vec![1.0 / particle_mass; n],
+            particle_mass,
+
+            distance_constraints: Vec::new(),
+            bending_constraints: Vec::new(),
+            volume_constraints: Vec::new(),
+            volume_gradients: Vec::new(),
+
+            triangles,
+            mesh_id,
+            vertex_particles,
+        };
+    }
+
+    // every triangle edge resists stretching, and the two far corners of each pair of triangles sharing an edge resist bending
+    fn add_edge_constraints(&mut self, stretch_compliance: f32, bend_compliance: f32) {
+        let mut edge_triangles: HashMap<(usize, usize), Vec<usize>> = HashMap::new(); // (lower particle, higher particle) -> the corner opposite that edge in each triangle that has it
+        let mut edge_order: Vec<(usize, usize)> = Vec::new(); // so constraints are always solved in the same order
+        for t in self.triangles.iter() {
+            for i in 0..3 {
+                let (a, b, opposite) = (t[i], t[(i + 1) % 3], t[(i + 2) % 3]);
+                let key = (a.min(b), a.max(b));
+                if !edge_triangles.contains_key(&key) {
+                    edge_order.push(key);
+                }
+                edge_triangles.entry(key).or_insert_with(Vec::new).push(opposite);
+            }
+        }
+
+        for (a, b) in edge_order {
+            self.distance_constraints.push(DistanceConstraint { a, b, rest_length: (self.positions[a] - self.positions[b]).magnitude(), compliance: stretch_compliance });
+            let opposite = &edge_triangles[&(a, b)];
+            if opposite.len() == 2 {
+                let (c, d) = (opposite[0], opposite[1]);
+                self.bending_constraints.push(DistanceConstraint { a: c, b: d, rest_length: (self.positions[c] - self.positions[d]).magnitude(), compliance: bend_compliance });
+            }
+        }
+    }
+
+    // keeps a closed mesh from collapsing, like a jelly
+    fn add_volume_constraint(&mut self, compliance: f32) {
+        let rest_volume = self.volume(&self.triangles);
+        self.volume_constraints.push(VolumeConstraint { triangles: self.triangles.clone(), rest_volume, compliance });
+        self.volume_gradients.resize(self.positions.len(), vec3(0.0, 0.0, 0.0));
+    }
+
+    fn volume(&self, triangles: &Vec<[usize; 3]>) -> f32 {
+        let mut volume = 0.0;
+        for t in triangles {
+            volume += self.positions[t[0]].cross(&self.positions[t[1]]).dot(&self.positions[t[2]]);
+        }
+        return volume / 6.0;
+    }
+
+    pub fn n_particles(&self) -> usize {
+        return self.positions.len();
+    }
+
+    pub fn mesh_id(&self) -> usize {
+        return self.mesh_id;
+    }
+
+    // particle position in micrometers
+    pub fn particle_pos(&self, particle: usize) -> I64Vec3 {
+        return self.origin + i64vec3_from_vec3(&self.positions[particle]);
+    }
+
+    // pinned particles never move (unless moved with set_particle_pos), use for attaching flags to poles, capes to shoulders, etc.
+    pub fn pin(&mut self, particle: usize) {
+        self.inverse_masses[particle] = 0.0;
+    }
+
+    pub fn unpin(&mut self, particle: usize) {
+        self.inverse_masses[particle] = 1.0 / self.particle_mass;
+    }
+
+    // pins every particle within a millimeter of the highest one, which for cloth() is the top edge
+    pub fn pin_top_edge(&mut self) {
+        let top = self.positions.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        for i in 0..self.positions.len() {
+            if self.positions[i].y > top - 0.001 {
+                self.pin(i);
+            }
+        }
+    }
+
+    pub fn set_particle_pos(&mut self, particle: usize, pos: I64Vec3) {
+        self.positions[particle] = vec3_from_i64vec3(&(pos - self.origin));
+        self.prev_positions[particle] = self.positions[particle];
+        self.velocities[particle] = vec3(0.0, 0.0, 0.0);
+    }
+
+    fn solve_distance_constraints(positions: &mut Vec<Vec3>, inverse_masses: &Vec<f32>, constraints: &Vec<DistanceConstraint>, h: f32) {
+        for c in constraints {
+            let w = inverse_masses[c.a] + inverse_masses[c.b];
+            if w == 0.0 {
+                continue;
+            }
+            let delta = positions[c.a] - positions[c.b];
+            let length = delta.magnitude();
+            if length == 0.0 {
+                continue;
+            }
+            let alpha = c.compliance / (h * h);
+            let lambda = -(length - c.rest_length) / (w + alpha);
+            let n = delta / length;
+            positions[c.a] += n * (lambda * inverse_masses[c.a]);
+            positions[c.b] -= n * (lambda * inverse_masses[c.b]);
+        }
+    }
+
+    fn solve_volume_constraints(&mut self, h: f32) {
+        for i in 0..self.volume_constraints.len() {
+            self.volume_gradients.fill(vec3(0.0, 0.0, 0.0));
+            for t in self.volume_constraints[i].triangles.iter() {
+                let (p0, p1, p2) = (self.positions[t[0]], self.positions[t[1]], self.positions[t[2]]);
+                self.volume_gradients[t[0]] += p1.cross(&p2) / 6.0;
+                self.volume_gradients[t[1]] += p2.cross(&p0) / 6.0;
+                self.volume_gradients[t[2]] += p0.cross(&p1) / 6.0;
+            }
+
+            let mut w = 0.0;
+            for p in 0..self.positions.len() {
+                w += self.inverse_masses[p] * self.volume_gradients[p].magnitude_squared();
+            }
+            if w == 0.0 {
+                continue;
+            }
+
+            let c = self.volume(&self.volume_constraints[i].triangles) - self.volume_constraints[i].rest_volume;
+            let alpha = self.volume_constraints[i].compliance / (h * h);
+            let lambda = -c / (w + alpha);
+            for p in 0..self.positions.len() {
+                self.positions[p] += self.volume_gradients[p] * (lambda * self.inverse_masses[p]);
+            }
+        }
+    }
+
+    // pushes particles out of any sphere/box colliders they've ended up inside of
+    fn solve_collisions(&mut self, colliders: &Vec<(ColliderType, Transform)>) {
+        for (collider_type, transform) in colliders {
+            let center = vec3_from_i64vec3(&(transform.pos() - self.origin));
+            for p in 0..self.positions.len() {
+                if self.inverse_masses[p] == 0.0 {
+                    continue;
+                }
+                let rel = self.positions[p] - center;
+                let pushed_to = match collider_type {
+                    ColliderType::Sphere => {
+                        let radius = transform.scl().x / 2.0 + COLLISION_MARGIN;
+                        let distance = rel.magnitude();
+                        if distance >= radius || distance == 0.0 {
+                            continue;
+                        }
+                        center + rel * (radius / distance)
+                    }
+                    ColliderType::Box => {
+                        // find the particle's position in the box's (unscaled) local space
+                        let local = (transform.unrotate() * vec4(rel.x, rel.y, rel.z, 1.0)).xyz();
+                        let extents = transform.scl() / 2.0 + vec3(COLLISION_MARGIN, COLLISION_MARGIN, COLLISION_MARGIN);
+                        let depth = extents - local.abs();
+                        if depth.x <= 0.0 || depth.y <= 0.0 || depth.z <= 0.0 {
+                            continue;
+                        }
+                        // push out through the nearest face
+                        let mut out = local;
+                        if depth.x <= depth.y && depth.x <= depth.z {
+                            out.x = extents.x * local.x.signum();
+                        }
+                        else if depth.y <= depth.z {
+                            out.y = extents.y * local.y.signum();
+                        }
+                        else {
+                            out.z = extents.z * local.z.signum();
+                        }
+                        center + (transform.rotatemat() * vec4(out.x, out.y, out.z, 1.0)).xyz()
+                    }
+                    _ => continue
+                };
+
+                // friction: cancel part of how far the particle slid along the surface this substep
+                let correction = pushed_to - self.positions[p];
+                let moved = pushed_to - self.prev_positions[p];
+                let normal = correction.normalize();
+                let sliding = moved - normal * moved.dot(&normal);
+                self.positions[p] = pushed_to - sliding * self.friction;
+            }
+        }
+    }
+
+    // advances the simulation by dt seconds
+    pub fn step(&mut self, dt: f32, sas: &mut SpatialAccelerationStructure) {
+        let colliders = self.find_colliders(dt, sas);
+        let h = dt / self.substeps as f32;
+        for _ in 0..self.substeps {
+            for p in 0..self.positions.len() {
+                if self.inverse_masses[p] == 0.0 {
+                    continue;
+                }
+                self.velocities[p] += self.gravity * h;
+                self.prev_positions[p] = self.positions[p];
+                self.positions[p] += self.velocities[p] * h;
+            }
+
+            SoftBody::solve_distance_constraints(&mut self.positions, &self.inverse_masses, &self.distance_constraints, h);
+            SoftBody::solve_distance_constraints(&mut self.positions, &self.inverse_masses, &self.bending_constraints, h);
+            self.solve_volume_constraints(h);
+            self.solve_collisions(&colliders);
+
+            for p in 0..self.positions.len() {
+                if self.inverse_masses[p] == 0.0 {
+                    continue;
+                }
+                self.velocities[p] = (self.positions[p] - self.prev_positions[p]) / h * self.damping;
+            }
+        }
+    }
+
+    // asks the broadphase what could touch us this step; copies out the transforms so we don't hold any borrows while solving
+    fn find_colliders(&self, dt: f32, sas: &mut SpatialAccelerationStructure) -> Vec<(ColliderType, Transform)> {
+        if self.positions.is_empty() {
+            return Vec::new();
+        }
+        let mut min = self.positions[0];
+        let mut max = self.positions[0];
+        for p in self.positions.iter() {
+            min = glm::min2(&min, p);
+            max = glm::max2(&max, p);
+        }
+        // pad every side by how far particles could travel this step (plus the margin) so fast moving cloth doesn't tunnel through things
+        let speed = self.velocities.iter().map(|v| v.magnitude()).fold(0.0, f32::max);
+        let mut bounds = Transform::new(self.origin + i64vec3_from_vec3(&((min + max) / 2.0)));
+        bounds.setscl((max - min).add_scalar((speed * dt + COLLISION_MARGIN) * 2.0));
+
+        let mut colliders = Vec::new();
+        for obj in sas.query_aabb(&AABB::new(&bounds)) {
+            let obj = obj.borrow();
+            colliders.push((obj.get_collider_type(), obj.transform().clone()));
+        }
+        return colliders;
+    }
+
+    // copies particle positions (and recalculated smooth normals) into the mesh's vertices, ready to be uploaded to the gpu
+    pub fn write_to_mesh(&self) {
+        let mut normals = vec![vec3(0.0, 0.0, 0.0); self.positions.len()];
+        for t in self.triangles.iter() {
+            let face_normal = (self.positions[t[1]] - self.positions[t[0]]).cross(&(self.positions[t[2]] - self.positions[t[0]])); // not normalized, so bigger triangles count for more
+            for p in t {
+                normals[*p] += face_normal;
+            }
+        }
+
+        let mut meshes = LOADED_MESHES.lock().unwrap();
+        let mesh = meshes.get_mut(&self.mesh_id).unwrap();
+        for (v, p) in self.vertex_particles.iter().enumerate() {
+            let normal = if normals[*p].magnitude_squared() > 0.0 {normals[*p].normalize()} else {vec3(0.0, 1.0, 0.0)};
+            let i = v * N_FLOATS_PER_VERTEX;
+            mesh.vertices[i..i + 3].copy_from_slice(self.positions[*p].as_slice());
+            mesh.vertices[i + 3..i + 6].copy_from_slice(normal.as_slice());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, cell::RefCell};
+
+    use crate::graphics::Primitive;
+    use crate::phys::PhysBody;
+
+    use super::*;
+
+    fn floor_sas() -> SpatialAccelerationStructure {
+        let floor = Rc::new(RefCell::new(PhysBody::new(ColliderType::Box)));
+        floor.borrow_mut().transform.setscl(vec3(20.0, 1.0, 20.0));
+        floor.borrow_mut().transform.setpos_meters(dvec3(0.0, -0.5, 0.0));
+        let mut sas = SpatialAccelerationStructure::new();
+        sas.insert(floor);
+        return sas;
+    }
+
+    // steps for another second and returns how far the particle that moved the most went, in meters.
+    // velocities never quite reach zero (each substep gravity adds some that the constraints then take back), so this is how "settled" gets measured
+    fn settle_distance(body: &mut SoftBody, sas: &mut SpatialAccelerationStructure) -> f32 {
+        let before = body.positions.clone();
+        for _ in 0..60 {
+            body.step(1.0 / 60.0, sas);
+        }
+        return body.positions.iter().zip(before.iter()).map(|(a, b)| (a - b).magnitude()).fold(0.0, f32::max);
+    }
+
+    // pinned by one corner, the cloth swings around and ends up hanging with the opposite corner straight below the pin
+    #[test]
+    fn cloth_hanging_from_corner_settles() {
+        let mut cloth = SoftBody::cloth(i64vec3(0, 2_000_000, 0), 1.0, 1.0, 8, 8, 1.0, 0.0, 0.01, 0, 0);
+        cloth.pin(0);
+        cloth.damping = 0.995; // so the test doesn't have to wait long for it to stop swinging
+        let mut sas = SpatialAccelerationStructure::new();
+        for _ in 0..60 * 6 {
+            cloth.step(1.0 / 60.0, &mut sas);
+        }
+
+        let moved = settle_distance(&mut cloth, &mut sas);
+        assert!(moved < 0.005, "Cloth is still moving, a particle went {}m in a second.", moved);
+        let pin = cloth.positions[0];
+        assert_eq!(pin, vec3(-0.5, 0.0, 0.0));
+        let corner = cloth.positions[cloth.n_particles() - 1] - pin;
+        assert!(corner.x.abs() < 0.02 && corner.z.abs() < 0.02, "Far corner should hang straight below the pin, but it's {:?} from it.", corner);
+        assert!((corner.y + 2.0f32.sqrt()).abs() < 0.05, "Far corner should be the cloth's diagonal below the pin, but it's {}m.", -corner.y);
+    }
+
+    // a closed mesh with a volume constraint dropped on the floor should bounce a bit, then sit on it without squashing
+    #[test]
+    fn pressure_ball_settles_on_floor() {
+        let (vertices, indices) = Primitive::Icosphere { radius: 1.0, subdivisions: 1 }.generate();
+        let mesh = Mesh::from_vertices(vertices, indices, 0, 0, true);
+        let mesh_id = mesh.uuid;
+        LOADED_MESHES.lock().unwrap().insert(mesh_id, mesh);
+
+        let mut ball = SoftBody::from_mesh(mesh_id, i64vec3(0, 1_000_000, 0), vec3(1.0, 1.0, 1.0), 1.0, 0.0001, 0.01, Some(0.0));
+        let rest_volume = ball.volume_constraints[0].rest_volume;
+        assert!(rest_volume > 0.43 && rest_volume < 0.5236, "A 1m ball should have a volume a bit under 0.52m^3 (a sphere's), not {}.", rest_volume); // the icosphere's flat faces cut inside the sphere
+
+        ball.damping = 0.995;
+        let mut sas = floor_sas();
+        for _ in 0..60 * 6 {
+            ball.step(1.0 / 60.0, &mut sas);
+        }
+
+        let moved = settle_distance(&mut ball, &mut sas);
+        assert!(moved < 0.005, "Ball is still moving, a particle went {}m in a second.", moved);
+        let lowest = (0..ball.n_particles()).map(|p| ball.particle_pos(p).y).min().unwrap();
+        assert!(lowest >= 0 && lowest < 20_000, "Ball should be resting on the floor, but its lowest point is {}um up.", lowest);
+        let volume = ball.volume(&ball.triangles);
+        assert!((volume / rest_volume - 1.0).abs() < 0.05, "Ball squashed from {}m^3 to {}m^3.", rest_volume, volume);
+        LOADED_MESHES.lock().unwrap().remove(&mesh_id);
+    }
+
+    #[test]
+    fn unpinning_restores_mass() {
+        let mut cloth = SoftBody::new(0, i64vec3(0, 0, 0), vec![vec3(0.0, 0.0, 0.0); 4], Vec::new(), Vec::new(), 2.0);
+        for p in 0..4 {
+            cloth.pin(p);
+        }
+        cloth.unpin(2);
+        assert_eq!(cloth.inverse_masses, vec![0.0, 0.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn empty_body_steps() {
+        let mut empty = SoftBody::new(0, i64vec3(0, 0, 0), Vec::new(), Vec::new(), Vec::new(), 1.0);
+        empty.step(1.0 / 60.0, &mut floor_sas());
+        assert_eq!(empty.n_particles(), 0);
+    }
+}
@@ -1,7 +1,7 @@
 // based on https://www.cs.nmsu.edu/~joshagam/Solace/papers/master-writeup-print.pdf
 // used for fast broadphase collision detection, efficiently figuring out what could be colliding without doing expensive math
 
-// URGENT TODO: UPDATE SAS WHEN OBJECT MOVES/DIES, ACTUALLY SPLIT NODES
+// URGENT TODO: ACTUALLY SPLIT NODES (moved objects only grow their leaf, see update())
 // TODO: WE GOT RID OF GAMEOBJECT POINTERS, BUT IS IT FAST?
 // STILL HAS SOME UNSAFE CODE
 
@@ -15,7 +15,7 @@ use crate::{gameobjects::GameObject, transform::{Transform, ObjectTransform, i64
 
 pub struct SpatialAccelerationStructure { // the root is always index 0
     nodes: Vec<Node>,
-    obj_locations: HashMap<*mut dyn Collides, *mut Node>,
+    obj_locations: HashMap<*const (), (*mut Node, usize)>, // key is the object's address, value is the leaf it's in and its index in that leaf's vecs
     //free_indices: Vec<i32> // before extending nodes, this vec should be emptied
 }
 
@@ -37,11 +37,10 @@ impl SpatialAccelerationStructure {
                 aabb: bbox.clone(), 
                 parent: null_mut(),
                 children: Vec::new(), 
-                gameobject_refs: vec![obj],
-                gameobject_aabbs: vec![bbox],
+                gameobject_refs: Vec::new(),
+                gameobject_aabbs: Vec::new(),
                 cannot_split: false
             }); 
-            return;
         }
 
         
@@ -54,7 +53,7 @@ impl SpatialAccelerationStructure {
         loop {
             if n.children.is_empty() { // if we're at a leaf in the tree then the object goes in here
                 backtrace.push(n as *mut Node);
-                self.obj_locations.insert(obj.as_ptr(), n as *mut Node);
+                self.obj_locations.insert(obj.as_ptr() as *const (), (n as *mut Node, n.gameobject_refs.len()));
                 n.gameobject_refs.push(obj);
                 n.gameobject_aabbs.push(bbox.clone());
                 n.cannot_split = false;          
@@ -105,8 +104,44 @@ impl SpatialAccelerationStructure {
 
     }
 
-    pub fn remove(&mut self, obj: *mut dyn ObjectTransform) {
-        
+    // returns false if obj wasn't in the SAS. O(1), the leaf's last object gets moved into the removed one's place.
+    // node AABBs aren't shrunk, they'll just stay a bit too big until the next insert into them
+    pub fn remove(&mut self, obj: &Rc<RefCell<dyn Collides>>) -> bool {
+        let (node, i) = match self.obj_locations.remove(&(obj.as_ptr() as *const ())) {
+            Some(location) => location,
+            None => return false
+        };
+        let node = unsafe {&mut *node};
+        node.gameobject_refs.swap_remove(i);
+        node.gameobject_aabbs.swap_remove(i);
+        if i < node.gameobject_refs.len() {
+            self.obj_locations.get_mut(&(node.gameobject_refs[i].as_ptr() as *const ())).unwrap().1 = i;
+        }
+        node.cannot_split = false;
+        return true;
+    }
+
+    // call after obj moves (or gets teleported) so queries find it where it is now, instead of where it was inserted.
+    // like insert(), its leaf and every node above that grow to fit it. returns false if obj wasn't in the SAS
+    pub fn update(&mut self, obj: &Rc<RefCell<dyn Collides>>) -> bool {
+        let (node, i) = match self.obj_locations.get(&(obj.as_ptr() as *const ())) {
+            Some(location) => *location,
+            None => return false
+        };
+        let bbox = AABB::new(obj.borrow().transform());
+        let leaf = unsafe {&mut *node};
+        let old = &leaf.gameobject_aabbs[i];
+        if old.min == bbox.min && old.max == bbox.max {
+            return true;
+        }
+        leaf.gameobject_aabbs[i] = bbox.clone();
+        let mut n = node;
+        while !n.is_null() {
+            let ancestor = unsafe {&mut *n};
+            ancestor.aabb.fit(&bbox);
+            n = ancestor.parent;
+        }
+        return true;
     }
 
     // Querying is also when splitting nodes with too many objects happens
@@ -207,6 +242,14 @@ impl AABB {
         return Self { min: center - distance, max: center + distance, center};
     }
 
+    pub fn min(&self) -> I64Vec3 {
+        return self.min;
+    }
+
+    pub fn max(&self) -> I64Vec3 {
+        return self.max;
+    }
+
     fn center(min: I64Vec3, max: I64Vec3) -> I64Vec3 {
         return i64vec3(max.x - min.x/2, max.y - min.y/2, max.z - min.z/2);
     }
@@ -228,7 +271,7 @@ impl AABB {
         return self.min.x < other.min.x && self.min.y < other.min.y && self.min.z < other.min.z && self.max.x > other.max.x && self.max.y > other.max.y && self.max.z > other.max.z; 
     }
 
-    fn touches(&self, other: &AABB) -> bool { // returns true if self is touching other
+    pub fn touches(&self, other: &AABB) -> bool { // returns true if self is touching other
         return
             self.min.x < other.max.x &&
             self.max.x > other.min.x &&
@@ -0,0 +1,328 @@
+// Raycast vehicle: a RigidBody chassis held up by wheels that are just rays with springs on them, like most arcade/sim-lite racing games.
+// Each wheel casts down from its mount point, the suspension pushes the chassis up, and tyre friction curves turn wheel spin and sideways sliding into forces.
+// Everything gets applied to the chassis with impulse_at_pos, so call Vehicle::update() once per physics tick before do_physics().
+// Chassis local axes: -z is forward, +y is up, +x is right.
+
+use std::{rc::Rc, cell::RefCell};
+
+use glm::{Vec3, vec3, vec4, Quat, I64Vec3};
+
+use crate::transform::*;
+use crate::gameobjects::*;
+
+use super::SpatialAccelerationStructure;
+
+// how much grip a tyre has (as a multiple of the load on it) for a given amount of slip.
+// grip rises linearly up to the peak, then falls off to the asymptote once the tyre is really sliding. (same shape as most games use instead of the full Pacejka formula)
+#[derive(Clone, Copy, Debug)]
+pub struct TyreCurve {
+    pub peak_slip: f32,
+    pub peak_grip: f32,
+    pub asymptote_slip: f32,
+    pub asymptote_grip: f32,
+}
+
+impl TyreCurve {
+    pub fn evaluate(&self, slip: f32) -> f32 {
+        let slip = slip.abs();
+        if slip <= self.peak_slip {
+            return self.peak_grip * slip / self.peak_slip;
+        }
+        if slip >= self.asymptote_slip {
+            return self.asymptote_grip;
+        }
+        let t = (slip - self.peak_slip) / (self.asymptote_slip - self.peak_slip);
+        return self.peak_grip + (self.asymptote_grip - self.peak_grip) * t;
+    }
+
+    // slip here is the slip ratio, (wheel surface speed - ground speed) / ground speed
+    pub fn default_longitudinal() -> Self {
+        return Self { peak_slip: 0.1, peak_grip: 1.0, asymptote_slip: 0.8, asymptote_grip: 0.75 };
+    }
+
+    // slip here is the slip angle in radians
+    pub fn default_lateral() -> Self {
+        return Self { peak_slip: 0.15, peak_grip: 1.0, asymptote_slip: 0.6, asymptote_grip: 0.7 };
+    }
+}
+
+pub struct WheelContact {
+    pub pos: I64Vec3,
+    pub normal: Vec3,
+    pub load: f32, // newtons pushing the tyre into the ground
+    pub slip_ratio: f32,
+    pub slip_angle: f32,
+}
+
+pub struct Wheel {
+    pub mount_point: Vec3, // meters from the chassis' center in chassis space, where the top of the suspension is
+    pub radius: f32,
+    pub suspension_length: f32, // meters of travel at rest (no load)
+    pub spring_stiffness: f32, // N/m
+    pub damping: f32, // N/(m/s)
+    pub inertia: f32, // kg*m^2
+    pub max_steer_angle: f32, // radians, 0 for wheels that don't steer
+    pub powered: bool,
+    pub longitudinal_friction: TyreCurve,
+    pub lateral_friction: TyreCurve,
+
+    pub angular_velocity: f32, // radians/sec, positive rolls forward
+    pub rotation: f32, // radians, just for drawing
+    pub steer_angle: f32,
+    compression: f32, // meters the spring is compressed
+    contact: Option<WheelContact>,
+}
+
+impl Wheel {
+    pub fn new(mount_point: Vec3, radius: f32, max_steer_angle: f32, powered: bool) -> Self {
+        return Self {
+            mount_point,
+            radius,
+            suspension_length: 0.3,
+            spring_stiffness: 35000.0,
+            damping: 3000.0,
+            inertia: 1.5,
+            max_steer_angle,
+            powered,
+            longitudinal_friction: TyreCurve::default_longitudinal(),
+            lateral_friction: TyreCurve::default_lateral(),
+
+            angular_velocity: 0.0,
+            rotation: 0.0,
+            steer_angle: 0.0,
+            compression: 0.0,
+            contact: None,
+        };
+    }
+
+    pub fn contact(&self) -> Option<&WheelContact> {
+        return self.contact.as_ref();
+    }
+
+    pub fn grounded(&self) -> bool {
+        return self.contact.is_some();
+    }
+}
+
+// what the driver is doing this tick
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VehicleInput {
+    pub throttle: f32, // -1 (reverse) to 1
+    pub brake: f32, // 0 to 1
+    pub steering: f32, // -1 (left) to 1 (right)
+}
+
+pub struct Vehicle {
+    pub chassis: Rc<RefCell<dyn RigidBody>>,
+    pub wheels: Vec<Wheel>,
+    pub engine_torque: f32, // Nm, split between powered wheels
+    pub brake_torque: f32, // Nm per wheel
+    pub input: VehicleInput,
+}
+
+impl Vehicle {
+    pub fn new(chassis: Rc<RefCell<dyn RigidBody>>) -> Self {
+        return Self { chassis, wheels: Vec::new(), engine_torque: 1500.0, brake_torque: 3000.0, input: VehicleInput::default() };
+    }
+
+    // four wheels at the corners of a width x length rectangle under the chassis, steering at the front and driven at the back
+    pub fn four_wheeled(chassis: Rc<RefCell<dyn RigidBody>>, width: f32, length: f32, mount_height: f32, wheel_radius: f32) -> Self {
+        let mut vehicle = Vehicle::new(chassis);
+        for (x, z) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let front = z < 0.0;
+            let mount = vec3(x * width / 2.0, mount_height, z * length / 2.0);
+            vehicle.wheels.push(Wheel::new(mount, wheel_radius, if front {0.6} else {0.0}, !front));
+        }
+        return vehicle;
+    }
+
+    // forward speed of the chassis in m/s
+    pub fn speed(&self) -> f32 {
+        let chassis = self.chassis.borrow();
+        let forward = (chassis.transform().rotatemat() * vec4(0.0, 0.0, -1.0, 0.0)).xyz();
+        return vec3_from_i64vec3(&chassis.velocity()).dot(&forward);
+    }
+
+    // where to draw a wheel
+    pub fn wheel_transform(&self, wheel: usize) -> (I64Vec3, Quat) {
+        let chassis = self.chassis.borrow();
+        let w = &self.wheels[wheel];
+        let chassis_rot = chassis.transform().rot_quat();
+        let local_pos = w.mount_point - vec3(0.0, w.suspension_length - w.compression, 0.0);
+        let pos = chassis.transform().pos() + i64vec3_from_vec3(&glm::quat_rotate_vec3(&chassis_rot, &local_pos));
+        let rot = chassis_rot * glm::quat_angle_axis(w.steer_angle, &vec3(0.0, -1.0, 0.0)) * glm::quat_angle_axis(w.rotation, &vec3(-1.0, 0.0, 0.0));
+        return (pos, rot);
+    }
+
+    // dt is in seconds, normally 1/60 to match do_physics()
+    pub fn update(&mut self, sas: &mut SpatialAccelerationStructure, dt: f32) {
+        let chassis_ptr = self.chassis.as_ptr() as *const ();
+        let (chassis_pos, rotation, mass) = {
+            let chassis = self.chassis.borrow();
+            (chassis.transform().pos(), chassis.transform().rotatemat(), chassis.mass())
+        };
+        let up = (rotation * vec4(0.0, 1.0, 0.0, 0.0)).xyz();
+        let n_powered = self.wheels.iter().filter(|w| w.powered).count().max(1) as f32;
+
+        // suspension rays first, so we know how many wheels share the chassis' weight
+        for wheel in self.wheels.iter_mut() {
+            wheel.steer_angle = wheel.max_steer_angle * self.input.steering.clamp(-1.0, 1.0);
+            let mount = chassis_pos + i64vec3_from_vec3(&(rotation * vec4(wheel.mount_point.x, wheel.mount_point.y, wheel.mount_point.z, 1.0)).xyz());
+            let hit = sas.raycast(mount, -up, wheel.suspension_length + wheel.radius, Some(chassis_ptr));
+
+            let previous_compression = wheel.compression;
+            wheel.contact = None;
+            if hit.is_none() {
+                wheel.compression = 0.0;
+                continue;
+            }
+            let hit = hit.unwrap();
+            wheel.compression = (wheel.suspension_length + wheel.radius - hit.distance).max(0.0);
+            let spring_force = wheel.spring_stiffness * wheel.compression + wheel.damping * (wheel.compression - previous_compression) / dt;
+            wheel.contact = Some(WheelContact { pos: hit.pos, normal: hit.normal, load: spring_force.max(0.0), slip_ratio: 0.0, slip_angle: 0.0 });
+        }
+        let n_grounded = self.wheels.iter().filter(|w| w.grounded()).count().max(1) as f32;
+        // every wheel works from the velocity the chassis had at the start of the tick, otherwise the first wheel's impulse would change what the others see and the car would pull to one side
+        let (chassis_velocity, chassis_angular_velocity) = {
+            let chassis = self.chassis.borrow();
+            (vec3_from_i64vec3(&chassis.velocity()), chassis.angular_velocity())
+        };
+
+        for wheel in self.wheels.iter_mut() {
+            // engine and brakes act on the wheel's spin whether it's touching anything or not
+            let drive_torque = if wheel.powered {self.engine_torque * self.input.throttle.clamp(-1.0, 1.0) / n_powered} else {0.0};
+            wheel.angular_velocity += drive_torque / wheel.inertia * dt;
+            let brake_change = self.brake_torque * self.input.brake.clamp(0.0, 1.0) / wheel.inertia * dt;
+            if brake_change >= wheel.angular_velocity.abs() {
+                wheel.angular_velocity = 0.0; // locked up
+            }
+            else {
+                wheel.angular_velocity -= brake_change * wheel.angular_velocity.signum();
+            }
+
+            if wheel.contact.is_none() {
+                wheel.rotation += wheel.angular_velocity * dt;
+                continue;
+            }
+            let contact = wheel.contact.as_mut().unwrap();
+            let rel_pos = contact.pos - chassis_pos;
+
+            // the tyre's directions, flattened onto the ground
+            let steer = glm::rotation(wheel.steer_angle, &vec3(0.0, -1.0, 0.0));
+            let wheel_forward = (rotation * steer * vec4(0.0, 0.0, -1.0, 0.0)).xyz();
+            let forward = (wheel_forward - contact.normal * wheel_forward.dot(&contact.normal)).normalize();
+            let right = forward.cross(&contact.normal);
+
+            let ground_velocity = chassis_velocity + chassis_angular_velocity.cross(&vec3_from_i64vec3(&rel_pos));
+            let forward_speed = ground_velocity.dot(&forward);
+            let sideways_speed = ground_velocity.dot(&right);
+
+            // slip is unstable when barely moving, so pretend we're going at least walking pace
+            contact.slip_ratio = (wheel.angular_velocity * wheel.radius - forward_speed) / forward_speed.abs().max(3.0);
+            contact.slip_angle = sideways_speed.atan2(forward_speed.abs().max(3.0));
+
+            let mut longitudinal = wheel.longitudinal_friction.evaluate(contact.slip_ratio) * contact.load * contact.slip_ratio.signum();
+            let mut lateral = -wheel.lateral_friction.evaluate(contact.slip_angle) * contact.load * contact.slip_angle.signum();
+
+            // never push sideways harder than it takes to stop sliding, or the car would wobble back and forth when parked
+            let max_lateral = sideways_speed.abs() * mass / n_grounded / dt;
+            lateral = lateral.clamp(-max_lateral, max_lateral);
+
+            // friction circle: a tyre that's already spinning/braking hard has less grip left for cornering
+            let max_grip = wheel.longitudinal_friction.peak_grip.max(wheel.lateral_friction.peak_grip) * contact.load;
+            let total = (longitudinal * longitudinal + lateral * lateral).sqrt();
+            if total > max_grip {
+                longitudinal *= max_grip / total;
+                lateral *= max_grip / total;
+            }
+
+            // the ground pushes back on the tyre's spin too, can't overshoot rolling speed though
+            let rolling_angular_velocity = forward_speed / wheel.radius;
+            let new_angular_velocity = wheel.angular_velocity - longitudinal * wheel.radius / wheel.inertia * dt;
+            if (wheel.angular_velocity - rolling_angular_velocity).signum() != (new_angular_velocity - rolling_angular_velocity).signum() {
+                wheel.angular_velocity = rolling_angular_velocity;
+            }
+            else {
+                wheel.angular_velocity = new_angular_velocity;
+            }
+            wheel.rotation += wheel.angular_velocity * dt;
+
+            let force = up * contact.load + forward * longitudinal + right * lateral;
+            self.chassis.borrow_mut().impulse_at_pos(force * dt, rel_pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phys::{PhysBody, PhysicsMode, GRAVITY, do_physics};
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    // a 1200kg car parked just above a big flat floor, both in the SAS like the game would have them
+    fn car_on_flat_ground() -> (SpatialAccelerationStructure, Rc<RefCell<PhysBody>>, Vehicle) {
+        let floor = Rc::new(RefCell::new(PhysBody::new(ColliderType::Box)));
+        floor.borrow_mut().transform.setscl(vec3(200.0, 1.0, 200.0));
+        floor.borrow_mut().transform.setpos_meters(dvec3(0.0, -0.5, 0.0));
+
+        let chassis = Rc::new(RefCell::new(PhysBody::new(ColliderType::Box)));
+        chassis.borrow_mut().density = 300.0;
+        chassis.borrow_mut().transform.setscl(vec3(2.0, 0.5, 4.0));
+        chassis.borrow_mut().transform.setpos_meters(dvec3(0.0, 1.0, 0.0));
+
+        let mut sas = SpatialAccelerationStructure::new();
+        sas.insert(floor);
+        sas.insert(chassis.clone());
+        let vehicle = Vehicle::four_wheeled(chassis.clone(), 1.6, 3.0, -0.25, 0.35);
+        return (sas, chassis, vehicle);
+    }
+
+    fn drive(sas: &mut SpatialAccelerationStructure, chassis: &Rc<RefCell<PhysBody>>, vehicle: &mut Vehicle, seconds: f32) {
+        let rigidbodies: Vec<Rc<RefCell<dyn RigidBody>>> = vec![chassis.clone()];
+        for _ in 0..(seconds / DT) as u32 {
+            vehicle.update(sas, DT);
+            do_physics(sas, &rigidbodies, PhysicsMode::Float);
+        }
+    }
+
+    #[test]
+    fn parked_car_rests_on_suspension() {
+        let (mut sas, chassis, mut vehicle) = car_on_flat_ground();
+        drive(&mut sas, &chassis, &mut vehicle, 10.0);
+
+        // each spring holds a quarter of the weight, using the same gravity do_physics() does
+        let gravity = -GRAVITY as f32 * 60.0 / UNITS_PER_METER as f32;
+        let mass = chassis.borrow().mass();
+        for (i, wheel) in vehicle.wheels.iter().enumerate() {
+            let expected = mass * gravity / 4.0 / wheel.spring_stiffness;
+            assert!(wheel.grounded(), "Wheel {} isn't touching the floor.", i);
+            assert!((wheel.compression - expected).abs() < 0.002, "Wheel {} should be compressed {}m at rest, but it's {}m.", i, expected, wheel.compression);
+            assert!(wheel.angular_velocity.abs() < 0.01, "Wheel {} is still spinning at {}rad/s.", i, wheel.angular_velocity);
+        }
+        assert!(vehicle.speed().abs() < 0.01, "Parked car is moving at {}m/s.", vehicle.speed());
+
+        let chassis = chassis.borrow();
+        let expected_height = 0.25 + 0.3 + 0.35 - vehicle.wheels[0].compression; // chassis half height + suspension + wheel
+        assert!((vec3_from_i64vec3(&chassis.transform.pos()).y - expected_height).abs() < 0.005, "Chassis should be {}m up, but it's at {:?}.", expected_height, chassis.transform.pos());
+        assert!(chassis.transform.pos().x.abs() < 1000 && chassis.transform.pos().z.abs() < 1000, "Parked car drifted to {:?}.", chassis.transform.pos());
+    }
+
+    #[test]
+    fn throttle_accelerates_forward() {
+        let (mut sas, chassis, mut vehicle) = car_on_flat_ground();
+        drive(&mut sas, &chassis, &mut vehicle, 3.0);
+
+        vehicle.input.throttle = 1.0;
+        drive(&mut sas, &chassis, &mut vehicle, 1.0);
+        let speed_after_1s = vehicle.speed();
+        drive(&mut sas, &chassis, &mut vehicle, 1.0);
+        let speed_after_2s = vehicle.speed();
+
+        assert!(speed_after_1s > 0.3, "Car only got to {}m/s after a second of full throttle.", speed_after_1s);
+        assert!(speed_after_2s > speed_after_1s + 0.3, "Car went from {}m/s to {}m/s during the second second of full throttle.", speed_after_1s, speed_after_2s);
+        let pos = vec3_from_i64vec3(&chassis.borrow().transform.pos());
+        assert!(pos.z < -0.5 && pos.x.abs() < 0.05, "Car should have driven straight ahead (-z), but it's at {:?}.", pos);
+    }
+}
@@ -0,0 +1,187 @@
+// Standalone physics world that doesn't need a window or an OpenGL context, for servers, tools and the golden-file checks in golden.rs.
+// Build one with PhysicsWorldBuilder, step it, and take snapshots of it.
+
+use std::fmt::Write;
+
+use glm::{I64Vec3, Vec3, Quat, vec3};
+
+use crate::transform::*;
+use crate::gameobjects::*;
+
+use super::*;
+
+pub struct PhysicsWorld {
+    bodies: BodySet,
+    mode: PhysicsMode,
+    steps: u64, // how many times step() has been called
+}
+
+pub struct PhysicsWorldBuilder {
+    bodies: Vec<PhysBody>,
+    mode: PhysicsMode,
+}
+
+impl PhysicsWorldBuilder {
+    pub fn new() -> Self {
+        return Self { bodies: Vec::new(), mode: PhysicsMode::Float };
+    }
+
+    pub fn mode(mut self, mode: PhysicsMode) -> Self {
+        self.mode = mode;
+        return self;
+    }
+
+    // adds an anchored box whose top surface is at the given height
+    pub fn floor(mut self, top_height_meters: f64, size_meters: f32) -> Self {
+        let mut floor = PhysBody::new(ColliderType::Box);
+        floor.name = String::from("Floor");
+        floor.anchored = true;
+        floor.transform.setscl(vec3(size_meters, 1.0, size_meters));
+        floor.transform.setpos_meters(dvec3(0.0, top_height_meters - 0.5, 0.0));
+        self.bodies.push(floor);
+        return self;
+    }
+
+    pub fn body(mut self, body: PhysBody) -> Self {
+        self.bodies.push(body);
+        return self;
+    }
+
+    // panics if a convex body is in a PhysicsMode::Deterministic world, since that mode can't collide them
+    pub fn build(self) -> PhysicsWorld {
+        let mut set = BodySet::new();
+        for body in self.bodies {
+            if self.mode == PhysicsMode::Deterministic {
+                assert_deterministic_collider(body.get_collider_type());
+            }
+            set.insert(body);
+        }
+        return PhysicsWorld { bodies: set, mode: self.mode, steps: 0 };
+    }
+}
+
+impl PhysicsWorld {
+    pub fn bodies(&self) -> &BodySet {
+        return &self.bodies;
+    }
+
+    pub fn insert(&mut self, body: PhysBody) -> BodyHandle {
+        if self.mode == PhysicsMode::Deterministic {
+            assert_deterministic_collider(body.get_collider_type());
+        }
+        return self.bodies.insert(body);
+    }
+
+    pub fn mode(&self) -> PhysicsMode {
+        return self.mode;
+    }
+
+    pub fn steps(&self) -> u64 {
+        return self.steps;
+    }
+
+    // advances the simulation by one physics tick (1/60th of a second)
+    pub fn step(&mut self) {
+        match self.mode {
+            PhysicsMode::Float => do_physics_parallel(&self.bodies),
+            PhysicsMode::Deterministic => self.step_deterministic(),
+        }
+        self.steps += 1;
+    }
+
+    pub fn step_n(&mut self, n: u64) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+
+    // steps until condition returns true or max_steps is hit, returns whether condition was met
+    pub fn step_until(&mut self, max_steps: u64, mut condition: impl FnMut(&PhysicsWorld) -> bool) -> bool {
+        for _ in 0..max_steps {
+            self.step();
+            if condition(self) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // bodies are stepped one at a time in handle order so the result never depends on thread scheduling
+    fn step_deterministic(&mut self) {
+        let handles: Vec<BodyHandle> = self.bodies.handles().collect();
+        let aabbs: Vec<AABB> = handles.iter().map(|h| AABB::new(self.bodies.get(*h).read().unwrap().transform())).collect();
+        for i in handles.iter() {
+            let body_lock = self.bodies.get(*i);
+            let mut body = body_lock.write().unwrap();
+            if body.anchored {
+                continue;
+            }
+
+            let other_locks: Vec<_> = handles.iter().filter(|j| *j != i && aabbs[i.0].touches(&aabbs[j.0])).map(|j| self.bodies.get(*j)).collect();
+            let mut others: Vec<_> = other_locks.iter().map(|lock| lock.write().unwrap()).collect();
+            let mut others: Vec<Obstacle> = others.iter_mut().map(|other| if other.anchored {Obstacle::Anchored(&**other)} else {Obstacle::Moving(&mut **other)}).collect();
+            step_rigidbody_deterministic(&mut *body, &mut others);
+        }
+    }
+
+    pub fn snapshot(&self) -> PhysicsSnapshot {
+        let bodies = self.bodies.handles().map(|h| {
+            let body = self.bodies.get(h);
+            let body = body.read().unwrap();
+            return BodySnapshot {
+                name: body.name.clone(),
+                pos: body.transform.pos(),
+                rot: body.transform.rot_quat(),
+                velocity: body.velocity,
+                angular_velocity: body.angular_velocity,
+            };
+        }).collect();
+        return PhysicsSnapshot { steps: self.steps, bodies };
+    }
+
+    // puts every body back how it was when the snapshot was taken; bodies added since then are left alone
+    pub fn restore(&mut self, snapshot: &PhysicsSnapshot) {
+        for (i, saved) in snapshot.bodies.iter().enumerate() {
+            let body = self.bodies.get(BodyHandle(i));
+            let mut body = body.write().unwrap();
+            body.transform.setpos(saved.pos);
+            body.transform.set_rot_quat(saved.rot);
+            body.velocity = saved.velocity;
+            body.angular_velocity = saved.angular_velocity;
+        }
+        self.steps = snapshot.steps;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BodySnapshot {
+    pub name: String,
+    pub pos: I64Vec3, // micrometers
+    pub rot: Quat,
+    pub velocity: I64Vec3,
+    pub angular_velocity: Vec3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicsSnapshot {
+    pub steps: u64,
+    pub bodies: Vec<BodySnapshot>,
+}
+
+impl PhysicsSnapshot {
+    // one line per body, floats written as their exact bits so two snapshots only compare equal if they are bit-identical
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "steps {}", self.steps).unwrap();
+        for body in &self.bodies {
+            writeln!(text, "{} pos {} {} {} rot {:08x} {:08x} {:08x} {:08x} vel {} {} {} angvel {:08x} {:08x} {:08x}",
+                body.name.replace(' ', "_"),
+                body.pos.x, body.pos.y, body.pos.z,
+                body.rot.w.to_bits(), body.rot.i.to_bits(), body.rot.j.to_bits(), body.rot.k.to_bits(),
+                body.velocity.x, body.velocity.y, body.velocity.z,
+                body.angular_velocity.x.to_bits(), body.angular_velocity.y.to_bits(), body.angular_velocity.z.to_bits()
+            ).unwrap();
+        }
+        return text;
+    }
+}
@@ -0,0 +1,667 @@
+// glTF 2.0 importer, for .gltf files (with their buffers/images next to them or embedded as data: uris) and .glb files.
+// The gltf crate does the parsing (json, glb chunks, buffers and accessors), this maps what it reads onto the engine:
+// every mesh primitive becomes its own Mesh in LOADED_MESHES, plus the node tree, materials, base color textures, and optionally skins and animations.
+// GltfModel::spawn() then turns the node tree into entities, which can be done as many times as needed since the meshes are shared (and instanced).
+//
+// How glTF maps onto the engine:
+//  - every node becomes an entity with a Name and a Transform (LocalTransform under its parent). glTF is y up, right handed and in meters, same as us
+//  - meshes get scaled into 1m^3 like every Mesh, so each primitive is a child entity of its node whose LocalTransform scales it back (GltfPrimitive::offset)
+//  - a material's base color factor becomes the RenderComponent's color, and its base color texture the mesh's texture (1 layer, so texture_z 0).
+//    the rest of the PBR parameters are kept in PbrMaterial for whoever wants them, the world shader doesn't use them
+//  - skins become a Skeleton and skinned primitives get a SkeletalAnimator. glTF ignores a skinned mesh node's own transform and puts
+//    the mesh wherever the skeleton is, so skinned primitives are attached to the skeleton's root joint's parent node instead of their own node
+//  - animations become a SkeletalClip per skin for joint channels and an AnimationClip per node for the rest. CUBICSPLINE keyframes are played
+//    as catmull-rom through the keyframe values (the tangents are ignored), and morph target weights aren't supported
+// The extensions that change how data is stored (draco, meshopt, basisu textures) aren't supported either.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use glm::{Vec3, Vec4, Quat, Mat4, vec3, vec4};
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::animation::*;
+use crate::graphics::*;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(std::io::Error),
+    Invalid(String), // not a valid glTF file
+    Unsupported(String), // valid, but uses something this importer can't do
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Io(err) => write!(f, "{}", err),
+            GltfError::Invalid(message) => write!(f, "{}", message),
+            GltfError::Unsupported(message) => write!(f, "unsupported: {}", message),
+        }
+    }
+}
+
+fn invalid(message: String) -> GltfError {
+    return GltfError::Invalid(message);
+}
+
+// the gltf crate reports a required extension it doesn't have enabled as a validation error, which is Unsupported for us rather than Invalid
+fn gltf_error(err: gltf::Error) -> GltfError {
+    match err {
+        gltf::Error::Io(err) => GltfError::Io(err),
+        gltf::Error::Validation(ref errors) if errors.iter().any(|(_, e)| matches!(e, gltf::json::validation::Error::Unsupported)) => GltfError::Unsupported(err.to_string()),
+        gltf::Error::UnsupportedScheme => GltfError::Unsupported(err.to_string()),
+        err => GltfError::Invalid(err.to_string())
+    }
+}
+
+pub struct GltfImportOptions {
+    pub skins: bool, // if false skinned meshes are loaded in their bind pose as normal meshes
+    pub animations: bool,
+}
+
+impl GltfImportOptions {
+    pub fn new() -> Self {
+        return Self { skins: true, animations: true };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+    Opaque,
+    Mask(f32), // alpha cutoff
+    Blend,
+}
+
+#[derive(Clone, Debug)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_color: Vec4,
+    pub base_color_texture: Option<u32>, // engine texture id
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vec3,
+    // these are indices into GltfModel::images, they aren't loaded onto the gpu since nothing would use them
+    pub metallic_roughness_image: Option<usize>,
+    pub normal_image: Option<usize>,
+    pub occlusion_image: Option<usize>,
+    pub emissive_image: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl PbrMaterial {
+    // what glTF says to use for primitives without a material
+    pub fn default() -> Self {
+        return Self {
+            name: None,
+            base_color: vec4(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: vec3(0.0, 0.0, 0.0),
+            metallic_roughness_image: None,
+            normal_image: None,
+            occlusion_image: None,
+            emissive_image: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        };
+    }
+}
+
+pub struct GltfImage {
+    pub name: Option<String>,
+    pub path: Option<String>, // file it was loaded from, None if it was embedded
+    pub texture_id: Option<u32>, // only loaded if some material uses it as its base color
+}
+
+pub struct GltfPrimitive {
+    pub mesh_id: usize,
+    pub material: Option<usize>, // index into GltfModel::materials
+    pub offset: Transform, // undoes the mesh being scaled into 1m^3, relative to the node (or the skeleton's parent node for skinned ones)
+}
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub transform: Transform, // relative to the parent node
+    pub children: Vec<usize>,
+    pub parent: Option<usize>,
+    pub mesh: Option<usize>, // index of the mesh in the file
+    pub skin: Option<usize>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+pub struct GltfSkin {
+    pub name: Option<String>,
+    pub skeleton: Rc<Skeleton>,
+    pub joint_nodes: Vec<usize>, // node of each of the skeleton's joints, in the skeleton's order (not the file's)
+    pub root_parent: Option<usize>, // node skinned primitives are attached to, None for the model's root
+}
+
+pub struct GltfAnimation {
+    pub name: String,
+    pub node_clips: Vec<(usize, Rc<AnimationClip>)>, // (node, clip) for nodes that aren't joints
+    pub skin_clips: Vec<(usize, Rc<SkeletalClip>)>, // (skin, clip)
+}
+
+pub struct GltfModel {
+    pub path: String,
+    pub nodes: Vec<GltfNode>,
+    pub roots: Vec<usize>, // nodes in the scene that gets spawned
+    pub materials: Vec<PbrMaterial>,
+    pub images: Vec<GltfImage>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimation>,
+}
+
+// the entities GltfModel::spawn() made
+pub struct GltfInstance {
+    pub root: Entity,
+    pub nodes: Vec<Option<Entity>>, // per node, None for nodes that aren't in the spawned scene
+    pub primitives: Vec<(Option<usize>, Entity)>, // (skin, entity) for every primitive
+}
+
+// how the gltf crate's readers get at the bytes of a buffer
+fn buffer_bytes<'s>(buffers: &'s [gltf::buffer::Data]) -> impl Fn(gltf::Buffer) -> Option<&'s [u8]> + Clone + 's {
+    return move |buffer| buffers.get(buffer.index()).map(|data| data.0.as_slice());
+}
+
+// the file an image uri points at, None for data uris
+fn uri_path(uri: &str, directory: &Path) -> Option<String> {
+    if uri.starts_with("data:") {
+        return None;
+    }
+    let uri = urlencoding::decode(uri).map(|uri| uri.into_owned()).unwrap_or_else(|_| uri.to_string());
+    return Some(directory.join(uri).to_string_lossy().into_owned());
+}
+
+// glTF quaternions are x, y, z, w
+fn quat_from_xyzw(v: &[f32; 4]) -> Quat {
+    return Quat::new(v[3], v[0], v[1], v[2]);
+}
+
+fn node_transform(transform: gltf::scene::Transform) -> Transform {
+    match transform {
+        gltf::scene::Transform::Matrix { matrix } => return transform_from_matrix(&Mat4::from(matrix)),
+        gltf::scene::Transform::Decomposed { translation, rotation, scale } => {
+            return Transform::from_parts(i64vec3_from_vec3(&glm::make_vec3(&translation)), quat_from_xyzw(&rotation).normalize(), glm::make_vec3(&scale));
+        }
+    }
+}
+
+// splits a translation * rotation * scale matrix back into its parts. a mirrored matrix gets a negative x scale.
+// an axis scaled to 0 has no direction left to take the rotation from, so it gets the identity's
+fn transform_from_matrix(m: &Mat4) -> Transform {
+    let columns = [m.fixed_view::<3, 1>(0, 0).into_owned(), m.fixed_view::<3, 1>(0, 1).into_owned(), m.fixed_view::<3, 1>(0, 2).into_owned()];
+    let mut scale = vec3(columns[0].norm(), columns[1].norm(), columns[2].norm());
+    if glm::mat4_to_mat3(m).determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+    let axis = |i: usize| if scale[i] == 0.0 {glm::Mat3::identity().column(i).into_owned()} else {columns[i] / scale[i]};
+    let rotation = glm::Mat3::from_columns(&[axis(0), axis(1), axis(2)]);
+    let translation = m.fixed_view::<3, 1>(0, 3).into_owned();
+    return Transform::from_parts(i64vec3_from_vec3(&translation), glm::mat3_to_quat(&rotation).normalize(), scale);
+}
+
+// turns the triangle (or strip/fan) indices of a primitive into a plain triangle list. None for points and lines
+fn triangle_list(mode: gltf::mesh::Mode, indices: Vec<u32>) -> Option<Vec<u32>> {
+    match mode {
+        gltf::mesh::Mode::Triangles => Some(indices),
+        gltf::mesh::Mode::TriangleStrip => Some((2..indices.len()).flat_map(|i| if i % 2 == 0 {[indices[i - 2], indices[i - 1], indices[i]]} else {[indices[i - 1], indices[i - 2], indices[i]]}).collect()),
+        gltf::mesh::Mode::TriangleFan => Some((2..indices.len()).flat_map(|i| [indices[0], indices[i - 1], indices[i]]).collect()),
+        _ => None
+    }
+}
+
+// a track through an animation channel's keyframes. CUBICSPLINE has (in tangent, value, out tangent) per keyframe, and only the value is used
+fn keyframe_track<T: Animatable>(interpolation: Interpolation, times: &[f32], values: &[T], what: &str) -> Result<Track<T>, GltfError> {
+    let (values_per_key, value_at) = if interpolation == Interpolation::Cubic {(3, 1)} else {(1, 0)};
+    if values.len() < times.len() * values_per_key {
+        return Err(invalid(format!("{} has fewer values than keyframe times", what)));
+    }
+    return Ok(times.iter().enumerate().fold(Track::new(interpolation), |track, (k, t)| track.key(*t, values[k * values_per_key + value_at].clone())));
+}
+
+// what an animation channel animates, with its keyframe values
+enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+impl GltfModel {
+    // loads a .gltf or .glb, registering its meshes in LOADED_MESHES and its base color textures with graphics
+    pub fn load(path: &str, graphics: &mut GraphicsEngine, options: &GltfImportOptions) -> Result<GltfModel, GltfError> {
+        let bytes = std::fs::read(path).map_err(GltfError::Io)?;
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(&bytes).map_err(gltf_error)?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+        let buffers = gltf::import_buffers(&document, Some(directory), blob).map_err(gltf_error)?;
+
+        let mut model = GltfModel { path: path.to_string(), nodes: Vec::new(), roots: Vec::new(), materials: Vec::new(), images: Vec::new(), skins: Vec::new(), animations: Vec::new() };
+        for image in document.images() {
+            model.images.push(GltfImage { name: image.name().map(|n| n.to_string()), path: None, texture_id: None });
+        }
+
+        // materials, loading base color images as they're needed
+        for (i, material) in document.materials().enumerate() {
+            let metallic_roughness = material.pbr_metallic_roughness();
+            let mut pbr = PbrMaterial::default();
+            pbr.name = material.name().map(|n| n.to_string());
+            pbr.base_color = glm::make_vec4(&metallic_roughness.base_color_factor());
+            pbr.metallic = metallic_roughness.metallic_factor();
+            pbr.roughness = metallic_roughness.roughness_factor();
+            pbr.metallic_roughness_image = metallic_roughness.metallic_roughness_texture().map(|t| t.texture().source().index());
+            if let Some(texture) = metallic_roughness.base_color_texture() {
+                pbr.base_color_texture = Some(model.load_image(&buffers, texture.texture().source(), directory, graphics).map_err(|err| invalid(format!("materials[{}]: {}", i, err)))?);
+            }
+            pbr.emissive = glm::make_vec3(&material.emissive_factor());
+            pbr.normal_image = material.normal_texture().map(|t| t.texture().source().index());
+            pbr.occlusion_image = material.occlusion_texture().map(|t| t.texture().source().index());
+            pbr.emissive_image = material.emissive_texture().map(|t| t.texture().source().index());
+            pbr.alpha_mode = match material.alpha_mode() {
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5)),
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque
+            };
+            pbr.double_sided = material.double_sided();
+            model.materials.push(pbr);
+        }
+
+        // node tree. the gltf crate checks every index is in range, but not that the nodes make a tree
+        for node in document.nodes() {
+            model.nodes.push(GltfNode {
+                name: node.name().map(|n| n.to_string()),
+                transform: node_transform(node.transform()),
+                children: node.children().map(|child| child.index()).collect(),
+                parent: None,
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                skin: if options.skins {node.skin().map(|skin| skin.index())} else {None},
+                primitives: Vec::new(),
+            });
+        }
+        for i in 0..model.nodes.len() {
+            for child in model.nodes[i].children.clone() {
+                if model.nodes[child].parent.is_some() {
+                    return Err(invalid(format!("nodes[{}].children has {}, which already has a parent", i, child)));
+                }
+                model.nodes[child].parent = Some(i);
+            }
+        }
+        // every node has at most one parent now, but they could still go around in a circle
+        for i in 0..model.nodes.len() {
+            let (mut ancestor, mut n_steps) = (model.nodes[i].parent, 0);
+            while let Some(a) = ancestor {
+                n_steps += 1;
+                if n_steps > model.nodes.len() {
+                    return Err(invalid(format!("nodes[{}] is its own ancestor", i)));
+                }
+                ancestor = model.nodes[a].parent;
+            }
+        }
+        model.roots = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            None => (0..model.nodes.len()).filter(|i| model.nodes[*i].parent.is_none()).collect()
+        };
+        if let Some(root) = model.roots.iter().find(|root| model.nodes[**root].parent.is_some()) {
+            return Err(invalid(format!("node {} can't be a root of the scene", root)));
+        }
+
+        // skins, before meshes since skinned meshes' joint numbers change to the skeleton's order
+        let mut joint_remaps = Vec::new(); // per skin, file joint index -> skeleton joint index
+        if options.skins {
+            for skin in document.skins() {
+                let (gltf_skin, remap) = model.read_skin(&buffers, &skin)?;
+                model.skins.push(gltf_skin);
+                joint_remaps.push(remap);
+            }
+        }
+
+        // meshes, one Mesh per primitive and per skin it's used with
+        let meshes: Vec<gltf::Mesh> = document.meshes().collect();
+        let mut made: HashMap<(usize, Option<usize>), Vec<(usize, Option<usize>, Mat4)>> = HashMap::new();
+        for n in 0..model.nodes.len() {
+            let mesh = match model.nodes[n].mesh {
+                Some(mesh) => mesh,
+                None => continue
+            };
+            let skin = model.nodes[n].skin;
+            if !made.contains_key(&(mesh, skin)) {
+                let mut primitives = Vec::new();
+                for primitive in meshes[mesh].primitives() {
+                    let what = format!("meshes[{}].primitives[{}]", mesh, primitive.index());
+                    let remap = skin.map(|skin| joint_remaps[skin].as_slice());
+                    if let Some(primitive) = model.read_primitive(&buffers, &primitive, remap, graphics, &what)? {
+                        primitives.push(primitive);
+                    }
+                }
+                made.insert((mesh, skin), primitives);
+            }
+            for (mesh_id, material, normalization) in made[&(mesh, skin)].iter() {
+                let offset = normalization_offset(normalization);
+                model.nodes[n].primitives.push(GltfPrimitive { mesh_id: *mesh_id, material: *material, offset });
+            }
+        }
+
+        if options.animations {
+            for animation in document.animations() {
+                let gltf_animation = model.read_animation(&buffers, &animation)?;
+                model.animations.push(gltf_animation);
+            }
+        }
+        return Ok(model);
+    }
+
+    // loads an image onto the gpu (once) and returns its texture id
+    fn load_image(&mut self, buffers: &[gltf::buffer::Data], image: gltf::Image, directory: &Path, graphics: &mut GraphicsEngine) -> Result<u32, GltfError> {
+        let index = image.index();
+        if let Some(id) = self.images[index].texture_id {
+            return Ok(id);
+        }
+        let (bytes, path) = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let bytes = buffers[view.buffer().index()].get(view.offset()..view.offset() + view.length()).ok_or_else(|| invalid(format!("images[{}] goes past the end of its buffer", index)))?;
+                (bytes.to_vec(), None)
+            }
+            // stb_image decodes the texture, so the uri is read the way buffer uris are instead of letting the gltf crate decode it
+            gltf::image::Source::Uri { uri, .. } => (gltf::buffer::Data::from_source(gltf::buffer::Source::Uri(uri), Some(directory)).map_err(gltf_error)?.0, uri_path(uri, directory))
+        };
+        // the world shader samples a texture array, so a plain image is an array with 1 layer
+        let id = match path.as_deref().and_then(|path| graphics.texture_id_from_path(path)) {
+            Some(id) => id,
+            None => graphics.load_texture_from_memory(&bytes, TextureType::TexArray2D, path.as_deref()).map_err(|err| invalid(format!("images[{}] couldn't be loaded: {}", index, err)))?.0
+        };
+        self.images[index].path = path;
+        self.images[index].texture_id = Some(id);
+        return Ok(id);
+    }
+
+    // returns the skin and what each of the file's joint numbers is in the skeleton
+    fn read_skin(&self, buffers: &[gltf::buffer::Data], skin: &gltf::Skin) -> Result<(GltfSkin, Vec<u16>), GltfError> {
+        let what = format!("skins[{}]", skin.index());
+        let joint_nodes: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+        if joint_nodes.is_empty() {
+            return Err(invalid(format!("{} has no joints", what)));
+        }
+        if joint_nodes.len() > MAX_JOINTS {
+            return Err(GltfError::Unsupported(format!("{} has {} joints, at most {} are supported", what, joint_nodes.len(), MAX_JOINTS)));
+        }
+        let inverse_binds: Vec<[[f32; 4]; 4]> = skin.reader(buffer_bytes(buffers)).read_inverse_bind_matrices().map(|matrices| matrices.collect()).unwrap_or_default();
+
+        // skeletons need parents before children, so sort the joints by how deep they are in the tree
+        let depth = |mut node: usize| {
+            let mut depth = 0;
+            while let Some(parent) = self.nodes[node].parent {
+                node = parent;
+                depth += 1;
+            }
+            return depth;
+        };
+        let mut order: Vec<usize> = (0..joint_nodes.len()).collect();
+        order.sort_by_key(|j| depth(joint_nodes[*j]));
+        let mut remap = vec![0u16; joint_nodes.len()];
+        for (skeleton_index, j) in order.iter().enumerate() {
+            remap[*j] = skeleton_index as u16;
+        }
+
+        let mut joints = Vec::new();
+        let mut root_parent = None;
+        for (skeleton_index, j) in order.iter().enumerate() {
+            let node = &self.nodes[joint_nodes[*j]];
+            // the closest ancestor that's also a joint
+            let mut ancestor = node.parent;
+            while ancestor.is_some() && !joint_nodes.contains(&ancestor.unwrap()) {
+                ancestor = self.nodes[ancestor.unwrap()].parent;
+            }
+            let parent = ancestor.map(|a| remap[joint_nodes.iter().position(|n| *n == a).unwrap()] as usize);
+            if parent.is_none() && skeleton_index == 0 {
+                root_parent = node.parent;
+            }
+            joints.push(Joint {
+                name: node.name.clone().unwrap_or_else(|| format!("joint{}", j)),
+                parent,
+                rest: JointTransform { translation: vec3_from_i64vec3(&node.transform.pos()), rotation: node.transform.rot_quat(), scale: node.transform.scl() },
+                inverse_bind: inverse_binds.get(*j).map(|m| Mat4::from(*m)).unwrap_or_else(glm::identity),
+            });
+        }
+
+        let skeleton = Skeleton::new(joints);
+        let sorted_nodes = order.iter().map(|j| joint_nodes[*j]).collect();
+        let name = skin.name().map(|n| n.to_string());
+        return Ok((GltfSkin { name, skeleton: Rc::new(skeleton), joint_nodes: sorted_nodes, root_parent }, remap));
+    }
+
+    // None for primitives that aren't triangles. joint_remap is Some for primitives of a skinned node
+    fn read_primitive(&self, buffers: &[gltf::buffer::Data], primitive: &gltf::Primitive, joint_remap: Option<&[u16]>, graphics: &GraphicsEngine, what: &str) -> Result<Option<(usize, Option<usize>, Mat4)>, GltfError> {
+        let reader = primitive.reader(buffer_bytes(buffers));
+        let positions: Vec<[f32; 3]> = reader.read_positions().ok_or_else(|| invalid(format!("{} has no POSITION", what)))?.collect();
+        let n_vertices = positions.len();
+        if n_vertices == 0 {
+            return Ok(None);
+        }
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+        let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect());
+        if normals.as_ref().is_some_and(|n| n.len() != n_vertices) || uvs.as_ref().is_some_and(|t| t.len() != n_vertices) {
+            return Err(invalid(format!("{} doesn't have a NORMAL and TEXCOORD_0 for every vertex", what)));
+        }
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..n_vertices as u32).collect()
+        };
+        if indices.iter().any(|i| *i as usize >= n_vertices) {
+            return Err(invalid(format!("{} has an index past its {} vertices", what, n_vertices)));
+        }
+        let indices = match triangle_list(primitive.mode(), indices) {
+            Some(indices) => indices,
+            None => {
+                println!("Skipped {} of {}, since it's points or lines", what, self.path);
+                return Ok(None);
+            }
+        };
+
+        let material = primitive.material().index();
+        let texture_id = material.and_then(|m| self.materials[m].base_color_texture).unwrap_or(0);
+
+        // skinned primitives are put together in the gpu layout, with joints and weights after each vertex, so they stay with their vertices through prepare_imported_mesh()
+        let skin = match (joint_remap, reader.read_joints(0), reader.read_weights(0)) {
+            (Some(remap), Some(joints), Some(weights)) => {
+                let joints: Vec<[u16; 4]> = joints.into_u16().collect();
+                let weights: Vec<[f32; 4]> = weights.into_f32().collect();
+                if joints.len() != n_vertices || weights.len() != n_vertices {
+                    return Err(invalid(format!("{} doesn't have joints and weights for every vertex", what)));
+                }
+                let mut skin = Vec::with_capacity(n_vertices * 8);
+                for v in 0..n_vertices {
+                    let mut joint = [0.0f32; 4];
+                    for k in 0..4 {
+                        joint[k] = *remap.get(joints[v][k] as usize).ok_or_else(|| invalid(format!("{} uses joint {}, which its skin doesn't have", what, joints[v][k])))? as f32;
+                    }
+                    let total: f32 = weights[v].iter().sum();
+                    let weight = if total > 0.0 {weights[v].map(|w| w / total)} else {weights[v]};
+                    skin.extend_from_slice(&joint);
+                    skin.extend_from_slice(&weight);
+                }
+                Some(skin)
+            }
+            _ => None
+        };
+        let floats_per_vertex = if skin.is_some() {N_FLOATS_PER_SKINNED_VERTEX} else {N_FLOATS_PER_VERTEX};
+
+        // glTF's uv (0, 0) is the top left of the image, which is also the first row we upload, so uvs don't need flipping
+        let mut vertices = Vec::with_capacity(n_vertices * floats_per_vertex);
+        for v in 0..n_vertices {
+            vertices.extend_from_slice(&positions[v]);
+            vertices.extend_from_slice(&normals.as_ref().map(|normals| normals[v]).unwrap_or([0.0, 0.0, 0.0]));
+            vertices.extend_from_slice(&uvs.as_ref().map(|uvs| uvs[v]).unwrap_or([0.0, 0.0]));
+            if let Some(skin) = &skin {
+                vertices.extend_from_slice(&skin[v * 8..v * 8 + 8]);
+            }
+        }
+        // the spec says primitives without normals get flat ones
+        let (vertices, indices) = prepare_imported_mesh(&vertices, &indices, floats_per_vertex, normals.is_some(), uvs.is_some(), GeneratedNormals::Flat);
+
+        let mesh = match skin {
+            Some(_) => {
+                let (vertices, joints, weights) = split_skinned_vertices(&vertices);
+                Mesh::skinned(vertices, indices, joints, weights, texture_id, graphics.skinned_shader_id)
+            }
+            None => Mesh::from_vertices(vertices, indices, texture_id, graphics.world_shader_id, false)
+        };
+
+        let result = (mesh.uuid, material, mesh.normalization);
+        LOADED_MESHES.lock().unwrap().insert(mesh.uuid, mesh);
+        return Ok(Some(result));
+    }
+
+    fn read_animation(&self, buffers: &[gltf::buffer::Data], animation: &gltf::Animation) -> Result<GltfAnimation, GltfError> {
+        let name = animation.name().map(|n| n.to_string()).unwrap_or_else(|| format!("animation{}", animation.index()));
+        let mut node_clips: HashMap<usize, AnimationClip> = HashMap::new();
+        let mut skin_clips: HashMap<usize, SkeletalClip> = HashMap::new();
+
+        for channel in animation.channels() {
+            let what = format!("animations[{}].channels[{}]", animation.index(), channel.index());
+            let node = channel.target().node().index();
+            let reader = channel.reader(buffer_bytes(buffers));
+            let times: Vec<f32> = reader.read_inputs().ok_or_else(|| invalid(format!("{} has no input", what)))?.collect();
+            let values = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::Translations(translations)) => ChannelValues::Translation(translations.map(|t| glm::make_vec3(&t)).collect()),
+                Some(gltf::animation::util::ReadOutputs::Rotations(rotations)) => ChannelValues::Rotation(rotations.into_f32().map(|r| quat_from_xyzw(&r).normalize()).collect()),
+                Some(gltf::animation::util::ReadOutputs::Scales(scales)) => ChannelValues::Scale(scales.map(|s| glm::make_vec3(&s)).collect()),
+                Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(_)) => continue,
+                None => return Err(invalid(format!("{} has no output", what)))
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::Cubic,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear
+            };
+
+            // joints of a skin go in that skin's clip, everything else in the node's own
+            let skins: Vec<usize> = (0..self.skins.len()).filter(|s| self.skins[*s].joint_nodes.contains(&node)).collect();
+            for skin in skins.iter() {
+                let joint = self.skins[*skin].joint_nodes.iter().position(|n| *n == node).unwrap();
+                let clip = skin_clips.entry(*skin).or_insert_with(|| SkeletalClip::new(&name, LoopMode::Loop));
+                let channel_index = match clip.channels.iter().position(|c| c.joint == joint) {
+                    Some(i) => i,
+                    None => {
+                        clip.channels.push(JointChannel::new(joint));
+                        clip.channels.len() - 1
+                    }
+                };
+                let joint_channel = &mut clip.channels[channel_index];
+                match &values {
+                    ChannelValues::Translation(translations) => joint_channel.translation = Some(keyframe_track(interpolation, &times, translations, &what)?),
+                    ChannelValues::Rotation(rotations) => joint_channel.rotation = Some(keyframe_track(interpolation, &times, rotations, &what)?),
+                    ChannelValues::Scale(scales) => joint_channel.scale = Some(keyframe_track(interpolation, &times, scales, &what)?),
+                }
+            }
+            if skins.is_empty() {
+                let clip = node_clips.entry(node).or_insert_with(|| AnimationClip::new(LoopMode::Loop));
+                match &values {
+                    ChannelValues::Translation(translations) => clip.position = Some(keyframe_track(interpolation, &times, &translations.iter().map(i64vec3_from_vec3).collect::<Vec<_>>(), &what)?),
+                    ChannelValues::Rotation(rotations) => clip.rotation = Some(keyframe_track(interpolation, &times, rotations, &what)?),
+                    ChannelValues::Scale(scales) => clip.scale = Some(keyframe_track(interpolation, &times, scales, &what)?),
+                }
+            }
+        }
+
+        let mut node_clips: Vec<(usize, Rc<AnimationClip>)> = node_clips.into_iter().map(|(node, clip)| (node, Rc::new(clip))).collect();
+        let mut skin_clips: Vec<(usize, Rc<SkeletalClip>)> = skin_clips.into_iter().map(|(skin, clip)| (skin, Rc::new(clip))).collect();
+        node_clips.sort_by_key(|(node, _)| *node);
+        skin_clips.sort_by_key(|(skin, _)| *skin);
+        return Ok(GltfAnimation { name, node_clips, skin_clips });
+    }
+
+    pub fn animation_index(&self, name: &str) -> Option<usize> {
+        return self.animations.iter().position(|animation| animation.name == name);
+    }
+
+    // makes an entity for the model at transform, with the scene's nodes (and their primitives) under it
+    pub fn spawn(&self, world: &mut World, transform: &Transform) -> GltfInstance {
+        let root = world.build_entity().with(transform.clone()).with(Name(Path::new(&self.path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default())).build();
+        let mut instance = GltfInstance { root, nodes: vec![None; self.nodes.len()], primitives: Vec::new() };
+        for node in self.roots.iter() {
+            self.spawn_node(world, *node, root, &mut instance);
+        }
+
+        // skinned primitives go with their skeleton, so they need every node spawned first
+        for (n, node) in self.nodes.iter().enumerate() {
+            if instance.nodes[n].is_none() {
+                continue;
+            }
+            let skin = node.skin.filter(|skin| *skin < self.skins.len());
+            for primitive in node.primitives.iter() {
+                let parent = match skin {
+                    Some(skin) => self.skins[skin].root_parent.and_then(|p| instance.nodes[p]).unwrap_or(root),
+                    None => instance.nodes[n].unwrap()
+                };
+                let material = primitive.material.map(|m| &self.materials[m]);
+                let mut render = RenderComponent::new(primitive.mesh_id);
+                render.set_rgba(material.map(|m| m.base_color).unwrap_or(vec4(1.0, 1.0, 1.0, 1.0)));
+                render.set_texture_z(if material.is_some_and(|m| m.base_color_texture.is_some()) {0.0} else {-1.0});
+
+                let entity = world.spawn();
+                world.set_parent_local(entity, parent, primitive.offset.clone());
+                world.insert(entity, render);
+                if let Some(skin) = skin {
+                    world.insert(entity, SkeletalAnimator::new(self.skins[skin].skeleton.clone()));
+                }
+                instance.primitives.push((skin, entity));
+            }
+        }
+        return instance;
+    }
+
+    fn spawn_node(&self, world: &mut World, node: usize, parent: Entity, instance: &mut GltfInstance) {
+        let entity = world.spawn();
+        world.set_parent_local(entity, parent, self.nodes[node].transform.clone());
+        if let Some(name) = &self.nodes[node].name {
+            world.insert(entity, Name(name.clone()));
+        }
+        instance.nodes[node] = Some(entity);
+        for child in self.nodes[node].children.iter() {
+            self.spawn_node(world, *child, entity, instance);
+        }
+    }
+
+    // starts an animation on a spawned instance: an Animator on every animated node, and the skin's clip on every SkeletalAnimator
+    pub fn play_animation(&self, world: &mut World, instance: &GltfInstance, animation: usize) {
+        let animation = &self.animations[animation];
+        for (node, clip) in animation.node_clips.iter() {
+            if let Some(entity) = instance.nodes[*node] {
+                match world.get_mut::<Animator>(entity) {
+                    Some(animator) => animator.play(clip.clone()),
+                    None => {world.insert(entity, Animator::with_clip(clip.clone()));}
+                }
+            }
+        }
+        for (skin, clip) in animation.skin_clips.iter() {
+            for (primitive_skin, entity) in instance.primitives.iter() {
+                if *primitive_skin == Some(*skin) {
+                    if let Some(animator) = world.get_mut::<SkeletalAnimator>(*entity) {
+                        animator.play(clip.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_scale_matrix_splits_into_finite_parts() {
+        let turned = glm::rotate_y(&Mat4::identity(), std::f32::consts::FRAC_PI_2);
+        let flattened = glm::scale(&glm::translate(&turned, &vec3(1.0, 2.0, 3.0)), &vec3(2.0, 0.0, 1.0));
+        let transform = transform_from_matrix(&flattened);
+        let (rotation, scale) = (transform.rot_quat(), transform.scl());
+        assert!(rotation.coords.iter().chain(scale.iter()).all(|f| f.is_finite()), "expected a finite rotation and scale, got {:?} and {:?}", rotation, scale);
+        assert!((scale - vec3(2.0, 0.0, 1.0)).norm() < 1e-5, "expected a scale of (2, 0, 1), got {:?}", scale);
+        // the axes that weren't squashed keep the quarter turn
+        let x = glm::quat_rotate_vec3(&rotation, &vec3(1.0, 0.0, 0.0));
+        assert!((x - vec3(0.0, 0.0, -1.0)).norm() < 1e-3, "expected x to be turned to -z, but it's at {:?}", x);
+    }
+}
@@ -0,0 +1,285 @@
+// Headless tests for the model importers. Meshes and textures go to a RecordingDevice, so no window or gpu is needed.
+// models/gltf_test.gltf and models/gltf_test.glb are the same small model: a red cube with a checkered cube on top of it (node hierarchy, materials,
+// an embedded png), and a bar skinned to two joints that are listed child first in the file, with an animation that bends it and moves the red cube.
+// models/obj_test.obj has a textured cube, a tetrahedron without normals or uvs and a floor whose material isn't in models/obj_test.mtl.
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::animation::*;
+use crate::graphics::*;
+use crate::scene::*;
+
+fn load_test_model(path: &str) -> GltfModel {
+    let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    return GltfModel::load(path, &mut graphics, &GltfImportOptions::new()).unwrap_or_else(|err| panic!("Couldn't load {}: {}", path, err));
+}
+
+fn close(a: f32, b: f32) -> bool {
+    return (a - b).abs() < 1e-4;
+}
+
+// the corners of the box around a spawned mesh's vertices, in meters
+fn world_bounds(world: &World, entity: Entity, mesh_id: usize) -> (glm::Vec3, glm::Vec3) {
+    let transform = world.get::<Transform>(entity).unwrap();
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let (mut min, mut max) = (glm::vec3(f32::MAX, f32::MAX, f32::MAX), glm::vec3(f32::MIN, f32::MIN, f32::MIN));
+    for vertex in meshes[&mesh_id].vertices.chunks_exact(N_FLOATS_PER_VERTEX) {
+        let position = vec3_from_i64vec3(&transform.pos()) + glm::quat_rotate_vec3(&transform.rot_quat(), &glm::vec3(vertex[0], vertex[1], vertex[2]).component_mul(&transform.scl()));
+        min = min.inf(&position);
+        max = max.sup(&position);
+    }
+    return (min, max);
+}
+
+fn close_bounds(bounds: (glm::Vec3, glm::Vec3), min: glm::Vec3, max: glm::Vec3) -> bool {
+    return (bounds.0 - min).abs().max() < 1e-3 && (bounds.1 - max).abs().max() < 1e-3;
+}
+
+fn mesh_counts(model: &GltfModel) -> Vec<(usize, usize)> {
+    let meshes = LOADED_MESHES.lock().unwrap();
+    return model.nodes.iter().flat_map(|node| node.primitives.iter()).map(|primitive| (meshes[&primitive.mesh_id].vertices.len(), meshes[&primitive.mesh_id].indices.len())).collect();
+}
+
+#[test]
+fn gltf_and_glb_match() {
+    let gltf = load_test_model("models/gltf_test.gltf");
+    let glb = load_test_model("models/gltf_test.glb");
+    let names = |model: &GltfModel| model.nodes.iter().map(|node| node.name.clone().unwrap_or_default()).collect::<Vec<String>>();
+    assert!(names(&gltf) == vec!["Base", "Top", "Bar", "Root", "Tip"] && names(&gltf) == names(&glb), "expected nodes Base, Top, Bar, Root and Tip, but the .gltf has {:?} and the .glb {:?}", names(&gltf), names(&glb));
+    assert!(gltf.roots == vec![0, 2, 3] && gltf.nodes[0].children == vec![1] && gltf.nodes[1].parent == Some(0), "expected roots [0, 2, 3] with Top under Base, but roots are {:?} and Base's children {:?}", gltf.roots, gltf.nodes[0].children);
+    let (gltf_counts, glb_counts) = (mesh_counts(&gltf), mesh_counts(&glb));
+    let expected = vec![(24 * N_FLOATS_PER_VERTEX, 36), (24 * N_FLOATS_PER_VERTEX, 36), (60 * N_FLOATS_PER_VERTEX, 60)]; // the bar gets a vertex per corner for its flat normals
+    assert!(gltf_counts == expected && glb_counts == expected, "expected (n floats, n indices) {:?}, but the .gltf gave {:?} and the .glb {:?}", expected, gltf_counts, glb_counts);
+    let top = &gltf.nodes[1].transform;
+    assert!(close(top.scl().x, 0.8) && top.pos() == i64vec3(0, 1000000, 0), "expected Top to be 1m above Base and scaled 0.8, but it's at {:?} scaled {:?}", top.pos(), top.scl());
+}
+
+#[test]
+fn gltf_materials() {
+    let model = load_test_model("models/gltf_test.gltf");
+    assert!(model.materials.len() == 3, "expected 3 materials, got {}", model.materials.len());
+    let (red, checker, green) = (&model.materials[0], &model.materials[1], &model.materials[2]);
+    assert!(red.base_color == glm::vec4(1.0, 0.0, 0.0, 1.0) && red.metallic == 0.0 && red.roughness == 0.5 && red.base_color_texture.is_none(), "Red material came out as {:?}", red);
+    assert!(checker.base_color_texture.is_some() && checker.base_color_texture == model.images[0].texture_id && model.images[0].path.is_none() && checker.double_sided && close(checker.emissive.z, 0.3), "Checker material should use the embedded image as its texture, but came out as {:?}", checker);
+    assert!(green.alpha_mode == AlphaMode::Mask(0.25), "Green material should be alpha masked at 0.25, but it's {:?}", green.alpha_mode);
+
+    // the textured cube's mesh uses the texture, the others don't have one
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let textures: Vec<u32> = model.nodes.iter().flat_map(|node| node.primitives.iter()).map(|primitive| meshes[&primitive.mesh_id].texture_id).collect();
+    assert!(textures == vec![0, checker.base_color_texture.unwrap(), 0], "expected only the checkered cube's mesh to have a texture, but their textures are {:?}", textures);
+}
+
+#[test]
+fn gltf_skin_joints_are_reordered() {
+    let model = load_test_model("models/gltf_test.gltf");
+    let skin = model.skins.get(0).expect("the skin wasn't loaded");
+    let joints: Vec<(String, Option<usize>)> = skin.skeleton.joints().iter().map(|joint| (joint.name.clone(), joint.parent)).collect();
+    assert!(joints == vec![(String::from("Root"), None), (String::from("Tip"), Some(0))] && skin.joint_nodes == vec![3, 4], "expected the skeleton to be Root then Tip (nodes 3 and 4), but it's {:?} (nodes {:?})", joints, skin.joint_nodes);
+    // in the bind pose every joint matrix is the identity
+    for (i, matrix) in skin.skeleton.joint_matrices(&skin.skeleton.rest_pose()).iter().enumerate() {
+        assert!((matrix - glm::Mat4::identity()).abs().max() <= 1e-5, "joint {}'s matrix in the bind pose isn't the identity: {:?}", i, matrix);
+    }
+
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let bar = &meshes[&model.nodes[2].primitives[0].mesh_id];
+    let skin_data = bar.skin.as_ref().expect("the bar mesh isn't skinned");
+    // the bottom ring only follows Root, the top ring only Tip. vertices get reordered on import, so they're found by height (-0.5 and 0.5 scaled into range)
+    let joint_weight = |v: usize, joint: u16| (0..4).filter(|k| skin_data.joints[v][*k] == joint).map(|k| skin_data.weights[v][k]).sum::<f32>();
+    for (v, vertex) in bar.vertices.chunks_exact(N_FLOATS_PER_VERTEX).enumerate() {
+        let expected = if close(vertex[1], -0.5) {(0, 1.0)} else if close(vertex[1], 0.5) {(1, 1.0)} else {(1, 0.5)};
+        assert!(close(joint_weight(v, expected.0), expected.1), "expected the bar's vertex at height {} to follow joint {} by {}, but it has joints {:?} and weights {:?}", vertex[1], expected.0, expected.1, skin_data.joints[v], skin_data.weights[v]);
+    }
+    // the file has no normals for the bar, so it gets flat ones like the spec says. the bottom cap's point straight down
+    assert!(bar.vertices.chunks_exact(N_FLOATS_PER_VERTEX).any(|v| close(v[1], -0.5) && close(v[4], -1.0)), "expected the bar's bottom cap to get flat normals pointing straight down");
+}
+
+#[test]
+fn gltf_animation() {
+    let model = load_test_model("models/gltf_test.gltf");
+    let index = model.animation_index("Bend").expect("there's no Bend animation");
+    let animation = &model.animations[index];
+    assert!(animation.node_clips.len() == 1 && animation.node_clips[0].0 == 0 && animation.skin_clips.len() == 1, "expected one clip for the Base node and one for the skin, got {} node clips and {} skin clips", animation.node_clips.len(), animation.skin_clips.len());
+    let position = animation.node_clips[0].1.position.as_ref().expect("the Base clip doesn't move it");
+    assert!(position.interpolation == Interpolation::Step && position.sample(0.5) == Some(i64vec3(-1000000, 0, -4000000)), "expected Base to step from its starting position, but at 0.5s it's at {:?}", position.sample(0.5));
+
+    let skeleton = &model.skins[0].skeleton;
+    let mut pose = skeleton.rest_pose();
+    animation.skin_clips[0].1.sample_into(0.5, &mut pose);
+    let tip = skeleton.joint_index("Tip").expect("the skeleton has no Tip");
+    let rotated = glm::quat_rotate_vec3(&pose.joints[tip].rotation, &glm::vec3(1.0, 0.0, 0.0));
+    assert!(close(rotated.x, 0.5f32.sqrt()) && close(rotated.y, 0.5f32.sqrt()), "expected Tip to be turned 45 degrees around z halfway through, but it turns x into {:?}", rotated);
+}
+
+#[test]
+fn gltf_spawn_builds_hierarchy() {
+    let model = load_test_model("models/gltf_test.glb");
+    let mut world = World::new();
+    let instance = model.spawn(&mut world, &Transform::meters(dvec3(10.0, 0.0, 0.0)));
+    propagate_transforms(&mut world);
+
+    assert!(world.n_entities() == 1 + 5 + 3 && instance.primitives.len() == 3, "expected 9 entities (the root, 5 nodes and 3 primitives), got {}", world.n_entities());
+    let top = instance.nodes[1].expect("Top wasn't spawned");
+    let top_pos = world.get::<Transform>(top).unwrap().pos();
+    assert!(top_pos == i64vec3(9000000, 1000000, -4000000), "expected Top at (9, 1, -4)m, but it's at {:?}um", top_pos);
+    assert!(world.get::<Name>(top).map(|name| name.0.as_str()) == Some("Top"), "Top doesn't have its name");
+
+    // a 1m cube's mesh was already 1m^3, so the primitive sits right on its node. Top's is scaled by Top's 0.8
+    let (_, top_primitive) = instance.primitives[1];
+    let transform = world.get::<Transform>(top_primitive).unwrap();
+    assert!(world.parent(top_primitive) == Some(top) && (transform.pos() - top_pos).abs().max() <= 1 && close(transform.scl().x, 0.8), "expected Top's cube at Top with scale 0.8, but it's at {:?} with scale {:?}", transform.pos(), transform.scl());
+
+    // the bar is skinned, so it goes under the skeleton's parent (the model's root), and its vertices should end up back where they are in the file
+    let (skin, bar) = instance.primitives[2];
+    assert!(skin == Some(0) && world.parent(bar) == Some(instance.root) && world.has::<SkeletalAnimator>(bar), "the bar should be attached to the root with a SkeletalAnimator");
+    let bounds = world_bounds(&world, bar, model.nodes[2].primitives[0].mesh_id);
+    assert!(close_bounds(bounds, glm::vec3(11.1, -0.5, -4.1), glm::vec3(11.3, 0.5, -3.9)), "expected the bar back between (11.1, -0.5, -4.1)m and (11.3, 0.5, -3.9)m, but it's between {:?} and {:?}", bounds.0, bounds.1);
+
+    model.play_animation(&mut world, &instance, 0);
+    assert!(world.has::<Animator>(instance.nodes[0].unwrap()) && world.get::<SkeletalAnimator>(bar).unwrap().playing().is_some(), "playing Bend should give Base an Animator and start the bar's SkeletalAnimator");
+}
+
+#[test]
+fn gltf_errors() {
+    let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    let directory = std::env::temp_dir();
+    let write = |name: &str, bytes: &[u8]| -> String {
+        let path = directory.join(name).to_string_lossy().into_owned();
+        std::fs::write(&path, bytes).unwrap();
+        return path;
+    };
+
+    let missing = GltfModel::load("models/doesnt_exist.gltf", &mut graphics, &GltfImportOptions::new());
+    assert!(matches!(missing, Err(GltfError::Io(_))), "loading a file that doesn't exist should be an Io error");
+    let glb = std::fs::read("models/gltf_test.glb").unwrap();
+    let cut_off = write("ig2_cut_off.glb", &glb[..glb.len() / 2]);
+    assert!(matches!(GltfModel::load(&cut_off, &mut graphics, &GltfImportOptions::new()), Err(GltfError::Invalid(_))), "a glb cut in half should be Invalid");
+    let draco = write("ig2_draco.gltf", br#"{"asset": {"version": "2.0"}, "extensionsRequired": ["KHR_draco_mesh_compression"]}"#);
+    assert!(matches!(GltfModel::load(&draco, &mut graphics, &GltfImportOptions::new()), Err(GltfError::Unsupported(_))), "a file requiring draco should be Unsupported");
+    let bad_index = write("ig2_bad_index.gltf", br#"{"asset": {"version": "2.0"}, "nodes": [{"children": [0]}]}"#);
+    assert!(matches!(GltfModel::load(&bad_index, &mut graphics, &GltfImportOptions::new()), Err(GltfError::Invalid(_))), "a node that's its own child should be Invalid");
+}
+
+fn load_test_obj(normals: GeneratedNormals) -> ObjModel {
+    let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    let options = ObjImportOptions { normals };
+    return ObjModel::load("models/obj_test.obj", &mut graphics, &options).unwrap_or_else(|err| panic!("Couldn't load models/obj_test.obj: {}", err));
+}
+
+#[test]
+fn obj_objects_and_materials() {
+    let model = load_test_obj(GeneratedNormals::Smooth);
+    let names: Vec<&str> = model.objects.iter().map(|object| object.name.as_str()).collect();
+    assert!(names == vec!["Crate", "Gem", "Floor"] && model.object_index("Gem") == Some(1), "expected objects Crate, Gem and Floor, got {:?}", names);
+    let materials: Vec<Option<&str>> = model.objects.iter().map(|object| object.material.map(|m| model.materials[m].name.as_str())).collect();
+    assert!(materials == vec![Some("Wood"), Some("Glass"), None], "expected materials Wood, Glass and none, got {:?}", materials);
+
+    let wood = &model.materials[model.objects[0].material.unwrap()];
+    let glass = &model.materials[model.objects[1].material.unwrap()];
+    assert!(wood.color == glm::vec4(1.0, 0.5, 0.2, 1.0) && wood.texture_id.is_some(), "expected Wood to be orange with a texture, but it came out as {:?}", wood);
+    assert!(glass.color == glm::vec4(0.2, 0.4, 1.0, 0.5) && glass.texture_id.is_none(), "expected Glass to be half see through blue without a texture, but it came out as {:?}", glass);
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let textures: Vec<u32> = model.objects.iter().map(|object| meshes[&object.mesh_id].texture_id).collect();
+    assert!(textures == vec![wood.texture_id.unwrap(), 0, 0], "expected only the Crate's mesh to have Wood's texture, but their textures are {:?}", textures);
+}
+
+#[test]
+fn obj_generates_normals() {
+    let smooth = load_test_obj(GeneratedNormals::Smooth);
+    let flat = load_test_obj(GeneratedNormals::Flat);
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let n_vertices = |model: &ObjModel| model.objects.iter().map(|object| meshes[&object.mesh_id].vertices.len() / N_FLOATS_PER_VERTEX).collect::<Vec<usize>>();
+    // the crate has its own normals so it's the same either way, the others' corners are split up into 3 per triangle when flat
+    assert!(n_vertices(&smooth) == vec![24, 4, 4] && n_vertices(&flat) == vec![24, 12, 6], "expected [24, 4, 4] vertices with smooth normals and [24, 12, 6] with flat ones, got {:?} and {:?}", n_vertices(&smooth), n_vertices(&flat));
+
+    // every generated normal should point out of the gem, and the flat ones should be the same across each face
+    for (model, what) in [(&smooth, "smooth"), (&flat, "flat")] {
+        let gem = &meshes[&model.objects[1].mesh_id];
+        let vertices: Vec<&[f32]> = gem.vertices.chunks_exact(N_FLOATS_PER_VERTEX).collect();
+        let center = vertices.iter().fold(glm::vec3(0.0, 0.0, 0.0), |sum, v| sum + glm::vec3(v[0], v[1], v[2])) / vertices.len() as f32;
+        for v in vertices.iter() {
+            let normal = glm::vec3(v[3], v[4], v[5]);
+            assert!(close(normal.norm(), 1.0) && normal.dot(&(glm::vec3(v[0], v[1], v[2]) - center)) > 0.0, "the gem's {} normal {:?} at {:?} doesn't point out of it", what, normal, &v[0..3]);
+        }
+        if what == "flat" {
+            for triangle in gem.indices.chunks_exact(3) {
+                let normals: Vec<&[f32]> = triangle.iter().map(|i| &vertices[*i as usize][3..6]).collect();
+                assert!(normals[0] == normals[1] && normals[0] == normals[2], "expected the flat normals to be the same across a face, but got {:?}", normals);
+            }
+        }
+    }
+
+    // the floor is flat anyway, so smooth normals should point straight up
+    let floor = &meshes[&smooth.objects[2].mesh_id];
+    assert!(floor.vertices.chunks_exact(N_FLOATS_PER_VERTEX).all(|v| close(v[4], 1.0)), "expected the floor's generated normals to point straight up");
+}
+
+#[test]
+fn obj_generates_uvs() {
+    let model = load_test_obj(GeneratedNormals::Smooth);
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let uvs = |object: usize| meshes[&model.objects[object].mesh_id].vertices.chunks_exact(N_FLOATS_PER_VERTEX).map(|v| (v[6], v[7])).collect::<Vec<(f32, f32)>>();
+    // the floor's uvs come from the file, and go up to 3 along its length
+    assert!(uvs(2).iter().map(|(u, _)| *u).fold(0.0, f32::max) == 3.0, "expected the floor to keep its uvs from the file, but they're {:?}", uvs(2));
+    // the gem doesn't have any, so they're projected from its positions and shouldn't all be the same
+    let gem = uvs(1);
+    assert!(gem.iter().any(|uv| *uv != gem[0]), "expected the gem to get generated uvs, but every vertex has {:?}", gem[0]);
+}
+
+#[test]
+fn obj_from_obj_merges_objects() {
+    let mesh_id = Mesh::from_obj("models/obj_test.obj", 0, 0).unwrap();
+    let meshes = LOADED_MESHES.lock().unwrap();
+    let mesh = &meshes[&mesh_id];
+    // 12 triangles for the crate, 4 for the gem and 2 for the floor
+    assert!(mesh.indices.len() == (12 + 4 + 2) * 3 && mesh.vertices.len() == (24 + 4 + 4) * N_FLOATS_PER_VERTEX && mesh.source_path.as_deref() == Some("models/obj_test.obj"), "expected every object in one mesh with 18 triangles and 32 vertices, got {} triangles and {} vertices", mesh.indices.len() / 3, mesh.vertices.len() / N_FLOATS_PER_VERTEX);
+    assert!(mesh.indices.iter().all(|i| (*i as usize) < mesh.vertices.len() / N_FLOATS_PER_VERTEX), "the merged mesh has indices past its last vertex");
+    // the whole file is scaled into 1m^3 together, so it's still 6m long
+    assert!(close(mesh.original_size.x, 6.0), "expected the merged mesh's original size to be 6m wide, but it's {:?}", mesh.original_size);
+}
+
+#[test]
+fn obj_spawn_puts_objects_back() {
+    let model = load_test_obj(GeneratedNormals::Smooth);
+    let mut world = World::new();
+    let instance = model.spawn(&mut world, &Transform::meters(dvec3(0.0, 0.0, -4.0)));
+    propagate_transforms(&mut world);
+
+    assert!(world.n_entities() == 4 && instance.objects.len() == 3, "expected 4 entities (the root and 3 objects), got {}", world.n_entities());
+    let expected = [
+        (glm::vec3(-2.0, -0.5, -4.5), glm::vec3(-1.0, 0.5, -3.5)),
+        (glm::vec3(1.0, -0.4, -4.5), glm::vec3(2.0, 0.6, -3.6)),
+        (glm::vec3(-3.0, -0.5, -5.0), glm::vec3(3.0, -0.5, -3.0)),
+    ];
+    for (i, (min, max)) in expected.iter().enumerate() {
+        let bounds = world_bounds(&world, instance.objects[i], model.objects[i].mesh_id);
+        assert!(world.parent(instance.objects[i]) == Some(instance.root) && close_bounds(bounds, *min, *max), "expected {} under the root between {:?} and {:?}, but it's between {:?} and {:?}", model.objects[i].name, min, max, bounds.0, bounds.1);
+    }
+
+    let texture_zs: Vec<f32> = instance.objects.iter().map(|entity| world.get::<RenderComponent>(*entity).unwrap().texture_z()).collect();
+    assert!(texture_zs == vec![0.0, -1.0, -1.0], "expected only the crate to be textured, but the texture_zs are {:?}", texture_zs);
+}
+
+#[test]
+fn obj_errors() {
+    let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+    let directory = std::env::temp_dir();
+    let write = |name: &str, text: &str| -> String {
+        let path = directory.join(name).to_string_lossy().into_owned();
+        std::fs::write(&path, text).unwrap();
+        return path;
+    };
+
+    assert!(matches!(Mesh::from_obj("models/doesnt_exist.obj", 0, 0), Err(ObjError::Load(_, tobj::LoadError::OpenFileFailed))), "loading a file that doesn't exist should be a Load error");
+    let points = write("ig2_points.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n");
+    assert!(matches!(Mesh::from_obj(&points, 0, 0), Err(ObjError::Empty(_))), "a file with vertices but no faces should be Empty");
+    let out_of_bounds = write("ig2_out_of_bounds.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 7\n");
+    assert!(matches!(Mesh::from_obj(&out_of_bounds, 0, 0), Err(ObjError::Load(..))), "a face using a vertex that isn't there should be a Load error");
+
+    // a missing mtl file just means no materials, but a material whose texture is missing is an error
+    let no_mtl = write("ig2_no_mtl.obj", "mtllib ig2_doesnt_exist.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl Anything\nf 1 2 3\n");
+    let model = ObjModel::load(&no_mtl, &mut graphics, &ObjImportOptions::new()).expect("a file whose mtl file is missing should still load");
+    assert!(model.materials.is_empty() && model.objects[0].material.is_none(), "a file whose mtl file is missing should load without materials");
+    write("ig2_missing_texture.mtl", "newmtl Missing\nmap_Kd ig2_doesnt_exist.png\n");
+    let missing_texture = write("ig2_missing_texture.obj", "mtllib ig2_missing_texture.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl Missing\nf 1 2 3\n");
+    assert!(matches!(ObjModel::load(&missing_texture, &mut graphics, &ObjImportOptions::new()), Err(ObjError::Texture(..))), "a material whose texture doesn't exist should be a Texture error");
+}
@@ -0,0 +1,10 @@
+mod scene_file;
+pub use scene_file::*;
+mod prefab;
+pub use prefab::*;
+mod gltf;
+pub use gltf::*;
+mod obj;
+pub use obj::*;
+#[cfg(test)]
+mod import_checks;
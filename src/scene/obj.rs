@@ -0,0 +1,110 @@
+// Imports Wavefront OBJ files with every object as its own mesh, colored and textured by its MTL material.
+//  - each object's mesh is scaled into 1m^3 on its own, so spawned objects are children of the model's root entity whose LocalTransform
+//    puts them back where they are in the file (ObjObject::offset)
+//  - the diffuse color (Kd) and dissolve (d) become the RenderComponent's color, the diffuse map (map_Kd) the mesh's texture
+
+use std::path::Path;
+
+use glm::{Vec4, vec4};
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::graphics::*;
+
+pub struct ObjImportOptions {
+    pub normals: GeneratedNormals, // for objects that don't have normals in the file
+}
+
+impl ObjImportOptions {
+    pub fn new() -> Self {
+        return Self { normals: GeneratedNormals::Smooth };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub color: Vec4, // Kd, with d as alpha
+    pub texture_id: Option<u32>, // from map_Kd
+}
+
+pub struct ObjObject {
+    pub name: String,
+    pub mesh_id: usize,
+    pub material: Option<usize>, // index into ObjModel::materials
+    pub offset: Transform, // undoes the mesh being scaled into 1m^3, relative to the model
+}
+
+pub struct ObjModel {
+    pub path: String,
+    pub objects: Vec<ObjObject>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+// the entities ObjModel::spawn() made
+pub struct ObjInstance {
+    pub root: Entity,
+    pub objects: Vec<Entity>, // one per object, in the same order as ObjModel::objects
+}
+
+impl ObjModel {
+    // loads the file's objects into LOADED_MESHES and its materials' textures into graphics
+    pub fn load(path: &str, graphics: &mut GraphicsEngine, options: &ObjImportOptions) -> Result<ObjModel, ObjError> {
+        let (parts, materials) = read_obj(path, options.normals)?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut model = ObjModel { path: path.to_string(), objects: Vec::new(), materials: Vec::new() };
+        for material in materials {
+            let diffuse = material.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+            let texture_id = match &material.diffuse_texture {
+                Some(texture) => Some(load_texture(&directory.join(texture).to_string_lossy(), graphics)?),
+                None => None
+            };
+            model.materials.push(ObjMaterial { name: material.name, color: vec4(diffuse[0], diffuse[1], diffuse[2], material.dissolve.unwrap_or(1.0)), texture_id });
+        }
+
+        for part in parts {
+            let texture_id = part.material.and_then(|m| model.materials[m].texture_id).unwrap_or(0);
+            let mut mesh = Mesh::from_vertices(part.vertices, part.indices, texture_id, graphics.world_shader_id, false);
+            println!("Created mesh with {} vertices.", mesh.vertices.len()/N_FLOATS_PER_VERTEX);
+            mesh.source_path = Some(path.to_string());
+            let object = ObjObject { name: part.name, mesh_id: mesh.uuid, material: part.material, offset: normalization_offset(&mesh.normalization) };
+            LOADED_MESHES.lock().unwrap().insert(mesh.uuid, mesh);
+            model.objects.push(object);
+        }
+        return Ok(model);
+    }
+
+    pub fn object_index(&self, name: &str) -> Option<usize> {
+        return self.objects.iter().position(|object| object.name == name);
+    }
+
+    // makes an entity for the model at transform, with one child per object
+    pub fn spawn(&self, world: &mut World, transform: &Transform) -> ObjInstance {
+        let root = world.build_entity().with(transform.clone()).with(Name(Path::new(&self.path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default())).build();
+        let mut instance = ObjInstance { root, objects: Vec::new() };
+        for object in self.objects.iter() {
+            let material = object.material.map(|m| &self.materials[m]);
+            let mut render = RenderComponent::new(object.mesh_id);
+            render.set_rgba(material.map(|m| m.color).unwrap_or(vec4(1.0, 1.0, 1.0, 1.0)));
+            render.set_texture_z(if material.is_some_and(|m| m.texture_id.is_some()) {0.0} else {-1.0});
+
+            let entity = world.spawn();
+            world.set_parent_local(entity, root, object.offset.clone());
+            world.insert(entity, Name(object.name.clone()));
+            world.insert(entity, render);
+            instance.objects.push(entity);
+        }
+        return instance;
+    }
+}
+
+// loads a texture once, the world shader samples a texture array so a plain image is an array with 1 layer
+fn load_texture(path: &str, graphics: &mut GraphicsEngine) -> Result<u32, ObjError> {
+    if let Some(id) = graphics.texture_id_from_path(path) {
+        return Ok(id);
+    }
+    let bytes = std::fs::read(path).map_err(|err| ObjError::Texture(path.to_string(), err.to_string()))?;
+    let (id, _) = graphics.load_texture_from_memory(&bytes, TextureType::TexArray2D, Some(path)).map_err(|err| ObjError::Texture(path.to_string(), err))?;
+    return Ok(id);
+}
@@ -0,0 +1,298 @@
+// Prefabs: named object templates loaded from JSON files, so something like the floor (mesh, texture, colour, collider, scale...) is written down once
+// and spawned wherever it's needed. Uses the same render/collider/rigidbody blocks as scene files.
+//
+// {
+//     "prefabs": {
+//         "Floor": {
+//             "transform": {"scl": [10.0, 1.0, 10.0]}, (optional, relative to where the prefab gets spawned. pos defaults to 0, scl to 1)
+//             "render": {"mesh": "models/rainbowcube.obj", "texture": "textures/grass.png", "texture_z": 0.0, "color": [0.4, 0.6, 0.4, 1.0]},
+//             "collider": {"type": "Box"},
+//             "children": [
+//                 {"transform": {"pos": [0, 1000000, 0]}, "render": {...}}, (children are relative to their parent)
+//                 {"prefab": "Lamp", "transform": {"pos": [4000000, 0, 0]}} (another prefab, with the rest of the object as overrides)
+//             ]
+//         }
+//     }
+// }
+//
+// Overrides are merged over the prefab: objects merge key by key, "children" merge by index, and anything else (numbers, colours, ...) is replaced.
+// That happens on the raw json, and only the fully resolved object gets read into a PartDesc.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::graphics::GraphicsEngine;
+
+use super::*;
+
+const MAX_PREFAB_DEPTH: usize = 32; // so a prefab that contains itself errors instead of overflowing the stack
+
+pub struct PrefabLibrary {
+    prefabs: HashMap<String, Value>,
+    mesh_cache: HashMap<(String, u32), usize>, // shared between spawns so every instance of a prefab uses the same Mesh and gets instanced
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        return Self { prefabs: HashMap::new(), mesh_cache: HashMap::new() };
+    }
+
+    // adds every prefab in the text, replacing any that already had the same name. returns their names
+    pub fn load_str(&mut self, text: &str) -> Result<Vec<String>, SceneError> {
+        let json: Value = serde_json::from_str(text).map_err(SceneError::Json)?;
+        let prefabs = json.get("prefabs").and_then(|p| p.as_object()).ok_or_else(|| SceneError::Invalid(String::from("prefab file should have a \"prefabs\" object")))?;
+        for (name, prefab) in prefabs {
+            if prefab.as_object().is_none() {
+                return Err(SceneError::Invalid(format!("prefab {} should be an object", name)));
+            }
+        }
+        for (name, prefab) in prefabs {
+            self.prefabs.insert(name.clone(), prefab.clone());
+        }
+        return Ok(prefabs.keys().cloned().collect());
+    }
+
+    pub fn load_file(&mut self, path: &str) -> Result<Vec<String>, SceneError> {
+        let text = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+        return self.load_str(&text);
+    }
+
+    // loads every .json file in the folder
+    pub fn load_dir(&mut self, path: &str) -> Result<Vec<String>, SceneError> {
+        let mut names = Vec::new();
+        let mut files: Vec<_> = std::fs::read_dir(path).map_err(SceneError::Io)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+        files.sort(); // so which file wins when two define the same prefab doesn't depend on the filesystem
+        for file in files {
+            if file.extension().map_or(false, |ext| ext == "json") {
+                names.extend(self.load_file(&file.to_string_lossy())?);
+            }
+        }
+        return Ok(names);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        return self.prefabs.contains_key(name);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        return self.prefabs.keys();
+    }
+
+    pub fn spawn_prefab(&mut self, name: &str, transform: Transform, world: &mut World, graphics: &mut GraphicsEngine) -> Result<Entity, SceneError> {
+        return self.spawn_prefab_with(name, transform, &Value::Object(Map::new()), world, graphics);
+    }
+
+    // spawns the prefab (and its children) with its root at transform, and returns the root.
+    // overrides is a prefab-shaped object merged over it for just this instance, like {"render": {"color": [1.0, 0.0, 0.0, 1.0]}}.
+    // everything gets drawn from the next GraphicsEngine::update_entities() and simulated from the next physics_system().
+    // if anything is wrong nothing is spawned
+    pub fn spawn_prefab_with(&mut self, name: &str, transform: Transform, overrides: &Value, world: &mut World, graphics: &mut GraphicsEngine) -> Result<Entity, SceneError> {
+        let mut with_name = overrides.as_object().cloned().ok_or_else(|| SceneError::Invalid(String::from("prefab overrides should be an object")))?;
+        with_name.insert(String::from("prefab"), Value::from(name));
+        let resolved = self.resolve(&Value::Object(with_name), 0)?;
+        let desc = PartDesc::deserialize(&resolved).map_err(|err| SceneError::Invalid(format!("prefab {}: {}", name, err)))?;
+        let part = self.read_part(&desc, name, graphics)?;
+        return Ok(spawn_part(part, &transform, None, world));
+    }
+
+    // replaces "prefab" references with the prefab they name, with the referencing object merged over it
+    fn resolve(&self, object: &Value, depth: usize) -> Result<Value, SceneError> {
+        if depth > MAX_PREFAB_DEPTH {
+            return Err(SceneError::Invalid(String::from("prefabs are nested too deep, does one contain itself?")));
+        }
+        let mut resolved = match object.get("prefab") {
+            Some(prefab_name) => {
+                let prefab_name = prefab_name.as_str().ok_or_else(|| SceneError::Invalid(String::from("\"prefab\" should be a prefab name")))?;
+                let prefab = self.prefabs.get(prefab_name).ok_or_else(|| SceneError::Invalid(format!("there's no prefab called {}", prefab_name)))?;
+                let mut merged = self.resolve(prefab, depth + 1)?;
+                merge_overrides(&mut merged, object);
+                if merged.get("name").is_none() {
+                    merged["name"] = Value::from(prefab_name);
+                }
+                merged
+            }
+            None => object.clone()
+        };
+
+        if let Some(children) = resolved.get("children") {
+            let children = children.as_array().ok_or_else(|| SceneError::Invalid(String::from("\"children\" should be an array")))?;
+            let children = children.iter().map(|child| self.resolve(child, depth + 1)).collect::<Result<Vec<_>, _>>()?;
+            resolved["children"] = Value::Array(children);
+        }
+        return Ok(resolved);
+    }
+
+    // reads everything (and loads meshes/textures) before anything gets spawned
+    fn read_part(&mut self, desc: &PartDesc, what: &str, graphics: &mut GraphicsEngine) -> Result<PrefabPart, SceneError> {
+        let children = desc.children.iter().enumerate().map(|(i, child)| self.read_part(child, &format!("{}.children[{}]", what, i), graphics)).collect::<Result<Vec<_>, _>>()?;
+        return Ok(PrefabPart {
+            name: desc.name.clone(),
+            transform: desc.transform.to_transform(),
+            render: match &desc.render {Some(r) => Some(r.to_component(graphics, &mut self.mesh_cache, &format!("{}.render", what))?), None => None},
+            collider: desc.collider.as_ref().map(|c| c.to_component()),
+            rigidbody: desc.rigidbody.as_ref().map(|r| r.to_component()),
+            children,
+        });
+    }
+}
+
+// a prefab after its "prefab" references have been resolved, with the same render/collider/rigidbody blocks as a scene file's entities
+#[derive(Deserialize)]
+struct PartDesc {
+    name: Option<String>,
+    #[serde(default)]
+    transform: TransformDesc, // relative to where it's spawned, or to its parent
+    render: Option<RenderDesc>,
+    collider: Option<ColliderDesc>,
+    rigidbody: Option<RigidBodyDesc>,
+    #[serde(default)]
+    children: Vec<PartDesc>,
+}
+
+struct PrefabPart {
+    name: Option<String>,
+    transform: Transform,
+    render: Option<RenderComponent>,
+    collider: Option<ColliderComponent>,
+    rigidbody: Option<RigidBodyComponent>,
+    children: Vec<PrefabPart>,
+}
+
+// the root's transform is placed relative to where it was spawned, children relative to their parent
+fn spawn_part(part: PrefabPart, at: &Transform, parent: Option<Entity>, world: &mut World) -> Entity {
+    let entity = world.spawn();
+    match parent {
+        Some(parent) => world.set_parent_local(entity, parent, part.transform),
+        None => {world.insert(entity, compose_transforms(at, &part.transform));}
+    }
+    if let Some(name) = part.name {
+        world.insert(entity, Name(name));
+    }
+    if let Some(render) = part.render {
+        world.insert(entity, render);
+    }
+    if let Some(collider) = part.collider {
+        world.insert(entity, collider);
+    }
+    if let Some(rigidbody) = part.rigidbody {
+        world.insert(entity, rigidbody);
+    }
+    for child in part.children {
+        spawn_part(child, at, Some(entity), world);
+    }
+    return entity;
+}
+
+fn merge_overrides(base: &mut Value, overrides: &Value) {
+    let overrides = match overrides.as_object() {
+        Some(o) => o,
+        None => return
+    };
+    if !base.is_object() {
+        *base = Value::Object(Map::new());
+    }
+    for (key, value) in overrides {
+        if key == "prefab" {
+            continue;
+        }
+        match (base.get_mut(key), value) {
+            (Some(base_value @ Value::Object(_)), Value::Object(_)) => merge_overrides(base_value, value),
+            (Some(Value::Array(base_children)), Value::Array(override_children)) if key == "children" => {
+                for (i, child) in override_children.iter().enumerate() {
+                    match base_children.get_mut(i) {
+                        Some(base_child) => merge_overrides(base_child, child),
+                        None => base_children.push(child.clone())
+                    }
+                }
+            }
+            _ => base[key.as_str()] = value.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::vec4;
+
+    use crate::gameobjects::ColliderType;
+    use crate::graphics::RecordingDevice;
+
+    use super::*;
+
+    const PREFABS: &str = r#"{
+        "prefabs": {
+            "Lamp": {
+                "render": {"mesh": "models/icosphere.obj", "color": [1.0, 1.0, 0.0, 1.0]},
+                "collider": {"type": "Sphere", "material": {"elasticity": 0.6}},
+                "children": [
+                    {"name": "Bulb", "transform": {"pos": [0, 500000, 0]}, "render": {"mesh": "models/icosphere.obj", "color": [1.0, 1.0, 1.0, 1.0]}}
+                ]
+            },
+            "Post": {
+                "render": {"mesh": "models/rainbowcube.obj", "color": [0.5, 0.5, 0.5, 1.0]},
+                "collider": {"type": "Box"},
+                "children": [
+                    {"prefab": "Lamp", "transform": {"pos": [0, 2000000, 0]}, "collider": {"material": {"elasticity": 0.25}}, "children": [{"render": {"color": [0.0, 0.0, 1.0, 1.0]}}]}
+                ]
+            },
+            "Loop": {"children": [{"prefab": "Loop"}]}
+        }
+    }"#;
+
+    fn name(world: &World, entity: Entity) -> &str {
+        return &world.get::<Name>(entity).unwrap().0;
+    }
+
+    #[test]
+    fn overrides_and_nested_prefabs() {
+        let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+        let mut library = PrefabLibrary::new();
+        let mut names = library.load_str(PREFABS).unwrap();
+        names.sort();
+        assert_eq!(names, ["Lamp", "Loop", "Post"]);
+
+        let mut world = World::new();
+        let overrides: Value = serde_json::from_str(r#"{"render": {"color": [1.0, 0.0, 0.0, 1.0]}}"#).unwrap();
+        let post = library.spawn_prefab_with("Post", Transform::meters(dvec3(10.0, 0.0, 0.0)), &overrides, &mut world, &mut graphics).unwrap();
+        propagate_transforms(&mut world);
+
+        // the root gets the instance's overrides on top of the prefab
+        assert_eq!(name(&world, post), "Post");
+        assert_eq!(world.get::<RenderComponent>(post).unwrap().rgba(), vec4(1.0, 0.0, 0.0, 1.0));
+        assert!(world.get::<ColliderComponent>(post).unwrap().collider_type == ColliderType::Box);
+        assert_eq!(world.get::<Transform>(post).unwrap().pos(), i64vec3(10_000_000, 0, 0));
+
+        // the nested Lamp keeps what Post didn't override, and the override merges into its material instead of replacing it
+        assert_eq!(world.children(post).len(), 1);
+        let lamp = world.children(post)[0];
+        assert_eq!(name(&world, lamp), "Lamp");
+        assert_eq!(world.get::<RenderComponent>(lamp).unwrap().rgba(), vec4(1.0, 1.0, 0.0, 1.0));
+        let collider = world.get::<ColliderComponent>(lamp).unwrap();
+        assert!(collider.collider_type == ColliderType::Sphere);
+        assert_eq!((collider.friction, collider.elasticity), (ColliderComponent::new(ColliderType::Sphere).friction, 0.25));
+        assert_eq!(world.get::<Transform>(lamp).unwrap().pos(), i64vec3(10_000_000, 2_000_000, 0));
+
+        // children merge by index, and both icospheres share one mesh so they get instanced
+        assert_eq!(world.children(lamp).len(), 1);
+        let bulb = world.children(lamp)[0];
+        assert_eq!(name(&world, bulb), "Bulb");
+        assert_eq!(world.get::<RenderComponent>(bulb).unwrap().rgba(), vec4(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(world.get::<Transform>(bulb).unwrap().pos(), i64vec3(10_000_000, 2_500_000, 0));
+        assert_eq!(world.get::<RenderComponent>(bulb).unwrap().mesh_id(), world.get::<RenderComponent>(lamp).unwrap().mesh_id());
+
+        // overrides only apply to the instance they were given for
+        let plain = library.spawn_prefab("Post", Transform::meters(dvec3(0.0, 0.0, 0.0)), &mut world, &mut graphics).unwrap();
+        assert_eq!(world.get::<RenderComponent>(plain).unwrap().rgba(), vec4(0.5, 0.5, 0.5, 1.0));
+        assert_eq!(world.get::<RenderComponent>(world.children(world.children(plain)[0])[0]).unwrap().rgba(), vec4(0.0, 0.0, 1.0, 1.0));
+
+        // a prefab that contains itself is an error, and nothing gets spawned
+        let n_entities = world.query::<Transform>().count();
+        assert!(library.spawn_prefab("Loop", Transform::meters(dvec3(0.0, 0.0, 0.0)), &mut world, &mut graphics).is_err());
+        assert_eq!(world.query::<Transform>().count(), n_entities);
+        graphics.cleanup();
+    }
+}
@@ -0,0 +1,379 @@
+// Saving/loading a World's entities as a JSON scene file, so levels can be made without recompiling.
+// Every entity with a Transform gets saved along with whichever of these it has: Name, RenderComponent (as mesh/texture file paths), ColliderComponent, RigidBodyComponent and Parent.
+// Positions are i64 micrometers and every float is written so it parses back to the exact same bits, so save -> load -> save gives an identical file.
+//
+// {
+//     "entities": [
+//         {
+//             "name": "Floor",
+//             "transform": {"pos": [0, -10000000, 0], "rot": [1.0, 0.0, 0.0, 0.0], "scl": [10.0, 1.0, 10.0]}, (rot is w, x, y, z)
+//             "render": {"mesh": "models/rainbowcube.obj", "texture": "textures/grass.png", "texture_z": 0.0, "color": [0.4, 0.6, 0.4, 1.0]},
+//             "collider": {"type": "Box", "material": {"friction": 0.4, "elasticity": 0.3}},
+//             "rigidbody": {"density": 1.0, "velocity": [0, 0, 0], "angular_velocity": [0.0, 0.0, 0.0]},
+//             "parent": 3 (index into entities, and transform is then relative to the parent)
+//         }
+//     ],
+//     "version": 1
+// }
+//
+// The file is read/written with serde, through the *Desc structs below, which are the scene file's layout. Missing fields get the component's defaults.
+
+use std::collections::HashMap;
+
+use glm::{vec3, vec4, Quat, I64Vec3};
+use serde::{Serialize, Deserialize};
+
+use crate::transform::*;
+use crate::ecs::*;
+use crate::gameobjects::ColliderType;
+use crate::graphics::{GraphicsEngine, Mesh, TextureType, LOADED_MESHES};
+
+pub const SCENE_VERSION: i64 = 1;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Json(serde_json::Error), // the file isn't json, or it doesn't have the layout of a scene
+    Invalid(String), // the json parsed but doesn't make sense as a scene (missing mesh, bad parent index...), or the world has something that can't be saved
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Json(err) => write!(f, "invalid json: {}", err),
+            SceneError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+fn invalid(message: String) -> SceneError {
+    return SceneError::Invalid(message);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneFile {
+    pub entities: Vec<EntityDesc>,
+    #[serde(default = "scene_version")]
+    pub version: i64,
+}
+
+fn scene_version() -> i64 {
+    return SCENE_VERSION;
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EntityDesc {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub transform: TransformDesc,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>, // index into entities, and transform is then relative to the parent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render: Option<RenderDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collider: Option<ColliderDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rigidbody: Option<RigidBodyDesc>,
+}
+
+// serde_json writes floats as the shortest text that parses back to the same bits, and i64s exactly, so transforms survive save -> load unchanged
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub pos: [i64; 3], // micrometers
+    pub rot: [f32; 4], // w, x, y, z
+    pub scl: [f32; 3],
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        return Self { pos: [0, 0, 0], rot: [1.0, 0.0, 0.0, 0.0], scl: [1.0, 1.0, 1.0] };
+    }
+}
+
+impl TransformDesc {
+    pub fn from_transform(transform: &Transform) -> Self {
+        let (pos, rot, scl) = (transform.pos(), transform.rot_quat(), transform.scl());
+        return Self { pos: [pos.x, pos.y, pos.z], rot: [rot.w, rot.i, rot.j, rot.k], scl: [scl.x, scl.y, scl.z] };
+    }
+
+    pub fn to_transform(&self) -> Transform {
+        let pos = I64Vec3::new(self.pos[0], self.pos[1], self.pos[2]);
+        let rot = Quat::new(self.rot[0], self.rot[1], self.rot[2], self.rot[3]);
+        return Transform::from_parts(pos, rot, vec3(self.scl[0], self.scl[1], self.scl[2]));
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RenderDesc {
+    pub mesh: String, // .obj path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<String>, // image path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_z: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>,
+}
+
+impl RenderDesc {
+    pub fn from_component(render: &RenderComponent, graphics: &GraphicsEngine) -> Result<Self, SceneError> {
+        let meshes = LOADED_MESHES.lock().unwrap();
+        let mesh = &meshes[&render.mesh_id()];
+        let mesh_path = mesh.source_path.clone().ok_or_else(|| invalid(format!("mesh {} wasn't loaded from a file, so it can't be saved in a scene", mesh.uuid)))?;
+        let texture = match mesh.texture_id {
+            0 => None,
+            id => Some(graphics.texture_path(id).ok_or_else(|| invalid(format!("texture {} wasn't loaded from a file, so it can't be saved in a scene", id)))?.to_string())
+        };
+        let rgba = render.rgba();
+        return Ok(Self { mesh: mesh_path, texture, texture_z: Some(render.texture_z()), color: Some([rgba.x, rgba.y, rgba.z, rgba.w]) });
+    }
+
+    // loads the mesh/texture (or reuses them if they're already loaded).
+    // mesh_cache is (mesh path, texture id) -> mesh id, so everything using the same model shares one Mesh and can be instanced.
+    // what: describes where in the file we are, for error messages
+    pub fn to_component(&self, graphics: &mut GraphicsEngine, mesh_cache: &mut HashMap<(String, u32), usize>, what: &str) -> Result<RenderComponent, SceneError> {
+        let texture_id = match &self.texture {
+            None => 0,
+            Some(path) => match graphics.texture_id_from_path(path) {
+                Some(id) => id,
+                None => graphics.load_texture_from_file(path, TextureType::TexArray2D).0
+            }
+        };
+
+        let key = (self.mesh.clone(), texture_id);
+        if !mesh_cache.contains_key(&key) {
+            if !std::path::Path::new(&self.mesh).exists() {
+                return Err(invalid(format!("{}.mesh: {} doesn't exist", what, self.mesh)));
+            }
+            // reuse a mesh that was already loaded outside of this scene if there is one
+            let existing = LOADED_MESHES.lock().unwrap().values()
+                .find(|m| !m.dynamic && m.source_path.as_deref() == Some(self.mesh.as_str()) && m.texture_id == texture_id && m.shader_id == graphics.world_shader_id)
+                .map(|m| m.uuid);
+            let mesh_id = match existing {
+                Some(mesh_id) => mesh_id,
+                None => Mesh::from_obj(&self.mesh, texture_id, graphics.world_shader_id).map_err(|err| invalid(format!("{}.mesh: {}", what, err)))?
+            };
+            mesh_cache.insert(key.clone(), mesh_id);
+        }
+
+        let mut render = RenderComponent::new(mesh_cache[&key]);
+        if let Some(texture_z) = self.texture_z {
+            render.set_texture_z(texture_z);
+        }
+        if let Some(c) = self.color {
+            render.set_rgba(vec4(c[0], c[1], c[2], c[3]));
+        }
+        return Ok(render);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ColliderDesc {
+    #[serde(rename = "type")]
+    pub collider_type: ColliderType,
+    #[serde(default)]
+    pub material: MaterialDesc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct MaterialDesc {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friction: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elasticity: Option<f32>,
+}
+
+impl ColliderDesc {
+    pub fn from_component(collider: &ColliderComponent) -> Self {
+        return Self { collider_type: collider.collider_type, material: MaterialDesc { friction: Some(collider.friction), elasticity: Some(collider.elasticity) } };
+    }
+
+    pub fn to_component(&self) -> ColliderComponent {
+        let mut collider = ColliderComponent::new(self.collider_type);
+        collider.friction = self.material.friction.unwrap_or(collider.friction);
+        collider.elasticity = self.material.elasticity.unwrap_or(collider.elasticity);
+        return collider;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RigidBodyDesc {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<[i64; 3]>, // micrometers/sec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angular_velocity: Option<[f32; 3]>,
+}
+
+impl RigidBodyDesc {
+    pub fn from_component(rigidbody: &RigidBodyComponent) -> Self {
+        let (v, av) = (rigidbody.velocity, rigidbody.angular_velocity);
+        return Self { density: Some(rigidbody.density), velocity: Some([v.x, v.y, v.z]), angular_velocity: Some([av.x, av.y, av.z]) };
+    }
+
+    pub fn to_component(&self) -> RigidBodyComponent {
+        let mut rigidbody = RigidBodyComponent::new();
+        rigidbody.density = self.density.unwrap_or(rigidbody.density);
+        if let Some(v) = self.velocity {
+            rigidbody.velocity = I64Vec3::new(v[0], v[1], v[2]);
+        }
+        if let Some(av) = self.angular_velocity {
+            rigidbody.angular_velocity = vec3(av[0], av[1], av[2]);
+        }
+        return rigidbody;
+    }
+}
+
+// entities are saved in the order their Transforms are stored in, parents always before their children
+pub fn save_scene(world: &World, graphics: &GraphicsEngine) -> Result<String, SceneError> {
+    let mut order: Vec<Entity> = Vec::new();
+    let mut index_of: HashMap<Entity, usize> = HashMap::new();
+    fn visit(world: &World, entity: Entity, order: &mut Vec<Entity>, index_of: &mut HashMap<Entity, usize>) {
+        if index_of.contains_key(&entity) || !world.has::<Transform>(entity) {
+            return;
+        }
+        if let Some(parent) = world.parent(entity) {
+            visit(world, parent, order, index_of);
+        }
+        index_of.insert(entity, order.len());
+        order.push(entity);
+    }
+    for (entity, _) in world.query::<Transform>() {
+        visit(world, entity, &mut order, &mut index_of);
+    }
+
+    let mut entities = Vec::new();
+    for entity in order.iter() {
+        let (parent, transform) = match (world.parent(*entity), world.get::<LocalTransform>(*entity)) {
+            (Some(parent), Some(local)) if index_of.contains_key(&parent) => (Some(index_of[&parent]), local.get()),
+            _ => (None, world.get::<Transform>(*entity).unwrap())
+        };
+        entities.push(EntityDesc {
+            name: world.get::<Name>(*entity).map(|name| name.0.clone()),
+            transform: TransformDesc::from_transform(transform),
+            parent,
+            render: match world.get::<RenderComponent>(*entity) {Some(render) => Some(RenderDesc::from_component(render, graphics)?), None => None},
+            collider: world.get::<ColliderComponent>(*entity).map(ColliderDesc::from_component),
+            rigidbody: world.get::<RigidBodyComponent>(*entity).map(RigidBodyDesc::from_component),
+        });
+    }
+
+    let scene = SceneFile { entities, version: SCENE_VERSION };
+    let mut text = serde_json::to_string_pretty(&scene).map_err(SceneError::Json)?;
+    text.push('\n');
+    return Ok(text);
+}
+
+pub fn save_scene_to_file(path: &str, world: &World, graphics: &GraphicsEngine) -> Result<(), SceneError> {
+    let text = save_scene(world, graphics)?;
+    return std::fs::write(path, text).map_err(SceneError::Io);
+}
+
+// spawns everything in the scene into world, returning the new entities in the same order as the file.
+// meshes/textures are loaded (or reused if already loaded) through the GraphicsEngine, and the entities get drawn from the next GraphicsEngine::update_entities().
+// if anything is wrong with the file, nothing gets spawned
+pub fn load_scene(text: &str, world: &mut World, graphics: &mut GraphicsEngine) -> Result<Vec<Entity>, SceneError> {
+    let scene: SceneFile = serde_json::from_str(text).map_err(SceneError::Json)?;
+    if scene.version > SCENE_VERSION {
+        return Err(invalid(format!("scene is version {}, but this build only understands up to version {}", scene.version, SCENE_VERSION)));
+    }
+
+    // read everything before spawning anything, so a bad file doesn't leave half a scene behind
+    struct LoadedEntity {
+        name: Option<String>,
+        transform: Transform,
+        parent: Option<usize>,
+        render: Option<RenderComponent>,
+        collider: Option<ColliderComponent>,
+        rigidbody: Option<RigidBodyComponent>,
+    }
+    let mut mesh_cache = HashMap::new();
+    let mut loaded = Vec::new();
+    for (i, desc) in scene.entities.iter().enumerate() {
+        let what = format!("entities[{}]", i);
+        if desc.parent.map_or(false, |p| p >= i) {
+            return Err(invalid(format!("{}.parent must come before it in the file", what)));
+        }
+        loaded.push(LoadedEntity {
+            name: desc.name.clone(),
+            transform: desc.transform.to_transform(),
+            parent: desc.parent,
+            render: match &desc.render {Some(r) => Some(r.to_component(graphics, &mut mesh_cache, &format!("{}.render", what))?), None => None},
+            collider: desc.collider.as_ref().map(|c| c.to_component()),
+            rigidbody: desc.rigidbody.as_ref().map(|r| r.to_component()),
+        });
+    }
+
+    let mut spawned: Vec<Entity> = Vec::new();
+    for l in loaded {
+        let entity = world.spawn();
+        if let Some(name) = l.name {
+            world.insert(entity, Name(name));
+        }
+        match l.parent {
+            Some(parent_index) => world.set_parent_local(entity, spawned[parent_index], l.transform),
+            None => {world.insert(entity, l.transform);}
+        }
+        if let Some(render) = l.render {
+            world.insert(entity, render);
+        }
+        if let Some(collider) = l.collider {
+            world.insert(entity, collider);
+        }
+        if let Some(rigidbody) = l.rigidbody {
+            world.insert(entity, rigidbody);
+        }
+        spawned.push(entity);
+    }
+    return Ok(spawned);
+}
+
+pub fn load_scene_from_file(path: &str, world: &mut World, graphics: &mut GraphicsEngine) -> Result<Vec<Entity>, SceneError> {
+    let text = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+    return load_scene(&text, world, graphics);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameobjects::ColliderType;
+    use crate::graphics::RecordingDevice;
+
+    use super::*;
+
+    #[test]
+    fn save_load_save_is_byte_stable() {
+        let mut graphics = GraphicsEngine::new(Box::new(RecordingDevice::null()), (64, 48));
+        let (texture_id, _) = graphics.load_texture_from_file("textures/grass.png", TextureType::TexArray2D);
+        let mesh = Mesh::from_obj("models/rainbowcube.obj", texture_id, graphics.world_shader_id).unwrap();
+
+        // floats that don't have short decimal representations, and a position further out than an f64 can hold to the micrometer
+        let mut world = World::new();
+        let mut floor_transform = Transform::new(I64Vec3::new(9_007_199_254_740_993, -10_000_000, 123_456_789));
+        floor_transform.set_rot_quat(glm::quat_angle_axis(0.3, &glm::normalize(&vec3(1.0, 2.0, 3.0))));
+        floor_transform.setscl(vec3(10.0, 1.0 / 3.0, 10.0));
+        let mut render = RenderComponent::new(mesh);
+        render.set_rgba(vec4(0.1, 0.2, 0.7, 1.0));
+        let floor = world.build_entity().with(Name(String::from("Floor"))).with(floor_transform).with(render).with(ColliderComponent::new(ColliderType::Box)).build();
+
+        let mut rigidbody = RigidBodyComponent::new();
+        rigidbody.velocity = I64Vec3::new(-1, 2_000_001, 3);
+        rigidbody.angular_velocity = vec3(0.1, -0.2, std::f32::consts::PI);
+        let mut local = Transform::new(I64Vec3::new(1_000_000, 2_500_000, -3));
+        local.set_rot_quat(glm::quat_angle_axis(-1.7, &vec3(0.0, 1.0, 0.0)));
+        let child = world.build_entity().with(ColliderComponent::new(ColliderType::Sphere)).with(rigidbody).build();
+        world.set_parent_local(child, floor, local);
+
+        let saved = save_scene(&world, &graphics).unwrap();
+        let mut loaded_world = World::new();
+        let loaded = load_scene(&saved, &mut loaded_world, &mut graphics).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded_world.get::<Transform>(loaded[0]).unwrap().pos(), world.get::<Transform>(floor).unwrap().pos());
+        assert_eq!(loaded_world.parent(loaded[1]), Some(loaded[0]));
+
+        let resaved = save_scene(&loaded_world, &graphics).unwrap();
+        assert!(saved == resaved, "Saving a loaded scene changed it\n--- saved\n{}--- resaved\n{}", saved, resaved);
+        graphics.cleanup();
+    }
+}
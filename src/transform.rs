@@ -4,43 +4,173 @@ use nalgebra_glm::{Vec3, vec3, I64Vec3, Number, DVec3, TVec3, vec4};
 
 pub const UNITS_PER_METER : i64 = 1000000; // 10^6 um per m
 
+// PRECISION
+// Positions are i64 micrometers, so they're exact anywhere within +-9.2*10^12 m (about 61 AU), and adding/subtracting them never loses anything.
+// Floats only ever hold *offsets* between two positions, or directions:
+// - f32 (Vec3) has ~7 significant digits: an offset of 1 km is good to ~0.1 mm, 1000 km to ~6 cm. Fine for anything physics or rendering looks at,
+//   as long as the subtraction happened in i64 first. vec3_from_i64vec3(&(a - b)) is right, vec3_from_i64vec3(&a) - vec3_from_i64vec3(&b) is not.
+// - f64 (DVec3) has ~16 digits: meters <-> micrometers round trips exactly for anything within 2^51 um (2 million km) of the origin
+//   (the divide and the multiply by 10^6 can each be off by half an ulp, which stays under half a micrometer up to there),
+//   so dvec3 is safe for absolute positions on planet scale maps, and for directions that get multiplied by big distances.
+// All the float -> i64 conversions here round to the nearest micrometer, and go through f64 so the multiply by 10^6 doesn't add f32 error on top.
+// Use relative_pos()/offset_to()/distance_to() and the *_d direction functions instead of converting positions to floats yourself.
+
+// ROTATION
+// Transform stores rotation as a quaternion; euler angles are only for setting/reading it.
+// Angles are radians unless they're wrapped in Degrees. Euler orders are intrinsic: YXZ means yaw around y, then pitch around the new x, then roll around the new z
+// (q = qy * qx * qz), which is what you want for objects and characters. A camera's view rotation is the inverse of that, which is XYZ with the angles negated,
+// so the freecam uses XYZ.
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Radians(pub f32);
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Degrees(pub f32);
+
+impl From<Degrees> for Radians {
+    fn from(degrees: Degrees) -> Self {
+        return Radians(degrees.0.to_radians());
+    }
+}
+
+impl From<Radians> for Degrees {
+    fn from(radians: Radians) -> Self {
+        return Degrees(radians.0.to_degrees());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EulerOrder {
+    YXZ,
+    XYZ,
+}
+
+// rotation around each axis
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EulerAngles {
+    pub x: Radians,
+    pub y: Radians,
+    pub z: Radians,
+}
+
+impl EulerAngles {
+    pub fn radians(x: f32, y: f32, z: f32) -> Self {
+        return Self { x: Radians(x), y: Radians(y), z: Radians(z) };
+    }
+
+    pub fn degrees(x: f32, y: f32, z: f32) -> Self {
+        return Self { x: Degrees(x).into(), y: Degrees(y).into(), z: Degrees(z).into() };
+    }
+
+    // radians
+    pub fn from_vec3(angles: &Vec3) -> Self {
+        return Self::radians(angles.x, angles.y, angles.z);
+    }
+
+    // radians
+    pub fn to_vec3(&self) -> Vec3 {
+        return vec3(self.x.0, self.y.0, self.z.0);
+    }
+
+    pub fn to_degrees(&self) -> Vec3 {
+        return vec3(self.x.0.to_degrees(), self.y.0.to_degrees(), self.z.0.to_degrees());
+    }
+
+    pub fn to_quat(&self, order: EulerOrder) -> nalgebra_glm::Quat {
+        let x = nalgebra_glm::quat_angle_axis(self.x.0, &vec3(1.0, 0.0, 0.0));
+        let y = nalgebra_glm::quat_angle_axis(self.y.0, &vec3(0.0, 1.0, 0.0));
+        let z = nalgebra_glm::quat_angle_axis(self.z.0, &vec3(0.0, 0.0, 1.0));
+        return match order {
+            EulerOrder::YXZ => y * x * z,
+            EulerOrder::XYZ => x * y * z,
+        };
+    }
+
+    // x/z (YXZ) or y/z (XYZ) come out in -pi..pi, and the middle angle in -pi/2..pi/2.
+    // at gimbal lock (middle angle at +-90 degrees) only the sum/difference of the other two matters, so z is 0 and the other gets all of it
+    pub fn from_quat(rot: &nalgebra_glm::Quat, order: EulerOrder) -> Self {
+        let m = nalgebra_glm::quat_to_mat3(&rot.normalize());
+        const LOCKED: f32 = 0.99999;
+        return match order {
+            // R = Ry * Rx * Rz, so m[(1, 2)] = -sin(x)
+            EulerOrder::YXZ => {
+                let sx = (-m[(1, 2)]).clamp(-1.0, 1.0);
+                if sx.abs() < LOCKED {
+                    Self::radians(sx.asin(), m[(0, 2)].atan2(m[(2, 2)]), m[(1, 0)].atan2(m[(1, 1)]))
+                }
+                else {
+                    Self::radians(sx.asin(), (-m[(2, 0)]).atan2(m[(0, 0)]), 0.0)
+                }
+            },
+            // R = Rx * Ry * Rz, so m[(0, 2)] = sin(y)
+            EulerOrder::XYZ => {
+                let sy = m[(0, 2)].clamp(-1.0, 1.0);
+                if sy.abs() < LOCKED {
+                    Self::radians((-m[(1, 2)]).atan2(m[(2, 2)]), sy.asin(), (-m[(0, 1)]).atan2(m[(0, 0)]))
+                }
+                else {
+                    Self::radians(m[(2, 1)].atan2(m[(1, 1)]), sy.asin(), 0.0)
+                }
+            },
+        };
+    }
+}
+
+// rotation that turns +z towards direction and +y as close to up as it can get. if they're parallel, some other up is picked so it doesn't produce NaNs
+pub fn quat_look_along(direction: &Vec3, up: &Vec3) -> nalgebra_glm::Quat {
+    let dir = direction.normalize();
+    let mut up = up.normalize();
+    if dir.cross(&up).norm() < 0.0001 {
+        up = if dir.x.abs() < 0.9 {vec3(1.0, 0.0, 0.0)} else {vec3(0.0, 0.0, 1.0)};
+    }
+    // get_look_vector() rotates +z by the conjugate, so this is the rotation taking direction to +z (left handed look at does exactly that)
+    return nalgebra_glm::quat_look_at_lh(&dir, &up);
+}
+
 #[derive(Debug)]
 pub struct Transform {
     pos: I64Vec3, // position is always in micrometers for int types and in meters for float types. 
     rot: nalgebra_glm::Quat,
     scl: nalgebra_glm::Vec3,
-    pub rotscalemat: nalgebra_glm::Mat4x4
+    rotscalemat: nalgebra_glm::Mat4x4 // always quat_to_mat4(rot) * scaling(scl), every mutator of rot/scl updates it. position isn't in it, see get_model()
 }
 
+// squares are done in u128, since i64 squares overflow once a component passes ~3000 km. never overflows
+pub fn i64vec3_mag_squared(vec: &I64Vec3) -> u128 {
+    let (x, y, z) = (vec.x.unsigned_abs() as u128, vec.y.unsigned_abs() as u128, vec.z.unsigned_abs() as u128);
+    return x * x + y * y + z * z;
+}
+
+// exact (rounded down to the micrometer), saturates at i64::MAX for vectors longer than that
 pub fn i64vec3_mag(vec: &I64Vec3) -> i64 {
-    return ((vec.x * vec.x + vec.y * vec.y + vec.z * vec.z) as f64).sqrt() as i64;
+    return crate::phys::isqrt(i64vec3_mag_squared(vec)).min(i64::MAX as u128) as i64;
 }
 
 pub fn i64vec3<T>(x: T, y: T, z: T) -> I64Vec3 where i64: From<T> {
     return I64Vec3::new(x.into(), y.into(), z.into());
 }
 
-// NOTE: DOUBLES ARE NOT USED IN PHYSICS OR GRAPHICS, AND ARE ONLY HERE SO YOU CAN CONVIENENTLY VIEW/SPECIFIY NUMBERS IN METERS (f64 because fp precision errors)
+// NOTE: DOUBLES ARE NOT STORED IN PHYSICS OR GRAPHICS, THEY'RE HERE SO YOU CAN CONVIENENTLY VIEW/SPECIFIY NUMBERS IN METERS AND DO PRECISE MATH ON OFFSETS (see PRECISION above)
 pub fn dvec3(x: f64, y: f64, z: f64) -> DVec3 {
     return TVec3::new(x, y, z);
 }
 
+// meters to micrometers. the multiply happens in f64, since 10^6 * an f32 gets rounded to f32 again (0.1 m became 100000.0015 -> 99999 um)
 pub fn i64vec3_from_vec3(vec: &Vec3) -> I64Vec3 {
-    return i64vec3::<i64>((vec.x * UNITS_PER_METER as f32) as i64, (vec.y * UNITS_PER_METER as f32) as i64, (vec.z * UNITS_PER_METER as f32) as i64);
+    return i64vec3_from_dvec3(&dvec3(vec.x as f64, vec.y as f64, vec.z as f64));
 }
 
 pub fn i64vec3_from_dvec3(vec: &DVec3) -> I64Vec3 {
-    
-    return i64vec3::<i64>((vec.x * UNITS_PER_METER as f64) as i64, (vec.y * UNITS_PER_METER as f64) as i64, (vec.z * UNITS_PER_METER as f64) as i64);
+    return i64vec3::<i64>((vec.x * UNITS_PER_METER as f64).round() as i64, (vec.y * UNITS_PER_METER as f64).round() as i64, (vec.z * UNITS_PER_METER as f64).round() as i64);
 }
 
 pub fn dvec3_to_vec3(vec: DVec3) -> Vec3 {
     return vec3(vec.x as f32, vec.y as f32, vec.z as f32);
 }
 
+// micrometers to meters. divides in f64 and only rounds to f32 at the end, casting the i64 straight to f32 lost everything below 1um * 2^24 = 16m
 pub fn vec3_from_i64vec3(vec: &I64Vec3) -> Vec3 {
-    let v = vec3(vec.x as f32/UNITS_PER_METER as f32, vec.y as f32/UNITS_PER_METER as f32, vec.z as f32/UNITS_PER_METER as f32);
-    return v;
+    return dvec3_to_vec3(dvec3_from_i64vec3(vec));
 }
 
 pub fn dvec3_from_i64vec3(vec: &I64Vec3) -> DVec3 {
@@ -48,6 +178,10 @@ pub fn dvec3_from_i64vec3(vec: &I64Vec3) -> DVec3 {
     return v;
 }
 
+pub fn dquat(q: &nalgebra_glm::Quat) -> nalgebra_glm::DQuat {
+    return nalgebra_glm::DQuat::new(q.w as f64, q.i as f64, q.j as f64, q.k as f64);
+}
+
 pub fn multiply_vec_by_matrix(vec: &Vec3, mat: &nalgebra_glm::Mat4) -> Vec3 {
     return (mat * vec4(vec.x, vec.y, vec.z, 1.0)).xyz();
 }
@@ -62,7 +196,7 @@ impl Transform {
             scl: vec3(1.0, 1.0, 1.0),
             rotscalemat: nalgebra_glm::identity()
         };
-        // t.update_rotscalemat();
+        t.update_rotscalemat();
         return t;
     }
     
@@ -73,10 +207,15 @@ impl Transform {
             scl: vec3(1.0, 1.0, 1.0),
             rotscalemat: nalgebra_glm::identity()
         };
-        // t.update_rotscalemat();
+        t.update_rotscalemat();
         return t;
     }
 
+    // for loading saved transforms. rot is used as is (not renormalized) so a saved transform comes back bit for bit the same
+    pub fn from_parts(pos_in_um: I64Vec3, rot: nalgebra_glm::Quat, scl: Vec3) -> Self {
+        return Self { pos: pos_in_um, rot, scl, rotscalemat: nalgebra_glm::quat_to_mat4(&rot) * nalgebra_glm::scaling(&scl) };
+    }
+
     pub fn empty() -> Self {
         return Transform::new(i64vec3(0, 0, 0))
     }
@@ -92,28 +231,21 @@ impl Transform {
 
     pub fn rotate_around_axis(&mut self, axis: nalgebra_glm::Vec3, angle: f32) {
         self.rot = nalgebra_glm::quat_cross(&self.rot, &nalgebra_glm::quat_angle_axis(angle, &axis));
-        // self.update_rotscalemat();
+        self.update_rotscalemat();
     }
 
-    // rotates intrinsically (like euler angles)
+    // rotates intrinsically (like euler angles), rot is in radians. see ROTATION above
     pub fn setrotyxz(&mut self, rot: nalgebra_glm::Vec3) {
-        let mut fixed_rot = rot.clone();
-        if fixed_rot.x >= 360.0 {fixed_rot.x = fixed_rot.x % 360.0};
-        if fixed_rot.y >= 360.0 {fixed_rot.y = fixed_rot.y % 360.0};
-        if fixed_rot.z >= 360.0 {fixed_rot.z = fixed_rot.z % 360.0};
-        //println!("its {}", fixed_rot.y);
-        //fixed_rot.x = 0.0;
-        //fixed_rot.z = 0.0;
-        self.rot = nalgebra_glm::quat_identity();
-        self.yaw(rot.y);
-        self.pitch(rot.x);
-        self.roll(rot.z);
-        // let y = nalgebra_glm::quat_angle_axis(fixed_rot.y, &vec3(0.0, 1.0, 0.0));
-        // let x = nalgebra_glm::quat_angle_axis(fixed_rot.x, &vec3(1.0, 0.0, 0.0));
-        // let z = nalgebra_glm::quat_angle_axis(fixed_rot.z, &vec3(0.0, 0.0, 1.0));
-        // self.rot = y * x * z;       
-        // self.update_rotscalemat();
-        //println!("we have {}", self.rot());
+        self.set_euler(EulerAngles::from_vec3(&rot), EulerOrder::YXZ);
+    }
+
+    pub fn set_euler(&mut self, angles: EulerAngles, order: EulerOrder) {
+        self.rot = angles.to_quat(order);
+        self.update_rotscalemat();
+    }
+
+    pub fn euler(&self, order: EulerOrder) -> EulerAngles {
+        return EulerAngles::from_quat(&self.rot, order);
     }
 
     // returns volume in meters^3 instead of um^3 because that is too big a number
@@ -135,11 +267,25 @@ impl Transform {
         return v;
     }
 
+    // afterwards get_look_vector() is vec (normalized), with the up vector as close to +y as possible
     pub fn set_look_vector(&mut self, vec: &Vec3) {
-        self.rot = nalgebra_glm::quat_look_at_rh(&vec, &vec3(0.0, 1.0, 0.0));
+        self.set_look_vector_up(vec, &vec3(0.0, 1.0, 0.0));
+    }
+
+    pub fn set_look_vector_up(&mut self, vec: &Vec3, up: &Vec3) {
+        self.rot = quat_look_along(vec, up);
         self.update_rotscalemat();
     }
 
+    // points the look vector at target. does nothing if target is where we already are
+    pub fn look_at(&mut self, target: &I64Vec3, up: &Vec3) {
+        let offset = dvec3_from_i64vec3(&(target - self.pos));
+        if offset.norm() == 0.0 {
+            return;
+        }
+        self.set_look_vector_up(&dvec3_to_vec3(offset.normalize()), up);
+    }
+
     pub fn get_up_vector(&self) -> Vec3 {
         return nalgebra_glm::quat_cross_vec(&self.rot.normalize().conjugate(), &nalgebra_glm::vec3(0.0,1.0, 0.0));
     }
@@ -186,8 +332,18 @@ impl Transform {
         self.update_rotscalemat();
     }
 
+    // YXZ euler angles in radians, the same ones setrotyxz() takes
     pub fn rot(&self) -> nalgebra_glm::Vec3 {
-        return nalgebra_glm::quat_euler_angles(&self.rot);
+        return self.euler(EulerOrder::YXZ).to_vec3();
+    }
+
+    pub fn rot_quat(&self) -> nalgebra_glm::Quat {
+        return self.rot;
+    }
+
+    pub fn set_rot_quat(&mut self, rot: nalgebra_glm::Quat) {
+        self.rot = rot;
+        self.update_rotscalemat();
     }
 
 
@@ -196,8 +352,7 @@ impl Transform {
     }
 
     pub fn setpos(&mut self, pos: I64Vec3) { // set position in MICROMETERS
-        self.pos = pos;
-        // self.update_rotscalemat()
+        self.pos = pos; // rotscalemat doesn't include position, so it doesn't need updating
     }
 
     pub fn setpos_meters(&mut self, pos: DVec3) {
@@ -212,6 +367,63 @@ impl Transform {
         return dvec3_from_i64vec3(&self.pos);
     }
 
+    // moves by offset micrometers, exactly
+    pub fn translate(&mut self, offset: I64Vec3) {
+        self.pos += offset;
+    }
+
+    pub fn translate_meters(&mut self, offset: DVec3) {
+        self.pos += i64vec3_from_dvec3(&offset);
+    }
+
+    // moves distance meters along direction (which should be normalized), using f64 so long moves don't drift
+    pub fn move_along(&mut self, direction: &DVec3, distance: f64) {
+        self.pos += i64vec3_from_dvec3(&(direction * distance));
+    }
+
+    // micrometers from self to other, exact
+    pub fn offset_to(&self, other: &Transform) -> I64Vec3 {
+        return other.pos - self.pos;
+    }
+
+    pub fn distance_to(&self, other: &Transform) -> i64 {
+        return i64vec3_mag(&self.offset_to(other));
+    }
+
+    // position in meters relative to origin (in micrometers), subtracted in i64 first so it's precise no matter how far both are from (0, 0, 0)
+    pub fn relative_pos(&self, origin: &I64Vec3) -> Vec3 {
+        return vec3_from_i64vec3(&(self.pos - origin));
+    }
+
+    pub fn relative_pos_d(&self, origin: &I64Vec3) -> DVec3 {
+        return dvec3_from_i64vec3(&(self.pos - origin));
+    }
+
+    // where a point given relative to this transform (in its rotated/scaled space, in meters) is in the world
+    pub fn local_to_world(&self, local: &DVec3) -> I64Vec3 {
+        let scaled = local.component_mul(&dvec3(self.scl.x as f64, self.scl.y as f64, self.scl.z as f64));
+        return self.pos + i64vec3_from_dvec3(&nalgebra_glm::quat_rotate_vec3(&dquat(&self.rot), &scaled));
+    }
+
+    // the inverse of local_to_world
+    pub fn world_to_local(&self, world_pos: &I64Vec3) -> DVec3 {
+        let offset = nalgebra_glm::quat_rotate_vec3(&dquat(&nalgebra_glm::quat_conjugate(&self.rot.normalize())), &dvec3_from_i64vec3(&(world_pos - self.pos)));
+        return offset.component_div(&dvec3(self.scl.x as f64, self.scl.y as f64, self.scl.z as f64));
+    }
+
+    // f64 versions of the look/up/right vectors, for multiplying by long distances
+    pub fn get_look_vector_d(&self) -> DVec3 {
+        return nalgebra_glm::quat_cross_vec(&dquat(&self.rot.normalize()).conjugate(), &dvec3(0.0, 0.0, 1.0));
+    }
+
+    pub fn get_up_vector_d(&self) -> DVec3 {
+        return nalgebra_glm::quat_cross_vec(&dquat(&self.rot.normalize()).conjugate(), &dvec3(0.0, 1.0, 0.0));
+    }
+
+    pub fn get_right_vector_d(&self) -> DVec3 {
+        return nalgebra_glm::quat_cross_vec(&dquat(&self.rot.normalize()).conjugate(), &dvec3(1.0, 0.0, 0.0));
+    }
+
     // TODO: translation direction is NOT relative to its rotation, should have an alt function when that behavior is undesired
     // returns a matrix that converts from model space to camera space (or to world space if cam_offset == (0, 0, 0))
     pub fn get_model(&self, cam_offset: &I64Vec3) -> nalgebra_glm::Mat4 {
@@ -244,6 +456,10 @@ impl Transform {
         return nalgebra_glm::quat_to_mat4(&self.rot);
     }
 
+    pub fn rotscalemat(&self) -> &nalgebra_glm::Mat4 {
+        return &self.rotscalemat;
+    }
+
     fn update_rotscalemat(&mut self) {
         self.rot = self.rot.normalize();
         self.rotscalemat = nalgebra_glm::quat_to_mat4(&self.rot) * nalgebra_glm::scaling(&self.scl);
@@ -286,4 +502,174 @@ macro_rules! impl_transform {
             }
         }     
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magnitude_is_exact() {
+        // (2, 3, 6) has length 7, so these are exact at 10^12 um (10^6 km), where an f64 sqrt of the square starts being off
+        assert_eq!(i64vec3_mag(&i64vec3(1_000_000_000_000i64, 0, 0)), 1_000_000_000_000);
+        assert_eq!(i64vec3_mag(&i64vec3(2_000_000_000_000i64, -3_000_000_000_000, 6_000_000_000_000)), 7_000_000_000_000);
+        assert_eq!(i64vec3_mag(&i64vec3(2_000_000_000_000i64, -3_000_000_000_000, 6_000_000_000_001)), 7_000_000_000_000); // rounds down
+
+        for v in [i64vec3(999_999_999_999i64, 1, 1), i64vec3(3_037_000_499i64, 3_037_000_499, 3_037_000_499), i64vec3(1i64 << 53, (1i64 << 53) + 1, 7)] {
+            let mag = i64vec3_mag(&v) as u128;
+            let squared = i64vec3_mag_squared(&v);
+            assert!(mag * mag <= squared && (mag + 1) * (mag + 1) > squared, "{} isn't the integer square root of {}", mag, squared);
+        }
+    }
+
+    #[test]
+    fn magnitude_doesnt_overflow_at_the_extremes() {
+        assert_eq!(i64vec3_mag_squared(&i64vec3(i64::MIN, i64::MIN, i64::MIN)), 3 * (1u128 << 126));
+        assert_eq!(i64vec3_mag(&i64vec3(i64::MAX, 0, 0)), i64::MAX);
+        assert_eq!(i64vec3_mag(&i64vec3(0, i64::MIN, 0)), i64::MAX); // 2^63 saturates
+        assert_eq!(i64vec3_mag(&i64vec3(i64::MIN, i64::MAX, i64::MIN)), i64::MAX);
+        assert_eq!(i64vec3_mag(&i64vec3(0i64, 0, 0)), 0);
+    }
+
+    #[test]
+    fn meters_round_trip_within_2_pow_51_um() {
+        let far = (1i64 << 51) - 1;
+        for v in [i64vec3(far, -far, 123_456_789_012_345), i64vec3(1i64, -1, 0), i64vec3(-far, 999_999, far - 1)] {
+            assert_eq!(i64vec3_from_dvec3(&dvec3_from_i64vec3(&v)), v);
+        }
+        let mut x: i64 = 12345;
+        for _ in 0..10000 {
+            x = (x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407) >> 13) % far;
+            let v = i64vec3(x, -x, x / 3);
+            assert_eq!(i64vec3_from_dvec3(&dvec3_from_i64vec3(&v)), v);
+        }
+        // and the multiply goes through f64, so 0.1m doesn't come out as 99999um
+        assert_eq!(i64vec3_from_vec3(&vec3(0.1, -0.1, 0.3)), i64vec3(100_000i64, -100_000, 300_000));
+    }
+
+    #[test]
+    fn offsets_far_from_the_origin_stay_precise() {
+        let origin = Transform::new(i64vec3(9_000_000_000_000_000i64, -9_000_000_000_000_000, 1));
+        let mut other = Transform::new(origin.pos() + i64vec3(1_000_000_000i64, 0, -1)); // 1km away, 9 million km out
+        assert_eq!(origin.distance_to(&other), 1_000_000_000);
+        let offset = other.relative_pos(&origin.pos());
+        assert!((offset.x - 1000.0).abs() < 0.0001 && offset.z == -0.000001, "1km offset came out as {:?}", offset);
+
+        other.setpos(other.pos() + i64vec3(1i64, 0, 0));
+        assert_eq!(origin.offset_to(&other), i64vec3(1_000_000_001i64, 0, -1));
+    }
+
+    // q and -q are the same rotation
+    fn same_rotation(a: &nalgebra_glm::Quat, b: &nalgebra_glm::Quat) -> bool {
+        return a.normalize().dot(&b.normalize()).abs() > 0.99999;
+    }
+
+    #[test]
+    fn degrees_radians_round_trip() {
+        for degrees in [-720.0, -90.0, 0.0, 45.0, 180.0, 359.0, 1080.0] {
+            let back: Degrees = Radians::from(Degrees(degrees)).into();
+            assert!((back.0 - degrees).abs() <= 0.001, "{} degrees came back as {}", degrees, back.0);
+        }
+        let right_angle: Radians = Degrees(90.0).into();
+        assert!((right_angle.0 - std::f32::consts::FRAC_PI_2).abs() <= 0.00001, "90 degrees is {} radians instead of pi/2", right_angle.0);
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        for order in [EulerOrder::YXZ, EulerOrder::XYZ] {
+            for x in [-170.0, -80.0, -30.0, 0.0, 15.0, 60.0, 89.0, 135.0] {
+                for y in [-179.0, -100.0, -45.0, 0.0, 30.0, 89.0, 120.0] {
+                    for z in [-150.0, -10.0, 0.0, 45.0, 170.0] {
+                        let angles = EulerAngles::degrees(x, y, z);
+                        let q = angles.to_quat(order);
+                        let back = EulerAngles::from_quat(&q, order);
+                        assert!(same_rotation(&q, &back.to_quat(order)), "{:?} {:?} came back as {:?} degrees, a different rotation", order, angles.to_degrees(), back.to_degrees());
+                        // angles already in the returned range should come back as they were
+                        let middle = if order == EulerOrder::YXZ {x} else {y};
+                        if (middle as f32).abs() < 90.0 && (x as f32).abs() < 180.0 && (y as f32).abs() < 180.0 {
+                            assert!((back.to_degrees() - angles.to_degrees()).abs().max() <= 0.01, "{:?} {:?} came back as {:?} degrees", order, angles.to_degrees(), back.to_degrees());
+                        }
+                    }
+                }
+            }
+        }
+
+        // setrotyxz() and rot() are YXZ radians, and should agree with each other
+        let mut t = Transform::empty();
+        let angles = vec3(0.3, -1.2, 0.7);
+        t.setrotyxz(angles);
+        assert!((t.rot() - angles).abs().max() <= 0.0001, "setrotyxz({:?}) then rot() gave {:?}", angles, t.rot());
+        // yaw only: the look vector spins around +y. get_look_vector() rotates by the conjugate, so +yaw turns it from +z towards -x
+        t.setrotyxz(vec3(0.0, std::f32::consts::FRAC_PI_2, 0.0));
+        assert!((t.get_look_vector() - vec3(-1.0, 0.0, 0.0)).norm() <= 0.0001, "yawing 90 degrees gave a look vector of {:?}", t.get_look_vector());
+    }
+
+    #[test]
+    fn euler_gimbal_lock() {
+        for (order, angles) in [(EulerOrder::YXZ, EulerAngles::degrees(90.0, 30.0, 20.0)), (EulerOrder::YXZ, EulerAngles::degrees(-90.0, -45.0, 10.0)), (EulerOrder::XYZ, EulerAngles::degrees(25.0, 90.0, -40.0)), (EulerOrder::XYZ, EulerAngles::degrees(0.0, -90.0, 60.0))] {
+            let q = angles.to_quat(order);
+            let back = EulerAngles::from_quat(&q, order);
+            assert!(!back.to_vec3().iter().any(|v| v.is_nan()), "{:?} {:?} came back as NaN", order, angles.to_degrees());
+            assert!(same_rotation(&q, &back.to_quat(order)), "{:?} {:?} came back as {:?} degrees, a different rotation", order, angles.to_degrees(), back.to_degrees());
+        }
+    }
+
+    #[test]
+    fn look_at() {
+        let mut t = Transform::meters(dvec3(1000.0, 5.0, -3.0));
+        for (direction, up) in [(vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0)), (vec3(1.0, 2.0, -3.0), vec3(0.0, 1.0, 0.0)), (vec3(-1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)), (vec3(0.0, -1.0, 0.0), vec3(0.0, 1.0, 0.0))] {
+            t.set_look_vector_up(&direction, &up);
+            let look = t.get_look_vector();
+            assert!(!look.iter().any(|v| v.is_nan()) && (look - direction.normalize()).norm() <= 0.0001, "looking along {:?} (up {:?}) gave a look vector of {:?}", direction, up, look);
+            let actual_up = t.get_up_vector();
+            assert!(actual_up.dot(&look).abs() <= 0.0001, "looking along {:?} with up {:?} gave an up vector of {:?}, which isn't perpendicular", direction, up, actual_up);
+            // unless it's looking straight along up, the new up should be on the same side as the one asked for
+            assert!(direction.normalize().cross(&up).norm() <= 0.0001 || actual_up.dot(&up) > 0.0, "looking along {:?} with up {:?} gave an up vector of {:?}", direction, up, actual_up);
+        }
+
+        let target = t.pos() + i64vec3(0, 0, -2 * UNITS_PER_METER);
+        t.look_at(&target, &vec3(0.0, 1.0, 0.0));
+        assert!((t.get_look_vector() - vec3(0.0, 0.0, -1.0)).norm() <= 0.0001, "look_at a point 2m towards -z gave a look vector of {:?}", t.get_look_vector());
+    }
+
+    #[test]
+    fn rotscalemat_after_every_mutator() {
+        fn consistent(what: &str, t: &Transform) {
+            let expected = nalgebra_glm::quat_to_mat4(&t.rot_quat()) * nalgebra_glm::scaling(&t.scl());
+            assert!((t.rotscalemat() - expected).abs().max() <= 0.00001, "rotscalemat is out of date after {}", what);
+        }
+
+        let mut t = Transform::new(i64vec3(1, 2, 3));
+        consistent("new", &t);
+        consistent("meters", &Transform::meters(dvec3(1.0, 2.0, 3.0)));
+        consistent("from_parts", &Transform::from_parts(i64vec3(0, 0, 0), nalgebra_glm::quat_angle_axis(0.5, &vec3(0.0, 1.0, 0.0)), vec3(2.0, 3.0, 4.0)));
+        t.setscl(vec3(2.0, 0.5, 3.0));
+        consistent("setscl", &t);
+        t.rotate_around_axis(vec3(0.0, 1.0, 0.0), 0.7);
+        consistent("rotate_around_axis", &t);
+        t.setrotyxz(vec3(0.1, 0.2, 0.3));
+        consistent("setrotyxz", &t);
+        t.set_euler(EulerAngles::degrees(10.0, 20.0, 30.0), EulerOrder::XYZ);
+        consistent("set_euler", &t);
+        t.yaw(0.3);
+        consistent("yaw", &t);
+        t.pitch(0.3);
+        consistent("pitch", &t);
+        t.roll(0.3);
+        consistent("roll", &t);
+        t.rotatex(0.2);
+        t.rotatey(0.2);
+        t.rotatez(0.2);
+        consistent("rotatex/y/z", &t);
+        t.set_rot_quat(nalgebra_glm::quat_angle_axis(1.0, &vec3(1.0, 0.0, 0.0)));
+        consistent("set_rot_quat", &t);
+        t.set_look_vector(&vec3(1.0, 1.0, 0.0));
+        consistent("set_look_vector", &t);
+        t.look_at(&i64vec3(0, 0, 0), &vec3(0.0, 1.0, 0.0));
+        consistent("look_at", &t);
+        t.setpos(i64vec3(5, 5, 5));
+        t.translate(i64vec3(1, 1, 1));
+        consistent("setpos/translate", &t);
+        consistent("clone", &t.clone());
+    }
+}